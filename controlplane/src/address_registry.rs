@@ -0,0 +1,134 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The cluster-wide registry of the addresses requested by the Gateways of blixt, which detects
+//! Gateways requesting an address another Gateway already requested. Both would otherwise
+//! program a LoadBalancer Service and the dataplane with the same address.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use gateway_api::apis::standard::{gatewayclasses::GatewayClass, gateways::Gateway};
+use kube::api::{Api, ListParams};
+
+use crate::*;
+use pagination::list_all;
+
+/// The reason of the Accepted=False condition of a Gateway whose address is in use.
+pub const ADDRESS_IN_USE_REASON: &str = "AddressInUse";
+
+// Orders the Gateways requesting an address: the one created first gets it, the namespace and
+// name break ties so that every reconciliation agrees.
+type Seniority = (Option<DateTime<Utc>>, NamespacedName);
+
+/// The Gateway each requested address belongs to.
+#[derive(Debug, Default)]
+pub struct AddressRegistry {
+    owners: HashMap<String, Seniority>,
+}
+
+impl AddressRegistry {
+    /// Registers the addresses requested by the Gateways. An address requested by several of
+    /// them belongs to the one created first.
+    pub fn from_gateways<'a>(gateways: impl IntoIterator<Item = &'a Gateway>) -> Self {
+        let mut registry = AddressRegistry::default();
+        for gateway in gateways {
+            let Some(seniority) = seniority(gateway) else {
+                continue;
+            };
+            for address in requested_addresses(gateway) {
+                registry
+                    .owners
+                    .entry(address.to_string())
+                    .and_modify(|owner| {
+                        if seniority < *owner {
+                            *owner = seniority.clone();
+                        }
+                    })
+                    .or_insert_with(|| seniority.clone());
+            }
+        }
+        registry
+    }
+
+    /// Returns the Gateway the address belongs to, if any Gateway requested it.
+    pub fn owner(&self, address: &str) -> Option<&NamespacedName> {
+        self.owners.get(address).map(|(_, owner)| owner)
+    }
+
+    /// Returns the first address the Gateway requests that belongs to an older Gateway, along
+    /// with that Gateway.
+    pub fn conflict<'a>(&'a self, gateway: &'a Gateway) -> Option<(&'a str, &'a NamespacedName)> {
+        let seniority = seniority(gateway)?;
+        requested_addresses(gateway).find_map(|address| match self.owners.get(address) {
+            Some(owner) if *owner < seniority => Some((address, &owner.1)),
+            _ => None,
+        })
+    }
+}
+
+/// Returns the registry of the addresses requested by the Gateways of the GatewayClasses of
+/// blixt, across all namespaces.
+pub async fn get_address_registry(ctx: &Context) -> Result<AddressRegistry> {
+    let gateway_classes = list_all(
+        &Api::<GatewayClass>::all(ctx.client.clone()),
+        &ListParams::default(),
+        ctx.list_page_size,
+    )
+    .await?;
+    let gateways = list_all(
+        &Api::<Gateway>::all(ctx.client.clone()),
+        &ListParams::default(),
+        ctx.list_page_size,
+    )
+    .await?;
+
+    Ok(AddressRegistry::from_gateways(gateways.iter().filter(
+        |gateway| {
+            gateway_classes.iter().any(|gateway_class| {
+                gateway_class.metadata.name.as_ref() == Some(&gateway.spec.gateway_class_name)
+                    && gateway_class.spec.controller_name == GATEWAY_CLASS_CONTROLLER_NAME
+            })
+        },
+    )))
+}
+
+// Returns the IP addresses the Gateway requests. Addresses of other types are rejected when the
+// Gateway is accepted, so they aren't registered.
+fn requested_addresses(gateway: &Gateway) -> impl Iterator<Item = &str> {
+    gateway
+        .spec
+        .addresses
+        .iter()
+        .flatten()
+        .filter(|address| {
+            address
+                .r#type
+                .as_ref()
+                .map_or(true, |address_type| address_type == "IPAddress")
+        })
+        .map(|address| address.value.as_str())
+}
+
+fn seniority(gateway: &Gateway) -> Option<Seniority> {
+    let name = NamespacedName::try_from(&gateway.metadata).ok()?;
+    let created = gateway
+        .metadata
+        .creation_timestamp
+        .as_ref()
+        .map(|time| time.0);
+    Some((created, name))
+}
@@ -0,0 +1,166 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Resolution of Service backendRefs into the backends the dataplane forwards traffic to.
+
+use std::net::Ipv4Addr;
+
+use k8s_openapi::api::core::v1::{Endpoints, Service};
+
+/// The reason of a route's ResolvedRefs condition when a backendRef's Service doesn't exist,
+/// or has no port matching the backendRef.
+pub const BACKEND_NOT_FOUND_REASON: &str = "BackendNotFound";
+/// The reason of a route's ResolvedRefs condition when a backendRef's Service can't be
+/// forwarded to.
+pub const UNSUPPORTED_VALUE_REASON: &str = "UnsupportedValue";
+/// The reason of a route's ResolvedRefs condition when a backendRef's Service port doesn't
+/// serve the route's protocol, e.g. a UDPRoute referencing a TCP-only Service.
+pub const UNSUPPORTED_PROTOCOL_REASON: &str = "UnsupportedProtocol";
+
+/// The backends of a Service backendRef.
+#[derive(Debug, PartialEq)]
+pub enum ServiceBackends {
+    /// The ready addresses and target port of the Service's endpoints. Normal and headless
+    /// Services are handled alike, since traffic is forwarded to the pods directly either way.
+    Endpoints(Vec<Ipv4Addr>, u16),
+    /// The DNS name of an ExternalName Service, passed through for the dataplane to resolve.
+    Hostname(String, u16),
+}
+
+/// Why a Service backendRef can't be resolved, to be reported in the route's ResolvedRefs
+/// condition.
+#[derive(Debug, PartialEq)]
+pub struct UnresolvedBackend {
+    pub reason: &'static str,
+    pub message: String,
+}
+
+impl UnresolvedBackend {
+    fn new(reason: &'static str, message: String) -> UnresolvedBackend {
+        UnresolvedBackend { reason, message }
+    }
+}
+
+/// Resolves a backendRef to the port of the Service, using the Service's Endpoints unless it
+/// is an ExternalName Service. The protocol is the one of the route, i.e. "TCP" or "UDP",
+/// which the Service port has to serve.
+pub fn resolve_service_backends(
+    service: &Service,
+    endpoints: Option<&Endpoints>,
+    port: u16,
+    protocol: &str,
+) -> Result<ServiceBackends, UnresolvedBackend> {
+    let name = service.metadata.name.as_deref().unwrap_or_default();
+    let spec = service.spec.as_ref().ok_or_else(|| {
+        UnresolvedBackend::new(
+            BACKEND_NOT_FOUND_REASON,
+            format!("Service {} has no spec", name),
+        )
+    })?;
+
+    // Services without declared ports (headless and ExternalName ones) can't be checked
+    let declared_protocols: Vec<&str> = spec
+        .ports
+        .iter()
+        .flatten()
+        .filter(|service_port| service_port.port == i32::from(port))
+        .map(|service_port| service_port.protocol.as_deref().unwrap_or("TCP"))
+        .collect();
+    if !declared_protocols.is_empty() && !declared_protocols.contains(&protocol) {
+        return Err(UnresolvedBackend::new(
+            UNSUPPORTED_PROTOCOL_REASON,
+            format!(
+                "port {} of Service {} serves {} but the route is {}",
+                port,
+                name,
+                declared_protocols.join("/"),
+                protocol
+            ),
+        ));
+    }
+
+    if spec.type_.as_deref() == Some("ExternalName") {
+        return match spec.external_name.as_deref() {
+            Some(hostname) if !hostname.is_empty() => {
+                Ok(ServiceBackends::Hostname(hostname.to_string(), port))
+            }
+            _ => Err(UnresolvedBackend::new(
+                UNSUPPORTED_VALUE_REASON,
+                format!("ExternalName Service {} has no externalName", name),
+            )),
+        };
+    }
+
+    // headless Services may not declare ports, in which case the backendRef port is used as is
+    let service_port = spec.ports.iter().flatten().find(|service_port| {
+        service_port.port == i32::from(port)
+            && service_port.protocol.as_deref().unwrap_or("TCP") == protocol
+    });
+    if service_port.is_none() && spec.ports.as_ref().is_some_and(|ports| !ports.is_empty()) {
+        return Err(UnresolvedBackend::new(
+            BACKEND_NOT_FOUND_REASON,
+            format!("Service {} has no port {}", name, port),
+        ));
+    }
+    let port_name = service_port.and_then(|service_port| service_port.name.as_deref());
+
+    let mut addresses = Vec::new();
+    let mut target_port = None;
+    for subset in endpoints
+        .and_then(|endpoints| endpoints.subsets.as_ref())
+        .into_iter()
+        .flatten()
+    {
+        let subset_port = match &subset.ports {
+            Some(ports) => ports
+                .iter()
+                .find(|endpoint_port| endpoint_port.name.as_deref() == port_name)
+                .map(|endpoint_port| endpoint_port.port),
+            None => Some(i32::from(port)),
+        };
+        let Some(subset_port) = subset_port else {
+            continue;
+        };
+        target_port = Some(subset_port);
+        for address in subset.addresses.iter().flatten() {
+            match address.ip.parse::<Ipv4Addr>() {
+                Ok(ip) => addresses.push(ip),
+                Err(_) => {
+                    return Err(UnresolvedBackend::new(
+                        UNSUPPORTED_VALUE_REASON,
+                        format!(
+                            "endpoint {} of Service {} is not an IPv4 address",
+                            address.ip, name
+                        ),
+                    ))
+                }
+            }
+        }
+    }
+
+    let target_port = match target_port.map(u16::try_from) {
+        Some(Ok(target_port)) => target_port,
+        // without endpoints there is nothing to forward to yet, which isn't an error
+        None => port,
+        Some(Err(_)) => {
+            return Err(UnresolvedBackend::new(
+                UNSUPPORTED_VALUE_REASON,
+                format!("Service {} has an invalid target port", name),
+            ))
+        }
+    };
+    Ok(ServiceBackends::Endpoints(addresses, target_port))
+}
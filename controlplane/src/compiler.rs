@@ -0,0 +1,318 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Compilation of the resolved backendRefs of a route into the targets the dataplane programs
+//! the vip of its Gateway listener with, merged with those of the other routes attached to the
+//! listener. Compilation is pure, so that what a route compiles to
+//! only depends on its inputs and can be checked against the golden files of the tests.
+
+use std::collections::BTreeMap;
+use std::net::Ipv4Addr;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::backend_refs::ServiceBackends;
+
+/// The weight of backendRefs that don't set one.
+pub const DEFAULT_WEIGHT: u32 = 1;
+
+/// The protocol of a route, i.e. of the vip of the listener it's attached to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl Protocol {
+    /// Returns the protocol as Services and routes spell it.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Protocol::Tcp => "TCP",
+            Protocol::Udp => "UDP",
+        }
+    }
+}
+
+/// A resolved backendRef of a route, along with its weight.
+#[derive(Debug, PartialEq)]
+pub struct WeightedBackends {
+    pub backends: ServiceBackends,
+    pub weight: u32,
+}
+
+/// The address a target is forwarded to.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TargetAddress {
+    Ip(Ipv4Addr),
+    /// The DNS name of an ExternalName Service, which the dataplane resolves.
+    Hostname(String),
+}
+
+/// A backend of the vip, which receives `weight` shares of its traffic.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Target {
+    pub address: TargetAddress,
+    pub port: u16,
+    pub weight: u32,
+}
+
+/// The targets of the vip of a listener.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompiledTargets {
+    pub vip: Ipv4Addr,
+    pub port: u16,
+    pub protocol: Protocol,
+    pub targets: Vec<Target>,
+}
+
+impl CompiledTargets {
+    /// Returns the number of backends the targets take in the dataplane, which gives every
+    /// backend of a vip the same share of its traffic: a target is a backend per unit of its
+    /// weight.
+    pub fn backends_len(&self) -> u64 {
+        self.targets.iter().map(|target| target.weight as u64).sum()
+    }
+}
+
+/// The targets of a vip have more addresses than the dataplane has backends for a vip, so that
+/// they don't fit even with a weight of 1.
+#[derive(Debug, PartialEq, Eq, Error)]
+#[error("{targets} targets don't fit in the {capacity} backends of a vip")]
+pub struct CapacityExceeded {
+    pub targets: usize,
+    pub capacity: usize,
+}
+
+/// The reason of the Accepted=False condition of a route that can't share the vip of its
+/// listener with the other routes attached to it.
+pub const MERGE_CONFLICT_REASON: &str = "NotAllowedByListeners";
+
+/// A route whose targets were compiled for another vip, port or protocol than those of the
+/// listener they were merged for. `index` is the position of the route in the merged routes.
+#[derive(Debug, PartialEq, Eq, Error)]
+#[error("route {index} is a {} route of {vip}:{port}, which doesn't match its listener", .protocol.as_str())]
+pub struct MergeConflict {
+    pub index: usize,
+    pub vip: Ipv4Addr,
+    pub port: u16,
+    pub protocol: Protocol,
+}
+
+/// Compiles the backendRefs of a route into the targets of the vip and port of the listener
+/// it's attached to.
+///
+/// Each backendRef gets the share of the traffic its weight asks for, split evenly between its
+/// backends, as the Gateway API specifies. Weights are scaled to the smallest integers that do
+/// so. BackendRefs of weight 0 and without backends get no traffic. A backend referenced more
+/// than once is a single target with the weights of every reference. Targets are sorted, so
+/// that the same route always compiles to the same targets.
+pub fn compile(
+    vip: Ipv4Addr,
+    port: u16,
+    protocol: Protocol,
+    backend_refs: &[WeightedBackends],
+) -> CompiledTargets {
+    let backend_refs: Vec<(Vec<(TargetAddress, u16)>, u64)> = backend_refs
+        .iter()
+        .filter(|backend_ref| backend_ref.weight > 0)
+        .map(|backend_ref| (targets_of(&backend_ref.backends), backend_ref.weight as u64))
+        .filter(|(targets, _)| !targets.is_empty())
+        .collect();
+
+    // every backend of a backendRef gets its weight times this many shares over its backends
+    let shares = backend_refs
+        .iter()
+        .fold(1, |shares, (targets, _)| lcm(shares, targets.len() as u64));
+
+    let mut weights = BTreeMap::new();
+    for (targets, weight) in &backend_refs {
+        let target_weight = weight.saturating_mul(shares / targets.len() as u64);
+        for target in targets {
+            let total = weights.entry(target.clone()).or_insert(0_u64);
+            *total = total.saturating_add(target_weight);
+        }
+    }
+
+    CompiledTargets {
+        vip,
+        port,
+        protocol,
+        targets: scaled_targets(weights),
+    }
+}
+
+/// Merges the targets compiled for each of the routes attached to a listener into the targets
+/// of its vip, which is the union of their backends.
+///
+/// Every route gets the same share of the traffic of the vip, split between its targets by
+/// their weights, so that adding a route doesn't change how the traffic of the others is split.
+/// A backend of several routes is a single target with the shares of each of them. Routes
+/// without targets get no traffic. Routes compiled for another vip, port or protocol than the
+/// listener's, e.g. a UDPRoute attached to a TCP listener, can't share its vip: they are left
+/// out and returned as conflicts.
+pub fn merge(
+    vip: Ipv4Addr,
+    port: u16,
+    protocol: Protocol,
+    routes: &[CompiledTargets],
+) -> (CompiledTargets, Vec<MergeConflict>) {
+    let mut conflicts = vec![];
+    let mut merged = vec![];
+    for (index, route) in routes.iter().enumerate() {
+        if (route.vip, route.port, route.protocol) != (vip, port, protocol) {
+            conflicts.push(MergeConflict {
+                index,
+                vip: route.vip,
+                port: route.port,
+                protocol: route.protocol,
+            });
+        } else if route.backends_len() > 0 {
+            merged.push(route);
+        }
+    }
+
+    // every route gets this many shares over the backends it takes
+    let shares = merged
+        .iter()
+        .fold(1, |shares, route| lcm(shares, route.backends_len()));
+
+    let mut weights = BTreeMap::new();
+    for route in merged {
+        let scale = shares / route.backends_len();
+        for target in &route.targets {
+            let total = weights
+                .entry((target.address.clone(), target.port))
+                .or_insert(0_u64);
+            *total = total.saturating_add((target.weight as u64).saturating_mul(scale));
+        }
+    }
+
+    let merged = CompiledTargets {
+        vip,
+        port,
+        protocol,
+        targets: scaled_targets(weights),
+    };
+    (merged, conflicts)
+}
+
+/// Fits the targets in the `capacity` backends the dataplane has for a vip, for routes whose
+/// weights would take more of them than that. Every target keeps a backend, and the others are
+/// split by largest remainder in proportion to the weights, so that the shares of the targets
+/// are as close to those of their weights as the capacity allows. The weights are then scaled
+/// to the smallest integers again. Targets that already fit are returned as they are.
+pub fn fit(
+    compiled: CompiledTargets,
+    capacity: usize,
+) -> Result<CompiledTargets, CapacityExceeded> {
+    if compiled.backends_len() <= capacity as u64 {
+        return Ok(compiled);
+    }
+    if compiled.targets.len() > capacity {
+        return Err(CapacityExceeded {
+            targets: compiled.targets.len(),
+            capacity,
+        });
+    }
+
+    let total = compiled.backends_len();
+    let spare = (capacity - compiled.targets.len()) as u64;
+    let mut shares: Vec<(u64, u64)> = compiled
+        .targets
+        .iter()
+        .map(|target| {
+            let share = target.weight as u64 * spare;
+            (1 + share / total, share % total)
+        })
+        .collect();
+
+    // the spare backends left by rounding down go to the largest remainders, the first
+    // targets break ties
+    let assigned: u64 = shares.iter().map(|(backends, _)| backends).sum();
+    let mut by_remainder: Vec<usize> = (0..shares.len()).collect();
+    by_remainder.sort_by(|a, b| shares[*b].1.cmp(&shares[*a].1));
+    for index in by_remainder
+        .into_iter()
+        .take((capacity as u64).saturating_sub(assigned) as usize)
+    {
+        shares[index].0 += 1;
+    }
+
+    let divisor = shares
+        .iter()
+        .fold(0, |divisor, (backends, _)| gcd(divisor, *backends));
+    let targets = compiled
+        .targets
+        .into_iter()
+        .zip(shares)
+        .map(|(target, (backends, _))| Target {
+            weight: (backends / divisor) as u32,
+            ..target
+        })
+        .collect();
+
+    Ok(CompiledTargets {
+        targets,
+        ..compiled
+    })
+}
+
+// Returns the targets of the weights of each address and port, scaled to the smallest integers
+// with the same ratios.
+fn scaled_targets(weights: BTreeMap<(TargetAddress, u16), u64>) -> Vec<Target> {
+    let divisor = weights
+        .values()
+        .fold(0, |divisor, weight| gcd(divisor, *weight));
+    weights
+        .into_iter()
+        .map(|((address, port), weight)| Target {
+            address,
+            port,
+            weight: u32::try_from(weight / divisor).unwrap_or(u32::MAX),
+        })
+        .collect()
+}
+
+// Returns the distinct addresses and ports of the backends.
+fn targets_of(backends: &ServiceBackends) -> Vec<(TargetAddress, u16)> {
+    let mut targets: Vec<(TargetAddress, u16)> = match backends {
+        ServiceBackends::Endpoints(addresses, port) => addresses
+            .iter()
+            .map(|address| (TargetAddress::Ip(*address), *port))
+            .collect(),
+        ServiceBackends::Hostname(hostname, port) => {
+            vec![(TargetAddress::Hostname(hostname.clone()), *port)]
+        }
+    };
+    targets.sort();
+    targets.dedup();
+    targets
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn lcm(a: u64, b: u64) -> u64 {
+    (a / gcd(a, b)).saturating_mul(b)
+}
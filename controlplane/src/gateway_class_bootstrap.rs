@@ -0,0 +1,89 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::BTreeMap;
+
+use gateway_api::apis::standard::gatewayclasses::{GatewayClass, GatewayClassSpec};
+use kube::{
+    api::{Api, PostParams},
+    ResourceExt,
+};
+use tracing::*;
+
+use crate::*;
+
+/// The name of the GatewayClass created at startup by default.
+pub const DEFAULT_GATEWAY_CLASS_NAME: &str = "blixt";
+
+// Returns the GatewayClass blixt creates at startup, annotated as owned by blixt so that it can
+// be told apart from GatewayClasses created by users.
+pub fn bootstrap_gateway_class(name: &str) -> GatewayClass {
+    let mut gateway_class = GatewayClass::new(
+        name,
+        GatewayClassSpec {
+            controller_name: GATEWAY_CLASS_CONTROLLER_NAME.to_string(),
+            description: Some("GatewayClass created by blixt at startup".to_string()),
+            parameters_ref: None,
+        },
+    );
+    gateway_class.metadata.annotations = Some(BTreeMap::from([(
+        GATEWAY_CLASS_OWNER_ANNOTATION.to_string(),
+        GATEWAY_CLASS_OWNER.to_string(),
+    )]));
+    gateway_class
+}
+
+// Creates the GatewayClass of blixt with the provided name if it doesn't exist. An existing
+// GatewayClass of that name is left as is, even if it belongs to another controller, so this is
+// safe to run on every startup.
+pub async fn ensure_gateway_class(ctx: &Context, name: &str) -> Result<()> {
+    let gateway_class_api: Api<GatewayClass> = Api::all(ctx.client.clone());
+    if let Some(existing) = gateway_class_api
+        .get_opt(name)
+        .await
+        .map_err(Error::KubeError)?
+    {
+        if existing.spec.controller_name != GATEWAY_CLASS_CONTROLLER_NAME {
+            warn!(
+                "GatewayClass {} exists and belongs to controller {}, not creating it",
+                name, existing.spec.controller_name
+            );
+        } else if !existing
+            .annotations()
+            .contains_key(GATEWAY_CLASS_OWNER_ANNOTATION)
+        {
+            debug!("GatewayClass {} exists and was created by the user", name);
+        }
+        return Ok(());
+    }
+
+    match gateway_class_api
+        .create(&PostParams::default(), &bootstrap_gateway_class(name))
+        .await
+    {
+        Ok(_) => {
+            info!("created GatewayClass {}", name);
+            Ok(())
+        }
+        // another replica created it in the meantime
+        Err(kube::Error::Api(response)) if response.code == 409 => Ok(()),
+        // the GatewayClass CRD isn't installed
+        Err(kube::Error::Api(response)) if response.code == 404 => {
+            Err(Error::CRDNotFoundError(kube::Error::Api(response)))
+        }
+        Err(error) => Err(Error::KubeError(error)),
+    }
+}
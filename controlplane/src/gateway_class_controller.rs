@@ -0,0 +1,169 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The controller of the GatewayClasses of blixt, which keeps a GatewayClass from being deleted
+//! while Gateways use it, as the Gateway API requires: the class holds the gateway-exists
+//! finalizer as long as any Gateway references it, and a deleted class reports the Gateways
+//! blocking its deletion in its status.
+
+use futures::StreamExt;
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use gateway_api::apis::standard::{gatewayclasses::GatewayClass, gateways::Gateway};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1 as metav1;
+use kube::{
+    api::{Api, ListParams},
+    runtime::{controller::Action, reflector::ObjectRef, watcher::Config, Controller},
+    Resource, ResourceExt,
+};
+use tracing::*;
+
+use crate::*;
+use gateway_utils::{patch_status, set_condition, set_finalizer};
+use pagination::list_all;
+
+/// The finalizer the Gateway API reserves for GatewayClasses referenced by Gateways.
+pub const GATEWAY_EXISTS_FINALIZER: &str = "gateway-exists-finalizer.gateway.networking.k8s.io";
+/// The type of the condition of a deleted GatewayClass whose Gateways still exist.
+pub const DELETION_BLOCKED_CONDITION: &str = "DeletionBlocked";
+/// The reason of the DeletionBlocked=True condition.
+pub const GATEWAYS_EXIST_REASON: &str = "GatewaysExist";
+
+// The most Gateways named in the message of the DeletionBlocked condition.
+const MAX_LISTED_GATEWAYS: usize = 5;
+
+pub async fn reconcile(gateway_class: Arc<GatewayClass>, ctx: Arc<Context>) -> Result<Action> {
+    if gateway_class.spec.controller_name != GATEWAY_CLASS_CONTROLLER_NAME {
+        return Ok(Action::await_change());
+    }
+    let name = gateway_class.name_any();
+    let gateway_class_api = Api::<GatewayClass>::all(ctx.client.clone());
+
+    let all_gateways = list_all(
+        &Api::<Gateway>::all(ctx.client.clone()),
+        &ListParams::default(),
+        ctx.list_page_size,
+    )
+    .await?;
+    let gateways = gateways_of_class(&name, &all_gateways);
+
+    if gateway_class.meta().deletion_timestamp.is_none() {
+        // the finalizer update triggers another reconciliation when it changes
+        set_finalizer(
+            &gateway_class_api,
+            gateway_class.as_ref(),
+            GATEWAY_EXISTS_FINALIZER,
+            !gateways.is_empty(),
+        )
+        .await?;
+        return Ok(Action::await_change());
+    }
+
+    if gateways.is_empty() {
+        set_finalizer(
+            &gateway_class_api,
+            gateway_class.as_ref(),
+            GATEWAY_EXISTS_FINALIZER,
+            false,
+        )
+        .await?;
+        return Ok(Action::await_change());
+    }
+
+    warn!(
+        "deletion of GatewayClass {} is blocked by {} Gateways",
+        name,
+        gateways.len()
+    );
+    let mut gc = gateway_class.as_ref().clone();
+    set_condition(&mut gc, deletion_blocked_condition(&gc, &gateways));
+    patch_status(&gateway_class_api, name, &gc.status.unwrap_or_default()).await?;
+    // deleted Gateways trigger a reconciliation, this catches the ones that were missed
+    Ok(Action::requeue(Duration::from_secs(60)))
+}
+
+/// Returns the Gateways of the GatewayClass, sorted by namespace and name.
+pub fn gateways_of_class<'a>(
+    name: &str,
+    gateways: impl IntoIterator<Item = &'a Gateway>,
+) -> Vec<NamespacedName> {
+    let mut names: Vec<NamespacedName> = gateways
+        .into_iter()
+        .filter(|gateway| gateway.spec.gateway_class_name == name)
+        .filter_map(|gateway| NamespacedName::try_from(&gateway.metadata).ok())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Returns the DeletionBlocked=True condition of the deleted GatewayClass, naming some of the
+/// Gateways that still use it.
+pub fn deletion_blocked_condition(
+    gateway_class: &GatewayClass,
+    gateways: &[NamespacedName],
+) -> metav1::Condition {
+    let mut listed: Vec<String> = gateways
+        .iter()
+        .take(MAX_LISTED_GATEWAYS)
+        .map(ToString::to_string)
+        .collect();
+    if gateways.len() > MAX_LISTED_GATEWAYS {
+        listed.push(format!("and {} more", gateways.len() - MAX_LISTED_GATEWAYS));
+    }
+    metav1::Condition {
+        type_: DELETION_BLOCKED_CONDITION.to_string(),
+        status: "True".to_string(),
+        reason: GATEWAYS_EXIST_REASON.to_string(),
+        message: format!(
+            "deletion is blocked until the Gateways of the GatewayClass are deleted: {}",
+            listed.join(", ")
+        ),
+        observed_generation: gateway_class.metadata.generation,
+        last_transition_time: metav1::Time(Utc::now()),
+    }
+}
+
+pub async fn controller(ctx: Context) -> Result<()> {
+    let gateway_classes = Api::<GatewayClass>::all(ctx.client.clone());
+    gateway_classes
+        .list(&ListParams::default().limit(1))
+        .await
+        .map_err(Error::CRDNotFoundError)?;
+
+    let watcher_config = Config::default()
+        .any_semantic()
+        .page_size(ctx.list_page_size);
+    Controller::new(gateway_classes, watcher_config.clone())
+        // the finalizer follows the Gateways of each class
+        .watches(
+            Api::<Gateway>::all(ctx.client.clone()),
+            watcher_config,
+            |gateway| Some(ObjectRef::new(&gateway.spec.gateway_class_name)),
+        )
+        .shutdown_on_signal()
+        .run(reconcile, error_policy, Arc::new(ctx))
+        .filter_map(|x| async move { std::result::Result::ok(x) })
+        .for_each(|_| futures::future::ready(()))
+        .await;
+
+    Ok(())
+}
+
+fn error_policy(_: Arc<GatewayClass>, error: &Error, _: Arc<Context>) -> Action {
+    warn!(error_code = %error.code(), "reconcile failed: {:?}", error);
+    Action::requeue(Duration::from_secs(5))
+}
@@ -27,16 +27,19 @@ use gateway_api::apis::standard::{
     constants::{GatewayConditionReason, GatewayConditionType},
     gatewayclasses::GatewayClass,
 };
-use k8s_openapi::api::core::v1::{Service, ServiceSpec, ServiceStatus};
+use k8s_openapi::api::core::v1::{ConfigMap, Service, ServiceSpec, ServiceStatus};
 use k8s_openapi::apimachinery::pkg::apis::meta::v1 as metav1;
 use kube::{
     api::{Api, ListParams, Patch, PatchParams},
-    runtime::{controller::Action, watcher::Config, Controller},
+    runtime::{controller::Action, reflector::ObjectRef, watcher::Config, Controller},
     Resource, ResourceExt,
 };
 
+use address_registry::{get_address_registry, ADDRESS_IN_USE_REASON};
 use chrono::Utc;
+use gateway_parameters::{get_gateway_parameters, GatewayParameters};
 use gateway_utils::*;
+use pagination::list_all;
 use tracing::*;
 
 pub async fn reconcile(gateway: Arc<Gateway>, ctx: Arc<Context>) -> Result<Action> {
@@ -75,8 +78,58 @@ pub async fn reconcile(gateway: Arc<Gateway>, ctx: Arc<Context>) -> Result<Actio
         gateway_class.name_any()
     );
 
+    // Deletion of protected Gateways is held back by a finalizer until the protection annotation
+    // is removed (or set to anything but "true"), which is the explicit override.
+    let protected = is_deletion_protected(&gw);
+    if gateway.meta().deletion_timestamp.is_some() {
+        if protected {
+            warn!(
+                "deletion of Gateway {}/{} is blocked by the {} annotation",
+                ns, name, DELETION_PROTECTION_ANNOTATION
+            );
+            return Ok(Action::requeue(Duration::from_secs(60)));
+        }
+        set_deletion_protection_finalizer(&gateway_api, &gw, false).await?;
+        if let Some(uid) = gateway.meta().uid.as_ref() {
+            ctx.metrics.forget_gateway(uid);
+        }
+        ctx.parameters_refs
+            .set(NamespacedName::new(&ns, &name), None);
+        return Ok(Action::await_change());
+    }
+    if set_deletion_protection_finalizer(&gateway_api, &gw, protected).await? {
+        // the finalizer update triggers another reconciliation
+        return Ok(Action::await_change());
+    }
+
     set_listener_status(&mut gw)?;
-    let accepted_cond = get_accepted_condition(&gw);
+    let mut accepted_cond = get_accepted_condition(&gw);
+    let mut parameters = GatewayParameters::default();
+    if accepted_cond.status == "True" {
+        match get_gateway_parameters(&ctx, &gw).await {
+            Ok(p) => parameters = p,
+            Err(Error::InvalidParametersError(message)) => {
+                accepted_cond.status = "False".to_string();
+                accepted_cond.reason = GatewayConditionReason::InvalidParameters.to_string();
+                accepted_cond.message = message;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    // Only Gateways requesting addresses can conflict, so the others don't list every Gateway.
+    if accepted_cond.status == "True" && gw.spec.addresses.as_ref().is_some_and(|a| !a.is_empty()) {
+        let registry = get_address_registry(&ctx).await?;
+        if let Some((address, owner)) = registry.conflict(&gw) {
+            accepted_cond.status = "False".to_string();
+            accepted_cond.reason = ADDRESS_IN_USE_REASON.to_string();
+            accepted_cond.message = format!(
+                "{}: address {} is already requested by Gateway {}",
+                ErrorCode::AddressInUse,
+                address,
+                owner
+            );
+        }
+    }
     set_condition(&mut gw, accepted_cond.clone());
 
     // If the controller can't accept responsibility, then set the Condition of type "Programmed" to False and error out.
@@ -101,14 +154,16 @@ pub async fn reconcile(gateway: Arc<Gateway>, ctx: Arc<Context>) -> Result<Actio
 
     // Try to fetch any existing Loadbalancer service(s) for this Gateway.
     let service_api: Api<Service> = Api::namespaced(client, &ns);
-    let services = service_api
-        .list(&ListParams::default().labels(&format!("{}={}", GATEWAY_SERVICE_LABEL, name)))
-        .await
-        .map_err(Error::KubeError)?;
+    let services = list_all(
+        &service_api,
+        &ListParams::default().labels(&format!("{}={}", GATEWAY_SERVICE_LABEL, name)),
+        ctx.list_page_size,
+    )
+    .await?;
 
-    if services.items.len() > 1 {
+    if services.len() > 1 {
         let mut names: Vec<String> = vec![];
-        for svc in services.items {
+        for svc in services {
             if let Some(name) = &svc.meta().name {
                 names.push(name.clone());
             }
@@ -122,9 +177,9 @@ pub async fn reconcile(gateway: Arc<Gateway>, ctx: Arc<Context>) -> Result<Actio
 
     // If we found a Loadbalancer service, then correct any drift if necessary, else create the service.
     let mut service: Service;
-    if let Some(val) = services.items.first() {
+    if let Some(val) = services.first() {
         service = val.clone();
-        let updated = update_service_for_gateway(gateway.as_ref(), &mut service)?;
+        let updated = update_service_for_gateway(gateway.as_ref(), &parameters, &mut service)?;
         if updated {
             info!("drift detected; updating loadbalancer service");
             let patch_parmas = PatchParams::default();
@@ -139,7 +194,7 @@ pub async fn reconcile(gateway: Arc<Gateway>, ctx: Arc<Context>) -> Result<Actio
         }
     } else {
         info!("creating loadbalancer service");
-        service = create_svc_for_gateway(ctx.clone(), gateway.as_ref()).await?;
+        service = create_svc_for_gateway(ctx.clone(), gateway.as_ref(), &parameters).await?;
     }
 
     // invalid_lb_condition is a Condition that signfies that the Loadbalancer service is invalid.
@@ -177,15 +232,29 @@ pub async fn reconcile(gateway: Arc<Gateway>, ctx: Arc<Context>) -> Result<Actio
     };
 
     let svc_key = get_service_key(&service)?;
-    if get_ingress_ip_len(svc_status) == 0 || svc_spec.cluster_ip.is_none() {
-        let msg = "LoadBalancer does not have a ingress IP address".to_string();
-        invalid_lb_condition.message.clone_from(&msg);
-        set_condition(&mut gw, invalid_lb_condition);
+    // Waiting for the LoadBalancer to assign an address is part of provisioning every Gateway,
+    // so it's reported as pending rather than as an error.
+    if get_ingress_ip_len(svc_status) == 0
+        || svc_spec.cluster_ip.is_none()
+        || is_requested_address_pending(&gw, svc_status)
+    {
+        let pending_cond = metav1::Condition {
+            last_transition_time: metav1::Time(Utc::now()),
+            observed_generation: gateway.meta().generation,
+            type_: GatewayConditionType::Programmed.to_string(),
+            status: "Unknown".to_string(),
+            reason: GatewayConditionReason::Pending.to_string(),
+            message: "Waiting for the LoadBalancer to assign an ingress IP address".to_string(),
+        };
+        set_condition(&mut gw, pending_cond);
         patch_status(&gateway_api, name, &gw.status.unwrap_or_default()).await?;
-        return Err(Error::LoadBalancerError(msg));
+        info!("waiting for loadbalancer ingress IP address");
+        return Ok(Action::requeue(Duration::from_secs(5)));
     }
 
-    create_endpoint_if_not_exists(ctx.clone(), &svc_key, svc_spec, svc_status).await?;
+    create_or_update_endpoint(ctx.clone(), &svc_key, svc_spec, svc_status).await?;
+    let mapping = get_dataplane_mapping(&svc_key, svc_spec, svc_status);
+    set_dataplane_mapping_annotation(&gateway_api, &gw, &mapping).await?;
     set_gateway_status_addresses(&mut gw, svc_status);
 
     let programmed_cond = metav1::Condition {
@@ -199,6 +268,10 @@ pub async fn reconcile(gateway: Arc<Gateway>, ctx: Arc<Context>) -> Result<Actio
     set_condition(&mut gw, programmed_cond);
 
     patch_status(&gateway_api, name, &gw.status.unwrap_or_default()).await?;
+    // the Gateway as it was before this reconciliation tells whether it was just programmed
+    if let Some(elapsed) = ctx.metrics.observe_gateway_programmed(&gateway, Utc::now()) {
+        info!("gateway programmed {:?} after its creation", elapsed);
+    }
 
     let duration = Instant::now().sub(start);
     info!("finished reconciling in {:?} ms", duration.as_millis());
@@ -212,7 +285,24 @@ pub async fn controller(ctx: Context) -> Result<()> {
         .await
         .map_err(Error::CRDNotFoundError)?;
 
-    Controller::new(gateway, Config::default().any_semantic())
+    let watcher_config = Config::default()
+        .any_semantic()
+        .page_size(ctx.list_page_size);
+    let parameters_refs = ctx.parameters_refs.clone();
+    Controller::new(gateway, watcher_config.clone())
+        // Gateways are reconciled when the ConfigMaps their parameters reference change
+        .watches(
+            Api::<ConfigMap>::all(ctx.client.clone()),
+            watcher_config,
+            move |configmap| {
+                let gateways = NamespacedName::try_from(&configmap.metadata)
+                    .map(|configmap| parameters_refs.gateways(&configmap))
+                    .unwrap_or_default();
+                gateways
+                    .into_iter()
+                    .map(|gateway| ObjectRef::new(&gateway.name).within(&gateway.namespace))
+            },
+        )
         .shutdown_on_signal()
         .run(reconcile, error_policy, Arc::new(ctx))
         .filter_map(|x| async move { std::result::Result::ok(x) })
@@ -223,6 +313,6 @@ pub async fn controller(ctx: Context) -> Result<()> {
 }
 
 fn error_policy(_: Arc<Gateway>, error: &Error, _: Arc<Context>) -> Action {
-    warn!("reconcile failed: {:?}", error);
+    warn!(error_code = %error.code(), "reconcile failed: {:?}", error);
     Action::requeue(Duration::from_secs(5))
 }
@@ -0,0 +1,174 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Per-Gateway parameters referenced by `.spec.infrastructure.parametersRef`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use crate::*;
+use gateway_api::apis::standard::gateways::Gateway;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{
+    api::{Api, ApiResource, DynamicObject},
+    ResourceExt,
+};
+use serde::Deserialize;
+
+/// The ConfigMap key holding annotations to add to the Gateway's Service, as a YAML map.
+pub const SERVICE_ANNOTATIONS_KEY: &str = "serviceAnnotations";
+/// The ConfigMap key holding the externalTrafficPolicy of the Gateway's Service, which decides
+/// whether traffic may be forwarded by the dataplanes of other nodes than the backends' own.
+pub const EXTERNAL_TRAFFIC_POLICY_KEY: &str = "externalTrafficPolicy";
+
+/// The ConfigMap key that would override the type of the Gateway's Service, which isn't supported:
+/// the Service of a Gateway is always a LoadBalancer, whose address the Gateway gets.
+pub const SERVICE_TYPE_KEY: &str = "serviceType";
+
+/// Overrides of the generated Service for a single Gateway.
+#[derive(Debug, Default, PartialEq)]
+pub struct GatewayParameters {
+    pub service_annotations: BTreeMap<String, String>,
+    pub external_traffic_policy: Option<String>,
+}
+
+// A LocalParametersReference.
+#[derive(Debug, Deserialize)]
+struct ParametersRef {
+    #[serde(default)]
+    group: String,
+    kind: String,
+    name: String,
+}
+
+/// The ConfigMap the parameters of each Gateway reference, so that the Gateways are reconciled
+/// when the ConfigMaps change.
+#[derive(Debug, Default)]
+pub struct ParametersRefs {
+    configmaps: Mutex<HashMap<NamespacedName, NamespacedName>>,
+}
+
+impl ParametersRefs {
+    /// Records the ConfigMap the parameters of the Gateway reference, or that they don't
+    /// reference one.
+    pub fn set(&self, gateway: NamespacedName, configmap: Option<NamespacedName>) {
+        let mut configmaps = self.configmaps.lock().unwrap();
+        match configmap {
+            Some(configmap) => configmaps.insert(gateway, configmap),
+            None => configmaps.remove(&gateway),
+        };
+    }
+
+    /// Returns the Gateways whose parameters reference the ConfigMap.
+    pub fn gateways(&self, configmap: &NamespacedName) -> Vec<NamespacedName> {
+        let configmaps = self.configmaps.lock().unwrap();
+        let mut gateways: Vec<NamespacedName> = configmaps
+            .iter()
+            .filter(|(_, referenced)| *referenced == configmap)
+            .map(|(gateway, _)| gateway.clone())
+            .collect();
+        gateways.sort();
+        gateways
+    }
+}
+
+/// Resolves the parameters referenced by the Gateway, if any. References to missing or
+/// unsupported objects, or to malformed parameters, are InvalidParametersErrors.
+///
+/// The parametersRef field is newer than the Gateway API types this controller is built with,
+/// so it is read from the Gateway as an untyped object.
+pub async fn get_gateway_parameters(ctx: &Context, gateway: &Gateway) -> Result<GatewayParameters> {
+    let ns = gateway.namespace().unwrap_or("default".to_string());
+    let gateway_api: Api<DynamicObject> =
+        Api::namespaced_with(ctx.client.clone(), &ns, &ApiResource::erase::<Gateway>(&()));
+    let raw_gateway = gateway_api
+        .get(&gateway.name_any())
+        .await
+        .map_err(Error::KubeError)?;
+    let gateway_name = NamespacedName::new(&ns, gateway.name_any());
+    let Some(raw_ref) = raw_gateway
+        .data
+        .pointer("/spec/infrastructure/parametersRef")
+    else {
+        ctx.parameters_refs.set(gateway_name, None);
+        return Ok(GatewayParameters::default());
+    };
+    let parameters_ref: ParametersRef = serde_json::from_value(raw_ref.clone())
+        .map_err(|err| Error::InvalidParametersError(format!("invalid parametersRef: {}", err)))?;
+
+    if !parameters_ref.group.is_empty() || parameters_ref.kind != "ConfigMap" {
+        ctx.parameters_refs.set(gateway_name, None);
+        return Err(Error::InvalidParametersError(format!(
+            "parametersRef kind {}/{} is not supported; only ConfigMaps are supported",
+            parameters_ref.group, parameters_ref.kind
+        )));
+    }
+
+    // recorded before the ConfigMap is read, so that a missing one is picked up once created
+    ctx.parameters_refs.set(
+        gateway_name,
+        Some(NamespacedName::new(&ns, &parameters_ref.name)),
+    );
+    let configmap_api: Api<ConfigMap> = Api::namespaced(ctx.client.clone(), &ns);
+    let configmap = configmap_api
+        .get_opt(&parameters_ref.name)
+        .await
+        .map_err(Error::KubeError)?
+        .ok_or_else(|| {
+            Error::InvalidParametersError(format!(
+                "parametersRef ConfigMap {} not found",
+                parameters_ref.name
+            ))
+        })?;
+
+    parse_parameters(&configmap.data.unwrap_or_default())
+}
+
+/// Parses the parameters from the data of a ConfigMap.
+pub fn parse_parameters(data: &BTreeMap<String, String>) -> Result<GatewayParameters> {
+    let mut parameters = GatewayParameters::default();
+    for (key, value) in data {
+        match key.as_str() {
+            SERVICE_ANNOTATIONS_KEY => {
+                parameters.service_annotations = serde_yaml::from_str(value).map_err(|err| {
+                    Error::InvalidParametersError(format!("invalid {}: {}", key, err))
+                })?;
+            }
+            EXTERNAL_TRAFFIC_POLICY_KEY => match value.as_str() {
+                "Cluster" | "Local" => parameters.external_traffic_policy = Some(value.clone()),
+                _ => {
+                    return Err(Error::InvalidParametersError(format!(
+                        "invalid {}: {}; must be Cluster or Local",
+                        key, value
+                    )))
+                }
+            },
+            SERVICE_TYPE_KEY => {
+                return Err(Error::InvalidParametersError(format!(
+                    "{} is not supported: the Service of a Gateway is always of type LoadBalancer",
+                    key
+                )))
+            }
+            _ => {
+                return Err(Error::InvalidParametersError(format!(
+                    "unknown parameter {}",
+                    key
+                )))
+            }
+        }
+    }
+    Ok(parameters)
+}
@@ -18,15 +18,18 @@ limitations under the License.
 
 use std::{
     collections::{BTreeMap, HashMap},
+    fmt::Debug,
     sync::Arc,
 };
 
+use crate::gateway_parameters::GatewayParameters;
 use crate::*;
 use gateway_api::apis::standard::{
     constants::{
         GatewayConditionReason, GatewayConditionType, ListenerConditionReason,
         ListenerConditionType,
     },
+    gatewayclasses::{GatewayClass, GatewayClassStatus},
     gateways::{
         Gateway, GatewayListeners, GatewayListenersAllowedRoutesKinds, GatewaySpec, GatewayStatus,
         GatewayStatusAddresses, GatewayStatusListeners, GatewayStatusListenersSupportedKinds,
@@ -45,6 +48,7 @@ use k8s_openapi::api::core::v1::{
 use k8s_openapi::apimachinery::pkg::apis::meta::v1 as metav1;
 
 use chrono::Utc;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::json;
 use tracing::*;
 
@@ -74,13 +78,14 @@ pub fn set_gateway_status_addresses(gateway: &mut Gateway, svc_status: &ServiceS
     }
 }
 
-// Creates an Endpoints object for the provided Service pointing to it's ingress IP address.
-// Since we don't set a selector on the Service (because we don't need to route incoming traffic
-// to a particular pod), no Endpoints object is created for it. An Endpoints object is required
-// because MetalLB does not respond to ARP packets until one exists for the LoadBalancer Service
-// causing traffic to never reach the node.
+// Creates an Endpoints object for the provided Service pointing to it's ingress IP address, or
+// points the existing one to it when the address changed (e.g. the Gateway's address was
+// updated). Since we don't set a selector on the Service (because we don't need to route
+// incoming traffic to a particular pod), no Endpoints object is created for it. An Endpoints
+// object is required because MetalLB does not respond to ARP packets until one exists for the
+// LoadBalancer Service causing traffic to never reach the node.
 // Ref: https://github.com/metallb/metallb/issues/1640
-pub async fn create_endpoint_if_not_exists(
+pub async fn create_or_update_endpoint(
     ctx: Arc<Context>,
     key: &NamespacedName,
     svc_spec: &ServiceSpec,
@@ -108,8 +113,41 @@ pub async fn create_endpoint_if_not_exists(
 
     let endpoints_api: Api<Endpoints> = Api::namespaced(ctx.client.clone(), &key.namespace);
 
-    if let Some(err) = endpoints_api.get(&key.name).await.err() {
-        if check_if_not_found_err(err) {
+    match endpoints_api.get(&key.name).await {
+        Ok(existing) => {
+            let stale_addresses: Vec<String> = existing
+                .subsets
+                .iter()
+                .flatten()
+                .flat_map(|subset| subset.addresses.iter().flatten())
+                .map(|addr| addr.ip.clone())
+                .filter(|ip| *ip != lb_addr_ip)
+                .collect();
+            if !stale_addresses.is_empty() {
+                let patch = Patch::Merge(json!({
+                    "subsets": [{
+                        "addresses": [{ "ip": lb_addr_ip }],
+                        "ports": existing
+                            .subsets
+                            .iter()
+                            .flatten()
+                            .next()
+                            .and_then(|subset| subset.ports.clone()),
+                    }]
+                }));
+                endpoints_api
+                    .patch(&key.name, &PatchParams::default(), &patch)
+                    .await
+                    .map_err(Error::KubeError)?;
+                info!(
+                    old = ?stale_addresses,
+                    new = lb_addr_ip,
+                    "updated the address of Endpoints object {}",
+                    key.name
+                );
+            }
+        }
+        Err(kube::Error::Api(response)) if response.code == 404 => {
             let mut ep_ports: Vec<EndpointPort> = vec![];
             if let Some(ports) = &svc_spec.ports {
                 for port in ports {
@@ -141,11 +179,85 @@ pub async fn create_endpoint_if_not_exists(
                 .map_err(Error::KubeError)?;
             info!("created Endpoints object {}", ep.name_any());
         }
+        Err(err) => return Err(Error::KubeError(err)),
     }
 
     Ok(())
 }
 
+// The dataplane objects of a Gateway, recorded on it in the DATAPLANE_MAPPING_ANNOTATION for
+// tooling: its LoadBalancer Service and the vips programmed for it, which are the same on every
+// dataplane node.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct DataplaneMapping {
+    pub service: String,
+    pub vips: Vec<DataplaneMappingVip>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct DataplaneMappingVip {
+    pub ip: String,
+    pub port: i32,
+    pub protocol: String,
+}
+
+// Returns the mapping of the Gateway to the provided Service and the vips of its ingress IPs.
+pub fn get_dataplane_mapping(
+    svc_key: &NamespacedName,
+    svc_spec: &ServiceSpec,
+    svc_status: &ServiceStatus,
+) -> DataplaneMapping {
+    let ips = svc_status
+        .load_balancer
+        .as_ref()
+        .and_then(|load_balancer| load_balancer.ingress.as_ref())
+        .into_iter()
+        .flatten()
+        .filter_map(|ingress| ingress.ip.clone());
+    let mut vips = vec![];
+    for ip in ips {
+        for port in svc_spec.ports.iter().flatten() {
+            vips.push(DataplaneMappingVip {
+                ip: ip.clone(),
+                port: port.port,
+                protocol: port.protocol.clone().unwrap_or("TCP".to_string()),
+            });
+        }
+    }
+    DataplaneMapping {
+        service: svc_key.to_string(),
+        vips,
+    }
+}
+
+// Records the mapping in the DATAPLANE_MAPPING_ANNOTATION of the Gateway, unless it's already
+// up to date.
+pub async fn set_dataplane_mapping_annotation(
+    gateway_api: &Api<Gateway>,
+    gateway: &Gateway,
+    mapping: &DataplaneMapping,
+) -> Result<()> {
+    let value = serde_json::to_string(mapping).map_err(|err| {
+        Error::InvalidConfigError(format!("failed to serialize dataplane mapping: {}", err))
+    })?;
+    if gateway.annotations().get(DATAPLANE_MAPPING_ANNOTATION) == Some(&value) {
+        return Ok(());
+    }
+
+    let patch = Patch::Merge(json!({
+        "metadata": {
+            "annotations": {
+                (DATAPLANE_MAPPING_ANNOTATION): value,
+            }
+        }
+    }));
+    gateway_api
+        .patch(&gateway.name_any(), &PatchParams::default(), &patch)
+        .await
+        .map_err(Error::KubeError)?;
+    Ok(())
+}
+
 // Returns true if the provided error is a not found error.
 pub fn check_if_not_found_err(error: kube::Error) -> bool {
     if let kube::Error::Api(response) = error {
@@ -156,6 +268,24 @@ pub fn check_if_not_found_err(error: kube::Error) -> bool {
     false
 }
 
+// Returns true if the Gateway requests an address the LoadBalancer Service hasn't been assigned
+// (yet), e.g. right after the requested address changed.
+pub fn is_requested_address_pending(gateway: &Gateway, svc_status: &ServiceStatus) -> bool {
+    let Some(requested) = gateway
+        .spec
+        .addresses
+        .as_ref()
+        .and_then(|addresses| addresses.first())
+    else {
+        return false;
+    };
+    !svc_status
+        .load_balancer
+        .iter()
+        .flat_map(|lb| lb.ingress.iter().flatten())
+        .any(|ingress| ingress.ip.as_ref() == Some(&requested.value))
+}
+
 // Returns the number of ingresses set on the LoadBalancer Service.
 pub fn get_ingress_ip_len(svc_status: &ServiceStatus) -> usize {
     if let Some(lb) = &svc_status.load_balancer {
@@ -167,7 +297,11 @@ pub fn get_ingress_ip_len(svc_status: &ServiceStatus) -> usize {
 }
 
 // Creates a LoadBalancer Service for the provided Gateway.
-pub async fn create_svc_for_gateway(ctx: Arc<Context>, gateway: &Gateway) -> Result<Service> {
+pub async fn create_svc_for_gateway(
+    ctx: Arc<Context>,
+    gateway: &Gateway,
+    parameters: &GatewayParameters,
+) -> Result<Service> {
     let mut svc_meta = ObjectMeta::default();
     let ns = gateway.namespace().unwrap_or("default".to_string());
     svc_meta.namespace = Some(ns.clone());
@@ -182,7 +316,7 @@ pub async fn create_svc_for_gateway(ctx: Arc<Context>, gateway: &Gateway) -> Res
         spec: Some(ServiceSpec::default()),
         status: Some(ServiceStatus::default()),
     };
-    update_service_for_gateway(gateway, &mut svc)?;
+    update_service_for_gateway(gateway, parameters, &mut svc)?;
 
     let svc_api: Api<Service> = Api::namespaced(ctx.client.clone(), ns.as_str());
     let service = svc_api
@@ -193,10 +327,24 @@ pub async fn create_svc_for_gateway(ctx: Arc<Context>, gateway: &Gateway) -> Res
     Ok(service)
 }
 
-// Updates the provided Service to match the desired state according to the provided Gateway.
-// Returns true if Service was modified.
-pub fn update_service_for_gateway(gateway: &Gateway, svc: &mut Service) -> Result<bool> {
+// Updates the provided Service to match the desired state according to the provided Gateway
+// and its parameters. Returns true if Service was modified.
+pub fn update_service_for_gateway(
+    gateway: &Gateway,
+    parameters: &GatewayParameters,
+    svc: &mut Service,
+) -> Result<bool> {
     let mut updated = false;
+    // annotations the parameters no longer set are left alone, since they may have been added
+    // by someone else
+    let annotations = svc.metadata.annotations.get_or_insert_with(BTreeMap::new);
+    for (key, value) in &parameters.service_annotations {
+        if annotations.get(key) != Some(value) {
+            annotations.insert(key.clone(), value.clone());
+            updated = true;
+        }
+    }
+
     let mut ports: Vec<ServicePort> = vec![];
     for listener in &gateway.spec.listeners {
         let mut port = ServicePort::default();
@@ -236,8 +384,8 @@ pub fn update_service_for_gateway(gateway: &Gateway, svc: &mut Service) -> Resul
     ))?;
 
     let lb_ip: Option<String> = svc_spec.load_balancer_ip.clone();
-    if let Some((addr, ip)) = address.clone().zip(lb_ip.clone()) {
-        if ip != addr.value {
+    if let Some(addr) = &address {
+        if lb_ip.as_ref() != Some(&addr.value) {
             svc_spec.load_balancer_ip = Some(addr.value.clone());
             updated = true;
         }
@@ -246,6 +394,12 @@ pub fn update_service_for_gateway(gateway: &Gateway, svc: &mut Service) -> Resul
         svc_spec.load_balancer_ip = None;
         updated = true;
     }
+    if let Some(policy) = &parameters.external_traffic_policy {
+        if svc_spec.external_traffic_policy.as_ref() != Some(policy) {
+            svc_spec.external_traffic_policy = Some(policy.clone());
+            updated = true;
+        }
+    }
     if let Some(ref mut t) = svc_spec.type_ {
         if t != "LoadBalancer" {
             *t = "LoadBalancer".to_string();
@@ -279,62 +433,164 @@ pub fn update_service_for_gateway(gateway: &Gateway, svc: &mut Service) -> Resul
     Ok(updated)
 }
 
-// Patch the provided status on the Gateway object.
-pub async fn patch_status(
+// Returns true if the Gateway opted into deletion protection and may be serving traffic, i.e.
+// it has been assigned an address.
+pub fn is_deletion_protected(gateway: &Gateway) -> bool {
+    let opted_in = gateway
+        .annotations()
+        .get(DELETION_PROTECTION_ANNOTATION)
+        .is_some_and(|value| value == "true");
+    let has_addresses = gateway
+        .status
+        .as_ref()
+        .and_then(|status| status.addresses.as_ref())
+        .is_some_and(|addresses| !addresses.is_empty());
+    opted_in && has_addresses
+}
+
+// Adds or removes the deletion protection finalizer of the Gateway. Returns true if the
+// finalizers were changed.
+pub async fn set_deletion_protection_finalizer(
     gateway_api: &Api<Gateway>,
-    name: String,
-    status: &GatewayStatus,
-) -> Result<()> {
-    let mut listeners = &vec![];
-    if let Some(l) = status.listeners.as_ref() {
-        listeners = l;
+    gateway: &Gateway,
+    protected: bool,
+) -> Result<bool> {
+    set_finalizer(
+        gateway_api,
+        gateway,
+        DELETION_PROTECTION_FINALIZER,
+        protected,
+    )
+    .await
+}
+
+// Adds or removes the finalizer of the object. Returns true if the finalizers were changed.
+pub async fn set_finalizer<K>(
+    api: &Api<K>,
+    object: &K,
+    finalizer: &str,
+    present: bool,
+) -> Result<bool>
+where
+    K: Resource + Clone + DeserializeOwned + Debug,
+{
+    let mut finalizers = object.finalizers().to_vec();
+    if finalizers.iter().any(|f| f == finalizer) == present {
+        return Ok(false);
+    }
+    if present {
+        finalizers.push(finalizer.to_string());
+    } else {
+        finalizers.retain(|f| f != finalizer);
+    }
+
+    // the resourceVersion makes the patch fail rather than drop finalizers added concurrently
+    let patch = Patch::Merge(json!({
+        "metadata": {
+            "finalizers": finalizers,
+            "resourceVersion": object.resource_version(),
+        }
+    }));
+    api.patch(&object.name_any(), &PatchParams::default(), &patch)
+        .await
+        .map_err(Error::KubeError)?;
+    Ok(true)
+}
+
+/// The objects blixt reports the status of with conditions, the Gateways and GatewayClasses, so
+/// that set_condition and patch_status work on either.
+pub trait ConditionStatus: Resource<DynamicType = ()> + Clone + DeserializeOwned + Debug {
+    type Status: Default;
+
+    fn status_mut(&mut self) -> &mut Option<Self::Status>;
+
+    fn conditions_mut(status: &mut Self::Status) -> &mut Option<Vec<metav1::Condition>>;
+
+    /// The fields of the status blixt owns, as patch_status applies them. Fields that are unset
+    /// are applied empty, so that the values blixt applied before are removed.
+    fn applied_status(status: &Self::Status) -> serde_json::Value;
+}
+
+impl ConditionStatus for Gateway {
+    type Status = GatewayStatus;
+
+    fn status_mut(&mut self) -> &mut Option<GatewayStatus> {
+        &mut self.status
     }
-    let mut conditions = &vec![];
-    if let Some(c) = status.conditions.as_ref() {
-        conditions = c;
+
+    fn conditions_mut(status: &mut GatewayStatus) -> &mut Option<Vec<metav1::Condition>> {
+        &mut status.conditions
+    }
+
+    fn applied_status(status: &GatewayStatus) -> serde_json::Value {
+        json!({
+            "listeners": status.listeners.as_deref().unwrap_or_default(),
+            "conditions": status.conditions.as_deref().unwrap_or_default(),
+            "addresses": status.addresses.as_deref().unwrap_or_default(),
+        })
     }
-    let mut addresses = &vec![];
-    if let Some(a) = status.addresses.as_ref() {
-        addresses = a;
+}
+
+impl ConditionStatus for GatewayClass {
+    type Status = GatewayClassStatus;
+
+    fn status_mut(&mut self) -> &mut Option<GatewayClassStatus> {
+        &mut self.status
     }
+
+    fn conditions_mut(status: &mut GatewayClassStatus) -> &mut Option<Vec<metav1::Condition>> {
+        &mut status.conditions
+    }
+
+    fn applied_status(status: &GatewayClassStatus) -> serde_json::Value {
+        json!({
+            "conditions": status.conditions.as_deref().unwrap_or_default(),
+        })
+    }
+}
+
+// Patch the provided status on the Gateway or GatewayClass object.
+pub async fn patch_status<K: ConditionStatus>(
+    api: &Api<K>,
+    name: String,
+    status: &K::Status,
+) -> Result<()> {
     let patch = Patch::Apply(json!({
-        "apiVersion": "gateway.networking.k8s.io/v1",
-        "kind": "Gateway",
-        "status": {
-            "listeners": listeners,
-            "conditions": conditions,
-            "addresses": addresses
-        }
+        "apiVersion": K::api_version(&()),
+        "kind": K::kind(&()),
+        "status": K::applied_status(status),
     }));
     let params = PatchParams::apply(BLIXT_FIELD_MANAGER).force();
-    gateway_api
-        .patch_status(name.as_str(), &params, &patch)
+    api.patch_status(name.as_str(), &params, &patch)
         .await
         .map_err(Error::KubeError)?;
     Ok(())
 }
 
-// Sets the provided condition on the Gateway object. The condition on the Gateway is only updated
-// if the new condition has a different status (except for the observed generation which is always
-// updated).
-pub fn set_condition(gateway: &mut Gateway, new_cond: metav1::Condition) {
-    if let Some(ref mut status) = gateway.status {
-        if let Some(ref mut conditions) = status.conditions {
-            for condition in conditions.iter_mut() {
-                if condition.type_ == new_cond.type_ {
-                    if condition.status == new_cond.status {
-                        // always update the observed generation
-                        condition.observed_generation = new_cond.observed_generation;
-                        return;
-                    }
-                    *condition = new_cond;
-                    return;
-                }
-            }
-            conditions.push(new_cond);
-        } else {
-            status.conditions = Some(vec![new_cond]);
-        }
+// Sets the provided condition on the Gateway or GatewayClass object. A condition of the same type
+// is replaced, except for its last transition time which is kept if the status didn't change.
+pub fn set_condition<K: ConditionStatus>(object: &mut K, new_cond: metav1::Condition) {
+    let status = object.status_mut().get_or_insert_with(K::Status::default);
+    let conditions = K::conditions_mut(status).get_or_insert_with(Vec::new);
+    match conditions
+        .iter_mut()
+        .find(|condition| condition.type_ == new_cond.type_)
+    {
+        Some(condition) => update_condition(condition, new_cond),
+        None => conditions.push(new_cond),
+    }
+}
+
+// Updates the condition to the new one of the same type. The observed generation, reason and
+// message are always updated, so that they describe the reconciled generation even when the
+// status stays the same, and the last transition time only when the status changed.
+pub fn update_condition(condition: &mut metav1::Condition, new_cond: metav1::Condition) {
+    if condition.status == new_cond.status {
+        let last_transition_time = condition.last_transition_time.clone();
+        *condition = new_cond;
+        condition.last_transition_time = last_transition_time;
+    } else {
+        *condition = new_cond;
     }
 }
 
@@ -374,7 +630,8 @@ pub fn get_accepted_condition(gateway: &Gateway) -> metav1::Condition {
                     accepted.status = String::from("False");
                     accepted.reason = GatewayConditionReason::UnsupportedAddress.to_string();
                     accepted.message = format!(
-                        "found an addres of type {}, only type IPAddress is supported",
+                        "{}: found an addres of type {}, only type IPAddress is supported",
+                        ErrorCode::UnsupportedAddress,
                         addr_type
                     );
                     break;
@@ -408,27 +665,22 @@ pub fn set_listener_status(gateway: &mut Gateway) -> Result<()> {
     for listener in &gateway_spec.listeners {
         let mut final_conditions = vec![];
         let (supported_kinds, conditions) = get_listener_status(listener, gen);
-        if let Some(current_listener_status) = current_listener_statuses.get(&listener.name) {
-            for condition in conditions {
-                let mut present = false;
-                for current_condition in &current_listener_status.conditions {
-                    if condition.type_ == current_condition.type_ {
-                        present = true;
-                        if condition.status == current_condition.status {
-                            let mut updated_condition = current_condition.clone();
-                            updated_condition.observed_generation = gateway.metadata.generation;
-                            final_conditions.push(updated_condition);
-                        } else {
-                            final_conditions.push(condition.clone());
-                        }
-                    }
-                }
-                if !present {
-                    final_conditions.push(condition.clone());
+        let current_conditions = current_listener_statuses
+            .get(&listener.name)
+            .map(|current_listener_status| current_listener_status.conditions.as_slice())
+            .unwrap_or_default();
+        for condition in conditions {
+            match current_conditions
+                .iter()
+                .find(|current_condition| current_condition.type_ == condition.type_)
+            {
+                Some(current_condition) => {
+                    let mut updated_condition = current_condition.clone();
+                    update_condition(&mut updated_condition, condition);
+                    final_conditions.push(updated_condition);
                 }
+                None => final_conditions.push(condition),
             }
-        } else {
-            final_conditions = conditions;
         }
 
         statuses.push(GatewayStatusListeners {
@@ -439,23 +691,16 @@ pub fn set_listener_status(gateway: &mut Gateway) -> Result<()> {
         });
     }
 
-    if let Some(ref mut status) = gateway.status {
-        status.listeners = Some(statuses);
-    }
+    gateway
+        .status
+        .get_or_insert_with(GatewayStatus::default)
+        .listeners = Some(statuses);
     Ok(())
 }
 
 pub fn get_service_key(service: &Service) -> Result<NamespacedName> {
-    let svc_name = service.meta().name.clone().ok_or(Error::LoadBalancerError(
-        "Loadbalancer service name not found".to_string(),
-    ))?;
-    let svc_ns = service.namespace().ok_or(Error::LoadBalancerError(
-        "Loadblancer service namespace not found".to_string(),
-    ))?;
-    Ok(NamespacedName {
-        name: svc_name,
-        namespace: svc_ns,
-    })
+    NamespacedName::try_from(service.meta())
+        .map_err(|err| Error::LoadBalancerError(format!("Loadbalancer service {}", err)))
 }
 
 // Inspects the provided Listener and returns the list GroupKind objects for support Routes and a
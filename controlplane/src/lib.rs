@@ -14,38 +14,80 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use std::sync::Arc;
+
 use kube::Client;
 use thiserror::Error;
 
+pub use common::{ErrorCode, NamespacedName};
+
+pub mod address_registry;
+pub mod backend_refs;
+pub mod compiler;
+pub mod gateway_class_bootstrap;
+pub mod gateway_class_controller;
 pub mod gateway_controller;
+pub mod gateway_parameters;
 pub mod gateway_utils;
+pub mod metrics;
+pub mod pagination;
 
 // Context for our reconciler
 #[derive(Clone)]
 pub struct Context {
     /// Kubernetes client
     pub client: Client,
+    /// Maximum number of objects requested per page when listing resources
+    pub list_page_size: u32,
+    /// Metrics of the controlplane
+    pub metrics: Arc<metrics::Metrics>,
+    /// ConfigMaps referenced by the parameters of Gateways
+    pub parameters_refs: Arc<gateway_parameters::ParametersRefs>,
 }
 
 #[derive(Error, Debug)]
 pub enum Error {
-    #[error("kube error: {0}")]
+    #[error("{}: kube error: {0}", ErrorCode::KubeApiFailed)]
     KubeError(#[source] kube::Error),
-    #[error("invalid configuration: `{0}`")]
+    #[error("{}: invalid configuration: `{0}`", ErrorCode::InvalidConfig)]
     InvalidConfigError(String),
-    #[error("error reconciling loadbalancer service: `{0}`")]
+    #[error("{}: invalid Gateway parameters: `{0}`", ErrorCode::InvalidConfig)]
+    InvalidParametersError(String),
+    #[error(
+        "{}: error reconciling loadbalancer service: `{0}`",
+        ErrorCode::LoadBalancerNotReady
+    )]
     LoadBalancerError(String),
-    #[error("error querying Gateway API CRDs: `{0}`; are the CRDs installed?")]
+    #[error(
+        "{}: error querying Gateway API CRDs: `{0}`; are the CRDs installed?",
+        ErrorCode::CRDNotFound
+    )]
     CRDNotFoundError(#[source] kube::Error),
 }
 
+impl Error {
+    /// Returns the blixt error code identifying this error.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::KubeError(_) => ErrorCode::KubeApiFailed,
+            Error::InvalidConfigError(_) | Error::InvalidParametersError(_) => {
+                ErrorCode::InvalidConfig
+            }
+            Error::LoadBalancerError(_) => ErrorCode::LoadBalancerNotReady,
+            Error::CRDNotFoundError(_) => ErrorCode::CRDNotFound,
+        }
+    }
+}
+
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
 pub const GATEWAY_CLASS_CONTROLLER_NAME: &str = "gateway.networking.k8s.io/blixt";
 pub const BLIXT_FIELD_MANAGER: &str = "blixt-field-manager";
 pub const GATEWAY_SERVICE_LABEL: &str = "blixt.gateway.networking.k8s.io/owned-by-gateway";
-
-pub struct NamespacedName {
-    pub name: String,
-    pub namespace: String,
-}
+pub const GATEWAY_CLASS_OWNER_ANNOTATION: &str = "blixt.gateway.networking.k8s.io/owned-by";
+pub const GATEWAY_CLASS_OWNER: &str = "blixt-controlplane";
+pub const DATAPLANE_MAPPING_ANNOTATION: &str = "blixt.gateway.networking.k8s.io/dataplane-mapping";
+pub const DELETION_PROTECTION_ANNOTATION: &str =
+    "blixt.gateway.networking.k8s.io/deletion-protection";
+pub const DELETION_PROTECTION_FINALIZER: &str =
+    "blixt.gateway.networking.k8s.io/deletion-protection";
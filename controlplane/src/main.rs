@@ -14,17 +14,46 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use clap::Parser;
 use controlplane::*;
 use kube::Client;
 use tracing::*;
 
+#[derive(Debug, Parser)]
+struct Options {
+    /// Maximum number of objects to request per page when listing resources.
+    ///
+    /// Smaller pages reduce API server response sizes and memory spikes in large clusters.
+    #[clap(long, default_value_t = pagination::DEFAULT_PAGE_SIZE)]
+    list_page_size: u32,
+
+    /// Create the GatewayClass of blixt at startup if it doesn't exist.
+    ///
+    /// Eases the first run of installations that only deploy the controlplane; an existing
+    /// GatewayClass of the same name is left untouched.
+    #[clap(long, default_value_t = false)]
+    create_gateway_class: bool,
+
+    /// Name of the GatewayClass created with --create-gateway-class.
+    #[clap(long, default_value = gateway_class_bootstrap::DEFAULT_GATEWAY_CLASS_NAME)]
+    gateway_class_name: String,
+
+    /// Address the Prometheus metrics are served on, at /metrics.
+    #[clap(long, default_value = metrics::DEFAULT_METRICS_BIND_ADDRESS)]
+    metrics_bind_address: SocketAddr,
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    run().await;
+    let opts = Options::parse();
+    run(opts).await;
     Ok(())
 }
 
-pub async fn run() {
+async fn run(opts: Options) {
     let subscriber = tracing_subscriber::FmtSubscriber::new();
     tracing::subscriber::set_global_default(subscriber).unwrap();
 
@@ -33,10 +62,33 @@ pub async fn run() {
         .expect("failed to create kube Client");
     let ctx = Context {
         client: client.clone(),
+        list_page_size: opts.list_page_size,
+        metrics: Arc::new(metrics::Metrics::default()),
+        parameters_refs: Arc::new(gateway_parameters::ParametersRefs::default()),
     };
 
-    if let Err(error) = gateway_controller::controller(ctx).await {
-        error!("failed to start Gateway contoller: {error:?}");
+    let metrics_server = metrics::serve(opts.metrics_bind_address, ctx.metrics.clone());
+    tokio::spawn(async move {
+        if let Err(error) = metrics_server.await {
+            error!("failed to serve metrics: {error:?}");
+        }
+    });
+
+    if opts.create_gateway_class {
+        if let Err(error) =
+            gateway_class_bootstrap::ensure_gateway_class(&ctx, &opts.gateway_class_name).await
+        {
+            error!("failed to create GatewayClass: {error:?}");
+            std::process::exit(1);
+        }
+    }
+
+    // the controllers run until shutdown, so this only returns early when one fails to start
+    if let Err(error) = futures::try_join!(
+        gateway_controller::controller(ctx.clone()),
+        gateway_class_controller::controller(ctx),
+    ) {
+        error!("failed to start controllers: {error:?}");
         std::process::exit(1);
     }
 }
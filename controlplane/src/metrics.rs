@@ -0,0 +1,135 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Metrics of the controlplane, served in the Prometheus text format.
+//!
+//! The time it takes for a Gateway to be programmed after it's created is the key SLO of the
+//! controlplane: it covers waiting for the LoadBalancer address as well as configuring the
+//! dataplane, and is what users experience when they create a Gateway.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use common::metrics::{self, PROMETHEUS_CONTENT_TYPE};
+use gateway_api::apis::standard::constants::GatewayConditionType;
+use gateway_api::apis::standard::gateways::Gateway;
+use tokio::net::TcpListener;
+use tracing::*;
+
+pub use common::metrics::Histogram;
+
+/// Default address the metrics are served on.
+pub const DEFAULT_METRICS_BIND_ADDRESS: &str = "0.0.0.0:8080";
+
+/// Upper bounds, in seconds, of the buckets of the time-to-programmed histogram. Gateways wait
+/// for the LoadBalancer to assign them an address, so the buckets go up to several minutes.
+pub const TIME_TO_PROGRAMMED_BUCKETS: &[f64] = &[
+    0.5, 1.0, 2.5, 5.0, 10.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0,
+];
+
+/// The metrics of the controlplane, shared by its controllers.
+#[derive(Debug)]
+pub struct Metrics {
+    gateway_time_to_programmed: Mutex<Histogram>,
+    // the UIDs of the Gateways whose time to be programmed was observed, so that a Gateway that
+    // stops being programmed, e.g. while its LoadBalancer changes, isn't observed again
+    programmed_gateways: Mutex<HashSet<String>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics {
+            gateway_time_to_programmed: Mutex::new(Histogram::new(TIME_TO_PROGRAMMED_BUCKETS)),
+            programmed_gateways: Mutex::new(HashSet::new()),
+        }
+    }
+}
+
+impl Metrics {
+    /// Observes the time the Gateway took to be programmed, if it's becoming programmed for the
+    /// first time, i.e. its current status isn't Programmed=True and it wasn't observed before.
+    /// Returns the observed time.
+    pub fn observe_gateway_programmed(
+        &self,
+        gateway: &Gateway,
+        now: DateTime<Utc>,
+    ) -> Option<Duration> {
+        if is_programmed(gateway) {
+            return None;
+        }
+        let uid = gateway.metadata.uid.clone()?;
+        let created = gateway.metadata.creation_timestamp.as_ref()?.0;
+        if !self.programmed_gateways.lock().unwrap().insert(uid) {
+            return None;
+        }
+
+        let elapsed = (now - created).to_std().unwrap_or_default();
+        self.gateway_time_to_programmed
+            .lock()
+            .unwrap()
+            .observe(elapsed.as_secs_f64(), None);
+        Some(elapsed)
+    }
+
+    /// Forgets the deleted Gateway of the UID.
+    pub fn forget_gateway(&self, uid: &str) {
+        self.programmed_gateways.lock().unwrap().remove(uid);
+    }
+
+    /// Returns the histogram of the time Gateways took to be programmed.
+    pub fn gateway_time_to_programmed(&self) -> Histogram {
+        self.gateway_time_to_programmed.lock().unwrap().clone()
+    }
+
+    /// Returns the metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let name = "blixt_gateway_time_to_programmed_seconds";
+        let _ = writeln!(
+            out,
+            "# HELP {} Time from the creation of a Gateway until it was first Programmed.",
+            name
+        );
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        self.gateway_time_to_programmed().render(name, "", &mut out);
+        out
+    }
+}
+
+// Returns whether the Gateway has the condition Programmed=True.
+fn is_programmed(gateway: &Gateway) -> bool {
+    gateway
+        .status
+        .as_ref()
+        .and_then(|status| status.conditions.as_ref())
+        .is_some_and(|conditions| {
+            conditions.iter().any(|condition| {
+                condition.type_ == GatewayConditionType::Programmed.to_string()
+                    && condition.status == "True"
+            })
+        })
+}
+
+/// Serves the metrics over HTTP on `GET /metrics`, until the listener fails.
+pub async fn serve(addr: SocketAddr, metrics: Arc<Metrics>) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("serving metrics on {}", addr);
+    metrics::serve(listener, PROMETHEUS_CONTENT_TYPE, move || metrics.render()).await
+}
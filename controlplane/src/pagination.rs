@@ -0,0 +1,44 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::fmt::Debug;
+
+use kube::api::{Api, ListParams};
+use serde::de::DeserializeOwned;
+
+use crate::*;
+
+/// The default number of objects requested per page, matching the kube-runtime watcher default.
+pub const DEFAULT_PAGE_SIZE: u32 = 500;
+
+// Lists all objects matching the provided params, requesting them from the API server in pages
+// of at most page_size objects instead of in a single (potentially very large) response.
+pub async fn list_all<K>(api: &Api<K>, params: &ListParams, page_size: u32) -> Result<Vec<K>>
+where
+    K: Clone + DeserializeOwned + Debug,
+{
+    let mut items = vec![];
+    let mut params = params.clone().limit(page_size);
+    loop {
+        let page = api.list(&params).await.map_err(Error::KubeError)?;
+        items.extend(page.items);
+        match page.metadata.continue_ {
+            Some(token) if !token.is_empty() => params = params.continue_token(&token),
+            _ => break,
+        }
+    }
+    Ok(items)
+}
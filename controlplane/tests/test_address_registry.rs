@@ -0,0 +1,126 @@
+use controlplane::address_registry::AddressRegistry;
+use controlplane::NamespacedName;
+use gateway_api::apis::standard::gateways::Gateway;
+
+fn gateway(name: &str, created: &str, addresses: &[(&str, &str)]) -> Gateway {
+    let mut gateway: Gateway = serde_yaml::from_str(&format!(
+        r#"
+apiVersion: gateway.networking.k8s.io/v1
+kind: Gateway
+metadata:
+  name: {}
+  namespace: default
+  creationTimestamp: "{}"
+spec:
+  gatewayClassName: blixt
+  listeners:
+  - name: tcp
+    protocol: TCP
+    port: 8080
+"#,
+        name, created
+    ))
+    .unwrap();
+    gateway.spec.addresses = Some(
+        addresses
+            .iter()
+            .map(|(address_type, value)| {
+                serde_yaml::from_str(&format!("{{type: {}, value: {}}}", address_type, value))
+                    .unwrap()
+            })
+            .collect(),
+    );
+    gateway
+}
+
+fn name(name: &str) -> NamespacedName {
+    NamespacedName::new("default", name)
+}
+
+#[test]
+fn test_address_belongs_to_oldest_gateway() {
+    let older = gateway(
+        "older",
+        "2024-01-01T00:00:00Z",
+        &[("IPAddress", "172.18.0.10")],
+    );
+    let newer = gateway(
+        "newer",
+        "2024-01-02T00:00:00Z",
+        &[("IPAddress", "172.18.0.10")],
+    );
+
+    // the order the Gateways are listed in doesn't matter
+    for registry in [
+        AddressRegistry::from_gateways([&older, &newer]),
+        AddressRegistry::from_gateways([&newer, &older]),
+    ] {
+        assert_eq!(registry.owner("172.18.0.10"), Some(&name("older")));
+        assert_eq!(registry.conflict(&older), None);
+        assert_eq!(
+            registry.conflict(&newer),
+            Some(("172.18.0.10", &name("older")))
+        );
+    }
+}
+
+#[test]
+fn test_same_creation_time_ordered_by_name() {
+    let a = gateway("a", "2024-01-01T00:00:00Z", &[("IPAddress", "172.18.0.10")]);
+    let b = gateway("b", "2024-01-01T00:00:00Z", &[("IPAddress", "172.18.0.10")]);
+
+    let registry = AddressRegistry::from_gateways([&b, &a]);
+    assert_eq!(registry.conflict(&a), None);
+    assert_eq!(registry.conflict(&b), Some(("172.18.0.10", &name("a"))));
+}
+
+#[test]
+fn test_distinct_addresses_dont_conflict() {
+    let first = gateway(
+        "first",
+        "2024-01-01T00:00:00Z",
+        &[("IPAddress", "172.18.0.10")],
+    );
+    let second = gateway(
+        "second",
+        "2024-01-02T00:00:00Z",
+        &[("IPAddress", "172.18.0.11")],
+    );
+
+    let registry = AddressRegistry::from_gateways([&first, &second]);
+    assert_eq!(registry.conflict(&first), None);
+    assert_eq!(registry.conflict(&second), None);
+}
+
+#[test]
+fn test_conflict_with_unregistered_gateway() {
+    // a Gateway that isn't listed yet still conflicts with the older Gateways
+    let older = gateway(
+        "older",
+        "2024-01-01T00:00:00Z",
+        &[("IPAddress", "172.18.0.10")],
+    );
+    let newer = gateway(
+        "newer",
+        "2024-01-02T00:00:00Z",
+        &[("IPAddress", "172.18.0.10")],
+    );
+
+    let registry = AddressRegistry::from_gateways([&older]);
+    assert_eq!(
+        registry.conflict(&newer),
+        Some(("172.18.0.10", &name("older")))
+    );
+}
+
+#[test]
+fn test_only_ip_addresses_registered() {
+    let gw = gateway(
+        "hostname",
+        "2024-01-01T00:00:00Z",
+        &[("Hostname", "example.com")],
+    );
+
+    let registry = AddressRegistry::from_gateways([&gw]);
+    assert_eq!(registry.owner("example.com"), None);
+}
@@ -0,0 +1,386 @@
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::Path;
+
+use controlplane::backend_refs::ServiceBackends;
+use controlplane::compiler::{
+    compile, fit, merge, CapacityExceeded, CompiledTargets, MergeConflict, Protocol, Target,
+    TargetAddress, WeightedBackends, DEFAULT_WEIGHT,
+};
+use serde::Deserialize;
+
+const VIP: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 10);
+
+fn endpoints(addresses: &[[u8; 4]], port: u16, weight: u32) -> WeightedBackends {
+    WeightedBackends {
+        backends: ServiceBackends::Endpoints(
+            addresses
+                .iter()
+                .map(|octets| Ipv4Addr::from(*octets))
+                .collect(),
+            port,
+        ),
+        weight,
+    }
+}
+
+fn ip_target(octets: [u8; 4], port: u16, weight: u32) -> Target {
+    Target {
+        address: TargetAddress::Ip(Ipv4Addr::from(octets)),
+        port,
+        weight,
+    }
+}
+
+#[test]
+fn test_compile_single_backend_ref() {
+    let compiled = compile(
+        VIP,
+        8080,
+        Protocol::Tcp,
+        &[endpoints(
+            &[[10, 0, 0, 2], [10, 0, 0, 1]],
+            80,
+            DEFAULT_WEIGHT,
+        )],
+    );
+    assert_eq!(
+        compiled,
+        CompiledTargets {
+            vip: VIP,
+            port: 8080,
+            protocol: Protocol::Tcp,
+            targets: vec![
+                ip_target([10, 0, 0, 1], 80, 1),
+                ip_target([10, 0, 0, 2], 80, 1),
+            ],
+        }
+    );
+}
+
+#[test]
+fn test_compile_splits_weights_between_backends() {
+    // the backendRef of weight 1 gets a third of the traffic, through a single backend
+    let compiled = compile(
+        VIP,
+        8080,
+        Protocol::Tcp,
+        &[
+            endpoints(&[[10, 0, 0, 1], [10, 0, 0, 2]], 80, 2),
+            endpoints(&[[10, 0, 1, 1]], 80, 1),
+        ],
+    );
+    assert_eq!(
+        compiled.targets,
+        vec![
+            ip_target([10, 0, 0, 1], 80, 1),
+            ip_target([10, 0, 0, 2], 80, 1),
+            ip_target([10, 0, 1, 1], 80, 1),
+        ]
+    );
+}
+
+#[test]
+fn test_compile_skips_weight_zero_and_empty_backend_refs() {
+    let compiled = compile(
+        VIP,
+        53,
+        Protocol::Udp,
+        &[
+            endpoints(&[[10, 0, 0, 1]], 53, 0),
+            endpoints(&[], 53, 5),
+            endpoints(&[[10, 0, 0, 2]], 53, 3),
+        ],
+    );
+    assert_eq!(compiled.targets, vec![ip_target([10, 0, 0, 2], 53, 1)]);
+}
+
+#[test]
+fn test_compile_dedups_backends() {
+    let compiled = compile(
+        VIP,
+        8080,
+        Protocol::Tcp,
+        &[
+            endpoints(&[[10, 0, 0, 1], [10, 0, 0, 1]], 80, 1),
+            endpoints(&[[10, 0, 0, 1], [10, 0, 0, 2]], 80, 2),
+        ],
+    );
+    assert_eq!(
+        compiled.targets,
+        vec![
+            ip_target([10, 0, 0, 1], 80, 2),
+            ip_target([10, 0, 0, 2], 80, 1),
+        ]
+    );
+}
+
+#[test]
+fn test_compile_without_backends() {
+    let compiled = compile(VIP, 8080, Protocol::Tcp, &[]);
+    assert!(compiled.targets.is_empty());
+}
+
+#[test]
+fn test_fit_keeps_targets_that_fit() {
+    let compiled = compile(
+        VIP,
+        8080,
+        Protocol::Tcp,
+        &[
+            endpoints(&[[10, 0, 0, 1]], 80, 3),
+            endpoints(&[[10, 0, 1, 1]], 80, 1),
+        ],
+    );
+    assert_eq!(compiled.backends_len(), 4);
+    assert_eq!(fit(compiled.clone(), 4), Ok(compiled));
+}
+
+#[test]
+fn test_fit_scales_weights_down() {
+    let compiled = CompiledTargets {
+        vip: VIP,
+        port: 8080,
+        protocol: Protocol::Tcp,
+        targets: vec![
+            ip_target([10, 0, 0, 1], 8080, 9),
+            ip_target([10, 0, 0, 2], 8080, 9),
+            ip_target([10, 0, 1, 1], 80, 2),
+            ip_target([10, 0, 1, 2], 80, 2),
+            ip_target([10, 0, 1, 3], 80, 2),
+        ],
+    };
+    let fitted = fit(compiled, 12).unwrap();
+    assert_eq!(fitted.backends_len(), 12);
+    // every target keeps a backend, and the spare ones go in proportion to the weights
+    assert_eq!(
+        fitted.targets,
+        vec![
+            ip_target([10, 0, 0, 1], 8080, 4),
+            ip_target([10, 0, 0, 2], 8080, 4),
+            ip_target([10, 0, 1, 1], 80, 2),
+            ip_target([10, 0, 1, 2], 80, 1),
+            ip_target([10, 0, 1, 3], 80, 1),
+        ]
+    );
+}
+
+#[test]
+fn test_fit_scales_weights_to_the_smallest_integers() {
+    let compiled = compile(
+        VIP,
+        8080,
+        Protocol::Tcp,
+        &[
+            endpoints(&[[10, 0, 0, 1]], 80, 1000),
+            endpoints(&[[10, 0, 1, 1]], 80, 1),
+        ],
+    );
+    assert_eq!(
+        fit(compiled, 2).unwrap().targets,
+        vec![
+            ip_target([10, 0, 0, 1], 80, 1),
+            ip_target([10, 0, 1, 1], 80, 1),
+        ]
+    );
+}
+
+#[test]
+fn test_fit_more_targets_than_backends() {
+    let compiled = compile(
+        VIP,
+        8080,
+        Protocol::Tcp,
+        &[endpoints(
+            &[[10, 0, 0, 1], [10, 0, 0, 2], [10, 0, 0, 3]],
+            80,
+            DEFAULT_WEIGHT,
+        )],
+    );
+    assert_eq!(
+        fit(compiled, 2),
+        Err(CapacityExceeded {
+            targets: 3,
+            capacity: 2,
+        })
+    );
+}
+
+#[test]
+fn test_merge_gives_routes_equal_shares() {
+    // a route of a single backend gets as much traffic as one of three
+    let routes = [
+        compile(
+            VIP,
+            8080,
+            Protocol::Tcp,
+            &[endpoints(
+                &[[10, 0, 0, 1], [10, 0, 0, 2], [10, 0, 0, 3]],
+                80,
+                DEFAULT_WEIGHT,
+            )],
+        ),
+        compile(
+            VIP,
+            8080,
+            Protocol::Tcp,
+            &[endpoints(&[[10, 0, 1, 1]], 80, DEFAULT_WEIGHT)],
+        ),
+    ];
+    let (merged, conflicts) = merge(VIP, 8080, Protocol::Tcp, &routes);
+    assert!(conflicts.is_empty());
+    assert_eq!(
+        merged.targets,
+        vec![
+            ip_target([10, 0, 0, 1], 80, 1),
+            ip_target([10, 0, 0, 2], 80, 1),
+            ip_target([10, 0, 0, 3], 80, 1),
+            ip_target([10, 0, 1, 1], 80, 3),
+        ]
+    );
+}
+
+#[test]
+fn test_merge_dedups_backends_across_routes() {
+    let routes = [
+        compile(
+            VIP,
+            8080,
+            Protocol::Tcp,
+            &[endpoints(
+                &[[10, 0, 0, 1], [10, 0, 0, 2]],
+                80,
+                DEFAULT_WEIGHT,
+            )],
+        ),
+        compile(
+            VIP,
+            8080,
+            Protocol::Tcp,
+            &[endpoints(
+                &[[10, 0, 0, 2], [10, 0, 0, 3]],
+                80,
+                DEFAULT_WEIGHT,
+            )],
+        ),
+        // routes without backends get no traffic
+        compile(
+            VIP,
+            8080,
+            Protocol::Tcp,
+            &[endpoints(&[], 80, DEFAULT_WEIGHT)],
+        ),
+    ];
+    let (merged, conflicts) = merge(VIP, 8080, Protocol::Tcp, &routes);
+    assert!(conflicts.is_empty());
+    assert_eq!(
+        merged.targets,
+        vec![
+            ip_target([10, 0, 0, 1], 80, 1),
+            ip_target([10, 0, 0, 2], 80, 2),
+            ip_target([10, 0, 0, 3], 80, 1),
+        ]
+    );
+}
+
+#[test]
+fn test_merge_leaves_out_conflicting_routes() {
+    let routes = [
+        compile(
+            VIP,
+            53,
+            Protocol::Udp,
+            &[endpoints(&[[10, 0, 0, 1]], 53, DEFAULT_WEIGHT)],
+        ),
+        compile(
+            VIP,
+            53,
+            Protocol::Tcp,
+            &[endpoints(&[[10, 0, 0, 2]], 53, DEFAULT_WEIGHT)],
+        ),
+    ];
+    let (merged, conflicts) = merge(VIP, 53, Protocol::Tcp, &routes);
+    assert_eq!(merged.targets, vec![ip_target([10, 0, 0, 2], 53, 1)]);
+    assert_eq!(
+        conflicts,
+        vec![MergeConflict {
+            index: 0,
+            vip: VIP,
+            port: 53,
+            protocol: Protocol::Udp,
+        }]
+    );
+}
+
+#[test]
+fn test_merge_without_routes() {
+    let (merged, conflicts) = merge(VIP, 8080, Protocol::Tcp, &[]);
+    assert!(merged.targets.is_empty());
+    assert!(conflicts.is_empty());
+}
+
+// The input of a golden file test: a route's backendRefs, as they were resolved.
+#[derive(Deserialize)]
+struct GoldenInput {
+    vip: Ipv4Addr,
+    port: u16,
+    protocol: Protocol,
+    backend_refs: Vec<GoldenBackendRef>,
+}
+
+#[derive(Deserialize)]
+struct GoldenBackendRef {
+    #[serde(default)]
+    addresses: Vec<Ipv4Addr>,
+    hostname: Option<String>,
+    port: u16,
+    weight: Option<u32>,
+}
+
+// Compiles every tests/testdata/compiler/<name>.input.yaml and compares the result with
+// <name>.golden.yaml. Run with UPDATE_GOLDEN=1 to rewrite the golden files after changing
+// what routes compile to, and review their diff.
+#[test]
+fn test_compile_golden_files() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/testdata/compiler");
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+    let mut cases = 0;
+    for entry in fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        let Some(name) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".input.yaml"))
+        else {
+            continue;
+        };
+        cases += 1;
+
+        let input: GoldenInput = serde_yaml::from_str(&fs::read_to_string(&path).unwrap())
+            .unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+        let backend_refs: Vec<WeightedBackends> = input
+            .backend_refs
+            .into_iter()
+            .map(|backend_ref| WeightedBackends {
+                backends: match backend_ref.hostname {
+                    Some(hostname) => ServiceBackends::Hostname(hostname, backend_ref.port),
+                    None => ServiceBackends::Endpoints(backend_ref.addresses, backend_ref.port),
+                },
+                weight: backend_ref.weight.unwrap_or(DEFAULT_WEIGHT),
+            })
+            .collect();
+        let compiled = compile(input.vip, input.port, input.protocol, &backend_refs);
+
+        let golden_path = dir.join(format!("{}.golden.yaml", name));
+        if update {
+            fs::write(&golden_path, serde_yaml::to_string(&compiled).unwrap()).unwrap();
+            continue;
+        }
+        let golden: CompiledTargets =
+            serde_yaml::from_str(&fs::read_to_string(&golden_path).unwrap())
+                .unwrap_or_else(|err| panic!("{}: {}", golden_path.display(), err));
+        assert_eq!(compiled, golden, "{}", name);
+    }
+    assert!(cases > 0, "no golden file tests in {}", dir.display());
+}
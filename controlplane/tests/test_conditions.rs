@@ -0,0 +1,92 @@
+use chrono::{TimeZone, Utc};
+use controlplane::gateway_utils::{set_condition, set_listener_status};
+use gateway_api::apis::standard::gateways::Gateway;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1 as metav1;
+
+fn gateway(generation: i64) -> Gateway {
+    let mut gateway: Gateway = serde_yaml::from_str(
+        r#"
+apiVersion: gateway.networking.k8s.io/v1
+kind: Gateway
+metadata:
+  name: test
+  namespace: default
+spec:
+  gatewayClassName: blixt
+  listeners:
+  - name: tcp
+    protocol: TCP
+    port: 8080
+"#,
+    )
+    .unwrap();
+    gateway.metadata.generation = Some(generation);
+    gateway
+}
+
+fn condition(status: &str, reason: &str, generation: i64, second: u32) -> metav1::Condition {
+    metav1::Condition {
+        type_: "Programmed".to_string(),
+        status: status.to_string(),
+        reason: reason.to_string(),
+        message: format!("{} at generation {}", reason, generation),
+        observed_generation: Some(generation),
+        last_transition_time: metav1::Time(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, second).unwrap()),
+    }
+}
+
+fn conditions(gateway: &Gateway) -> &[metav1::Condition] {
+    gateway
+        .status
+        .as_ref()
+        .and_then(|status| status.conditions.as_deref())
+        .unwrap_or_default()
+}
+
+#[test]
+fn test_set_condition_without_status() {
+    let mut gw = gateway(1);
+    set_condition(&mut gw, condition("True", "Programmed", 1, 0));
+    assert_eq!(conditions(&gw), [condition("True", "Programmed", 1, 0)]);
+}
+
+#[test]
+fn test_set_condition_same_status_after_generation_bump() {
+    let mut gw = gateway(1);
+    set_condition(&mut gw, condition("Unknown", "Pending", 1, 0));
+    gw.metadata.generation = Some(2);
+    set_condition(&mut gw, condition("Unknown", "AddressNotAssigned", 2, 1));
+
+    // everything but the last transition time describes the new generation
+    let mut expected = condition("Unknown", "AddressNotAssigned", 2, 1);
+    expected.last_transition_time = condition("Unknown", "Pending", 1, 0).last_transition_time;
+    assert_eq!(conditions(&gw), [expected]);
+}
+
+#[test]
+fn test_set_condition_status_change() {
+    let mut gw = gateway(1);
+    set_condition(&mut gw, condition("Unknown", "Pending", 1, 0));
+    set_condition(&mut gw, condition("True", "Programmed", 2, 1));
+    assert_eq!(conditions(&gw), [condition("True", "Programmed", 2, 1)]);
+}
+
+#[test]
+fn test_set_listener_status_after_generation_bump() {
+    let mut gw = gateway(1);
+    set_listener_status(&mut gw).unwrap();
+    gw.metadata.generation = Some(3);
+    set_listener_status(&mut gw).unwrap();
+
+    let listeners = gw.status.unwrap().listeners.unwrap();
+    assert_eq!(listeners.len(), 1);
+    assert!(!listeners[0].conditions.is_empty());
+    for condition in &listeners[0].conditions {
+        assert_eq!(
+            condition.observed_generation,
+            Some(3),
+            "{}",
+            condition.type_
+        );
+    }
+}
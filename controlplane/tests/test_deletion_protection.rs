@@ -0,0 +1,70 @@
+use controlplane::gateway_utils::is_deletion_protected;
+use controlplane::DELETION_PROTECTION_ANNOTATION;
+use gateway_api::apis::standard::gateways::{Gateway, GatewayStatus, GatewayStatusAddresses};
+
+fn gateway(annotation: Option<&str>, addresses: Option<Vec<&str>>) -> Gateway {
+    let mut gateway: Gateway = serde_yaml::from_str(
+        r#"
+apiVersion: gateway.networking.k8s.io/v1
+kind: Gateway
+metadata:
+  name: test
+  namespace: default
+spec:
+  gatewayClassName: blixt
+  listeners:
+  - name: tcp
+    protocol: TCP
+    port: 8080
+"#,
+    )
+    .unwrap();
+    if let Some(value) = annotation {
+        gateway.metadata.annotations = Some(
+            [(
+                DELETION_PROTECTION_ANNOTATION.to_string(),
+                value.to_string(),
+            )]
+            .into_iter()
+            .collect(),
+        );
+    }
+    if let Some(addresses) = addresses {
+        gateway.status = Some(GatewayStatus {
+            addresses: Some(
+                addresses
+                    .into_iter()
+                    .map(|value| GatewayStatusAddresses {
+                        r#type: Some("IPAddress".to_string()),
+                        value: value.to_string(),
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        });
+    }
+    gateway
+}
+
+#[test]
+fn test_deletion_protected_with_addresses() {
+    let addresses = || Some(vec!["172.18.0.100"]);
+    assert!(is_deletion_protected(&gateway(Some("true"), addresses())));
+    assert!(!is_deletion_protected(&gateway(Some("false"), addresses())));
+    assert!(!is_deletion_protected(&gateway(None, addresses())));
+    // only "true" opts in
+    assert!(!is_deletion_protected(&gateway(Some("yes"), addresses())));
+}
+
+#[test]
+fn test_deletion_protected_without_addresses() {
+    for annotation in [Some("true"), Some("false"), None] {
+        // without a status, and with a status but no addresses assigned yet
+        assert!(!is_deletion_protected(&gateway(annotation, None)));
+        assert!(!is_deletion_protected(&gateway(annotation, Some(vec![]))));
+
+        let mut gw = gateway(annotation, None);
+        gw.status = Some(GatewayStatus::default());
+        assert!(!is_deletion_protected(&gw));
+    }
+}
@@ -0,0 +1,108 @@
+use controlplane::gateway_class_controller::{
+    deletion_blocked_condition, gateways_of_class, DELETION_BLOCKED_CONDITION,
+    GATEWAYS_EXIST_REASON,
+};
+use controlplane::gateway_utils::set_condition;
+use controlplane::NamespacedName;
+use gateway_api::apis::standard::{gatewayclasses::GatewayClass, gateways::Gateway};
+
+fn gateway_class() -> GatewayClass {
+    serde_yaml::from_str(
+        r#"
+apiVersion: gateway.networking.k8s.io/v1
+kind: GatewayClass
+metadata:
+  name: blixt
+  generation: 2
+spec:
+  controllerName: gateway.networking.k8s.io/blixt
+"#,
+    )
+    .unwrap()
+}
+
+fn gateway(namespace: &str, name: &str, class: &str) -> Gateway {
+    serde_yaml::from_str(&format!(
+        r#"
+apiVersion: gateway.networking.k8s.io/v1
+kind: Gateway
+metadata:
+  name: {}
+  namespace: {}
+spec:
+  gatewayClassName: {}
+  listeners:
+  - name: tcp
+    protocol: TCP
+    port: 8080
+"#,
+        name, namespace, class
+    ))
+    .unwrap()
+}
+
+fn names(names: &[(&str, &str)]) -> Vec<NamespacedName> {
+    names
+        .iter()
+        .map(|(namespace, name)| NamespacedName::new(namespace, name))
+        .collect()
+}
+
+#[test]
+fn test_gateways_of_class() {
+    let gateways = [
+        gateway("default", "b", "blixt"),
+        gateway("other", "a", "blixt"),
+        gateway("default", "c", "other-class"),
+        gateway("default", "a", "blixt"),
+    ];
+    assert_eq!(
+        gateways_of_class("blixt", &gateways),
+        names(&[("default", "a"), ("default", "b"), ("other", "a")])
+    );
+    assert_eq!(gateways_of_class("unused", &gateways), vec![]);
+}
+
+#[test]
+fn test_deletion_blocked_condition() {
+    let condition = deletion_blocked_condition(
+        &gateway_class(),
+        &names(&[("default", "a"), ("other", "b")]),
+    );
+    assert_eq!(condition.type_, DELETION_BLOCKED_CONDITION);
+    assert_eq!(condition.status, "True");
+    assert_eq!(condition.reason, GATEWAYS_EXIST_REASON);
+    assert_eq!(condition.observed_generation, Some(2));
+    assert!(condition.message.ends_with(": default/a, other/b"));
+}
+
+#[test]
+fn test_deletion_blocked_condition_many_gateways() {
+    let gateways: Vec<NamespacedName> = (0..8)
+        .map(|i| NamespacedName::new("default", format!("gw-{}", i)))
+        .collect();
+    let condition = deletion_blocked_condition(&gateway_class(), &gateways);
+    assert!(condition.message.ends_with(
+        ": default/gw-0, default/gw-1, default/gw-2, default/gw-3, default/gw-4, and 3 more"
+    ));
+}
+
+#[test]
+fn test_set_condition_replaces_same_type() {
+    let mut gc = gateway_class();
+    let first = deletion_blocked_condition(&gc, &names(&[("default", "a")]));
+    set_condition(&mut gc, first.clone());
+    set_condition(
+        &mut gc,
+        deletion_blocked_condition(&gc, &names(&[("default", "b")])),
+    );
+
+    let conditions = gc.status.unwrap().conditions.unwrap();
+    assert_eq!(conditions.len(), 1);
+    assert!(conditions[0].message.ends_with(": default/b"));
+    // the status didn't change, so neither did the last transition time
+    assert_eq!(
+        conditions[0].last_transition_time,
+        first.last_transition_time
+    );
+}
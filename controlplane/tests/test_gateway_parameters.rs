@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+
+use controlplane::gateway_parameters::{
+    parse_parameters, GatewayParameters, ParametersRefs, EXTERNAL_TRAFFIC_POLICY_KEY,
+    SERVICE_ANNOTATIONS_KEY, SERVICE_TYPE_KEY,
+};
+use controlplane::{Error, NamespacedName};
+
+fn data(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+    entries
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+fn invalid_parameters(entries: &[(&str, &str)]) -> String {
+    match parse_parameters(&data(entries)) {
+        Err(Error::InvalidParametersError(message)) => message,
+        other => panic!("expected InvalidParametersError, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_parse_parameters() {
+    let parameters = parse_parameters(&data(&[
+        (
+            SERVICE_ANNOTATIONS_KEY,
+            "service.beta.kubernetes.io/aws-load-balancer-type: nlb\nexample.com/team: edge\n",
+        ),
+        (EXTERNAL_TRAFFIC_POLICY_KEY, "Local"),
+    ]))
+    .unwrap();
+    assert_eq!(
+        parameters,
+        GatewayParameters {
+            service_annotations: data(&[
+                ("example.com/team", "edge"),
+                ("service.beta.kubernetes.io/aws-load-balancer-type", "nlb"),
+            ]),
+            external_traffic_policy: Some("Local".to_string()),
+        }
+    );
+}
+
+#[test]
+fn test_parse_no_parameters() {
+    assert_eq!(
+        parse_parameters(&BTreeMap::new()).unwrap(),
+        GatewayParameters::default()
+    );
+}
+
+#[test]
+fn test_parse_invalid_parameters() {
+    let message = invalid_parameters(&[(EXTERNAL_TRAFFIC_POLICY_KEY, "Nearest")]);
+    assert_eq!(
+        message,
+        "invalid externalTrafficPolicy: Nearest; must be Cluster or Local"
+    );
+
+    let message = invalid_parameters(&[(SERVICE_ANNOTATIONS_KEY, "- not\n- a map\n")]);
+    assert!(
+        message.starts_with("invalid serviceAnnotations: "),
+        "{}",
+        message
+    );
+
+    let message = invalid_parameters(&[("loadBalancerClass", "example.com/lb")]);
+    assert_eq!(message, "unknown parameter loadBalancerClass");
+}
+
+#[test]
+fn test_parse_service_type_unsupported() {
+    let message = invalid_parameters(&[(SERVICE_TYPE_KEY, "NodePort")]);
+    assert_eq!(
+        message,
+        "serviceType is not supported: the Service of a Gateway is always of type LoadBalancer"
+    );
+}
+
+#[test]
+fn test_parameters_refs() {
+    let refs = ParametersRefs::default();
+    let configmap = NamespacedName::new("default", "params");
+    refs.set(NamespacedName::new("default", "b"), Some(configmap.clone()));
+    refs.set(NamespacedName::new("default", "a"), Some(configmap.clone()));
+    refs.set(
+        NamespacedName::new("default", "c"),
+        Some(NamespacedName::new("default", "other")),
+    );
+    assert_eq!(
+        refs.gateways(&configmap),
+        vec![
+            NamespacedName::new("default", "a"),
+            NamespacedName::new("default", "b"),
+        ]
+    );
+    // ConfigMaps of the same name in other namespaces aren't referenced
+    assert!(refs
+        .gateways(&NamespacedName::new("other", "params"))
+        .is_empty());
+
+    // Gateways that no longer reference the ConfigMap, or were deleted
+    refs.set(
+        NamespacedName::new("default", "a"),
+        Some(NamespacedName::new("default", "other")),
+    );
+    refs.set(NamespacedName::new("default", "b"), None);
+    assert!(refs.gateways(&configmap).is_empty());
+}
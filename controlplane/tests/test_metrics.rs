@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use controlplane::gateway_utils::set_condition;
+use controlplane::metrics::Metrics;
+use gateway_api::apis::standard::gateways::Gateway;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1 as metav1;
+
+fn gateway(uid: &str) -> Gateway {
+    let mut gateway: Gateway = serde_yaml::from_str(
+        r#"
+apiVersion: gateway.networking.k8s.io/v1
+kind: Gateway
+metadata:
+  name: test
+  namespace: default
+  creationTimestamp: "2024-01-01T00:00:00Z"
+spec:
+  gatewayClassName: blixt
+  listeners:
+  - name: tcp
+    protocol: TCP
+    port: 8080
+"#,
+    )
+    .unwrap();
+    gateway.metadata.uid = Some(uid.to_string());
+    gateway
+}
+
+fn programmed(gateway: &mut Gateway, status: &str) {
+    set_condition(
+        gateway,
+        metav1::Condition {
+            type_: "Programmed".to_string(),
+            status: status.to_string(),
+            reason: "Programmed".to_string(),
+            message: String::new(),
+            observed_generation: Some(1),
+            last_transition_time: metav1::Time(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()),
+        },
+    );
+}
+
+#[test]
+fn test_metrics_render() {
+    let metrics = Metrics::default();
+    let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 3).unwrap();
+    metrics.observe_gateway_programmed(&gateway("a"), now);
+
+    let rendered = metrics.render();
+    assert!(rendered.starts_with(
+        "# HELP blixt_gateway_time_to_programmed_seconds Time from the creation of a Gateway until it was first Programmed.\n\
+         # TYPE blixt_gateway_time_to_programmed_seconds histogram\n"
+    ));
+    assert!(rendered.contains("blixt_gateway_time_to_programmed_seconds_bucket{le=\"2.5\"} 0\n"));
+    assert!(rendered.contains("blixt_gateway_time_to_programmed_seconds_bucket{le=\"5.0\"} 1\n"));
+    assert!(rendered.contains("blixt_gateway_time_to_programmed_seconds_bucket{le=\"+Inf\"} 1\n"));
+    assert!(rendered.ends_with(
+        "blixt_gateway_time_to_programmed_seconds_sum 3\n\
+         blixt_gateway_time_to_programmed_seconds_count 1\n"
+    ));
+}
+
+#[test]
+fn test_observe_gateway_programmed_once() {
+    let metrics = Metrics::default();
+    let mut gw = gateway("a");
+    let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 12).unwrap();
+
+    programmed(&mut gw, "Unknown");
+    assert_eq!(
+        metrics.observe_gateway_programmed(&gw, now),
+        Some(Duration::from_secs(12))
+    );
+    // reconciling the Gateway again before its status is updated
+    assert_eq!(metrics.observe_gateway_programmed(&gw, now), None);
+    assert_eq!(metrics.gateway_time_to_programmed().count(), 1);
+}
+
+#[test]
+fn test_observe_gateway_already_programmed() {
+    let metrics = Metrics::default();
+    let mut gw = gateway("a");
+    programmed(&mut gw, "True");
+
+    let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 1, 0).unwrap();
+    assert_eq!(metrics.observe_gateway_programmed(&gw, now), None);
+    assert_eq!(metrics.gateway_time_to_programmed().count(), 0);
+}
+
+#[test]
+fn test_observe_gateway_programmed_again_after_forget() {
+    let metrics = Metrics::default();
+    let gw = gateway("a");
+    let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 1).unwrap();
+
+    assert!(metrics.observe_gateway_programmed(&gw, now).is_some());
+    metrics.forget_gateway("a");
+    assert!(metrics.observe_gateway_programmed(&gw, now).is_some());
+    assert_eq!(metrics.gateway_time_to_programmed().count(), 2);
+}
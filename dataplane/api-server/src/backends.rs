@@ -5,16 +5,38 @@ pub struct Vip {
     pub ip: u32,
     #[prost(uint32, tag = "2")]
     pub port: u32,
+    /// protocol tells apart the TCP, UDP and SCTP listeners of a Gateway on the same address and
+    /// port, which are separate vips.
+    #[prost(enumeration = "Protocol", tag = "3")]
+    pub protocol: i32,
+    /// port_end, when set, makes the vip cover the range of ports from port to port_end. Ports
+    /// that another vip has to itself go to that vip, and ranges of the same address and protocol
+    /// may not overlap. The range from 0 to 65535 covers all ports of the address.
+    #[prost(uint32, tag = "4")]
+    pub port_end: u32,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Target {
     #[prost(uint32, tag = "1")]
     pub daddr: u32,
+    /// dport 0 sends each connection to the port its client connected to, e.g. for vips of a
+    /// range of ports.
     #[prost(uint32, tag = "2")]
     pub dport: u32,
+    /// ifindex is the interface the backend is reached through. When it's unset the dataplane
+    /// looks up the route to daddr for every packet, so that it follows changes of the routes.
     #[prost(uint32, optional, tag = "3")]
     pub ifindex: ::core::option::Option<u32>,
+    /// hostname is a DNS name the dataplane resolves into daddr, which is ignored when it's set
+    /// (e.g. for ExternalName Services). The name is resolved again periodically.
+    #[prost(string, tag = "4")]
+    pub hostname: ::prost::alloc::string::String,
+    /// draining stops assigning new connections to the backend, while the connections it has
+    /// continue, e.g. to remove it gracefully once they are done. UDP packets are load balanced
+    /// as they come but those of the QUIC connections bound to the backend.
+    #[prost(bool, tag = "5")]
+    pub draining: bool,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -23,6 +45,58 @@ pub struct Targets {
     pub vip: ::core::option::Option<Vip>,
     #[prost(message, repeated, tag = "2")]
     pub targets: ::prost::alloc::vec::Vec<Target>,
+    /// mirror is an optional backend that receives a copy of all traffic to the vip (traffic
+    /// shadowing). Its responses are not expected to reach clients. The copies are sent through
+    /// the host's route to the mirror, whatever its ifindex, and only once its neighbor is known.
+    #[prost(message, optional, tag = "3")]
+    pub mirror: ::core::option::Option<Target>,
+    #[prost(enumeration = "LoadBalancing", tag = "4")]
+    pub load_balancing: i32,
+    /// affinity_timeout_seconds enables session affinity when set: for that many seconds after a
+    /// client's first connection, all of its connections go to the same backend.
+    #[prost(uint32, tag = "5")]
+    pub affinity_timeout_seconds: u32,
+    /// removed_backend_policy is what happens to the tracked connections of the backends this
+    /// update removes from the vip.
+    #[prost(enumeration = "RemovedBackendPolicy", tag = "6")]
+    pub removed_backend_policy: i32,
+    /// full_nat rewrites the source of the vip's traffic to the dataplane's SNAT address, for
+    /// backends that have no route back to the clients. Requires the dataplane to be started with
+    /// an SNAT address.
+    #[prost(bool, tag = "7")]
+    pub full_nat: bool,
+    /// quic_connection_id_length, when set, sends the UDP packets of QUIC connections (e.g.
+    /// HTTP/3) to a backend by their destination connection ID rather than by their source, so
+    /// that connections stay on their backend when clients migrate to another address. It's the
+    /// length of the connection IDs the backends issue, which short header packets don't carry.
+    #[prost(uint32, tag = "8")]
+    pub quic_connection_id_length: u32,
+    /// rate_limit, when set, drops the packets each client address sends to the vip over the
+    /// limit, to protect the backends from abusive clients.
+    #[prost(message, optional, tag = "9")]
+    pub rate_limit: ::core::option::Option<RateLimit>,
+    /// proxy_protocol inserts a PROXY protocol version 2 header before the data of the vip's
+    /// connections, so that backends behind more NAT learn the address of the clients. Only TCP
+    /// vips support it, and their backends must expect the header.
+    #[prost(bool, tag = "10")]
+    pub proxy_protocol: bool,
+    /// dscp, when set, marks the packets forwarded to the vip's backends with this
+    /// differentiated services code point, so that the network on the way to them can
+    /// prioritize the traffic of the vip. It's at most 63.
+    #[prost(uint32, optional, tag = "11")]
+    pub dscp: ::core::option::Option<u32>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RateLimit {
+    /// packets_per_second is the rate each client address may send packets at, 0 disables the
+    /// limit.
+    #[prost(uint32, tag = "1")]
+    pub packets_per_second: u32,
+    /// burst is the number of packets a client may send at once after being idle, which is
+    /// packets_per_second when unset.
+    #[prost(uint32, tag = "2")]
+    pub burst: u32,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -42,6 +116,603 @@ pub struct InterfaceIndexConfirmation {
     #[prost(uint32, tag = "1")]
     pub ifindex: u32,
 }
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Gateway {
+    #[prost(string, tag = "1")]
+    pub namespace: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub name: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ConsistencyCheck {
+    /// repair removes orphaned entries and restores missing indexes when set.
+    #[prost(bool, tag = "1")]
+    pub repair: bool,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ConsistencyReport {
+    /// orphaned_indexes is the number of GATEWAY_INDEXES entries without a BACKENDS entry.
+    #[prost(uint32, tag = "1")]
+    pub orphaned_indexes: u32,
+    /// missing_indexes is the number of BACKENDS entries without a GATEWAY_INDEXES entry.
+    #[prost(uint32, tag = "2")]
+    pub missing_indexes: u32,
+    /// orphaned_connections is the number of LB_CONNECTIONS entries without a BACKENDS entry.
+    #[prost(uint32, tag = "3")]
+    pub orphaned_connections: u32,
+    #[prost(bool, tag = "4")]
+    pub repaired: bool,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Program {
+    /// name is the name of an attached eBPF program, e.g. tc_egress.
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+}
+/// VipPair swaps the backends of two vips, along with which of them are draining and the settings
+/// of how they're load balanced, such as the mirror, session affinity and rate limit. The ACLs,
+/// the VIP_STATS counters, and the rate limit state of the clients are those of the vips and stay
+/// with them. The round-robin positions of both vips start over at their first backend. Vips with
+/// more than 128 backends can't be swapped.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct VipPair {
+    #[prost(message, optional, tag = "1")]
+    pub first: ::core::option::Option<Vip>,
+    #[prost(message, optional, tag = "2")]
+    pub second: ::core::option::Option<Vip>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DrainRequest {
+    /// draining starts (true) or stops (false) draining, the state is only reported when unset.
+    #[prost(bool, optional, tag = "1")]
+    pub draining: ::core::option::Option<bool>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DrainStatus {
+    #[prost(bool, tag = "1")]
+    pub draining: bool,
+    /// active_connections is the number of tracked connections, draining is complete at 0.
+    #[prost(uint32, tag = "2")]
+    pub active_connections: u32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListRequest {
+    /// page_size is the maximum number of vips returned, all of them are returned when unset.
+    #[prost(uint32, tag = "1")]
+    pub page_size: u32,
+    /// page_token is the next_page_token of the previous page, to continue listing after it.
+    #[prost(string, tag = "2")]
+    pub page_token: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TargetsList {
+    #[prost(message, repeated, tag = "1")]
+    pub targets: ::prost::alloc::vec::Vec<Targets>,
+    /// next_page_token is set when there are more vips to list.
+    #[prost(string, tag = "2")]
+    pub next_page_token: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DataplaneInfoRequest {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DataplaneInfo {
+    #[prost(uint32, tag = "1")]
+    pub vips: u32,
+    #[prost(uint32, tag = "2")]
+    pub max_vips: u32,
+    #[prost(uint32, tag = "3")]
+    pub connections: u32,
+    #[prost(uint32, tag = "4")]
+    pub max_connections: u32,
+    /// degraded is true while the occupancy of a map is above its high watermark, a sign that
+    /// the map should be resized before updates to it start failing.
+    #[prost(bool, tag = "5")]
+    pub degraded: bool,
+    /// degraded_reasons describes each map above its high watermark.
+    #[prost(string, repeated, tag = "6")]
+    pub degraded_reasons: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// standby is true until the dataplane is activated, traffic isn't load balanced meanwhile.
+    #[prost(bool, tag = "7")]
+    pub standby: bool,
+    /// max_backends_per_vip is the most targets an update may have, vips with more backends
+    /// need to be split by the client.
+    #[prost(uint32, tag = "8")]
+    pub max_backends_per_vip: u32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ActivateRequest {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CollectFlowsRequest {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct FlowRecord {
+    #[prost(uint32, tag = "1")]
+    pub client_ip: u32,
+    /// client_port is 0 for UDP, whose clients are tracked by address only.
+    #[prost(uint32, tag = "2")]
+    pub client_port: u32,
+    #[prost(message, optional, tag = "3")]
+    pub vip: ::core::option::Option<Vip>,
+    #[prost(message, optional, tag = "4")]
+    pub backend: ::core::option::Option<Target>,
+    /// packets and bytes count the traffic the connection carried, in both directions.
+    #[prost(uint64, tag = "5")]
+    pub packets: u64,
+    #[prost(uint64, tag = "6")]
+    pub bytes: u64,
+    /// gateway is the Gateway that owned the vip when the connection closed, if any.
+    #[prost(message, optional, tag = "7")]
+    pub gateway: ::core::option::Option<Gateway>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct FlowRecords {
+    #[prost(message, repeated, tag = "1")]
+    pub records: ::prost::alloc::vec::Vec<FlowRecord>,
+    /// dropped is the number of records discarded since the last collection because too many
+    /// were buffered.
+    #[prost(uint64, tag = "2")]
+    pub dropped: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct VipStatsRequest {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct VipStats {
+    #[prost(message, optional, tag = "1")]
+    pub vip: ::core::option::Option<Vip>,
+    /// packets and bytes count the traffic the vip load balanced since it was added, in both
+    /// directions.
+    #[prost(uint64, tag = "2")]
+    pub packets: u64,
+    #[prost(uint64, tag = "3")]
+    pub bytes: u64,
+    /// gateway is the Gateway that owns the vip, if any.
+    #[prost(message, optional, tag = "4")]
+    pub gateway: ::core::option::Option<Gateway>,
+    /// latency is how long the backends of the vip took to answer new connections: from the
+    /// SYN of a TCP connection until its SYN-ACK, and from the first datagram of a UDP flow until
+    /// its first reply. Only the replies that go through the dataplane's interface are measured.
+    #[prost(message, optional, tag = "5")]
+    pub latency: ::core::option::Option<LatencyHistogram>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct LatencyHistogram {
+    #[prost(message, repeated, tag = "1")]
+    pub buckets: ::prost::alloc::vec::Vec<LatencyBucket>,
+    #[prost(uint64, tag = "2")]
+    pub count: u64,
+    #[prost(uint64, tag = "3")]
+    pub sum_nanoseconds: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct LatencyBucket {
+    /// le_microseconds is the upper bound of the bucket, unset for the last one which has none.
+    #[prost(uint64, optional, tag = "1")]
+    pub le_microseconds: ::core::option::Option<u64>,
+    /// count is the number of latencies up to the bound, including those of the smaller buckets.
+    #[prost(uint64, tag = "2")]
+    pub count: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct VipStatsList {
+    #[prost(message, repeated, tag = "1")]
+    pub stats: ::prost::alloc::vec::Vec<VipStats>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DropStatsRequest {}
+/// DropStats counts the packets to vips that weren't load balanced since the dataplane started,
+/// by reason. Depending on the reason, they were dropped or passed on unmodified.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DropStats {
+    /// no_backends counts the packets to vips without backends.
+    #[prost(uint64, tag = "1")]
+    pub no_backends: u64,
+    /// backend_out_of_range counts the packets assigned a backend past those of their vip, e.g.
+    /// while its backends were being updated.
+    #[prost(uint64, tag = "2")]
+    pub backend_out_of_range: u64,
+    /// rewrite_failed counts the packets whose addresses, ports or checksums failed to be
+    /// rewritten, which are dropped rather than sent half rewritten.
+    #[prost(uint64, tag = "3")]
+    pub rewrite_failed: u64,
+    /// no_route counts the packets to backends without an ifindex that there was no route to.
+    #[prost(uint64, tag = "4")]
+    pub no_route: u64,
+    /// redirect_failed counts the packets that failed to be sent to their backend or mirror.
+    #[prost(uint64, tag = "5")]
+    pub redirect_failed: u64,
+    /// acl_denied counts the packets of clients the ACL of their vip denies.
+    #[prost(uint64, tag = "6")]
+    pub acl_denied: u64,
+    /// rate_limited counts the packets of clients over the rate limit of their vip.
+    #[prost(uint64, tag = "7")]
+    pub rate_limited: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Cidr {
+    #[prost(uint32, tag = "1")]
+    pub ip: u32,
+    #[prost(uint32, tag = "2")]
+    pub prefix_len: u32,
+}
+/// Acl is the access control list of the clients of a vip, by source address. The packets of
+/// the clients it denies are dropped before they are load balanced, including those of the
+/// connections established before it was set.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Acl {
+    #[prost(message, optional, tag = "1")]
+    pub vip: ::core::option::Option<Vip>,
+    #[prost(enumeration = "AclMode", tag = "2")]
+    pub mode: i32,
+    /// cidrs are the clients the mode applies to, at most 256 of them.
+    #[prost(message, repeated, tag = "3")]
+    pub cidrs: ::prost::alloc::vec::Vec<Cidr>,
+}
+/// TargetRef identifies the targets of a vip with an address and port, the address they were
+/// resolved into for targets specified by hostname.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TargetRef {
+    #[prost(message, optional, tag = "1")]
+    pub vip: ::core::option::Option<Vip>,
+    #[prost(uint32, tag = "2")]
+    pub daddr: u32,
+    #[prost(uint32, tag = "3")]
+    pub dport: u32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GatewayIndex {
+    #[prost(message, optional, tag = "1")]
+    pub vip: ::core::option::Option<Vip>,
+    /// index is the position in the backends of the vip of the backend the next new client is
+    /// assigned with round-robin load balancing.
+    #[prost(uint32, tag = "2")]
+    pub index: u32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CaptureRequest {
+    #[prost(message, optional, tag = "1")]
+    pub vip: ::core::option::Option<Vip>,
+    /// count is the number of packets of the vip to capture, 10 when unset. Each packet is
+    /// captured twice: as received, and once rewritten to be redirected to its backend.
+    #[prost(uint32, tag = "2")]
+    pub count: u32,
+    /// snaplen is the maximum number of bytes captured of each packet, 1518 when unset.
+    #[prost(uint32, tag = "3")]
+    pub snaplen: u32,
+    /// timeout_seconds is how long to wait for the packets, 10 when unset.
+    #[prost(uint32, tag = "4")]
+    pub timeout_seconds: u32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CapturedPacketInfo {
+    #[prost(enumeration = "CaptureStage", tag = "1")]
+    pub stage: i32,
+    /// ifindex is the interface the packet was received on, or is redirected to once rewritten.
+    #[prost(uint32, tag = "2")]
+    pub ifindex: u32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PacketCapture {
+    /// pcap is a pcap file of the captured packets, in the order they were captured.
+    #[prost(bytes = "vec", tag = "1")]
+    pub pcap: ::prost::alloc::vec::Vec<u8>,
+    /// packets describes the packets of the pcap file, in the same order.
+    #[prost(message, repeated, tag = "2")]
+    pub packets: ::prost::alloc::vec::Vec<CapturedPacketInfo>,
+    /// lost is the number of packets that were captured but not read in time.
+    #[prost(uint64, tag = "3")]
+    pub lost: u64,
+}
+/// ProbeRequest runs a synthetic packet through the ingress program. The round-robin position
+/// of the vip and the session affinity pin of the client are put back afterwards, as is the
+/// connection tracking of the client unless it had a real connection, but the probe is counted
+/// by VIP_STATS and the rate limits like any other packet.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProbeRequest {
+    #[prost(message, optional, tag = "1")]
+    pub vip: ::core::option::Option<Vip>,
+    #[prost(enumeration = "Protocol", tag = "2")]
+    pub protocol: i32,
+    /// client_ip and client_port are the source of the synthetic packet, a documentation
+    /// address (192.0.2.1:40000) is used when unset.
+    #[prost(uint32, tag = "3")]
+    pub client_ip: u32,
+    #[prost(uint32, tag = "4")]
+    pub client_port: u32,
+    /// tos is the type of service byte (DSCP and ECN) of the synthetic packet.
+    #[prost(uint32, tag = "5")]
+    pub tos: u32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProbeResult {
+    /// retval is the TC action returned by the ingress program.
+    #[prost(int32, tag = "1")]
+    pub retval: i32,
+    /// daddr and dport are the destination of the packet after it went through the datapath.
+    #[prost(uint32, tag = "2")]
+    pub daddr: u32,
+    #[prost(uint32, tag = "3")]
+    pub dport: u32,
+    /// rewritten is true when the destination was rewritten to a backend.
+    #[prost(bool, tag = "4")]
+    pub rewritten: bool,
+    /// tos is the type of service byte of the packet after it went through the datapath, which
+    /// must be the one it was sent with.
+    #[prost(uint32, tag = "5")]
+    pub tos: u32,
+    /// checksum_valid is true when the IPv4 header checksum of the packet is still valid after it
+    /// went through the datapath.
+    #[prost(bool, tag = "6")]
+    pub checksum_valid: bool,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RuntimeConfig {
+    /// flow_records enables the records of closed connections returned by CollectFlows.
+    #[prost(bool, optional, tag = "1")]
+    pub flow_records: ::core::option::Option<bool>,
+    /// vip_stats enables the traffic counters returned by GetVipStats.
+    #[prost(bool, optional, tag = "2")]
+    pub vip_stats: ::core::option::Option<bool>,
+    /// log_level is the most verbose level of the per-packet log lines that are emitted.
+    #[prost(enumeration = "LogLevel", optional, tag = "3")]
+    pub log_level: ::core::option::Option<i32>,
+    /// log_sample_rate emits only 1 in this many per-packet log lines, 0 and 1 emit every line.
+    #[prost(uint32, optional, tag = "4")]
+    pub log_sample_rate: ::core::option::Option<u32>,
+    /// drop_policy is what happens to the packets of vips without backends.
+    #[prost(enumeration = "DropPolicy", optional, tag = "5")]
+    pub drop_policy: ::core::option::Option<i32>,
+    /// tcp_idle_timeout_seconds and udp_idle_timeout_seconds are how long connections that saw
+    /// no packets are tracked for, 0 tracks them until they are closed.
+    #[prost(uint32, optional, tag = "6")]
+    pub tcp_idle_timeout_seconds: ::core::option::Option<u32>,
+    #[prost(uint32, optional, tag = "7")]
+    pub udp_idle_timeout_seconds: ::core::option::Option<u32>,
+    /// tcp_mss_clamp is the MSS that TCP SYN packets advertising a larger one are clamped to, 0
+    /// disables clamping.
+    #[prost(uint32, optional, tag = "8")]
+    pub tcp_mss_clamp: ::core::option::Option<u32>,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum LoadBalancing {
+    /// ROUND_ROBIN assigns new clients the backends in turn.
+    RoundRobin = 0,
+    /// MAGLEV assigns clients by consistent hashing of their 5-tuple, so that a client keeps its
+    /// backend across dataplane restarts and most changes of the backends.
+    Maglev = 1,
+}
+impl LoadBalancing {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            LoadBalancing::RoundRobin => "ROUND_ROBIN",
+            LoadBalancing::Maglev => "MAGLEV",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "ROUND_ROBIN" => Some(Self::RoundRobin),
+            "MAGLEV" => Some(Self::Maglev),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum RemovedBackendPolicy {
+    /// PRESERVE keeps sending the tracked connections of a removed backend to it, e.g. to let
+    /// them finish while the backend drains.
+    Preserve = 0,
+    /// REBALANCE stops tracking the connections of a removed backend, so that their next packet
+    /// is load balanced again. TCP connections are reset by their new backend.
+    Rebalance = 1,
+}
+impl RemovedBackendPolicy {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            RemovedBackendPolicy::Preserve => "PRESERVE",
+            RemovedBackendPolicy::Rebalance => "REBALANCE",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "PRESERVE" => Some(Self::Preserve),
+            "REBALANCE" => Some(Self::Rebalance),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum AclMode {
+    /// ALLOWLIST only lets the clients in the CIDRs of the ACL reach the vip.
+    Allowlist = 0,
+    /// DENYLIST lets every client reach the vip but those in the CIDRs of the ACL.
+    Denylist = 1,
+}
+impl AclMode {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            AclMode::Allowlist => "ALLOWLIST",
+            AclMode::Denylist => "DENYLIST",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "ALLOWLIST" => Some(Self::Allowlist),
+            "DENYLIST" => Some(Self::Denylist),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum CaptureStage {
+    /// RECEIVED packets were captured as they reached the dataplane.
+    Received = 0,
+    /// REWRITTEN packets were captured once rewritten, before being redirected to their backend.
+    Rewritten = 1,
+}
+impl CaptureStage {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            CaptureStage::Received => "RECEIVED",
+            CaptureStage::Rewritten => "REWRITTEN",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "RECEIVED" => Some(Self::Received),
+            "REWRITTEN" => Some(Self::Rewritten),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum Protocol {
+    Tcp = 0,
+    Udp = 1,
+    /// SCTP vips don't support full NAT or mirrors, and load balance the primary path of
+    /// multi-homed associations only.
+    Sctp = 2,
+}
+impl Protocol {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Protocol::Tcp => "TCP",
+            Protocol::Udp => "UDP",
+            Protocol::Sctp => "SCTP",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "TCP" => Some(Self::Tcp),
+            "UDP" => Some(Self::Udp),
+            "SCTP" => Some(Self::Sctp),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum LogLevel {
+    /// OFF emits none of the log lines the eBPF programs write for every packet.
+    Off = 0,
+    Info = 1,
+    /// DEBUG also emits the lines that detail how the backend of new connections is selected.
+    Debug = 2,
+}
+impl LogLevel {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            LogLevel::Off => "OFF",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "OFF" => Some(Self::Off),
+            "INFO" => Some(Self::Info),
+            "DEBUG" => Some(Self::Debug),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum DropPolicy {
+    /// REJECT resets new TCP connections and replies to UDP packets with an ICMP port
+    /// unreachable error, so that clients fail fast.
+    Reject = 0,
+    /// DROP silently drops the packets.
+    Drop = 1,
+    /// PASS passes the packets to the node, as if the vip didn't exist.
+    Pass = 2,
+}
+impl DropPolicy {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            DropPolicy::Reject => "REJECT",
+            DropPolicy::Drop => "DROP",
+            DropPolicy::Pass => "PASS",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "REJECT" => Some(Self::Reject),
+            "DROP" => Some(Self::Drop),
+            "PASS" => Some(Self::Pass),
+            _ => None,
+        }
+    }
+}
 /// Generated client implementations.
 pub mod backends_client {
     #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
@@ -178,114 +849,1138 @@ pub mod backends_client {
                 .insert(GrpcMethod::new("backends.backends", "Delete"));
             self.inner.unary(req, path, codec).await
         }
-    }
-}
-/// Generated server implementations.
-pub mod backends_server {
-    #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
-    use tonic::codegen::*;
-    /// Generated trait containing gRPC methods that should be implemented for use with BackendsServer.
-    #[async_trait]
-    pub trait Backends: Send + Sync + 'static {
-        async fn get_interface_index(
-            &self,
-            request: tonic::Request<super::PodIp>,
-        ) -> std::result::Result<tonic::Response<super::InterfaceIndexConfirmation>, tonic::Status>;
-        async fn update(
-            &self,
-            request: tonic::Request<super::Targets>,
-        ) -> std::result::Result<tonic::Response<super::Confirmation>, tonic::Status>;
-        async fn delete(
-            &self,
-            request: tonic::Request<super::Vip>,
-        ) -> std::result::Result<tonic::Response<super::Confirmation>, tonic::Status>;
-    }
-    #[derive(Debug)]
-    pub struct BackendsServer<T: Backends> {
-        inner: _Inner<T>,
-        accept_compression_encodings: EnabledCompressionEncodings,
-        send_compression_encodings: EnabledCompressionEncodings,
-        max_decoding_message_size: Option<usize>,
-        max_encoding_message_size: Option<usize>,
-    }
-    struct _Inner<T>(Arc<T>);
-    impl<T: Backends> BackendsServer<T> {
-        pub fn new(inner: T) -> Self {
-            Self::from_arc(Arc::new(inner))
-        }
-        pub fn from_arc(inner: Arc<T>) -> Self {
-            let inner = _Inner(inner);
-            Self {
-                inner,
-                accept_compression_encodings: Default::default(),
-                send_compression_encodings: Default::default(),
-                max_decoding_message_size: None,
-                max_encoding_message_size: None,
-            }
-        }
-        pub fn with_interceptor<F>(inner: T, interceptor: F) -> InterceptedService<Self, F>
-        where
-            F: tonic::service::Interceptor,
-        {
-            InterceptedService::new(Self::new(inner), interceptor)
+        pub async fn delete_by_gateway(
+            &mut self,
+            request: impl tonic::IntoRequest<super::Gateway>,
+        ) -> std::result::Result<tonic::Response<super::Confirmation>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/backends.backends/DeleteByGateway");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("backends.backends", "DeleteByGateway"));
+            self.inner.unary(req, path, codec).await
         }
-        /// Enable decompressing requests with the given encoding.
-        #[must_use]
-        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
-            self.accept_compression_encodings.enable(encoding);
-            self
+        pub async fn check_consistency(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ConsistencyCheck>,
+        ) -> std::result::Result<tonic::Response<super::ConsistencyReport>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/backends.backends/CheckConsistency");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("backends.backends", "CheckConsistency"));
+            self.inner.unary(req, path, codec).await
         }
-        /// Compress responses with the given encoding, if the client supports it.
-        #[must_use]
-        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
-            self.send_compression_encodings.enable(encoding);
-            self
+        pub async fn detach_program(
+            &mut self,
+            request: impl tonic::IntoRequest<super::Program>,
+        ) -> std::result::Result<tonic::Response<super::Confirmation>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/backends.backends/DetachProgram");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("backends.backends", "DetachProgram"));
+            self.inner.unary(req, path, codec).await
         }
-        /// Limits the maximum size of a decoded message.
-        ///
-        /// Default: `4MB`
-        #[must_use]
-        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
-            self.max_decoding_message_size = Some(limit);
-            self
+        pub async fn probe(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ProbeRequest>,
+        ) -> std::result::Result<tonic::Response<super::ProbeResult>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/backends.backends/Probe");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("backends.backends", "Probe"));
+            self.inner.unary(req, path, codec).await
         }
-        /// Limits the maximum size of an encoded message.
-        ///
-        /// Default: `usize::MAX`
-        #[must_use]
-        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
-            self.max_encoding_message_size = Some(limit);
-            self
+        pub async fn swap(
+            &mut self,
+            request: impl tonic::IntoRequest<super::VipPair>,
+        ) -> std::result::Result<tonic::Response<super::Confirmation>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/backends.backends/Swap");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("backends.backends", "Swap"));
+            self.inner.unary(req, path, codec).await
         }
-    }
-    impl<T, B> tonic::codegen::Service<http::Request<B>> for BackendsServer<T>
-    where
-        T: Backends,
-        B: Body + Send + 'static,
-        B::Error: Into<StdError> + Send + 'static,
-    {
-        type Response = http::Response<tonic::body::BoxBody>;
-        type Error = std::convert::Infallible;
-        type Future = BoxFuture<Self::Response, Self::Error>;
-        fn poll_ready(
+        pub async fn drain_node(
             &mut self,
-            _cx: &mut Context<'_>,
-        ) -> Poll<std::result::Result<(), Self::Error>> {
-            Poll::Ready(Ok(()))
+            request: impl tonic::IntoRequest<super::DrainRequest>,
+        ) -> std::result::Result<tonic::Response<super::DrainStatus>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/backends.backends/DrainNode");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("backends.backends", "DrainNode"));
+            self.inner.unary(req, path, codec).await
         }
-        fn call(&mut self, req: http::Request<B>) -> Self::Future {
-            let inner = self.inner.clone();
-            match req.uri().path() {
-                "/backends.backends/GetInterfaceIndex" => {
+        pub async fn list(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ListRequest>,
+        ) -> std::result::Result<tonic::Response<super::TargetsList>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/backends.backends/List");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("backends.backends", "List"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// CollectFlows returns the records of the connections closed since the last collection.
+        pub async fn collect_flows(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CollectFlowsRequest>,
+        ) -> std::result::Result<tonic::Response<super::FlowRecords>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/backends.backends/CollectFlows");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("backends.backends", "CollectFlows"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn get_dataplane_info(
+            &mut self,
+            request: impl tonic::IntoRequest<super::DataplaneInfoRequest>,
+        ) -> std::result::Result<tonic::Response<super::DataplaneInfo>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/backends.backends/GetDataplaneInfo");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("backends.backends", "GetDataplaneInfo"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// GetVipStats returns the traffic counters of every vip.
+        pub async fn get_vip_stats(
+            &mut self,
+            request: impl tonic::IntoRequest<super::VipStatsRequest>,
+        ) -> std::result::Result<tonic::Response<super::VipStatsList>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/backends.backends/GetVipStats");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("backends.backends", "GetVipStats"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// GetGatewayIndex returns the round-robin position of a vip.
+        pub async fn get_gateway_index(
+            &mut self,
+            request: impl tonic::IntoRequest<super::Vip>,
+        ) -> std::result::Result<tonic::Response<super::GatewayIndex>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/backends.backends/GetGatewayIndex");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("backends.backends", "GetGatewayIndex"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// SetGatewayIndex moves the round-robin position of a vip, e.g. to reset it or to make the
+        /// backend of the next new client deterministic.
+        pub async fn set_gateway_index(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GatewayIndex>,
+        ) -> std::result::Result<tonic::Response<super::Confirmation>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/backends.backends/SetGatewayIndex");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("backends.backends", "SetGatewayIndex"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// CapturePackets captures packets of a vip and returns them as a pcap file, to debug how
+        /// they are rewritten. Only one capture runs at a time.
+        pub async fn capture_packets(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CaptureRequest>,
+        ) -> std::result::Result<tonic::Response<super::PacketCapture>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/backends.backends/CapturePackets");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("backends.backends", "CapturePackets"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// Activate starts load balancing on a dataplane started in standby.
+        pub async fn activate(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ActivateRequest>,
+        ) -> std::result::Result<tonic::Response<super::Confirmation>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/backends.backends/Activate");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("backends.backends", "Activate"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// SetRuntimeConfig changes the settings of the eBPF programs that are set, and returns all
+        /// of them. Changes apply to the next packet, without reloading the programs.
+        pub async fn set_runtime_config(
+            &mut self,
+            request: impl tonic::IntoRequest<super::RuntimeConfig>,
+        ) -> std::result::Result<tonic::Response<super::RuntimeConfig>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/backends.backends/SetRuntimeConfig");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("backends.backends", "SetRuntimeConfig"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// GetDropStats returns the counters of the packets to vips that weren't load balanced, by
+        /// reason.
+        pub async fn get_drop_stats(
+            &mut self,
+            request: impl tonic::IntoRequest<super::DropStatsRequest>,
+        ) -> std::result::Result<tonic::Response<super::DropStats>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/backends.backends/GetDropStats");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("backends.backends", "GetDropStats"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// SetAcl replaces the ACL of a vip. Vips without an ACL allow every client.
+        pub async fn set_acl(
+            &mut self,
+            request: impl tonic::IntoRequest<super::Acl>,
+        ) -> std::result::Result<tonic::Response<super::Confirmation>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/backends.backends/SetAcl");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("backends.backends", "SetAcl"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// GetAcl returns the ACL of a vip, an empty DENYLIST when it has none.
+        pub async fn get_acl(
+            &mut self,
+            request: impl tonic::IntoRequest<super::Vip>,
+        ) -> std::result::Result<tonic::Response<super::Acl>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/backends.backends/GetAcl");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("backends.backends", "GetAcl"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// DeleteAcl removes the ACL of a vip, which then allows every client. The ACL of a vip is
+        /// also removed along with the vip.
+        pub async fn delete_acl(
+            &mut self,
+            request: impl tonic::IntoRequest<super::Vip>,
+        ) -> std::result::Result<tonic::Response<super::Confirmation>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/backends.backends/DeleteAcl");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("backends.backends", "DeleteAcl"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// DrainBackend starts draining the targets of a vip, which are no longer assigned new
+        /// connections. Updates of the vip set whether each of its targets is draining.
+        pub async fn drain_backend(
+            &mut self,
+            request: impl tonic::IntoRequest<super::TargetRef>,
+        ) -> std::result::Result<tonic::Response<super::Confirmation>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/backends.backends/DrainBackend");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("backends.backends", "DrainBackend"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// UndrainBackend stops draining the targets of a vip.
+        pub async fn undrain_backend(
+            &mut self,
+            request: impl tonic::IntoRequest<super::TargetRef>,
+        ) -> std::result::Result<tonic::Response<super::Confirmation>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/backends.backends/UndrainBackend");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("backends.backends", "UndrainBackend"));
+            self.inner.unary(req, path, codec).await
+        }
+    }
+}
+/// Generated server implementations.
+pub mod backends_server {
+    #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
+    use tonic::codegen::*;
+    /// Generated trait containing gRPC methods that should be implemented for use with BackendsServer.
+    #[async_trait]
+    pub trait Backends: Send + Sync + 'static {
+        async fn get_interface_index(
+            &self,
+            request: tonic::Request<super::PodIp>,
+        ) -> std::result::Result<tonic::Response<super::InterfaceIndexConfirmation>, tonic::Status>;
+        async fn update(
+            &self,
+            request: tonic::Request<super::Targets>,
+        ) -> std::result::Result<tonic::Response<super::Confirmation>, tonic::Status>;
+        async fn delete(
+            &self,
+            request: tonic::Request<super::Vip>,
+        ) -> std::result::Result<tonic::Response<super::Confirmation>, tonic::Status>;
+        async fn delete_by_gateway(
+            &self,
+            request: tonic::Request<super::Gateway>,
+        ) -> std::result::Result<tonic::Response<super::Confirmation>, tonic::Status>;
+        async fn check_consistency(
+            &self,
+            request: tonic::Request<super::ConsistencyCheck>,
+        ) -> std::result::Result<tonic::Response<super::ConsistencyReport>, tonic::Status>;
+        async fn detach_program(
+            &self,
+            request: tonic::Request<super::Program>,
+        ) -> std::result::Result<tonic::Response<super::Confirmation>, tonic::Status>;
+        async fn probe(
+            &self,
+            request: tonic::Request<super::ProbeRequest>,
+        ) -> std::result::Result<tonic::Response<super::ProbeResult>, tonic::Status>;
+        async fn swap(
+            &self,
+            request: tonic::Request<super::VipPair>,
+        ) -> std::result::Result<tonic::Response<super::Confirmation>, tonic::Status>;
+        async fn drain_node(
+            &self,
+            request: tonic::Request<super::DrainRequest>,
+        ) -> std::result::Result<tonic::Response<super::DrainStatus>, tonic::Status>;
+        async fn list(
+            &self,
+            request: tonic::Request<super::ListRequest>,
+        ) -> std::result::Result<tonic::Response<super::TargetsList>, tonic::Status>;
+        /// CollectFlows returns the records of the connections closed since the last collection.
+        async fn collect_flows(
+            &self,
+            request: tonic::Request<super::CollectFlowsRequest>,
+        ) -> std::result::Result<tonic::Response<super::FlowRecords>, tonic::Status>;
+        async fn get_dataplane_info(
+            &self,
+            request: tonic::Request<super::DataplaneInfoRequest>,
+        ) -> std::result::Result<tonic::Response<super::DataplaneInfo>, tonic::Status>;
+        /// GetVipStats returns the traffic counters of every vip.
+        async fn get_vip_stats(
+            &self,
+            request: tonic::Request<super::VipStatsRequest>,
+        ) -> std::result::Result<tonic::Response<super::VipStatsList>, tonic::Status>;
+        /// GetGatewayIndex returns the round-robin position of a vip.
+        async fn get_gateway_index(
+            &self,
+            request: tonic::Request<super::Vip>,
+        ) -> std::result::Result<tonic::Response<super::GatewayIndex>, tonic::Status>;
+        /// SetGatewayIndex moves the round-robin position of a vip, e.g. to reset it or to make the
+        /// backend of the next new client deterministic.
+        async fn set_gateway_index(
+            &self,
+            request: tonic::Request<super::GatewayIndex>,
+        ) -> std::result::Result<tonic::Response<super::Confirmation>, tonic::Status>;
+        /// CapturePackets captures packets of a vip and returns them as a pcap file, to debug how
+        /// they are rewritten. Only one capture runs at a time.
+        async fn capture_packets(
+            &self,
+            request: tonic::Request<super::CaptureRequest>,
+        ) -> std::result::Result<tonic::Response<super::PacketCapture>, tonic::Status>;
+        /// Activate starts load balancing on a dataplane started in standby.
+        async fn activate(
+            &self,
+            request: tonic::Request<super::ActivateRequest>,
+        ) -> std::result::Result<tonic::Response<super::Confirmation>, tonic::Status>;
+        /// SetRuntimeConfig changes the settings of the eBPF programs that are set, and returns all
+        /// of them. Changes apply to the next packet, without reloading the programs.
+        async fn set_runtime_config(
+            &self,
+            request: tonic::Request<super::RuntimeConfig>,
+        ) -> std::result::Result<tonic::Response<super::RuntimeConfig>, tonic::Status>;
+        /// GetDropStats returns the counters of the packets to vips that weren't load balanced, by
+        /// reason.
+        async fn get_drop_stats(
+            &self,
+            request: tonic::Request<super::DropStatsRequest>,
+        ) -> std::result::Result<tonic::Response<super::DropStats>, tonic::Status>;
+        /// SetAcl replaces the ACL of a vip. Vips without an ACL allow every client.
+        async fn set_acl(
+            &self,
+            request: tonic::Request<super::Acl>,
+        ) -> std::result::Result<tonic::Response<super::Confirmation>, tonic::Status>;
+        /// GetAcl returns the ACL of a vip, an empty DENYLIST when it has none.
+        async fn get_acl(
+            &self,
+            request: tonic::Request<super::Vip>,
+        ) -> std::result::Result<tonic::Response<super::Acl>, tonic::Status>;
+        /// DeleteAcl removes the ACL of a vip, which then allows every client. The ACL of a vip is
+        /// also removed along with the vip.
+        async fn delete_acl(
+            &self,
+            request: tonic::Request<super::Vip>,
+        ) -> std::result::Result<tonic::Response<super::Confirmation>, tonic::Status>;
+        /// DrainBackend starts draining the targets of a vip, which are no longer assigned new
+        /// connections. Updates of the vip set whether each of its targets is draining.
+        async fn drain_backend(
+            &self,
+            request: tonic::Request<super::TargetRef>,
+        ) -> std::result::Result<tonic::Response<super::Confirmation>, tonic::Status>;
+        /// UndrainBackend stops draining the targets of a vip.
+        async fn undrain_backend(
+            &self,
+            request: tonic::Request<super::TargetRef>,
+        ) -> std::result::Result<tonic::Response<super::Confirmation>, tonic::Status>;
+    }
+    #[derive(Debug)]
+    pub struct BackendsServer<T: Backends> {
+        inner: _Inner<T>,
+        accept_compression_encodings: EnabledCompressionEncodings,
+        send_compression_encodings: EnabledCompressionEncodings,
+        max_decoding_message_size: Option<usize>,
+        max_encoding_message_size: Option<usize>,
+    }
+    struct _Inner<T>(Arc<T>);
+    impl<T: Backends> BackendsServer<T> {
+        pub fn new(inner: T) -> Self {
+            Self::from_arc(Arc::new(inner))
+        }
+        pub fn from_arc(inner: Arc<T>) -> Self {
+            let inner = _Inner(inner);
+            Self {
+                inner,
+                accept_compression_encodings: Default::default(),
+                send_compression_encodings: Default::default(),
+                max_decoding_message_size: None,
+                max_encoding_message_size: None,
+            }
+        }
+        pub fn with_interceptor<F>(inner: T, interceptor: F) -> InterceptedService<Self, F>
+        where
+            F: tonic::service::Interceptor,
+        {
+            InterceptedService::new(Self::new(inner), interceptor)
+        }
+        /// Enable decompressing requests with the given encoding.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.accept_compression_encodings.enable(encoding);
+            self
+        }
+        /// Compress responses with the given encoding, if the client supports it.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.send_compression_encodings.enable(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.max_decoding_message_size = Some(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.max_encoding_message_size = Some(limit);
+            self
+        }
+    }
+    impl<T, B> tonic::codegen::Service<http::Request<B>> for BackendsServer<T>
+    where
+        T: Backends,
+        B: Body + Send + 'static,
+        B::Error: Into<StdError> + Send + 'static,
+    {
+        type Response = http::Response<tonic::body::BoxBody>;
+        type Error = std::convert::Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+        fn poll_ready(
+            &mut self,
+            _cx: &mut Context<'_>,
+        ) -> Poll<std::result::Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, req: http::Request<B>) -> Self::Future {
+            let inner = self.inner.clone();
+            match req.uri().path() {
+                "/backends.backends/GetInterfaceIndex" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetInterfaceIndexSvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::PodIp> for GetInterfaceIndexSvc<T> {
+                        type Response = super::InterfaceIndexConfirmation;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(&mut self, request: tonic::Request<super::PodIp>) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Backends>::get_interface_index(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetInterfaceIndexSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/backends.backends/Update" => {
+                    #[allow(non_camel_case_types)]
+                    struct UpdateSvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::Targets> for UpdateSvc<T> {
+                        type Response = super::Confirmation;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::Targets>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move { <T as Backends>::update(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = UpdateSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/backends.backends/Delete" => {
+                    #[allow(non_camel_case_types)]
+                    struct DeleteSvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::Vip> for DeleteSvc<T> {
+                        type Response = super::Confirmation;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(&mut self, request: tonic::Request<super::Vip>) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move { <T as Backends>::delete(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = DeleteSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/backends.backends/DeleteByGateway" => {
+                    #[allow(non_camel_case_types)]
+                    struct DeleteByGatewaySvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::Gateway> for DeleteByGatewaySvc<T> {
+                        type Response = super::Confirmation;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::Gateway>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Backends>::delete_by_gateway(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = DeleteByGatewaySvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/backends.backends/CheckConsistency" => {
+                    #[allow(non_camel_case_types)]
+                    struct CheckConsistencySvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::ConsistencyCheck> for CheckConsistencySvc<T> {
+                        type Response = super::ConsistencyReport;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ConsistencyCheck>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Backends>::check_consistency(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = CheckConsistencySvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/backends.backends/DetachProgram" => {
+                    #[allow(non_camel_case_types)]
+                    struct DetachProgramSvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::Program> for DetachProgramSvc<T> {
+                        type Response = super::Confirmation;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::Program>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Backends>::detach_program(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = DetachProgramSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/backends.backends/Probe" => {
+                    #[allow(non_camel_case_types)]
+                    struct ProbeSvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::ProbeRequest> for ProbeSvc<T> {
+                        type Response = super::ProbeResult;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ProbeRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move { <T as Backends>::probe(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = ProbeSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/backends.backends/Swap" => {
+                    #[allow(non_camel_case_types)]
+                    struct SwapSvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::VipPair> for SwapSvc<T> {
+                        type Response = super::Confirmation;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::VipPair>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move { <T as Backends>::swap(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = SwapSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/backends.backends/DrainNode" => {
+                    #[allow(non_camel_case_types)]
+                    struct DrainNodeSvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::DrainRequest> for DrainNodeSvc<T> {
+                        type Response = super::DrainStatus;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::DrainRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as Backends>::drain_node(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = DrainNodeSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/backends.backends/List" => {
+                    #[allow(non_camel_case_types)]
+                    struct ListSvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::ListRequest> for ListSvc<T> {
+                        type Response = super::TargetsList;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ListRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move { <T as Backends>::list(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = ListSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/backends.backends/CollectFlows" => {
+                    #[allow(non_camel_case_types)]
+                    struct CollectFlowsSvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::CollectFlowsRequest> for CollectFlowsSvc<T> {
+                        type Response = super::FlowRecords;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CollectFlowsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Backends>::collect_flows(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = CollectFlowsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/backends.backends/GetDataplaneInfo" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetDataplaneInfoSvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::DataplaneInfoRequest>
+                        for GetDataplaneInfoSvc<T>
+                    {
+                        type Response = super::DataplaneInfo;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::DataplaneInfoRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Backends>::get_dataplane_info(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetDataplaneInfoSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/backends.backends/GetVipStats" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetVipStatsSvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::VipStatsRequest> for GetVipStatsSvc<T> {
+                        type Response = super::VipStatsList;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::VipStatsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Backends>::get_vip_stats(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetVipStatsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/backends.backends/GetGatewayIndex" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetGatewayIndexSvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::Vip> for GetGatewayIndexSvc<T> {
+                        type Response = super::GatewayIndex;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(&mut self, request: tonic::Request<super::Vip>) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Backends>::get_gateway_index(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetGatewayIndexSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/backends.backends/SetGatewayIndex" => {
                     #[allow(non_camel_case_types)]
-                    struct GetInterfaceIndexSvc<T: Backends>(pub Arc<T>);
-                    impl<T: Backends> tonic::server::UnaryService<super::PodIp> for GetInterfaceIndexSvc<T> {
-                        type Response = super::InterfaceIndexConfirmation;
+                    struct SetGatewayIndexSvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::GatewayIndex> for SetGatewayIndexSvc<T> {
+                        type Response = super::Confirmation;
                         type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
-                        fn call(&mut self, request: tonic::Request<super::PodIp>) -> Self::Future {
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GatewayIndex>,
+                        ) -> Self::Future {
                             let inner = Arc::clone(&self.0);
                             let fut = async move {
-                                <T as Backends>::get_interface_index(&inner, request).await
+                                <T as Backends>::set_gateway_index(&inner, request).await
                             };
                             Box::pin(fut)
                         }
@@ -297,7 +1992,7 @@ pub mod backends_server {
                     let inner = self.inner.clone();
                     let fut = async move {
                         let inner = inner.0;
-                        let method = GetInterfaceIndexSvc(inner);
+                        let method = SetGatewayIndexSvc(inner);
                         let codec = tonic::codec::ProstCodec::default();
                         let mut grpc = tonic::server::Grpc::new(codec)
                             .apply_compression_config(
@@ -313,18 +2008,59 @@ pub mod backends_server {
                     };
                     Box::pin(fut)
                 }
-                "/backends.backends/Update" => {
+                "/backends.backends/CapturePackets" => {
                     #[allow(non_camel_case_types)]
-                    struct UpdateSvc<T: Backends>(pub Arc<T>);
-                    impl<T: Backends> tonic::server::UnaryService<super::Targets> for UpdateSvc<T> {
+                    struct CapturePacketsSvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::CaptureRequest> for CapturePacketsSvc<T> {
+                        type Response = super::PacketCapture;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CaptureRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Backends>::capture_packets(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = CapturePacketsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/backends.backends/Activate" => {
+                    #[allow(non_camel_case_types)]
+                    struct ActivateSvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::ActivateRequest> for ActivateSvc<T> {
                         type Response = super::Confirmation;
                         type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
                         fn call(
                             &mut self,
-                            request: tonic::Request<super::Targets>,
+                            request: tonic::Request<super::ActivateRequest>,
                         ) -> Self::Future {
                             let inner = Arc::clone(&self.0);
-                            let fut = async move { <T as Backends>::update(&inner, request).await };
+                            let fut =
+                                async move { <T as Backends>::activate(&inner, request).await };
                             Box::pin(fut)
                         }
                     }
@@ -335,7 +2071,7 @@ pub mod backends_server {
                     let inner = self.inner.clone();
                     let fut = async move {
                         let inner = inner.0;
-                        let method = UpdateSvc(inner);
+                        let method = ActivateSvc(inner);
                         let codec = tonic::codec::ProstCodec::default();
                         let mut grpc = tonic::server::Grpc::new(codec)
                             .apply_compression_config(
@@ -351,15 +2087,132 @@ pub mod backends_server {
                     };
                     Box::pin(fut)
                 }
-                "/backends.backends/Delete" => {
+                "/backends.backends/SetRuntimeConfig" => {
                     #[allow(non_camel_case_types)]
-                    struct DeleteSvc<T: Backends>(pub Arc<T>);
-                    impl<T: Backends> tonic::server::UnaryService<super::Vip> for DeleteSvc<T> {
+                    struct SetRuntimeConfigSvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::RuntimeConfig> for SetRuntimeConfigSvc<T> {
+                        type Response = super::RuntimeConfig;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::RuntimeConfig>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Backends>::set_runtime_config(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = SetRuntimeConfigSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/backends.backends/GetDropStats" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetDropStatsSvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::DropStatsRequest> for GetDropStatsSvc<T> {
+                        type Response = super::DropStats;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::DropStatsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Backends>::get_drop_stats(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetDropStatsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/backends.backends/SetAcl" => {
+                    #[allow(non_camel_case_types)]
+                    struct SetAclSvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::Acl> for SetAclSvc<T> {
                         type Response = super::Confirmation;
                         type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(&mut self, request: tonic::Request<super::Acl>) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as Backends>::set_acl(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = SetAclSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/backends.backends/GetAcl" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetAclSvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::Vip> for GetAclSvc<T> {
+                        type Response = super::Acl;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
                         fn call(&mut self, request: tonic::Request<super::Vip>) -> Self::Future {
                             let inner = Arc::clone(&self.0);
-                            let fut = async move { <T as Backends>::delete(&inner, request).await };
+                            let fut =
+                                async move { <T as Backends>::get_acl(&inner, request).await };
                             Box::pin(fut)
                         }
                     }
@@ -370,7 +2223,123 @@ pub mod backends_server {
                     let inner = self.inner.clone();
                     let fut = async move {
                         let inner = inner.0;
-                        let method = DeleteSvc(inner);
+                        let method = GetAclSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/backends.backends/DeleteAcl" => {
+                    #[allow(non_camel_case_types)]
+                    struct DeleteAclSvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::Vip> for DeleteAclSvc<T> {
+                        type Response = super::Confirmation;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(&mut self, request: tonic::Request<super::Vip>) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as Backends>::delete_acl(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = DeleteAclSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/backends.backends/DrainBackend" => {
+                    #[allow(non_camel_case_types)]
+                    struct DrainBackendSvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::TargetRef> for DrainBackendSvc<T> {
+                        type Response = super::Confirmation;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::TargetRef>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Backends>::drain_backend(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = DrainBackendSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/backends.backends/UndrainBackend" => {
+                    #[allow(non_camel_case_types)]
+                    struct UndrainBackendSvc<T: Backends>(pub Arc<T>);
+                    impl<T: Backends> tonic::server::UnaryService<super::TargetRef> for UndrainBackendSvc<T> {
+                        type Response = super::Confirmation;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::TargetRef>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Backends>::undrain_backend(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = UndrainBackendSvc(inner);
                         let codec = tonic::codec::ProstCodec::default();
                         let mut grpc = tonic::server::Grpc::new(codec)
                             .apply_compression_config(
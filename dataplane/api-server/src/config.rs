@@ -3,29 +3,125 @@ Copyright 2023 The Kubernetes Authors.
 
 SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
 */
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
+// The keys of the certificates in Kubernetes Secrets: those of kubernetes.io/tls Secrets, and
+// that of the CA, as cert-manager names them.
+pub const SECRET_CERTIFICATE_KEY: &str = "tls.crt";
+pub const SECRET_PRIVATE_KEY_KEY: &str = "tls.key";
+pub const SECRET_CA_CERTIFICATE_KEY: &str = "ca.crt";
+
 #[derive(Debug, Subcommand)]
 pub enum TLSConfig {
     TLS(ServerOnlyTLSConfig),
     MutualTLS(MutualTLSConfig),
+    SecretTLS(SecretTLSConfig),
 }
 
 #[derive(Debug, Parser, Clone)]
 pub struct ServerOnlyTLSConfig {
-    #[clap(short, long)]
+    #[clap(short, long, env = "BLIXT_TLS_SERVER_CERTIFICATE_PATH")]
     pub server_certificate_path: PathBuf,
-    #[clap(short, long)]
+    #[clap(short, long, env = "BLIXT_TLS_SERVER_PRIVATE_KEY_PATH")]
     pub server_private_key_path: PathBuf,
+    #[clap(flatten)]
+    pub options: TLSOptions,
 }
 
 #[derive(Debug, Parser, Clone)]
 pub struct MutualTLSConfig {
-    #[clap(short, long)]
+    #[clap(short, long, env = "BLIXT_TLS_SERVER_CERTIFICATE_PATH")]
     pub server_certificate_path: PathBuf,
-    #[clap(short, long)]
+    #[clap(short, long, env = "BLIXT_TLS_SERVER_PRIVATE_KEY_PATH")]
     pub server_private_key_path: PathBuf,
-    #[clap(short, long)]
+    #[clap(short, long, env = "BLIXT_TLS_CLIENT_CERTIFICATE_AUTHORITY_ROOT_PATH")]
     pub client_certificate_authority_root_path: PathBuf,
+    #[clap(flatten)]
+    pub options: TLSOptions,
+}
+
+/// TLS with the certificates of Kubernetes Secrets mounted as volumes, which are reloaded when
+/// the Secrets are updated.
+#[derive(Debug, Parser, Clone)]
+pub struct SecretTLSConfig {
+    /// Directory a kubernetes.io/tls Secret is mounted at, with the certificate and private key
+    /// of the server.
+    #[clap(long, env = "BLIXT_TLS_SERVER_SECRET_DIR")]
+    pub server_secret_dir: PathBuf,
+    /// Directory a Secret with the CA of client certificates in ca.crt is mounted at, for
+    /// mutual TLS. It can be the directory of the server's Secret.
+    #[clap(long, env = "BLIXT_TLS_CLIENT_CA_SECRET_DIR")]
+    pub client_ca_secret_dir: Option<PathBuf>,
+    /// Interval in seconds at which the Secrets are checked for updates.
+    #[clap(long, env = "BLIXT_TLS_SECRET_CHECK_INTERVAL", default_value_t = 10)]
+    pub check_interval: u64,
+    #[clap(flatten)]
+    pub options: TLSOptions,
+}
+
+/// The files of the certificates of a TLS config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TLSPaths {
+    pub server_certificate: PathBuf,
+    pub server_private_key: PathBuf,
+    /// The CA of client certificates, with mutual TLS.
+    pub client_certificate_authority_root: Option<PathBuf>,
+}
+
+/// The versions and cipher suites of TLS the API server accepts, for deployments with security
+/// baselines stricter than the defaults.
+#[derive(Debug, Parser, Clone, Default)]
+pub struct TLSOptions {
+    /// The minimum version of TLS clients may use.
+    #[clap(long, env = "BLIXT_TLS_MIN_VERSION", value_enum, default_value_t)]
+    pub min_tls_version: TLSVersion,
+    /// The cipher suites clients may use, by their IANA names, separated by commas. All the
+    /// supported cipher suites of the accepted versions by default.
+    #[clap(long, env = "BLIXT_TLS_CIPHER_SUITES", value_delimiter = ',')]
+    pub cipher_suites: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum TLSVersion {
+    #[default]
+    #[value(name = "1.2")]
+    V1_2,
+    #[value(name = "1.3")]
+    V1_3,
+}
+
+impl TLSConfig {
+    pub fn options(&self) -> &TLSOptions {
+        match self {
+            TLSConfig::TLS(config) => &config.options,
+            TLSConfig::MutualTLS(config) => &config.options,
+            TLSConfig::SecretTLS(config) => &config.options,
+        }
+    }
+
+    pub fn paths(&self) -> TLSPaths {
+        match self {
+            TLSConfig::TLS(config) => TLSPaths {
+                server_certificate: config.server_certificate_path.clone(),
+                server_private_key: config.server_private_key_path.clone(),
+                client_certificate_authority_root: None,
+            },
+            TLSConfig::MutualTLS(config) => TLSPaths {
+                server_certificate: config.server_certificate_path.clone(),
+                server_private_key: config.server_private_key_path.clone(),
+                client_certificate_authority_root: Some(
+                    config.client_certificate_authority_root_path.clone(),
+                ),
+            },
+            TLSConfig::SecretTLS(config) => TLSPaths {
+                server_certificate: config.server_secret_dir.join(SECRET_CERTIFICATE_KEY),
+                server_private_key: config.server_secret_dir.join(SECRET_PRIVATE_KEY_KEY),
+                client_certificate_authority_root: config
+                    .client_ca_secret_dir
+                    .as_ref()
+                    .map(|dir| dir.join(SECRET_CA_CERTIFICATE_KEY)),
+            },
+        }
+    }
 }
@@ -6,31 +6,95 @@ SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
 
 pub mod backends;
 pub mod config;
+pub mod metrics;
 pub mod netutils;
+pub mod pcap;
+pub mod probe;
+pub mod resolver;
+pub mod scan;
 pub mod server;
+pub mod tls;
 
 use std::{
-    fs,
+    collections::HashMap as StdHashMap,
     net::{Ipv4Addr, SocketAddrV4},
+    sync::{Arc, RwLock},
+    time::Duration,
 };
 
-use anyhow::{Context, Result};
-use aya::maps::{HashMap, MapData};
-use log::info;
-use tonic::transport::{Certificate, Identity, Server, ServerTlsConfig};
+use anyhow::{anyhow, Result};
+use aya::maps::{
+    perf::AsyncPerfEventArray, Array, HashMap, LpmTrie, MapData, PerCpuArray, PerCpuHashMap,
+    RingBuf,
+};
+use log::{error, info};
+use tokio_rustls::TlsAcceptor;
+use tonic::transport::{server::TcpIncoming, Server};
+use tonic_health::ServingStatus;
 
 use backends::backends_server::BackendsServer;
-use common::{BackendKey, BackendList, ClientKey, LoadBalancerMapping};
+use common::{
+    AclKey, Affinity, AffinityKey, BackendKey, BackendList, BackendSlot, BackendSlotKey,
+    CaptureConfig, ClientKey, LoadBalancerMapping, PortRangeKey, VipStats,
+};
 use config::TLSConfig;
 
+#[allow(clippy::too_many_arguments)]
 pub async fn start(
     addr: Ipv4Addr,
     port: u16,
+    bind_device: Option<String>,
+    metrics_port: Option<u16>,
     backends_map: HashMap<MapData, BackendKey, BackendList>,
+    backend_slots_map: HashMap<MapData, BackendSlotKey, BackendSlot>,
+    port_ranges_map: LpmTrie<MapData, PortRangeKey, BackendKey>,
     gateway_indexes_map: HashMap<MapData, BackendKey, u16>,
     tcp_conns_map: HashMap<MapData, ClientKey, LoadBalancerMapping>,
+    affinities_map: HashMap<MapData, AffinityKey, Affinity>,
+    state_map: Array<MapData, u32>,
+    vip_stats_map: PerCpuHashMap<MapData, BackendKey, VipStats>,
+    drop_stats_map: PerCpuArray<MapData, u64>,
+    vip_acls_map: LpmTrie<MapData, AclKey, u32>,
+    attached_programs: StdHashMap<String, server::AttachedProgram>,
+    flow_records: RingBuf<MapData>,
+    capture_config_map: Array<MapData, CaptureConfig>,
+    packet_captures: AsyncPerfEventArray<MapData>,
+    limits: server::MapLimits,
+    self_test_ifindex: Option<u32>,
     tls_config: Option<TLSConfig>,
 ) -> Result<()> {
+    // The listeners are bound up front, so that an address or device that can't be bound fails
+    // the startup. Only the API server is bound to the device: the healthchecks are read-only,
+    // and the kubelet probes them over the loopback interface.
+    let health_incoming = TcpIncoming::from_listener(
+        netutils::bind_listener(SocketAddrV4::new(addr, port + 1), None)?,
+        false,
+        None,
+    )
+    .map_err(|err| anyhow!(err))?;
+    let api_incoming = TcpIncoming::from_listener(
+        netutils::bind_listener(SocketAddrV4::new(addr, port), bind_device.as_deref())?,
+        false,
+        None,
+    )
+    .map_err(|err| anyhow!(err))?;
+    info!("api server listening on {}:{}", addr, port);
+    // the acceptor is swapped for one with the new certificates when Secrets are updated
+    let tls_acceptor = setup_tls(&tls_config)?.map(|acceptor| Arc::new(RwLock::new(acceptor)));
+    if let (Some(secret_tls @ TLSConfig::SecretTLS(config)), Some(acceptor)) =
+        (&tls_config, &tls_acceptor)
+    {
+        tokio::spawn(tls::watch(
+            secret_tls.paths(),
+            config.options.clone(),
+            acceptor.clone(),
+            Duration::from_secs(config.check_interval),
+        ));
+    }
+    let metrics_listener = metrics_port
+        .map(|metrics_port| netutils::bind_listener(SocketAddrV4::new(addr, metrics_port), None))
+        .transpose()?;
+
     // Tonic itself doesn't provide a built-in mechanism for selectively
     // applying TLS based on routes, as TLS configuration is tied to the
     // entire server and managed at the transport layer, not at the
@@ -39,26 +103,95 @@ pub async fn start(
     // Solution: separate gRPC services
     //
     // Public server without TLS (healthchecks ONLY)
+    //
+    // With a self-test, the dataplane reports itself as not serving until it passed.
+    let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
+    if self_test_ifindex.is_some() {
+        health_reporter
+            .set_service_status("", ServingStatus::NotServing)
+            .await;
+    }
     let healthchecks = tokio::spawn(async move {
-        let (_, health_service) = tonic_health::server::health_reporter();
         let mut server_builder = Server::builder();
         server_builder
             .add_service(health_service)
-            .serve(SocketAddrV4::new(addr, port + 1).into())
+            .serve_with_incoming(health_incoming)
             .await
             .unwrap();
     });
 
+    // Metrics of the RPCs of the secure server, served without TLS like the healthchecks
+    let rpc_metrics = Arc::new(metrics::RpcMetrics::default());
+    if let Some(listener) = metrics_listener {
+        let rpc_metrics = rpc_metrics.clone();
+        tokio::spawn(async move {
+            if let Err(err) = metrics::serve(listener, rpc_metrics).await {
+                error!("failed to serve metrics: {}", err);
+            }
+        });
+    }
+
     // Secure server with (optional) mTLS
     let backends = tokio::spawn(async move {
-        let server = server::BackendService::new(backends_map, gateway_indexes_map, tcp_conns_map);
-        let mut server_builder = Server::builder();
-        server_builder = setup_tls(server_builder, &tls_config).unwrap();
-        server_builder
-            .add_service(BackendsServer::new(server))
-            .serve(SocketAddrV4::new(addr, port).into())
-            .await
-            .unwrap();
+        let server = server::BackendService::new(
+            backends_map,
+            backend_slots_map,
+            port_ranges_map,
+            gateway_indexes_map,
+            tcp_conns_map,
+            affinities_map,
+            state_map,
+            vip_stats_map,
+            drop_stats_map,
+            vip_acls_map,
+            capture_config_map,
+            attached_programs,
+            limits,
+        );
+        tokio::spawn(server.clone().run_connection_cleanup(
+            server::CONNECTION_CLEANUP_INTERVAL,
+            server::CONNECTION_CLEANUP_BATCH_SIZE,
+        ));
+        tokio::spawn(server.clone().run_idle_connection_expiry(
+            server::IDLE_CONNECTION_EXPIRY_INTERVAL,
+            server::CONNECTION_CLEANUP_BATCH_SIZE,
+        ));
+        tokio::spawn(server.clone().run_flow_collection(flow_records));
+        tokio::spawn(server.clone().run_packet_capture(packet_captures));
+        tokio::spawn(
+            server
+                .clone()
+                .run_hostname_refresh(server::HOSTNAME_REFRESH_INTERVAL),
+        );
+        tokio::spawn(
+            server
+                .clone()
+                .run_occupancy_check(server::OCCUPANCY_CHECK_INTERVAL),
+        );
+        if let Some(ifindex) = self_test_ifindex {
+            match server.self_test(ifindex).await {
+                Ok(()) => {
+                    info!("self-test passed");
+                    health_reporter
+                        .set_service_status("", ServingStatus::Serving)
+                        .await;
+                }
+                Err(err) => error!("self-test failed, reporting not serving: {}", err),
+            }
+        }
+        let router = Server::builder().add_service(metrics::RpcMetricsService::new(
+            BackendsServer::new(server),
+            rpc_metrics,
+        ));
+        match tls_acceptor {
+            Some(acceptor) => {
+                router
+                    .serve_with_incoming(tls::incoming(api_incoming, acceptor))
+                    .await
+            }
+            None => router.serve_with_incoming(api_incoming).await,
+        }
+        .unwrap();
     });
 
     tokio::try_join!(healthchecks, backends)?;
@@ -66,67 +199,27 @@ pub async fn start(
     Ok(())
 }
 
-pub fn setup_tls(mut builder: Server, tls_config: &Option<TLSConfig>) -> Result<Server> {
-    // TLS implementation drawn from Tonic examples.
-    // See: https://github.com/hyperium/tonic/blob/master/examples/src/tls_client_auth/server.rs
-    match tls_config {
-        Some(TLSConfig::TLS(config)) => {
-            let mut tls = ServerTlsConfig::new();
-
-            let cert = fs::read_to_string(&config.server_certificate_path).with_context(|| {
-                format!(
-                    "Failed to read certificate from {:?}",
-                    config.server_certificate_path
-                )
-            })?;
-            let key = fs::read_to_string(&config.server_private_key_path).with_context(|| {
-                format!(
-                    "Failed to read key from {:?}",
-                    config.server_private_key_path
-                )
-            })?;
-            let server_identity = Identity::from_pem(cert, key);
-            tls = tls.identity(server_identity);
+/// Returns the acceptor of the TLS connections of the API server, if it serves TLS.
+pub fn setup_tls(tls_config: &Option<TLSConfig>) -> Result<Option<TlsAcceptor>> {
+    let Some(tls_config) = tls_config else {
+        return Ok(None);
+    };
+    let paths = tls_config.paths();
+    let options = tls_config.options();
+    let acceptor = tls::acceptor(&paths, options)?;
 
-            builder = builder.tls_config(tls)?;
-            info!("gRPC TLS enabled");
-            Ok(builder)
-        }
-        Some(TLSConfig::MutualTLS(config)) => {
-            let mut tls = ServerTlsConfig::new();
-
-            let cert =
-                fs::read_to_string(config.server_certificate_path.clone()).with_context(|| {
-                    format!(
-                        "Failed to read certificate from {:?}",
-                        config.server_certificate_path
-                    )
-                })?;
-            let key =
-                fs::read_to_string(config.server_private_key_path.clone()).with_context(|| {
-                    format!(
-                        "Failed to read key from {:?}",
-                        config.server_private_key_path
-                    )
-                })?;
-            let server_identity = Identity::from_pem(cert, key);
-            tls = tls.identity(server_identity);
-
-            let client_ca_cert =
-                fs::read_to_string(config.client_certificate_authority_root_path.clone())
-                    .with_context(|| {
-                        format!(
-                            "Failed to read client CA from {:?}",
-                            config.client_certificate_authority_root_path
-                        )
-                    })?;
-            let client_ca_root = Certificate::from_pem(client_ca_cert);
-            tls = tls.client_ca_root(client_ca_root);
-
-            builder = builder.tls_config(tls)?;
-            info!("gRPC mTLS enabled");
-            Ok(builder)
-        }
-        None => Ok(builder),
+    if paths.client_certificate_authority_root.is_some() {
+        info!("gRPC mTLS enabled");
+    } else {
+        info!("gRPC TLS enabled");
     }
+    info!(
+        "gRPC TLS minimum version {:?}, cipher suites {:?}",
+        options.min_tls_version,
+        tls::cipher_suites(options)?
+            .iter()
+            .map(|suite| suite.suite())
+            .collect::<Vec<_>>()
+    );
+    Ok(Some(acceptor))
 }
@@ -0,0 +1,250 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! Metrics of the API of the dataplane, served in the OpenMetrics text format.
+//!
+//! Every RPC is counted by method and status code, failed ones by their blixt error code as
+//! well, and the time it took is recorded in a histogram of its method. Each bucket of the
+//! histograms keeps the Gateway of the last request it observed as an exemplar, so that slow
+//! updates can be traced to the Gateways they were made for.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use common::metrics::{self, OPENMETRICS_CONTENT_TYPE};
+use common::NamespacedName;
+use log::info;
+use tokio::net::TcpListener;
+use tonic::{
+    body::BoxBody,
+    codegen::{http, BoxFuture, Context, Poll, Service},
+    server::NamedService,
+    Code,
+};
+
+use crate::server::{ERROR_CODE_METADATA_KEY, GATEWAY_METADATA_KEY};
+
+pub use common::metrics::{Exemplar, Histogram};
+
+/// Upper bounds, in seconds, of the buckets of the histograms of the latency of RPCs. Updates
+/// rewrite maps of the kernel and take milliseconds, while the occupancy checks of large maps
+/// can take seconds.
+pub const RPC_LATENCY_BUCKETS: &[f64] = &[
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// The method requests to methods the API doesn't have are recorded under, so that clients
+/// can't grow the metrics without bounds.
+pub const UNKNOWN_METHOD: &str = "unknown";
+
+// The longest the labels of an exemplar can be, names and values together, in OpenMetrics.
+const MAX_EXEMPLAR_LABELS_LEN: usize = 128;
+const EXEMPLAR_LABEL: &str = "gateway";
+
+#[derive(Debug)]
+struct MethodMetrics {
+    // by gRPC status code
+    requests: BTreeMap<String, u64>,
+    // by blixt error code, of the failed requests that had one
+    errors: BTreeMap<String, u64>,
+    latency: Histogram,
+}
+
+impl Default for MethodMetrics {
+    fn default() -> Self {
+        MethodMetrics {
+            requests: BTreeMap::new(),
+            errors: BTreeMap::new(),
+            latency: Histogram::new(RPC_LATENCY_BUCKETS),
+        }
+    }
+}
+
+/// The metrics of the RPCs of the API, by method.
+#[derive(Debug, Default)]
+pub struct RpcMetrics {
+    methods: Mutex<BTreeMap<String, MethodMetrics>>,
+}
+
+impl RpcMetrics {
+    /// Records an RPC to the method, which returned the status code, and the blixt error code if
+    /// it failed with one, after `elapsed`. The Gateway the request was made for becomes the
+    /// exemplar of its latency if it's a valid `namespace/name`.
+    pub fn observe(
+        &self,
+        method: &str,
+        code: Code,
+        error_code: Option<&str>,
+        elapsed: Duration,
+        gateway: Option<&str>,
+    ) {
+        let method = match code {
+            Code::Unimplemented => UNKNOWN_METHOD,
+            _ => method,
+        };
+        let gateway = gateway
+            .and_then(|gateway| gateway.parse::<NamespacedName>().ok())
+            .map(|gateway| gateway.to_string())
+            .filter(|gateway| EXEMPLAR_LABEL.len() + gateway.len() <= MAX_EXEMPLAR_LABELS_LEN);
+
+        let mut methods = self.methods.lock().unwrap();
+        let metrics = methods.entry(method.to_string()).or_default();
+        *metrics.requests.entry(format!("{:?}", code)).or_default() += 1;
+        if let Some(error_code) = error_code {
+            *metrics.errors.entry(error_code.to_string()).or_default() += 1;
+        }
+        metrics
+            .latency
+            .observe(elapsed.as_secs_f64(), gateway.as_deref());
+    }
+
+    /// Returns the number of RPCs to the method that returned the status code.
+    pub fn requests(&self, method: &str, code: Code) -> u64 {
+        self.methods
+            .lock()
+            .unwrap()
+            .get(method)
+            .and_then(|metrics| metrics.requests.get(&format!("{:?}", code)).copied())
+            .unwrap_or(0)
+    }
+
+    /// Returns the histogram of the latency of the RPCs to the method, if it was called.
+    pub fn latency(&self, method: &str) -> Option<Histogram> {
+        self.methods
+            .lock()
+            .unwrap()
+            .get(method)
+            .map(|metrics| metrics.latency.clone())
+    }
+
+    /// Returns the metrics in the OpenMetrics text format.
+    pub fn render(&self) -> String {
+        let methods = self.methods.lock().unwrap();
+        let mut out = String::new();
+
+        let name = "blixt_dataplane_rpc_requests";
+        let _ = writeln!(
+            out,
+            "# HELP {} RPCs to the API, by method and status code.",
+            name
+        );
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        for (method, metrics) in methods.iter() {
+            for (code, count) in &metrics.requests {
+                let _ = writeln!(
+                    out,
+                    "{}_total{{method=\"{}\",code=\"{}\"}} {}",
+                    name, method, code, count
+                );
+            }
+        }
+
+        let name = "blixt_dataplane_rpc_errors";
+        let _ = writeln!(
+            out,
+            "# HELP {} Failed RPCs to the API, by blixt error code.",
+            name
+        );
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        for (method, metrics) in methods.iter() {
+            for (error_code, count) in &metrics.errors {
+                let _ = writeln!(
+                    out,
+                    "{}_total{{method=\"{}\",error_code=\"{}\"}} {}",
+                    name, method, error_code, count
+                );
+            }
+        }
+
+        let name = "blixt_dataplane_rpc_duration_seconds";
+        let _ = writeln!(out, "# HELP {} Time the API took to answer RPCs.", name);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (method, metrics) in methods.iter() {
+            metrics
+                .latency
+                .render(name, &format!("method=\"{}\"", method), &mut out);
+        }
+
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+/// Wraps a gRPC service to record the metrics of its RPCs.
+#[derive(Clone, Debug)]
+pub struct RpcMetricsService<S> {
+    inner: S,
+    metrics: Arc<RpcMetrics>,
+}
+
+impl<S> RpcMetricsService<S> {
+    pub fn new(inner: S, metrics: Arc<RpcMetrics>) -> Self {
+        RpcMetricsService { inner, metrics }
+    }
+}
+
+impl<S, B> Service<http::Request<B>> for RpcMetricsService<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<BoxBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let method = req.uri().path().rsplit('/').next().unwrap_or_default();
+        let method = method.to_string();
+        let gateway = header(req.headers(), GATEWAY_METADATA_KEY);
+        let metrics = self.metrics.clone();
+        let start = Instant::now();
+        let response = self.inner.call(req);
+
+        Box::pin(async move {
+            let response = response.await?;
+            // the status of unary RPCs that failed is in the headers, those that succeeded have
+            // it in the trailers
+            let code = header(response.headers(), "grpc-status")
+                .and_then(|code| code.parse().ok())
+                .map(Code::from_i32)
+                .unwrap_or(Code::Ok);
+            let error_code = header(response.headers(), ERROR_CODE_METADATA_KEY);
+            metrics.observe(
+                &method,
+                code,
+                error_code.as_deref(),
+                start.elapsed(),
+                gateway.as_deref(),
+            );
+            Ok(response)
+        })
+    }
+}
+
+impl<S: NamedService> NamedService for RpcMetricsService<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+fn header(headers: &http::HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from)
+}
+
+/// Serves the metrics over HTTP on `GET /metrics`, until the listener fails.
+pub async fn serve(listener: TcpListener, metrics: Arc<RpcMetrics>) -> std::io::Result<()> {
+    info!("serving metrics on {}", listener.local_addr()?);
+    metrics::serve(listener, OPENMETRICS_CONTENT_TYPE, move || metrics.render()).await
+}
@@ -7,17 +7,24 @@ SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
 use anyhow::Error;
 use netlink_packet_core::{NetlinkHeader, NetlinkMessage, NetlinkPayload, NLM_F_REQUEST};
 use netlink_packet_route::{
+    link::{InfoKind, LinkAttribute, LinkInfo, LinkMessage},
     route::{RouteAddress, RouteAttribute, RouteFlags, RouteHeader, RouteMessage},
     AddressFamily, RouteNetlinkMessage,
 };
 use netlink_sys::{protocols::NETLINK_ROUTE, Socket, SocketAddr};
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use tokio::net::{TcpListener, TcpSocket};
 
 const ERR_NO_IFINDEX: &str = "no ifindex found to route";
+const ERR_NO_LINK: &str = "no link found for";
 const ERR_PACKET_CONSTRUCTION: &str = "construct packet failed";
 
-/// Returns an network interface index for a Ipv4 address (like the command `ip route get to $IP`)
-pub fn if_index_for_routing_ip(ip_addr: Ipv4Addr) -> Result<u32, Error> {
+// ethtool ioctl commands and flags, see include/uapi/linux/ethtool.h
+const ETHTOOL_GFLAGS: u32 = 0x25;
+pub const ETH_FLAG_LRO: u32 = 1 << 15;
+
+// Sends a request over a NETLINK_ROUTE socket and returns the (first) reply.
+fn netlink_request(message: RouteNetlinkMessage) -> Result<RouteNetlinkMessage, Error> {
     let socket = Socket::new(NETLINK_ROUTE)?;
     socket.connect(&SocketAddr::new(0, 0))?;
 
@@ -26,24 +33,8 @@ pub fn if_index_for_routing_ip(ip_addr: Ipv4Addr) -> Result<u32, Error> {
     // NNLM_F_REQUEST: Must be set on all request messages
     nl_hdr.flags = NLM_F_REQUEST;
 
-    // construct RouteMessage
-    let route_header = RouteHeader {
-        address_family: AddressFamily::Inet,
-        flags: RouteFlags::LookupTable,
-        destination_prefix_length: 32,
-        table: RouteHeader::RT_TABLE_MAIN,
-        ..Default::default()
-    };
-    let route_attribute = RouteAttribute::Destination(RouteAddress::Inet(ip_addr));
-    let mut route_message = RouteMessage::default();
-    route_message.attributes = vec![route_attribute];
-    route_message.header = route_header;
-
     // construct a message packet for netlink and serialize it to send it over the socket
-    let mut packet = NetlinkMessage::new(
-        nl_hdr,
-        NetlinkPayload::from(RouteNetlinkMessage::GetRoute(route_message)),
-    );
+    let mut packet = NetlinkMessage::new(nl_hdr, NetlinkPayload::from(message));
     packet.finalize();
     let mut buf = vec![0; packet.header.length as usize];
     // check packet
@@ -57,12 +48,35 @@ pub fn if_index_for_routing_ip(ip_addr: Ipv4Addr) -> Result<u32, Error> {
 
     // read all returned messages at once
     let (raw_netlink_message, _) = socket.recv_from_full()?;
-    let recv_route_message =
-        <NetlinkMessage<RouteNetlinkMessage>>::deserialize(&raw_netlink_message)?;
+    let reply = <NetlinkMessage<RouteNetlinkMessage>>::deserialize(&raw_netlink_message)?;
+    match reply.payload {
+        NetlinkPayload::InnerMessage(message) => Ok(message),
+        NetlinkPayload::Error(err) => Err(err.to_io().into()),
+        payload => Err(Error::msg(format!(
+            "unexpected netlink reply: {:?}",
+            payload
+        ))),
+    }
+}
+
+/// Returns an network interface index for a Ipv4 address (like the command `ip route get to $IP`)
+pub fn if_index_for_routing_ip(ip_addr: Ipv4Addr) -> Result<u32, Error> {
+    // construct RouteMessage
+    let route_header = RouteHeader {
+        address_family: AddressFamily::Inet,
+        flags: RouteFlags::LookupTable,
+        destination_prefix_length: 32,
+        table: RouteHeader::RT_TABLE_MAIN,
+        ..Default::default()
+    };
+    let route_attribute = RouteAttribute::Destination(RouteAddress::Inet(ip_addr));
+    let mut route_message = RouteMessage::default();
+    route_message.attributes = vec![route_attribute];
+    route_message.header = route_header;
 
     // extract returned RouteNetLinkMessage
-    if let NetlinkPayload::InnerMessage(RouteNetlinkMessage::NewRoute(message)) =
-        recv_route_message.payload
+    if let RouteNetlinkMessage::NewRoute(message) =
+        netlink_request(RouteNetlinkMessage::GetRoute(route_message))?
     {
         if let Some(RouteAttribute::Oif(idex_if)) = message
             .attributes
@@ -74,3 +88,158 @@ pub fn if_index_for_routing_ip(ip_addr: Ipv4Addr) -> Result<u32, Error> {
     }
     Err(Error::msg(format!("{} {}", ERR_NO_IFINDEX, ip_addr)))
 }
+
+// Looks up a link (like the command `ip link show $NAME`), either by index or by name.
+fn get_link(index: u32, name: Option<&str>) -> Result<LinkMessage, Error> {
+    let mut link_message = LinkMessage::default();
+    link_message.header.index = index;
+    if let Some(name) = name {
+        link_message
+            .attributes
+            .push(LinkAttribute::IfName(name.to_string()));
+    }
+
+    match netlink_request(RouteNetlinkMessage::GetLink(link_message))? {
+        RouteNetlinkMessage::NewLink(message) => Ok(message),
+        _ => Err(Error::msg(format!(
+            "{} {}",
+            ERR_NO_LINK,
+            name.map_or(index.to_string(), str::to_string)
+        ))),
+    }
+}
+
+/// Returns the index of the network interface (like the command `ip link show $NAME`).
+pub fn if_index_for_name(iface: &str) -> Result<u32, Error> {
+    Ok(get_link(0, Some(iface))?.header.index)
+}
+
+fn link_name(link: &LinkMessage) -> Option<&str> {
+    link.attributes.iter().find_map(|attr| match attr {
+        LinkAttribute::IfName(name) => Some(name.as_str()),
+        _ => None,
+    })
+}
+
+fn link_kind(link: &LinkMessage) -> Option<&InfoKind> {
+    link.attributes.iter().find_map(|attr| match attr {
+        LinkAttribute::LinkInfo(infos) => infos.iter().find_map(|info| match info {
+            LinkInfo::Kind(kind) => Some(kind),
+            _ => None,
+        }),
+        _ => None,
+    })
+}
+
+/// Returns the name of the device the eBPF programs should be attached to for the provided
+/// interface. If the interface is a port of a bond or bridge, the addresses (and therefore VIP
+/// traffic) live on the bond or bridge, so that device is returned instead.
+pub fn attach_device_for(iface: &str) -> Result<String, Error> {
+    let link = get_link(0, Some(iface))?;
+    let controller_index = link.attributes.iter().find_map(|attr| match attr {
+        LinkAttribute::Controller(index) => Some(*index),
+        _ => None,
+    });
+
+    if let Some(controller_index) = controller_index {
+        let controller = get_link(controller_index, None)?;
+        if matches!(
+            link_kind(&controller),
+            Some(InfoKind::Bond) | Some(InfoKind::Bridge)
+        ) {
+            if let Some(name) = link_name(&controller) {
+                return Ok(name.to_string());
+            }
+        }
+    }
+
+    Ok(iface.to_string())
+}
+
+// Reads a value from the device using the SIOCETHTOOL ioctl (like the command `ethtool -k`).
+fn ethtool_value(iface: &str, cmd: u32) -> Result<u32, Error> {
+    #[repr(C)]
+    struct EthtoolValue {
+        cmd: u32,
+        data: u32,
+    }
+
+    if iface.len() >= libc::IFNAMSIZ {
+        return Err(Error::msg(format!("interface name {} is too long", iface)));
+    }
+
+    let mut value = EthtoolValue { cmd, data: 0 };
+    // SAFETY: ifreq is a plain C struct for which all zeroes is a valid value.
+    let mut ifr: libc::ifreq = unsafe { std::mem::zeroed() };
+    for (dst, src) in ifr.ifr_name.iter_mut().zip(iface.bytes()) {
+        *dst = src as libc::c_char;
+    }
+    ifr.ifr_ifru.ifru_data = &mut value as *mut EthtoolValue as *mut libc::c_char;
+
+    // SAFETY: the socket is only used for the ioctl and closed right after, and ifr points to
+    // value, which outlives the call.
+    unsafe {
+        let fd = libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0);
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let ret = libc::ioctl(fd, libc::SIOCETHTOOL as _, &mut ifr);
+        let err = std::io::Error::last_os_error();
+        libc::close(fd);
+        if ret < 0 {
+            return Err(err.into());
+        }
+    }
+
+    Ok(value.data)
+}
+
+/// Returns warnings for offload settings of the device that interfere with the eBPF programs:
+/// the packets merged by large receive offload can't be redirected to the backends. Checksum
+/// offloads don't, the programs rewrite checksums incrementally.
+pub fn offload_warnings(iface: &str) -> Vec<String> {
+    flags_warnings(iface, ethtool_value(iface, ETHTOOL_GFLAGS))
+}
+
+/// Returns the warnings of offload_warnings for the ethtool flags of the device, or the error
+/// reading them. Devices without offload flags, such as most virtual ones, have nothing to warn
+/// about.
+pub fn flags_warnings(iface: &str, flags: Result<u32, Error>) -> Vec<String> {
+    match flags {
+        Ok(flags) if flags & ETH_FLAG_LRO != 0 => vec![format!(
+            "large-receive-offload is enabled on {}, the packets it merges can't be load balanced (disable with `ethtool -K {} lro off`)",
+            iface, iface
+        )],
+        Ok(_) => Vec::new(),
+        Err(err)
+            if err
+                .downcast_ref::<std::io::Error>()
+                .and_then(std::io::Error::raw_os_error)
+                == Some(libc::EOPNOTSUPP) =>
+        {
+            Vec::new()
+        }
+        Err(err) => vec![format!(
+            "could not read offload settings of {}: {}",
+            iface, err
+        )],
+    }
+}
+
+// The backlog of the listening sockets of the API server.
+const LISTEN_BACKLOG: u32 = 1024;
+
+/// Binds a listening TCP socket to the address. With a device, the socket is also bound to it
+/// (SO_BINDTODEVICE), so that connections arriving through other interfaces of the node are
+/// refused even if they're addressed to the bound address.
+pub fn bind_listener(addr: SocketAddrV4, device: Option<&str>) -> Result<TcpListener, Error> {
+    let socket = TcpSocket::new_v4()?;
+    socket.set_reuseaddr(true)?;
+    if let Some(device) = device {
+        socket
+            .bind_device(Some(device.as_bytes()))
+            .map_err(|err| Error::msg(format!("failed to bind to device {}: {}", device, err)))?;
+    }
+    socket.bind(addr.into())?;
+    Ok(socket.listen(LISTEN_BACKLOG)?)
+}
@@ -0,0 +1,65 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+// The magic number of pcap files with nanosecond resolution timestamps.
+const PCAP_MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+const PCAP_VERSION_MAJOR: u16 = 2;
+const PCAP_VERSION_MINOR: u16 = 4;
+const LINKTYPE_ETHERNET: u32 = 1;
+
+/// A packet copied from the datapath.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CapturedPacket {
+    /// Nanoseconds since the UNIX epoch at which the packet was captured.
+    pub timestamp: u64,
+    /// The length of the packet, of which `data` may only be the start.
+    pub len: u32,
+    /// The captured bytes of the packet, starting with its ethernet header.
+    pub data: Vec<u8>,
+}
+
+/// The length of the header of pcap files.
+pub const PCAP_HEADER_LEN: usize = 24;
+/// The length of the header of each packet of pcap files.
+pub const PCAP_RECORD_HEADER_LEN: usize = 16;
+
+/// Writes the packets as a pcap file that can be opened in Wireshark or tcpdump.
+pub fn write_pcap(packets: &[CapturedPacket], snaplen: u32) -> Vec<u8> {
+    let size = PCAP_HEADER_LEN
+        + packets
+            .iter()
+            .map(|packet| PCAP_RECORD_HEADER_LEN + packet.data.len())
+            .sum::<usize>();
+    let mut pcap = Vec::with_capacity(size);
+    write_pcap_header(&mut pcap, snaplen);
+    for packet in packets {
+        write_pcap_record(&mut pcap, packet);
+    }
+    pcap
+}
+
+/// Writes the header of a pcap file, which its packets follow, for streaming packets as they're
+/// captured.
+pub fn write_pcap_header(pcap: &mut Vec<u8>, snaplen: u32) {
+    pcap.extend_from_slice(&PCAP_MAGIC_NANOS.to_le_bytes());
+    pcap.extend_from_slice(&PCAP_VERSION_MAJOR.to_le_bytes());
+    pcap.extend_from_slice(&PCAP_VERSION_MINOR.to_le_bytes());
+    // the timezone offset and timestamp accuracy, both always 0
+    pcap.extend_from_slice(&[0; 8]);
+    pcap.extend_from_slice(&snaplen.to_le_bytes());
+    pcap.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+}
+
+/// Writes a packet of a pcap file.
+pub fn write_pcap_record(pcap: &mut Vec<u8>, packet: &CapturedPacket) {
+    let seconds = (packet.timestamp / 1_000_000_000) as u32;
+    let nanos = (packet.timestamp % 1_000_000_000) as u32;
+    pcap.extend_from_slice(&seconds.to_le_bytes());
+    pcap.extend_from_slice(&nanos.to_le_bytes());
+    pcap.extend_from_slice(&(packet.data.len() as u32).to_le_bytes());
+    pcap.extend_from_slice(&packet.len.to_le_bytes());
+    pcap.extend_from_slice(&packet.data);
+}
@@ -0,0 +1,202 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+use std::net::Ipv4Addr;
+use std::os::fd::{AsFd, AsRawFd};
+
+use anyhow::Error;
+use common::sctp::{crc32c, CHUNK_INIT};
+
+use crate::backends::Protocol;
+
+/// The source address of synthetic packets when the caller doesn't provide one
+/// (TEST-NET-1, see RFC 5737).
+pub const DEFAULT_CLIENT_IP: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+/// The source port of synthetic packets when the caller doesn't provide one.
+pub const DEFAULT_CLIENT_PORT: u16 = 40000;
+
+const ETH_HDR_LEN: usize = 14;
+const IPV4_HDR_LEN: usize = 20;
+const TCP_HDR_LEN: usize = 20;
+const UDP_HDR_LEN: usize = 8;
+// the common header of SCTP packets, followed by an INIT chunk without parameters
+const SCTP_HDR_LEN: usize = 12;
+const SCTP_INIT_LEN: usize = 20;
+
+const ETH_P_IP: u16 = 0x0800;
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+const IPPROTO_SCTP: u8 = 132;
+const TCP_FLAG_SYN: u8 = 0x02;
+
+// see include/uapi/linux/bpf.h
+const BPF_PROG_TEST_RUN: libc::c_long = 10;
+
+/// The result of running a packet through an eBPF program with bpf_prog_test_run.
+#[derive(Debug)]
+pub struct TestRunOutput {
+    /// The value the program returned (the TC action).
+    pub retval: u32,
+    /// The packet after the program ran.
+    pub data: Vec<u8>,
+}
+
+/// Builds an ethernet frame carrying an IPv4 TCP SYN, UDP packet or SCTP INIT from the client to
+/// the destination, as the ingress program would receive it.
+pub fn build_packet(
+    protocol: Protocol,
+    client: (Ipv4Addr, u16),
+    destination: (Ipv4Addr, u16),
+) -> Vec<u8> {
+    let (proto, l4_len) = match protocol {
+        Protocol::Tcp => (IPPROTO_TCP, TCP_HDR_LEN),
+        Protocol::Udp => (IPPROTO_UDP, UDP_HDR_LEN),
+        Protocol::Sctp => (IPPROTO_SCTP, SCTP_HDR_LEN + SCTP_INIT_LEN),
+    };
+    let mut packet = vec![0u8; ETH_HDR_LEN + IPV4_HDR_LEN + l4_len];
+
+    // ethernet: the MAC addresses are irrelevant to the datapath and left zeroed
+    packet[12..14].copy_from_slice(&ETH_P_IP.to_be_bytes());
+
+    let ip = &mut packet[ETH_HDR_LEN..ETH_HDR_LEN + IPV4_HDR_LEN];
+    ip[0] = 0x45; // version 4, 5 words header
+    ip[2..4].copy_from_slice(&((IPV4_HDR_LEN + l4_len) as u16).to_be_bytes());
+    ip[8] = 64; // ttl
+    ip[9] = proto;
+    ip[12..16].copy_from_slice(&client.0.octets());
+    ip[16..20].copy_from_slice(&destination.0.octets());
+    let checksum = ipv4_checksum(ip);
+    ip[10..12].copy_from_slice(&checksum.to_be_bytes());
+
+    let l4 = &mut packet[ETH_HDR_LEN + IPV4_HDR_LEN..];
+    l4[0..2].copy_from_slice(&client.1.to_be_bytes());
+    l4[2..4].copy_from_slice(&destination.1.to_be_bytes());
+    match protocol {
+        Protocol::Tcp => {
+            l4[12] = ((TCP_HDR_LEN / 4) as u8) << 4;
+            l4[13] = TCP_FLAG_SYN;
+            l4[14..16].copy_from_slice(&u16::MAX.to_be_bytes()); // window
+        }
+        // a zero UDP checksum means no checksum
+        Protocol::Udp => l4[4..6].copy_from_slice(&(UDP_HDR_LEN as u16).to_be_bytes()),
+        Protocol::Sctp => {
+            // the verification tag of an INIT is 0, the initiate tag and TSN aren't
+            let init = &mut l4[SCTP_HDR_LEN..];
+            init[0] = CHUNK_INIT;
+            init[2..4].copy_from_slice(&(SCTP_INIT_LEN as u16).to_be_bytes());
+            init[4..8].copy_from_slice(&1u32.to_be_bytes()); // initiate tag
+            init[8..12].copy_from_slice(&65535u32.to_be_bytes()); // a_rwnd
+            init[12..14].copy_from_slice(&1u16.to_be_bytes()); // outbound streams
+            init[14..16].copy_from_slice(&1u16.to_be_bytes()); // inbound streams
+            init[16..20].copy_from_slice(&1u32.to_be_bytes()); // initial TSN
+            let checksum = crc32c(l4);
+            l4[8..12].copy_from_slice(&checksum.to_le_bytes());
+        }
+    }
+
+    packet
+}
+
+/// Sets the type of service byte (DSCP and ECN) of a packet built by build_packet, updating the
+/// IPv4 header checksum.
+pub fn set_tos(packet: &mut [u8], tos: u8) {
+    let ip = &mut packet[ETH_HDR_LEN..ETH_HDR_LEN + IPV4_HDR_LEN];
+    ip[1] = tos;
+    ip[10..12].copy_from_slice(&[0, 0]);
+    let checksum = ipv4_checksum(ip);
+    ip[10..12].copy_from_slice(&checksum.to_be_bytes());
+}
+
+/// Returns the type of service byte of a packet built by build_packet, or None if the packet
+/// is too short.
+pub fn packet_tos(packet: &[u8]) -> Option<u8> {
+    packet.get(ETH_HDR_LEN + 1).copied()
+}
+
+/// Returns true if the IPv4 header checksum of a packet built by build_packet is valid.
+pub fn ipv4_checksum_valid(packet: &[u8]) -> bool {
+    packet
+        .get(ETH_HDR_LEN..ETH_HDR_LEN + IPV4_HDR_LEN)
+        .is_some_and(|ip| ipv4_checksum(ip) == 0)
+}
+
+/// Returns the destination address and port of a packet built by build_packet (after it may
+/// have been rewritten), or None if the packet is too short.
+pub fn packet_destination(packet: &[u8]) -> Option<(Ipv4Addr, u16)> {
+    let ip = packet.get(ETH_HDR_LEN..ETH_HDR_LEN + IPV4_HDR_LEN)?;
+    let l4 = packet.get(ETH_HDR_LEN + IPV4_HDR_LEN..ETH_HDR_LEN + IPV4_HDR_LEN + 4)?;
+    let daddr = Ipv4Addr::new(ip[16], ip[17], ip[18], ip[19]);
+    let dport = u16::from_be_bytes([l4[2], l4[3]]);
+    Some((daddr, dport))
+}
+
+fn ipv4_checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = header
+        .chunks(2)
+        .map(|word| u32::from(u16::from_be_bytes([word[0], word[1]])))
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Runs the packet through the program once with bpf_prog_test_run. Packets aren't actually
+/// sent anywhere, but the program's map updates do take effect.
+pub fn test_run(program: impl AsFd, packet: &[u8]) -> Result<TestRunOutput, Error> {
+    // the test member of union bpf_attr
+    #[repr(C)]
+    #[derive(Default)]
+    struct TestRunAttr {
+        prog_fd: u32,
+        retval: u32,
+        data_size_in: u32,
+        data_size_out: u32,
+        data_in: u64,
+        data_out: u64,
+        repeat: u32,
+        duration: u32,
+        ctx_size_in: u32,
+        ctx_size_out: u32,
+        ctx_in: u64,
+        ctx_out: u64,
+        flags: u32,
+        cpu: u32,
+        batch_size: u32,
+        _pad: u32,
+    }
+
+    // leave room for programs that grow the packet
+    let mut data_out = vec![0u8; packet.len() + 256];
+    let mut attr = TestRunAttr {
+        prog_fd: program.as_fd().as_raw_fd() as u32,
+        data_size_in: packet.len() as u32,
+        data_size_out: data_out.len() as u32,
+        data_in: packet.as_ptr() as u64,
+        data_out: data_out.as_mut_ptr() as u64,
+        repeat: 1,
+        ..Default::default()
+    };
+
+    // SAFETY: attr is a valid bpf_attr prefix and the buffers it points to outlive the call.
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_bpf,
+            BPF_PROG_TEST_RUN,
+            &mut attr as *mut TestRunAttr,
+            std::mem::size_of::<TestRunAttr>(),
+        )
+    };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    data_out.truncate(attr.data_size_out as usize);
+    Ok(TestRunOutput {
+        retval: attr.retval,
+        data: data_out,
+    })
+}
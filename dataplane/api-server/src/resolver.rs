@@ -0,0 +1,59 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! Resolution of the hostnames of targets into their addresses.
+
+use std::net::SocketAddr;
+
+use anyhow::{anyhow, Error};
+
+use crate::backends::{Target, Targets};
+
+/// Looks up the addresses of hostnames.
+#[tonic::async_trait]
+pub trait Resolver: Send + Sync {
+    /// Returns the addresses of the hostname, with the port.
+    async fn lookup(&self, hostname: &str, port: u16) -> std::io::Result<Vec<SocketAddr>>;
+}
+
+/// Looks up hostnames with the resolver of the system.
+pub struct SystemResolver;
+
+#[tonic::async_trait]
+impl Resolver for SystemResolver {
+    async fn lookup(&self, hostname: &str, port: u16) -> std::io::Result<Vec<SocketAddr>> {
+        Ok(tokio::net::lookup_host((hostname, port)).await?.collect())
+    }
+}
+
+/// Resolves the hostname of a target, if it has one, into its first IPv4 address.
+pub async fn resolve_target(resolver: &dyn Resolver, target: &Target) -> Result<Target, Error> {
+    if target.hostname.is_empty() {
+        return Ok(target.clone());
+    }
+    let daddr = resolver
+        .lookup(&target.hostname, target.dport as u16)
+        .await?
+        .into_iter()
+        .find_map(|addr| match addr {
+            SocketAddr::V4(addr) => Some(*addr.ip()),
+            SocketAddr::V6(_) => None,
+        })
+        .ok_or_else(|| anyhow!("no IPv4 address found for {}", target.hostname))?;
+    Ok(Target {
+        daddr: daddr.into(),
+        ..target.clone()
+    })
+}
+
+/// Returns true if any of the targets, or the mirror, is specified by hostname.
+pub fn has_hostnames(targets: &Targets) -> bool {
+    targets
+        .targets
+        .iter()
+        .chain(targets.mirror.as_ref())
+        .any(|target| !target.hostname.is_empty())
+}
@@ -0,0 +1,199 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! Scans of large eBPF maps, such as LB_CONNECTIONS, in chunks.
+//!
+//! The maps are behind the locks every RPC that uses them takes, so scanning all of a map at
+//! once would hold those RPCs back for as long as the scan takes. The scans here lock the map
+//! for a chunk of entries at a time instead, resuming from the last key of the previous chunk.
+
+use std::collections::BTreeMap;
+use std::os::fd::{AsFd, AsRawFd};
+
+use anyhow::Error;
+use aya::maps::{HashMap, IterableMap, MapData, MapError};
+use aya::Pod;
+use tokio::sync::Mutex;
+
+// see include/uapi/linux/bpf.h
+const BPF_MAP_GET_NEXT_KEY: libc::c_long = 4;
+
+/// Entries of a map, with their keys.
+pub type Entries<K, V> = Vec<(K, V)>;
+
+/// A map whose entries can be read in chunks, in the order of its keys.
+pub trait ChunkedMap {
+    type Key: Copy;
+    type Value;
+
+    /// Returns up to `max` entries, those following `after` or the first ones when it's None.
+    fn entries_after(
+        &self,
+        after: Option<&Self::Key>,
+        max: usize,
+    ) -> Result<Entries<Self::Key, Self::Value>, Error>;
+
+    /// Removes the entry of the key, if it's still there.
+    fn remove_key(&mut self, key: &Self::Key) -> Result<(), Error>;
+}
+
+impl<K: Pod, V: Pod> ChunkedMap for HashMap<MapData, K, V> {
+    type Key = K;
+    type Value = V;
+
+    fn entries_after(&self, after: Option<&K>, max: usize) -> Result<Entries<K, V>, Error> {
+        let mut entries = Vec::with_capacity(max);
+        let mut key = after.copied();
+        while entries.len() < max {
+            let Some(next) = next_key(self.map(), key.as_ref())? else {
+                break;
+            };
+            key = Some(next);
+            // the entry may be removed by the eBPF programs in the meantime
+            match IterableMap::get(self, &next) {
+                Ok(value) => entries.push((next, value)),
+                Err(MapError::KeyNotFound) => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(entries)
+    }
+
+    fn remove_key(&mut self, key: &K) -> Result<(), Error> {
+        match self.remove(key) {
+            Ok(()) | Err(MapError::KeyNotFound) => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+impl<K: Ord + Copy, V: Clone> ChunkedMap for BTreeMap<K, V> {
+    type Key = K;
+    type Value = V;
+
+    fn entries_after(&self, after: Option<&K>, max: usize) -> Result<Entries<K, V>, Error> {
+        let entries = match after {
+            Some(after) => {
+                self.range((std::ops::Bound::Excluded(after), std::ops::Bound::Unbounded))
+            }
+            None => self.range(..),
+        };
+        Ok(entries
+            .take(max)
+            .map(|(key, value)| (*key, value.clone()))
+            .collect())
+    }
+
+    fn remove_key(&mut self, key: &K) -> Result<(), Error> {
+        self.remove(key);
+        Ok(())
+    }
+}
+
+/// The result of remove_stale.
+#[derive(Debug)]
+pub struct Removed<K, V> {
+    /// The entries that were removed.
+    pub entries: Entries<K, V>,
+    /// Whether every entry of the map was checked, rather than the scan stopping at the limit.
+    pub complete: bool,
+}
+
+/// Removes up to `limit` entries of the map that `is_stale` selects, locking the map for at most
+/// `chunk_size` entries at a time so that other users of the map take turns with the scan.
+///
+/// The next key of a key that's no longer in a hash map is its first key, so the last key of a
+/// chunk is only removed once the next chunk was read from it. When the eBPF programs remove it
+/// in the meantime the scan starts over, which is why it visits at most `max_visits` entries,
+/// and isn't complete when it stops there.
+pub async fn remove_stale<M: ChunkedMap>(
+    map: &Mutex<M>,
+    chunk_size: usize,
+    limit: usize,
+    max_visits: usize,
+    mut is_stale: impl FnMut(&M::Key, &M::Value) -> bool,
+) -> Result<Removed<M::Key, M::Value>, Error> {
+    let mut entries = Vec::new();
+    let mut cursor: Option<M::Key> = None;
+    // the stale last key of the previous chunk, removed once the next chunk was read
+    let mut deferred: Option<M::Key> = None;
+    let mut visits = 0;
+    let complete = loop {
+        let mut guard = map.lock().await;
+        let chunk = guard.entries_after(cursor.as_ref(), chunk_size)?;
+        if let Some(key) = deferred.take() {
+            guard.remove_key(&key)?;
+        }
+        let last = chunk.len().saturating_sub(1);
+        let done = chunk.len() < chunk_size;
+        cursor = chunk.last().map(|(key, _)| *key);
+        for (i, (key, value)) in chunk.into_iter().enumerate() {
+            visits += 1;
+            if entries.len() == limit || !is_stale(&key, &value) {
+                continue;
+            }
+            if i == last && !done {
+                deferred = Some(key);
+            } else {
+                guard.remove_key(&key)?;
+            }
+            entries.push((key, value));
+        }
+        drop(guard);
+
+        if done {
+            break true;
+        }
+        if entries.len() == limit || visits >= max_visits {
+            break false;
+        }
+        tokio::task::yield_now().await;
+    };
+    if let Some(key) = deferred {
+        map.lock().await.remove_key(&key)?;
+    }
+    Ok(Removed { entries, complete })
+}
+
+// Returns the key following `key` in the map, or its first key when `key` is None or no longer in
+// it. None once there are no more keys.
+fn next_key<K: Pod>(map: &MapData, key: Option<&K>) -> Result<Option<K>, Error> {
+    // the map element members of union bpf_attr
+    #[repr(C)]
+    struct NextKeyAttr {
+        map_fd: u32,
+        _pad: u32,
+        key: u64,
+        next_key: u64,
+    }
+
+    let mut next = std::mem::MaybeUninit::<K>::uninit();
+    let mut attr = NextKeyAttr {
+        map_fd: map.fd().as_fd().as_raw_fd() as u32,
+        _pad: 0,
+        key: key.map_or(0, |key| key as *const K as u64),
+        next_key: next.as_mut_ptr() as u64,
+    };
+
+    // SAFETY: attr is a valid bpf_attr prefix and the keys it points to outlive the call.
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_bpf,
+            BPF_MAP_GET_NEXT_KEY,
+            &mut attr as *mut NextKeyAttr,
+            std::mem::size_of::<NextKeyAttr>(),
+        )
+    };
+    if ret < 0 {
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::ENOENT) {
+            return Ok(None);
+        }
+        return Err(err.into());
+    }
+    // SAFETY: the kernel wrote the next key on success.
+    Ok(Some(unsafe { next.assume_init() }))
+}
@@ -4,195 +4,2818 @@ Copyright 2023 The Kubernetes Authors.
 SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
 */
 
+use std::collections::{HashMap as StdHashMap, HashSet, VecDeque};
+use std::fmt::Display;
 use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use anyhow::Error;
-use aya::maps::{HashMap, MapData, MapError};
-use tokio::sync::Mutex;
-use tonic::{Request, Response, Status};
+use anyhow::{anyhow, Error};
+use aya::maps::{
+    lpm_trie::Key, perf::AsyncPerfEventArray, Array, HashMap, LpmTrie, MapData, MapError,
+    PerCpuArray, PerCpuHashMap, RingBuf,
+};
+use aya::programs::{tc::SchedClassifierLink, Link, ProgramFd};
+use aya::util::online_cpus;
+use bytes::BytesMut;
+use log::{debug, info, warn};
+use tokio::io::unix::AsyncFd;
+use tokio::sync::{oneshot, Mutex};
+use tonic::{metadata::MetadataMap, Code, Request, Response, Status};
 
 use crate::backends::backends_server::Backends;
-use crate::backends::{Confirmation, InterfaceIndexConfirmation, PodIp, Targets, Vip};
+use crate::backends::{
+    Acl, AclMode, ActivateRequest, CaptureRequest, CaptureStage, CapturedPacketInfo, Cidr,
+    CollectFlowsRequest, Confirmation, ConsistencyCheck, ConsistencyReport, DataplaneInfo,
+    DataplaneInfoRequest, DrainRequest, DrainStatus, DropPolicy, DropStats, DropStatsRequest,
+    FlowRecord, FlowRecords, Gateway, GatewayIndex, InterfaceIndexConfirmation, LatencyBucket,
+    LatencyHistogram, ListRequest, LoadBalancing, LogLevel, PacketCapture, PodIp, ProbeRequest,
+    ProbeResult, Program, Protocol, RateLimit, RemovedBackendPolicy, RuntimeConfig, Target,
+    TargetRef, Targets, TargetsList, Vip, VipPair, VipStats, VipStatsList, VipStatsRequest,
+};
 use crate::netutils::if_index_for_routing_ip;
+use crate::pcap::{write_pcap, CapturedPacket};
+use crate::probe;
+use crate::resolver::{has_hostnames, resolve_target, Resolver, SystemResolver};
+use crate::scan;
 use common::{
-    Backend, BackendKey, BackendList, ClientKey, LoadBalancerMapping, BACKENDS_ARRAY_CAPACITY,
+    acl,
+    drain::{is_draining, set_draining},
+    dscp::DSCP_MAX,
+    latency::latency_bucket_bound,
+    maglev,
+    mss::TCP_MIN_MSS,
+    port_range,
+    slots::overflow_slots,
+    AclKey, Affinity, AffinityKey, Backend, BackendKey, BackendList, BackendSlot, BackendSlotKey,
+    CaptureConfig, CaptureHeader, ClientKey, ErrorCode, LoadBalancerMapping, NamespacedName,
+    PortRangeKey, ACL_ACTION_ALLOW, ACL_ACTION_DENY, BACKENDS_ARRAY_CAPACITY, CAPTURE_MAX_SNAPLEN,
+    CAPTURE_STAGE_REWRITTEN, DRAINING_WORDS, DROP_POLICY_DROP, DROP_POLICY_PASS,
+    DROP_POLICY_REJECT, DROP_STATS_ACL_DENIED, DROP_STATS_BACKEND_OUT_OF_RANGE,
+    DROP_STATS_NO_BACKENDS, DROP_STATS_NO_ROUTE, DROP_STATS_RATE_LIMITED,
+    DROP_STATS_REDIRECT_FAILED, DROP_STATS_REWRITE_FAILED, FEATURE_FLOW_RECORDS, FEATURE_VIP_STATS,
+    IPPROTO_SCTP, IPPROTO_TCP, IPPROTO_UDP, LOG_LEVEL_DEBUG, LOG_LEVEL_INFO, LOG_LEVEL_OFF,
+    MAGLEV_TABLE_SIZE, MAX_ACL_CIDRS, QUIC_MAX_CID_LEN, STATE_DRAINING, STATE_DROP_POLICY,
+    STATE_FEATURES, STATE_LOG_LEVEL, STATE_LOG_SAMPLE_RATE, STATE_SNAT_IP, STATE_STANDBY,
+    STATE_TCP_IDLE_TIMEOUT, STATE_TCP_MSS_CLAMP, STATE_UDP_IDLE_TIMEOUT,
 };
 
+/// The gRPC metadata key clients use to identify the Gateway (as `namespace/name`) that a
+/// request is made on behalf of.
+pub const GATEWAY_METADATA_KEY: &str = "blixt-gateway";
+
+/// The gRPC metadata key under which failed responses carry their blixt error code
+/// (e.g. `BLX-1001`).
+pub const ERROR_CODE_METADATA_KEY: &str = "blixt-error-code";
+
+// Builds a Status whose message is prefixed with the error code and whose metadata carries it.
+fn error_status(code: Code, error_code: ErrorCode, message: impl Display) -> Status {
+    let mut metadata = MetadataMap::new();
+    if let Ok(value) = error_code.to_string().parse() {
+        metadata.insert(ERROR_CODE_METADATA_KEY, value);
+    }
+    Status::with_metadata(code, format!("{}: {}", error_code, message), metadata)
+}
+
+// Returns the Gateway a request was made on behalf of, if the client provided a valid one.
+fn gateway_from_metadata<T>(request: &Request<T>) -> Option<NamespacedName> {
+    request
+        .metadata()
+        .get(GATEWAY_METADATA_KEY)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+}
+
+// Converts a target into a Backend. Backends without an interface are routed by the dataplane,
+// which looks up the route to them for every packet.
+fn backend_for_target(target: &Target) -> Backend {
+    Backend {
+        daddr: target.daddr,
+        dport: target.dport,
+        ifindex: target.ifindex.unwrap_or(0) as u16,
+    }
+}
+
+// Returns the interface of the backend, if it was provided rather than routed.
+fn ifindex_of(backend: &Backend) -> Option<u32> {
+    (backend.ifindex != 0).then_some(backend.ifindex as u32)
+}
+
+// Ensures that a request made on behalf of the provided Gateway may modify the VIP. A VIP owned by
+// a Gateway may only be modified by requests of that Gateway, while VIPs without an owner may be
+// modified by any request, including those that don't identify a Gateway.
+fn check_owner(
+    vip_owners: &StdHashMap<BackendKey, NamespacedName>,
+    key: &BackendKey,
+    gateway: &Option<NamespacedName>,
+) -> Result<(), Status> {
+    let Some(owner) = vip_owners.get(key) else {
+        return Ok(());
+    };
+    match gateway {
+        Some(gateway) if gateway == owner => Ok(()),
+        Some(gateway) => Err(error_status(
+            Code::PermissionDenied,
+            ErrorCode::GatewayOwnershipConflict,
+            format!(
+                "vip {}:{} is owned by gateway {}, not {}",
+                Ipv4Addr::from(key.ip),
+                key.port,
+                owner,
+                gateway
+            ),
+        )),
+        None => Err(error_status(
+            Code::PermissionDenied,
+            ErrorCode::GatewayOwnershipConflict,
+            format!(
+                "vip {}:{} is owned by gateway {}, requests must identify it with the {} \
+                 metadata",
+                Ipv4Addr::from(key.ip),
+                key.port,
+                owner,
+                GATEWAY_METADATA_KEY
+            ),
+        )),
+    }
+}
+
+// Returns the Maglev lookup table of the backends that aren't draining, whose entries are
+// indexes into all of the backends.
+fn maglev_table(
+    backends: &[Backend],
+    draining: &[u64; DRAINING_WORDS],
+) -> [u16; MAGLEV_TABLE_SIZE] {
+    let undrained: Vec<u16> = (0..backends.len() as u16)
+        .filter(|index| !is_draining(draining, *index))
+        .collect();
+    let selected: Vec<Backend> = undrained
+        .iter()
+        .map(|index| backends[*index as usize])
+        .collect();
+    maglev::populate(&selected).map(|entry| undrained.get(entry as usize).copied().unwrap_or(0))
+}
+
+// Returns the number of draining backends of the vip.
+fn draining_len(list: &BackendList) -> u16 {
+    (0..list.backends_len)
+        .filter(|index| is_draining(&list.draining, *index))
+        .count() as u16
+}
+
+// The backends of a vip: its BACKENDS entry, which holds the first BACKENDS_ARRAY_CAPACITY of
+// them, and the BACKEND_SLOTS entries holding the others.
+#[derive(Clone, Debug, PartialEq)]
+struct VipBackends {
+    list: BackendList,
+    // the entry of slot n is at index n - 1
+    slots: Vec<BackendSlot>,
+}
+
+impl VipBackends {
+    // Returns the backends of the vip, in order.
+    fn backends(&self) -> impl Iterator<Item = &Backend> {
+        self.list
+            .backends
+            .iter()
+            .chain(self.slots.iter().flat_map(|slot| slot.backends.iter()))
+            .take(self.list.backends_len as usize)
+    }
+}
+
+// Returns the backends of the vip.
+fn read_backends(
+    backends_map: &HashMap<MapData, BackendKey, BackendList>,
+    backend_slots_map: &HashMap<MapData, BackendSlotKey, BackendSlot>,
+    key: BackendKey,
+) -> Result<VipBackends, Error> {
+    let list = backends_map.get(&key, 0)?;
+    let slots = (1..=overflow_slots(list.backends_len))
+        .map(|slot| {
+            backend_slots_map.get(
+                &BackendSlotKey {
+                    backend_key: key,
+                    slot,
+                },
+                0,
+            )
+        })
+        .collect::<Result<_, MapError>>()?;
+    Ok(VipBackends { list, slots })
+}
+
+// Writes the backends of the vip. The slots are written before the BACKENDS entry that refers to
+// them, and those it no longer refers to are removed after it.
+fn write_backends(
+    backends_map: &mut HashMap<MapData, BackendKey, BackendList>,
+    backend_slots_map: &mut HashMap<MapData, BackendSlotKey, BackendSlot>,
+    key: BackendKey,
+    bks: &VipBackends,
+) -> Result<(), Error> {
+    let previous_slots = backends_map
+        .get(&key, 0)
+        .map_or(0, |list| overflow_slots(list.backends_len));
+
+    for (i, slot) in bks.slots.iter().enumerate() {
+        let slot_key = BackendSlotKey {
+            backend_key: key,
+            slot: i as u32 + 1,
+        };
+        backend_slots_map.insert(slot_key, slot, 0)?;
+    }
+    backends_map.insert(key, bks.list, 0)?;
+    for slot in bks.slots.len() as u32 + 1..=previous_slots {
+        backend_slots_map.remove(&BackendSlotKey {
+            backend_key: key,
+            slot,
+        })?;
+    }
+    Ok(())
+}
+
+// Builds the BACKENDS and BACKEND_SLOTS entries for the targets, resolving their hostnames.
+async fn backend_list_for(
+    resolver: &dyn Resolver,
+    targets: &Targets,
+    max_backends: usize,
+) -> Result<VipBackends, Status> {
+    let resolve = |target| async move {
+        let target = resolve_target(resolver, target).await.map_err(|err| {
+            error_status(
+                Code::Unavailable,
+                ErrorCode::HostnameResolutionFailed,
+                format!("failed to resolve {}: {}", target.hostname, err),
+            )
+        })?;
+        Ok(backend_for_target(&target))
+    };
+
+    if targets.targets.len() > max_backends {
+        return Err(error_status(
+            Code::ResourceExhausted,
+            ErrorCode::BackendsCapacityExceeded,
+            format!(
+                "BPF map value capacity exceeded, only {} backends supported per vip but {} were requested",
+                max_backends,
+                targets.targets.len()
+            ),
+        ));
+    }
+
+    if targets.quic_connection_id_length as usize > QUIC_MAX_CID_LEN {
+        return Err(error_status(
+            Code::InvalidArgument,
+            ErrorCode::QuicConnectionIdTooLong,
+            format!(
+                "QUIC connection IDs are at most {} bytes long but {} were requested",
+                QUIC_MAX_CID_LEN, targets.quic_connection_id_length
+            ),
+        ));
+    }
+
+    if targets.proxy_protocol
+        && targets
+            .vip
+            .as_ref()
+            .is_some_and(|vip| vip.protocol() != Protocol::Tcp)
+    {
+        return Err(error_status(
+            Code::InvalidArgument,
+            ErrorCode::ProxyProtocolNotSupported,
+            "the PROXY protocol is only supported by TCP vips",
+        ));
+    }
+
+    if (targets.full_nat || targets.mirror.is_some())
+        && targets
+            .vip
+            .as_ref()
+            .is_some_and(|vip| vip.protocol() == Protocol::Sctp)
+    {
+        return Err(error_status(
+            Code::InvalidArgument,
+            ErrorCode::SctpNotSupported,
+            "full NAT and mirrors aren't supported by SCTP vips",
+        ));
+    }
+
+    if let Some(dscp) = targets.dscp.filter(|dscp| *dscp > DSCP_MAX as u32) {
+        return Err(error_status(
+            Code::InvalidArgument,
+            ErrorCode::InvalidDscp,
+            format!("DSCPs are at most {} but {} was requested", DSCP_MAX, dscp),
+        ));
+    }
+
+    let mut backends = Vec::with_capacity(targets.targets.len());
+    for backend_target in &targets.targets {
+        backends.push(resolve(backend_target).await?);
+    }
+
+    let mirror = match &targets.mirror {
+        Some(mirror) => Some(resolve(mirror).await?),
+        None => None,
+    };
+
+    let mut draining = [0; DRAINING_WORDS];
+    for (index, target) in targets.targets.iter().enumerate() {
+        set_draining(&mut draining, index as u16, target.draining);
+    }
+
+    let maglev = match targets.load_balancing() {
+        LoadBalancing::RoundRobin => None,
+        LoadBalancing::Maglev => Some(maglev_table(&backends, &draining)),
+    };
+
+    let rate_limit = targets
+        .rate_limit
+        .as_ref()
+        .filter(|limit| limit.packets_per_second > 0)
+        .map(|limit| common::RateLimit {
+            packets_per_second: limit.packets_per_second,
+            burst: if limit.burst > 0 {
+                limit.burst
+            } else {
+                limit.packets_per_second
+            },
+        });
+
+    let to_array = |chunk: &[Backend]| {
+        let mut array = [Backend::default(); BACKENDS_ARRAY_CAPACITY];
+        array[..chunk.len()].copy_from_slice(chunk);
+        array
+    };
+    let mut chunks = backends.chunks(BACKENDS_ARRAY_CAPACITY);
+    let first = chunks
+        .next()
+        .map_or([Backend::default(); BACKENDS_ARRAY_CAPACITY], to_array);
+    let slots = chunks
+        .map(|chunk| BackendSlot {
+            backends: to_array(chunk),
+        })
+        .collect();
+
+    Ok(VipBackends {
+        list: BackendList {
+            backends: first,
+            backends_len: backends.len() as u16,
+            mirror: mirror.unwrap_or_default(),
+            has_mirror: mirror.is_some() as u8,
+            maglev: maglev.unwrap_or([0; MAGLEV_TABLE_SIZE]),
+            has_maglev: maglev.is_some() as u8,
+            affinity_timeout: targets.affinity_timeout_seconds,
+            full_nat: targets.full_nat as u8,
+            proxy_protocol: targets.proxy_protocol as u8,
+            quic_cid_len: targets.quic_connection_id_length as u8,
+            rate_limit: rate_limit.unwrap_or_default(),
+            has_rate_limit: rate_limit.is_some() as u8,
+            dscp: targets.dscp.unwrap_or_default() as u8,
+            has_dscp: targets.dscp.is_some() as u8,
+            draining,
+            draining_len: targets
+                .targets
+                .iter()
+                .filter(|target| target.draining)
+                .count() as u16,
+        },
+        slots,
+    })
+}
+
+// Sums up the latency histograms of a vip recorded on each CPU, with cumulative buckets.
+fn latency_histogram<'a>(
+    per_cpu: impl Iterator<Item = &'a common::latency::LatencyHistogram>,
+) -> LatencyHistogram {
+    let mut histogram = common::latency::LatencyHistogram::default();
+    for cpu in per_cpu {
+        histogram.merge(cpu);
+    }
+    let mut cumulative = 0;
+    LatencyHistogram {
+        buckets: histogram
+            .buckets
+            .iter()
+            .enumerate()
+            .map(|(bucket, count)| {
+                cumulative += count;
+                LatencyBucket {
+                    le_microseconds: latency_bucket_bound(bucket),
+                    count: cumulative,
+                }
+            })
+            .collect(),
+        count: histogram.count(),
+        sum_nanoseconds: histogram.sum_ns,
+    }
+}
+
+// Returns the key of the vip in the BPF maps.
+fn backend_key_for(vip: &Vip) -> BackendKey {
+    BackendKey {
+        ip: vip.ip,
+        port: vip.port,
+        port_end: vip.port_end,
+        proto: match vip.protocol() {
+            Protocol::Tcp => IPPROTO_TCP,
+            Protocol::Udp => IPPROTO_UDP,
+            Protocol::Sctp => IPPROTO_SCTP,
+        },
+    }
+}
+
+// Returns the vip of a key of the BPF maps.
+fn vip_for(key: &BackendKey) -> Vip {
+    let protocol = match key.proto {
+        IPPROTO_UDP => Protocol::Udp,
+        IPPROTO_SCTP => Protocol::Sctp,
+        _ => Protocol::Tcp,
+    };
+    Vip {
+        ip: key.ip,
+        port: key.port,
+        protocol: protocol as i32,
+        port_end: key.port_end,
+    }
+}
+
+// Returns the DATAPLANE_STATE value of the log level, and the log level of a value.
+fn log_level_value(level: LogLevel) -> u32 {
+    match level {
+        LogLevel::Off => LOG_LEVEL_OFF,
+        LogLevel::Info => LOG_LEVEL_INFO,
+        LogLevel::Debug => LOG_LEVEL_DEBUG,
+    }
+}
+
+fn log_level_of(value: u32) -> LogLevel {
+    match value {
+        LOG_LEVEL_OFF => LogLevel::Off,
+        LOG_LEVEL_DEBUG => LogLevel::Debug,
+        _ => LogLevel::Info,
+    }
+}
+
+// Returns the DATAPLANE_STATE value of the drop policy, and the drop policy of a value.
+fn drop_policy_value(policy: DropPolicy) -> u32 {
+    match policy {
+        DropPolicy::Reject => DROP_POLICY_REJECT,
+        DropPolicy::Drop => DROP_POLICY_DROP,
+        DropPolicy::Pass => DROP_POLICY_PASS,
+    }
+}
+
+fn drop_policy_of(value: u32) -> DropPolicy {
+    match value {
+        DROP_POLICY_DROP => DropPolicy::Drop,
+        DROP_POLICY_PASS => DropPolicy::Pass,
+        _ => DropPolicy::Reject,
+    }
+}
+
+/// Encodes the key a page of vips ended at as the token of the next page.
+pub fn page_token(key: &BackendKey) -> String {
+    format!(
+        "{:08x}{:08x}{:08x}{:02x}",
+        key.ip, key.port, key.port_end, key.proto
+    )
+}
+
+/// Decodes the key a page token continues after, None if it isn't a token page_token returns.
+pub fn parse_page_token(token: &str) -> Option<BackendKey> {
+    // from_str_radix would take a sign too
+    if token.len() != 26 || !token.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(BackendKey {
+        ip: u32::from_str_radix(&token[..8], 16).ok()?,
+        port: u32::from_str_radix(&token[8..16], 16).ok()?,
+        port_end: u32::from_str_radix(&token[16..24], 16).ok()?,
+        proto: u32::from_str_radix(&token[24..], 16).ok()?,
+    })
+}
+
+// Returns the VIP_PORT_RANGES keys of the prefixes of the ports of a vip of a range of ports.
+fn port_range_keys(key: &BackendKey) -> impl Iterator<Item = Key<PortRangeKey>> + '_ {
+    port_range::prefixes(key.port as u16, key.port_end as u16).map(|(port, prefix_len)| {
+        Key::new(
+            PortRangeKey::FIXED_PREFIX_LEN + prefix_len,
+            PortRangeKey::new(key.ip, key.proto, port),
+        )
+    })
+}
+
+// Returns the prefix lengths, data and actions of the VIP_ACLS entries of the ACL of the vip, or
+// the first of its CIDRs that isn't one.
+fn acl_entries(key: BackendKey, acl: &Acl) -> Result<Vec<(u32, AclKey, u32)>, &Cidr> {
+    let action = match acl.mode() {
+        AclMode::Allowlist => ACL_ACTION_ALLOW,
+        AclMode::Denylist => ACL_ACTION_DENY,
+    };
+    let mut entries = acl
+        .cidrs
+        .iter()
+        .map(|cidr| {
+            acl::cidr_key(key, cidr.ip, cidr.prefix_len)
+                .map(|(prefix_len, data)| (prefix_len, data, action))
+                .ok_or(cidr)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if acl.mode() == AclMode::Allowlist {
+        let (prefix_len, data) = acl::any_client_key(key);
+        entries.push((prefix_len, data, ACL_ACTION_DENY));
+    }
+    Ok(entries)
+}
+
+// Returns true if the error is the result of operating on a map key that does not exist.
+fn is_missing_key_error(err: &Error) -> bool {
+    err.to_string().contains("syscall failed with code -1")
+}
+
+// The tracked connections of a vip queued for cleanup.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum PendingCleanup {
+    // The vip was removed, so all of its connections are.
+    All,
+    // Only the connections to these backends (by address and port), which were removed from
+    // the vip, are.
+    Backends(HashSet<(u32, u32)>),
+}
+
+/// How often queued connection cleanup runs.
+pub const CONNECTION_CLEANUP_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The maximum number of tracked connections removed per cleanup tick.
+pub const CONNECTION_CLEANUP_BATCH_SIZE: usize = 1024;
+
+/// The number of tracked connections scans of them lock the map for at a time.
+pub const SCAN_CHUNK_SIZE: usize = 256;
+
+/// How often tracked connections that have been idle for longer than their idle timeout are
+/// expired.
+pub const IDLE_CONNECTION_EXPIRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often the occupancy of the maps is compared with their high watermarks.
+pub const OCCUPANCY_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The capacity of the maps, and the occupancy above which the dataplane reports itself as
+/// degraded.
+#[derive(Clone, Copy, Debug)]
+pub struct MapLimits {
+    /// The maximum number of entries of the BACKENDS map.
+    pub max_vips: u32,
+    /// The maximum number of entries of the LB_CONNECTIONS map.
+    pub max_connections: u32,
+    /// The maximum number of backends of a vip.
+    pub max_backends_per_vip: u32,
+    /// The percentage of max_vips above which the dataplane is degraded.
+    pub vips_high_watermark: u32,
+    /// The percentage of max_connections above which the dataplane is degraded.
+    pub connections_high_watermark: u32,
+}
+
+/// How often the hostnames of targets are resolved again. The system resolver doesn't expose
+/// record TTLs, so this bounds how long a changed record can take to be picked up.
+pub const HOSTNAME_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The maximum number of flow records buffered between collections, older records are
+/// dropped beyond it.
+pub const FLOW_RECORDS_BUFFER_CAPACITY: usize = 65536;
+
+// Records of closed connections waiting to be collected.
+#[derive(Default)]
+struct FlowBuffer {
+    records: VecDeque<FlowRecord>,
+    // The number of records dropped since the last collection.
+    dropped: u64,
+}
+
+/// The vip and backend of the self-test, in TEST-NET-2 (see RFC 5737) so that they can't be
+/// those of real traffic.
+pub const SELF_TEST_VIP: (Ipv4Addr, u16) = (Ipv4Addr::new(198, 51, 100, 1), 9);
+pub const SELF_TEST_BACKEND: (Ipv4Addr, u16) = (Ipv4Addr::new(198, 51, 100, 2), 9);
+
+// The TC action of packets redirected to another interface, see include/uapi/linux/pkt_cls.h.
+const TC_ACT_REDIRECT: i32 = 7;
+
+/// The number of packets captured by a CapturePackets request that doesn't specify it.
+pub const DEFAULT_CAPTURE_COUNT: u32 = 10;
+
+/// The maximum number of packets a CapturePackets request may capture.
+pub const MAX_CAPTURE_COUNT: u32 = 1000;
+
+/// How long a CapturePackets request that doesn't specify it waits for packets.
+pub const DEFAULT_CAPTURE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The longest a CapturePackets request may wait for packets.
+pub const MAX_CAPTURE_TIMEOUT: Duration = Duration::from_secs(60);
+
+// The number of pages of the perf buffer of each CPU that captured packets are read from.
+const CAPTURE_PERF_BUFFER_PAGES: usize = 16;
+
+// The number of captured packets read from a perf buffer at once.
+const CAPTURE_READ_BATCH: usize = 16;
+
+// A running packet capture, which is complete once it holds `limit` packets.
+struct CaptureSession {
+    packets: Vec<(CapturedPacket, CapturedPacketInfo)>,
+    limit: usize,
+    // The number of packets that were lost because they weren't read in time.
+    lost: u64,
+    // Notified once the capture is complete.
+    done: Option<oneshot::Sender<()>>,
+}
+
+/// An eBPF program attached by the loader.
+pub struct AttachedProgram {
+    /// The link that keeps the program attached, detached on drop.
+    pub link: SchedClassifierLink,
+    /// The program, used to run synthetic packets through it.
+    pub fd: ProgramFd,
+}
+
+#[derive(Clone)]
 pub struct BackendService {
     backends_map: Arc<Mutex<HashMap<MapData, BackendKey, BackendList>>>,
+    // Always locked after backends_map by the operations that hold both.
+    backend_slots_map: Arc<Mutex<HashMap<MapData, BackendSlotKey, BackendSlot>>>,
+    // The vips of ranges of ports by the prefixes of their ports, written after their BACKENDS
+    // entry and removed before it.
+    port_ranges_map: Arc<Mutex<LpmTrie<MapData, PortRangeKey, BackendKey>>>,
     gateway_indexes_map: Arc<Mutex<HashMap<MapData, BackendKey, u16>>>,
     tcp_conns_map: Arc<Mutex<HashMap<MapData, ClientKey, LoadBalancerMapping>>>,
+    // The clients pinned to a backend of vips with session affinity.
+    affinities_map: Arc<Mutex<HashMap<MapData, AffinityKey, Affinity>>>,
+    state_map: Arc<Mutex<Array<MapData, u32>>>,
+    vip_stats_map: Arc<Mutex<PerCpuHashMap<MapData, BackendKey, common::VipStats>>>,
+    // The counters of the packets to vips that weren't load balanced, by reason.
+    drop_stats_map: Arc<Mutex<PerCpuArray<MapData, u64>>>,
+    // The entries of the ACLs of vips, see common::acl.
+    vip_acls_map: Arc<Mutex<LpmTrie<MapData, AclKey, u32>>>,
+    // The ACL of each vip that has one, which its VIP_ACLS entries are built from. Locked before
+    // vip_acls_map, and after backends_map by the operations that hold both.
+    acls: Arc<Mutex<StdHashMap<BackendKey, Acl>>>,
+    capture_config_map: Arc<Mutex<Array<MapData, CaptureConfig>>>,
+    capture: Arc<Mutex<Option<CaptureSession>>>,
+    // The Gateway that owns each VIP, for VIPs that were programmed on behalf of a Gateway.
+    // Requests made on behalf of one Gateway may not modify VIPs owned by another.
+    vip_owners: Arc<Mutex<StdHashMap<BackendKey, NamespacedName>>>,
+    // Backend keys that were removed, or had backends removed, but may still have entries in
+    // the tcp connection tracking map.
+    pending_cleanup: Arc<Mutex<StdHashMap<BackendKey, PendingCleanup>>>,
+    // The attached eBPF programs, by program name, so they can be probed and detached at
+    // runtime.
+    attached_programs: Arc<Mutex<StdHashMap<String, AttachedProgram>>>,
+    flow_records: Arc<Mutex<FlowBuffer>>,
+    // The requested targets of the VIPs with targets specified by hostname, so they can be
+    // resolved again. Locked before the maps by the operations that hold both.
+    hostname_targets: Arc<Mutex<StdHashMap<BackendKey, Targets>>>,
+    resolver: Arc<dyn Resolver>,
+    limits: MapLimits,
+    // Whether a map was above its high watermark at the last occupancy check.
+    degraded: Arc<AtomicBool>,
 }
 
 impl BackendService {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         backends_map: HashMap<MapData, BackendKey, BackendList>,
+        backend_slots_map: HashMap<MapData, BackendSlotKey, BackendSlot>,
+        port_ranges_map: LpmTrie<MapData, PortRangeKey, BackendKey>,
         gateway_indexes_map: HashMap<MapData, BackendKey, u16>,
         tcp_conns_map: HashMap<MapData, ClientKey, LoadBalancerMapping>,
+        affinities_map: HashMap<MapData, AffinityKey, Affinity>,
+        state_map: Array<MapData, u32>,
+        vip_stats_map: PerCpuHashMap<MapData, BackendKey, common::VipStats>,
+        drop_stats_map: PerCpuArray<MapData, u64>,
+        vip_acls_map: LpmTrie<MapData, AclKey, u32>,
+        capture_config_map: Array<MapData, CaptureConfig>,
+        attached_programs: StdHashMap<String, AttachedProgram>,
+        limits: MapLimits,
     ) -> BackendService {
         BackendService {
             backends_map: Arc::new(Mutex::new(backends_map)),
+            backend_slots_map: Arc::new(Mutex::new(backend_slots_map)),
+            port_ranges_map: Arc::new(Mutex::new(port_ranges_map)),
             gateway_indexes_map: Arc::new(Mutex::new(gateway_indexes_map)),
             tcp_conns_map: Arc::new(Mutex::new(tcp_conns_map)),
+            affinities_map: Arc::new(Mutex::new(affinities_map)),
+            state_map: Arc::new(Mutex::new(state_map)),
+            vip_stats_map: Arc::new(Mutex::new(vip_stats_map)),
+            drop_stats_map: Arc::new(Mutex::new(drop_stats_map)),
+            vip_acls_map: Arc::new(Mutex::new(vip_acls_map)),
+            acls: Arc::new(Mutex::new(StdHashMap::new())),
+            capture_config_map: Arc::new(Mutex::new(capture_config_map)),
+            capture: Arc::new(Mutex::new(None)),
+            vip_owners: Arc::new(Mutex::new(StdHashMap::new())),
+            pending_cleanup: Arc::new(Mutex::new(StdHashMap::new())),
+            attached_programs: Arc::new(Mutex::new(attached_programs)),
+            flow_records: Arc::new(Mutex::new(FlowBuffer::default())),
+            hostname_targets: Arc::new(Mutex::new(StdHashMap::new())),
+            resolver: Arc::new(SystemResolver),
+            limits,
+            degraded: Arc::new(AtomicBool::new(false)),
         }
     }
 
-    async fn insert(&self, key: BackendKey, bks: BackendList) -> Result<(), Error> {
-        let mut backends_map = self.backends_map.lock().await;
-        backends_map.insert(key, bks, 0)?;
+    /// Resolves the hostnames of targets with the resolver rather than that of the system.
+    pub fn with_resolver(mut self, resolver: Arc<dyn Resolver>) -> BackendService {
+        self.resolver = resolver;
+        self
+    }
+
+    // Ensures that a request made on behalf of the provided Gateway may modify the VIP, see
+    // check_owner.
+    async fn check_vip_owner(
+        &self,
+        key: &BackendKey,
+        gateway: &Option<NamespacedName>,
+    ) -> Result<(), Status> {
+        check_owner(&self.vip_owners.lock().await, key, gateway)
+    }
+
+    // Ensures that the ports of a vip are a single port, or a range of ports that doesn't overlap
+    // the range of another vip of the same address and protocol.
+    async fn check_port_range(&self, key: &BackendKey) -> Result<(), Status> {
+        if key.port_end == 0 {
+            return Ok(());
+        }
+        let addr_ddn = Ipv4Addr::from(key.ip);
+        if key.port_end <= key.port || key.port_end > u16::MAX as u32 {
+            return Err(error_status(
+                Code::InvalidArgument,
+                ErrorCode::InvalidPortRange,
+                format!(
+                    "ports {}-{} of vip {} are not a range of ports",
+                    key.port, key.port_end, addr_ddn
+                ),
+            ));
+        }
+
+        let backends_map = self.backends_map.lock().await;
+        for other in backends_map.keys() {
+            let other = other.map_err(|err| {
+                error_status(
+                    Code::Internal,
+                    ErrorCode::MapUpdateFailed,
+                    format!("failed to list vips: {}", err),
+                )
+            })?;
+            if other != *key
+                && other.ip == key.ip
+                && other.proto == key.proto
+                && other.port_end != 0
+                && other.port <= key.port_end
+                && key.port <= other.port_end
+            {
+                return Err(error_status(
+                    Code::FailedPrecondition,
+                    ErrorCode::InvalidPortRange,
+                    format!(
+                        "ports {}-{} of vip {} overlap its ports {}-{}",
+                        key.port, key.port_end, addr_ddn, other.port, other.port_end
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Sends the ports of a vip of a range of ports to it. Vips of a single port are found by
+    // their BACKENDS entry alone.
+    async fn insert_port_range(&self, key: BackendKey) -> Result<(), Error> {
+        if key.port_end == 0 {
+            return Ok(());
+        }
+        let mut port_ranges_map = self.port_ranges_map.lock().await;
+        for range_key in port_range_keys(&key) {
+            port_ranges_map.insert(&range_key, key, 0)?;
+        }
         Ok(())
     }
 
-    async fn insert_and_reset_index(&self, key: BackendKey, bks: BackendList) -> Result<(), Error> {
-        self.insert(key, bks).await?;
+    // Returns the backends of the vip.
+    async fn read_backends(&self, key: BackendKey) -> Result<VipBackends, Error> {
+        let backends_map = self.backends_map.lock().await;
+        let backend_slots_map = self.backend_slots_map.lock().await;
+        read_backends(&backends_map, &backend_slots_map, key)
+    }
+
+    // Writes the backends of the vip, see write_backends.
+    async fn insert(&self, key: BackendKey, bks: &VipBackends) -> Result<(), Error> {
+        let mut backends_map = self.backends_map.lock().await;
+        let mut backend_slots_map = self.backend_slots_map.lock().await;
+        write_backends(&mut backends_map, &mut backend_slots_map, key, bks)
+    }
+
+    // Replaces the backends of the vip, queueing the connections of the backends it removes for
+    // cleanup when the policy is to rebalance them.
+    async fn insert_and_reset_index(
+        &self,
+        key: BackendKey,
+        bks: VipBackends,
+        policy: RemovedBackendPolicy,
+    ) -> Result<(), Error> {
+        let previous = self.read_backends(key).await.ok();
+        self.insert(key, &bks).await?;
+        self.queue_removed_backends(key, previous.as_ref(), &bks, policy)
+            .await;
+
         let mut gateway_indexes_map = self.gateway_indexes_map.lock().await;
         gateway_indexes_map.insert(key, 0, 0)?;
         Ok(())
     }
 
-    async fn remove(&self, key: BackendKey) -> Result<(), Error> {
+    // Queues the connections of the backends that the vip no longer has for cleanup, those of
+    // the previous backends when the policy is to rebalance them.
+    async fn queue_removed_backends(
+        &self,
+        key: BackendKey,
+        previous: Option<&VipBackends>,
+        bks: &VipBackends,
+        policy: RemovedBackendPolicy,
+    ) {
+        let addresses = |bks: &VipBackends| -> HashSet<(u32, u32)> {
+            bks.backends()
+                .map(|backend| (backend.daddr, backend.dport))
+                .collect()
+        };
+        let mut pending_cleanup = self.pending_cleanup.lock().await;
+        // A re-added key must not have its new connections cleaned up, but backends removed by
+        // earlier updates still have theirs cleaned up unless they were added back.
+        let mut stale = match pending_cleanup.remove(&key) {
+            Some(PendingCleanup::Backends(backends)) => backends,
+            _ => HashSet::new(),
+        };
+        if let (RemovedBackendPolicy::Rebalance, Some(previous)) = (policy, previous) {
+            stale.extend(addresses(previous));
+        }
+        let current = addresses(bks);
+        stale.retain(|backend| !current.contains(backend));
+        if !stale.is_empty() {
+            pending_cleanup.insert(key, PendingCleanup::Backends(stale));
+        }
+    }
+
+    // Sets the round-robin position of the vip if it's within its backends, returning the
+    // number of backends of the vip either way. The BACKENDS entry stays locked so that the
+    // backends can't shrink before the index is written.
+    async fn set_gateway_index(&self, key: BackendKey, index: u16) -> Result<u16, MapError> {
+        let backends_map = self.backends_map.lock().await;
+        let backends_len = backends_map.get(&key, 0)?.backends_len;
+        if index < backends_len {
+            let mut gateway_indexes_map = self.gateway_indexes_map.lock().await;
+            gateway_indexes_map.insert(key, index, 0)?;
+        }
+        Ok(backends_len)
+    }
+
+    // Swaps the backends of two vips, see VipPair. Each vip switches over to its new backends in
+    // a single map update; if the second update fails the first one is reverted. The round-robin
+    // positions are reset beforehand, since 0 is valid for any backends, so that a failure to
+    // reset them leaves the backends as they were.
+    async fn swap_backends(&self, first: BackendKey, second: BackendKey) -> Result<(), Error> {
+        let mut hostname_targets = self.hostname_targets.lock().await;
         let mut backends_map = self.backends_map.lock().await;
-        backends_map.remove(&key)?;
+        let first_backends = backends_map.get(&first, 0)?;
+        let second_backends = backends_map.get(&second, 0)?;
+        // the BACKENDS entries can be swapped atomically, but not along with their slots
+        if overflow_slots(first_backends.backends_len) > 0
+            || overflow_slots(second_backends.backends_len) > 0
+        {
+            return Err(anyhow!(
+                "vips with more than {} backends can't be swapped",
+                BACKENDS_ARRAY_CAPACITY
+            ));
+        }
+
         let mut gateway_indexes_map = self.gateway_indexes_map.lock().await;
-        gateway_indexes_map.remove(&key)?;
+        gateway_indexes_map.insert(first, 0, 0)?;
+        gateway_indexes_map.insert(second, 0, 0)?;
+        drop(gateway_indexes_map);
 
-        // Delete all entries in our tcp connection tracking map that this backend
-        // key was related to. This is needed because the TCPRoute might have been
-        // deleted with TCP connection(s) still open, so without the below logic
-        // they'll hang around forever.
-        // Its better to do this rather than maintain a reverse index because the index
-        // would need to be updated with each new connection. With remove being a less
-        // frequently used operation, the performance cost is less visible.
-        let mut tcp_conns_map = self.tcp_conns_map.lock().await;
-        for item in tcp_conns_map
-            .iter()
-            .collect::<Vec<Result<(ClientKey, LoadBalancerMapping), MapError>>>()
-        {
-            match item {
-                Ok((
-                    client_key,
-                    LoadBalancerMapping {
-                        backend: _,
-                        backend_key,
-                        tcp_state: _,
-                    },
-                )) => {
-                    if backend_key == key {
-                        tcp_conns_map.remove(&client_key)?;
-                    };
-                }
-                Err(err) => return Err(err.into()),
-            };
+        backends_map.insert(first, second_backends, 0)?;
+        if let Err(err) = backends_map.insert(second, first_backends, 0) {
+            backends_map.insert(first, first_backends, 0)?;
+            return Err(err.into());
+        }
+
+        let first_targets = hostname_targets.remove(&first);
+        if let Some(targets) = hostname_targets.remove(&second) {
+            hostname_targets.insert(first, targets);
+        }
+        if let Some(targets) = first_targets {
+            hostname_targets.insert(second, targets);
         }
         Ok(())
     }
-}
 
-#[tonic::async_trait]
-impl Backends for BackendService {
-    async fn get_interface_index(
-        &self,
-        request: Request<PodIp>,
-    ) -> Result<Response<InterfaceIndexConfirmation>, Status> {
-        let pod = request.into_inner();
-        let ip = pod.ip;
-        let ip_addr = std::net::Ipv4Addr::from(ip);
+    // Optionally starts or stops draining, and reports whether the dataplane is draining along
+    // with the number of connections still being tracked.
+    async fn drain(&self, draining: Option<bool>) -> Result<DrainStatus, Error> {
+        let mut state_map = self.state_map.lock().await;
+        if let Some(draining) = draining {
+            state_map.set(STATE_DRAINING, draining as u32, 0)?;
+        }
+        let draining = state_map.get(&STATE_DRAINING, 0)? == 1;
+        drop(state_map);
 
-        let ifindex = match if_index_for_routing_ip(ip_addr) {
-            Ok(ifindex) => ifindex,
-            Err(err) => return Err(Status::internal(err.to_string())),
-        };
+        let tcp_conns_map = self.tcp_conns_map.lock().await;
+        let active_connections = tcp_conns_map.keys().count() as u32;
 
-        Ok(Response::new(InterfaceIndexConfirmation { ifindex }))
+        Ok(DrainStatus {
+            draining,
+            active_connections,
+        })
     }
 
-    async fn update(&self, request: Request<Targets>) -> Result<Response<Confirmation>, Status> {
-        let targets = request.into_inner();
+    // Writes the settings of the update that are set to DATAPLANE_STATE, where the eBPF programs
+    // read them for every packet, and returns all of the settings.
+    async fn runtime_config(&self, update: &RuntimeConfig) -> Result<RuntimeConfig, Error> {
+        let mut state_map = self.state_map.lock().await;
 
-        let vip = match targets.vip {
-            Some(vip) => vip,
-            None => return Err(Status::invalid_argument("missing vip ip and port")),
+        let mut features = state_map.get(&STATE_FEATURES, 0)?;
+        for (enabled, feature) in [
+            (update.flow_records, FEATURE_FLOW_RECORDS),
+            (update.vip_stats, FEATURE_VIP_STATS),
+        ] {
+            match enabled {
+                Some(true) => features |= feature,
+                Some(false) => features &= !feature,
+                None => {}
+            }
+        }
+        let values = [
+            (STATE_FEATURES, Some(features)),
+            (
+                STATE_LOG_LEVEL,
+                update
+                    .log_level
+                    .map(|_| log_level_value(update.log_level())),
+            ),
+            (STATE_LOG_SAMPLE_RATE, update.log_sample_rate),
+            (
+                STATE_DROP_POLICY,
+                update
+                    .drop_policy
+                    .map(|_| drop_policy_value(update.drop_policy())),
+            ),
+            (STATE_TCP_IDLE_TIMEOUT, update.tcp_idle_timeout_seconds),
+            (STATE_UDP_IDLE_TIMEOUT, update.udp_idle_timeout_seconds),
+            (STATE_TCP_MSS_CLAMP, update.tcp_mss_clamp),
+        ];
+        for (index, value) in values {
+            if let Some(value) = value {
+                state_map.set(index, value, 0)?;
+            }
+        }
+
+        Ok(RuntimeConfig {
+            flow_records: Some(features & FEATURE_FLOW_RECORDS != 0),
+            vip_stats: Some(features & FEATURE_VIP_STATS != 0),
+            log_level: Some(log_level_of(state_map.get(&STATE_LOG_LEVEL, 0)?) as i32),
+            log_sample_rate: Some(state_map.get(&STATE_LOG_SAMPLE_RATE, 0)?),
+            drop_policy: Some(drop_policy_of(state_map.get(&STATE_DROP_POLICY, 0)?) as i32),
+            tcp_idle_timeout_seconds: Some(state_map.get(&STATE_TCP_IDLE_TIMEOUT, 0)?),
+            udp_idle_timeout_seconds: Some(state_map.get(&STATE_UDP_IDLE_TIMEOUT, 0)?),
+            tcp_mss_clamp: Some(state_map.get(&STATE_TCP_MSS_CLAMP, 0)?),
+        })
+    }
+
+    // Counts the entries of the maps and compares them with their high watermarks, logging
+    // when the dataplane becomes degraded or recovers.
+    async fn check_occupancy(&self) -> Result<DataplaneInfo, Error> {
+        let vips = self.backends_map.lock().await.keys().count() as u32;
+        let connections = self.tcp_conns_map.lock().await.keys().count() as u32;
+
+        let mut degraded_reasons = Vec::new();
+        let maps = [
+            (
+                "BACKENDS",
+                vips,
+                self.limits.max_vips,
+                self.limits.vips_high_watermark,
+            ),
+            (
+                "LB_CONNECTIONS",
+                connections,
+                self.limits.max_connections,
+                self.limits.connections_high_watermark,
+            ),
+        ];
+        for (name, entries, max_entries, watermark) in maps {
+            if u64::from(entries) * 100 > u64::from(max_entries) * u64::from(watermark) {
+                degraded_reasons.push(format!(
+                    "{} holds {} of {} entries, above the {}% high watermark",
+                    name, entries, max_entries, watermark
+                ));
+            }
+        }
+
+        let standby = self.state_map.lock().await.get(&STATE_STANDBY, 0)? == 1;
+
+        let degraded = !degraded_reasons.is_empty();
+        let was_degraded = self.degraded.swap(degraded, Ordering::Relaxed);
+        if degraded && !was_degraded {
+            warn!("dataplane degraded: {}", degraded_reasons.join("; "));
+        } else if !degraded && was_degraded {
+            info!("dataplane no longer degraded, map occupancy is below the high watermarks");
+        }
+
+        Ok(DataplaneInfo {
+            vips,
+            max_vips: self.limits.max_vips,
+            connections,
+            max_connections: self.limits.max_connections,
+            degraded,
+            degraded_reasons,
+            standby,
+            max_backends_per_vip: self.limits.max_backends_per_vip,
+        })
+    }
+
+    // Returns the counters of the packets to vips that weren't load balanced, summed across CPUs.
+    async fn drop_stats(&self) -> Result<DropStats, Error> {
+        let drop_stats_map = self.drop_stats_map.lock().await;
+        let total = |reason: u32| -> Result<u64, MapError> {
+            Ok(drop_stats_map.get(&reason, 0)?.iter().sum())
         };
+        Ok(DropStats {
+            no_backends: total(DROP_STATS_NO_BACKENDS)?,
+            backend_out_of_range: total(DROP_STATS_BACKEND_OUT_OF_RANGE)?,
+            rewrite_failed: total(DROP_STATS_REWRITE_FAILED)?,
+            no_route: total(DROP_STATS_NO_ROUTE)?,
+            redirect_failed: total(DROP_STATS_REDIRECT_FAILED)?,
+            acl_denied: total(DROP_STATS_ACL_DENIED)?,
+            rate_limited: total(DROP_STATS_RATE_LIMITED)?,
+        })
+    }
 
-        let key = BackendKey {
-            ip: vip.ip,
-            port: vip.port,
+    // Replaces the ACL of the vip, or removes it when there's none. The entries of the new ACL
+    // are written before those of the old one that it doesn't have are removed, so that the vip
+    // isn't left without an ACL while it's replaced.
+    async fn replace_acl(&self, key: BackendKey, acl: Option<Acl>) -> Result<(), Error> {
+        let mut acls = self.acls.lock().await;
+        let mut vip_acls_map = self.vip_acls_map.lock().await;
+        let entries = match &acl {
+            Some(acl) => acl_entries(key, acl).map_err(|cidr| {
+                anyhow!(
+                    "{}/{} is not a CIDR",
+                    Ipv4Addr::from(cidr.ip),
+                    cidr.prefix_len
+                )
+            })?,
+            None => Vec::new(),
+        };
+        for (prefix_len, data, action) in &entries {
+            vip_acls_map.insert(&Key::new(*prefix_len, *data), action, 0)?;
+        }
+        let old_entries = acls
+            .get(&key)
+            .and_then(|old| acl_entries(key, old).ok())
+            .unwrap_or_default();
+        for (prefix_len, data, _) in old_entries {
+            if entries
+                .iter()
+                .any(|(len, new, _)| *len == prefix_len && *new == data)
+            {
+                continue;
+            }
+            match vip_acls_map
+                .remove(&Key::new(prefix_len, data))
+                .map_err(Error::from)
+            {
+                Err(err) if !is_missing_key_error(&err) => return Err(err),
+                _ => {}
+            }
+        }
+        match acl {
+            Some(acl) => acls.insert(key, acl),
+            None => acls.remove(&key),
         };
-        let mut backends: [Backend; BACKENDS_ARRAY_CAPACITY] =
-            [Backend::default(); BACKENDS_ARRAY_CAPACITY];
-        let mut count: u16 = 0;
-        let backend_targets = targets.targets;
+        Ok(())
+    }
 
-        for backend_target in backend_targets {
-            let ifindex = match backend_target.ifindex {
-                Some(ifindex) => ifindex,
-                None => {
-                    let ip_addr = Ipv4Addr::from(backend_target.daddr);
-                    match if_index_for_routing_ip(ip_addr) {
-                        Ok(ifindex) => ifindex,
-                        Err(err) => {
-                            return Err(Status::internal(format!(
-                                "failed to determine ifindex: {}",
-                                err
-                            )))
-                        }
-                    }
-                }
-            };
+    // Sets whether the backends of the vip at the address and port are draining, returning how
+    // many of them there are, None when the vip doesn't exist. The targets of the vip specified
+    // by hostname are updated along, so that the backends keep draining when their addresses
+    // are refreshed.
+    async fn set_backends_draining(
+        &self,
+        key: BackendKey,
+        daddr: u32,
+        dport: u32,
+        draining: bool,
+    ) -> Result<Option<usize>, Error> {
+        // updates of the vip hold the hostname targets while they write its backends
+        let mut hostname_targets = self.hostname_targets.lock().await;
+        let mut bks = match self.read_backends(key).await {
+            Ok(bks) => bks,
+            Err(err) if matches!(err.downcast_ref::<MapError>(), Some(MapError::KeyNotFound)) => {
+                return Ok(None)
+            }
+            Err(err) => return Err(err),
+        };
+        let indexes: Vec<u16> = bks
+            .backends()
+            .enumerate()
+            .filter(|(_, backend)| backend.daddr == daddr && backend.dport == dport)
+            .map(|(index, _)| index as u16)
+            .collect();
+        if indexes.is_empty() {
+            return Ok(Some(0));
+        }
 
-            if (count as usize) < BACKENDS_ARRAY_CAPACITY {
-                let bk = Backend {
-                    daddr: backend_target.daddr,
-                    dport: backend_target.dport,
-                    ifindex: ifindex as u16,
-                };
-                backends[count as usize] = bk;
-                count += 1;
-            } else {
-                return Err(Status::resource_exhausted(
-                    "BPF map value capacity exceeded, only 128 backends supported per Gateway",
-                ));
+        for index in &indexes {
+            set_draining(&mut bks.list.draining, *index, draining);
+        }
+        bks.list.draining_len = draining_len(&bks.list);
+        if bks.list.maglev().is_some() {
+            let backends: Vec<Backend> = bks.backends().copied().collect();
+            bks.list.maglev = maglev_table(&backends, &bks.list.draining);
+        }
+        self.insert(key, &bks).await?;
+
+        if let Some(targets) = hostname_targets.get_mut(&key) {
+            for index in &indexes {
+                if let Some(target) = targets.targets.get_mut(*index as usize) {
+                    target.draining = draining;
+                }
             }
         }
+        Ok(Some(indexes.len()))
+    }
 
-        let backend_list = BackendList {
-            backends,
-            backends_len: count,
+    // Starts or stops draining the targets of a vip.
+    async fn drain_targets(
+        &self,
+        request: Request<TargetRef>,
+        draining: bool,
+    ) -> Result<Response<Confirmation>, Status> {
+        let gateway = gateway_from_metadata(&request);
+        let target = request.into_inner();
+        let vip = match target.vip {
+            Some(vip) => vip,
+            None => {
+                return Err(error_status(
+                    Code::InvalidArgument,
+                    ErrorCode::MissingVip,
+                    "missing vip ip and port",
+                ))
+            }
         };
-        match self.insert_and_reset_index(key, backend_list).await {
-            Ok(_) => Ok(Response::new(Confirmation {
+        let key = backend_key_for(&vip);
+        let addr_ddn = Ipv4Addr::from(vip.ip);
+        let target_ddn = Ipv4Addr::from(target.daddr);
+        self.check_vip_owner(&key, &gateway).await?;
+
+        match self
+            .set_backends_draining(key, target.daddr, target.dport, draining)
+            .await
+        {
+            Ok(Some(0)) => Err(error_status(
+                Code::NotFound,
+                ErrorCode::BackendNotFound,
+                format!(
+                    "vip {}:{} has no target {}:{}",
+                    addr_ddn, vip.port, target_ddn, target.dport
+                ),
+            )),
+            Ok(Some(_)) => Ok(Response::new(Confirmation {
                 confirmation: format!(
-                    "success, vip {}:{} was updated with {} backends",
-                    Ipv4Addr::from(vip.ip),
+                    "success, target {}:{} of vip {}:{} is {}",
+                    target_ddn,
+                    target.dport,
+                    addr_ddn,
                     vip.port,
-                    count,
+                    if draining {
+                        "draining"
+                    } else {
+                        "no longer draining"
+                    }
                 ),
             })),
-            Err(err) => Err(Status::internal(format!("failure: {}", err))),
+            Ok(None) => Err(error_status(
+                Code::NotFound,
+                ErrorCode::VipNotFound,
+                format!("vip {}:{} not found", addr_ddn, vip.port),
+            )),
+            Err(err) => Err(error_status(
+                Code::Internal,
+                ErrorCode::MapUpdateFailed,
+                format!("failure: {}", err),
+            )),
         }
     }
 
-    async fn delete(&self, request: Request<Vip>) -> Result<Response<Confirmation>, Status> {
-        let vip = request.into_inner();
+    // Returns the traffic counters of every vip, summed across CPUs.
+    async fn vip_stats(&self) -> Result<Vec<VipStats>, Error> {
+        let counters = self
+            .vip_stats_map
+            .lock()
+            .await
+            .iter()
+            .collect::<Result<Vec<_>, MapError>>()?;
 
-        let key = BackendKey {
-            ip: vip.ip,
-            port: vip.port,
-        };
+        let vip_owners = self.vip_owners.lock().await;
+        let mut stats: Vec<VipStats> = counters
+            .into_iter()
+            .map(|(key, per_cpu)| VipStats {
+                vip: Some(vip_for(&key)),
+                packets: per_cpu.iter().map(|stats| stats.packets).sum(),
+                bytes: per_cpu.iter().map(|stats| stats.bytes).sum(),
+                gateway: vip_owners.get(&key).map(|owner| Gateway {
+                    namespace: owner.namespace.clone(),
+                    name: owner.name.clone(),
+                }),
+                latency: Some(latency_histogram(
+                    per_cpu.iter().map(|stats| &stats.latency),
+                )),
+            })
+            .collect();
+        stats.sort_by_key(|stats| {
+            stats
+                .vip
+                .as_ref()
+                .map(|vip| (vip.ip, vip.port, vip.port_end, vip.protocol))
+        });
+        Ok(stats)
+    }
 
-        let addr_ddn = Ipv4Addr::from(vip.ip);
+    /// Runs a synthetic TCP, UDP and SCTP packet through the ingress program against a temporary vip,
+    /// whose backend is on the interface of `ifindex`, and checks that they were rewritten to
+    /// the backend and redirected to it. Nothing is checked in standby, where packets pass
+    /// through untouched.
+    pub async fn self_test(&self, ifindex: u32) -> Result<(), Error> {
+        if self.state_map.lock().await.get(&STATE_STANDBY, 0)? == 1 {
+            info!("skipping the self-test in standby");
+            return Ok(());
+        }
+        for protocol in [Protocol::Tcp, Protocol::Udp, Protocol::Sctp] {
+            let vip = Vip {
+                ip: SELF_TEST_VIP.0.into(),
+                port: SELF_TEST_VIP.1 as u32,
+                protocol: protocol as i32,
+                port_end: 0,
+            };
+            let targets = Targets {
+                vip: Some(vip.clone()),
+                targets: vec![Target {
+                    daddr: SELF_TEST_BACKEND.0.into(),
+                    dport: SELF_TEST_BACKEND.1 as u32,
+                    ifindex: Some(ifindex),
+                    hostname: String::new(),
+                    draining: false,
+                }],
+                ..Default::default()
+            };
+            Backends::update(self, Request::new(targets)).await?;
+            let result = Backends::probe(
+                self,
+                Request::new(ProbeRequest {
+                    vip: Some(vip.clone()),
+                    protocol: protocol as i32,
+                    ..Default::default()
+                }),
+            )
+            .await;
+            let deleted = Backends::delete(self, Request::new(vip)).await;
+            let result = result?.into_inner();
+            deleted?;
 
-        match self.remove(key).await {
-            Ok(()) => Ok(Response::new(Confirmation {
-                confirmation: format!("success, vip {}:{} was deleted", addr_ddn, vip.port),
-            })),
-            Err(err) if err.to_string().contains("syscall failed with code -1") => {
-                Ok(Response::new(Confirmation {
-                    confirmation: format!("success, vip {}:{} did not exist", addr_ddn, vip.port),
-                }))
+            let destination = (Ipv4Addr::from(result.daddr), result.dport as u16);
+            if destination != SELF_TEST_BACKEND {
+                return Err(anyhow!(
+                    "the {} packet to {}:{} was sent to {}:{} instead of the backend {}:{}",
+                    protocol.as_str_name(),
+                    SELF_TEST_VIP.0,
+                    SELF_TEST_VIP.1,
+                    destination.0,
+                    destination.1,
+                    SELF_TEST_BACKEND.0,
+                    SELF_TEST_BACKEND.1
+                ));
+            }
+            if !result.checksum_valid {
+                return Err(anyhow!(
+                    "the {} packet was rewritten with an invalid IPv4 checksum",
+                    protocol.as_str_name()
+                ));
+            }
+            if result.retval != TC_ACT_REDIRECT {
+                return Err(anyhow!(
+                    "the {} packet wasn't redirected to interface {}, the ingress program returned {}",
+                    protocol.as_str_name(),
+                    ifindex,
+                    result.retval
+                ));
             }
-            Err(err) => Err(Status::internal(format!("failure: {}", err))),
         }
+        Ok(())
     }
+
+    /// Periodically compares the occupancy of the maps with their high watermarks.
+    pub async fn run_occupancy_check(self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = self.check_occupancy().await {
+                warn!("failed to check the occupancy of the maps: {}", err);
+            }
+        }
+    }
+
+    // Returns up to page_size (all when 0) of the vips currently programmed in the dataplane
+    // along with their backends, in order of address, starting after the provided vip. The last
+    // vip returned is also returned when there are more to list.
+    async fn list_targets(
+        &self,
+        page_size: usize,
+        after: Option<BackendKey>,
+    ) -> Result<(Vec<Targets>, Option<BackendKey>), Error> {
+        // targets specified by hostname are listed with their hostname and current address
+        let to_target = |backend: &Backend, requested: Option<&Target>, draining: bool| Target {
+            daddr: backend.daddr,
+            dport: backend.dport,
+            ifindex: ifindex_of(backend),
+            hostname: requested
+                .map(|target| target.hostname.clone())
+                .unwrap_or_default(),
+            draining,
+        };
+
+        // the keys are read with BACKENDS locked alone, and each entry of the page with the maps
+        // locked for that entry only, so that updates aren't held back for a whole page. Vips
+        // removed in between are left out of the page.
+        let mut keys = self
+            .backends_map
+            .lock()
+            .await
+            .keys()
+            .collect::<Result<Vec<BackendKey>, MapError>>()?;
+        let order = |key: &BackendKey| (key.ip, key.port, key.port_end, key.proto);
+        keys.sort_by_key(order);
+        let start = after.map_or(0, |after| {
+            keys.partition_point(|key| order(key) <= order(&after))
+        });
+        let page_size = if page_size == 0 {
+            keys.len()
+        } else {
+            page_size
+        };
+        let end = keys.len().min(start.saturating_add(page_size));
+        let mut entries = Vec::with_capacity(end - start);
+        for key in &keys[start..end] {
+            let hostname_targets = self.hostname_targets.lock().await;
+            let backends_map = self.backends_map.lock().await;
+            let backend_slots_map = self.backend_slots_map.lock().await;
+            let backend_list = match backends_map.get(key, 0) {
+                Ok(backend_list) => backend_list,
+                Err(MapError::KeyNotFound) => continue,
+                Err(err) => return Err(err.into()),
+            };
+            let slots = (1..=overflow_slots(backend_list.backends_len))
+                .map(|slot| {
+                    backend_slots_map.get(
+                        &BackendSlotKey {
+                            backend_key: *key,
+                            slot,
+                        },
+                        0,
+                    )
+                })
+                .collect::<Result<_, MapError>>()?;
+            let backends = VipBackends {
+                list: backend_list,
+                slots,
+            };
+            entries.push((*key, backends, hostname_targets.get(key).cloned()));
+        }
+
+        let next = (end < keys.len()).then(|| keys[end - 1]);
+        let mut targets = Vec::with_capacity(entries.len());
+        for (key, backends, requested) in entries {
+            let backend_list = &backends.list;
+            let requested = requested.as_ref();
+            targets.push(Targets {
+                vip: Some(vip_for(&key)),
+                targets: backends
+                    .backends()
+                    .enumerate()
+                    .map(|(i, backend)| {
+                        to_target(
+                            backend,
+                            requested.and_then(|r| r.targets.get(i)),
+                            is_draining(&backend_list.draining, i as u16),
+                        )
+                    })
+                    .collect(),
+                mirror: backend_list.mirror().map(|backend| {
+                    to_target(&backend, requested.and_then(|r| r.mirror.as_ref()), false)
+                }),
+                load_balancing: if backend_list.maglev().is_some() {
+                    LoadBalancing::Maglev
+                } else {
+                    LoadBalancing::RoundRobin
+                } as i32,
+                affinity_timeout_seconds: backend_list.affinity_timeout,
+                // the policy applies to the update that removes backends, it isn't kept
+                removed_backend_policy: RemovedBackendPolicy::Preserve as i32,
+                full_nat: backend_list.full_nat(),
+                quic_connection_id_length: backend_list.quic_cid_len as u32,
+                rate_limit: backend_list.rate_limit().map(|limit| RateLimit {
+                    packets_per_second: limit.packets_per_second,
+                    burst: limit.burst,
+                }),
+                proxy_protocol: backend_list.proxy_protocol(),
+                dscp: backend_list.dscp().map(u32::from),
+            });
+        }
+        Ok((targets, next))
+    }
+
+    async fn remove(&self, key: BackendKey) -> Result<(), Error> {
+        let mut hostname_targets = self.hostname_targets.lock().await;
+        hostname_targets.remove(&key);
+        if key.port_end != 0 {
+            let mut port_ranges_map = self.port_ranges_map.lock().await;
+            for range_key in port_range_keys(&key) {
+                match port_ranges_map.remove(&range_key).map_err(Error::from) {
+                    Err(err) if !is_missing_key_error(&err) => return Err(err),
+                    _ => {}
+                }
+            }
+        }
+        let mut backends_map = self.backends_map.lock().await;
+        let slots = backends_map
+            .get(&key, 0)
+            .map_or(0, |list| overflow_slots(list.backends_len));
+        backends_map.remove(&key)?;
+        let mut backend_slots_map = self.backend_slots_map.lock().await;
+        for slot in 1..=slots {
+            backend_slots_map.remove(&BackendSlotKey {
+                backend_key: key,
+                slot,
+            })?;
+        }
+        drop(backend_slots_map);
+        let mut gateway_indexes_map = self.gateway_indexes_map.lock().await;
+        gateway_indexes_map.remove(&key)?;
+        // The vip may not have carried any traffic yet.
+        match self
+            .vip_stats_map
+            .lock()
+            .await
+            .remove(&key)
+            .map_err(Error::from)
+        {
+            Err(err) if !is_missing_key_error(&err) => return Err(err),
+            _ => {}
+        }
+        self.replace_acl(key, None).await?;
+
+        // Entries in our tcp connection tracking map that this backend key was related to
+        // need to be deleted too, because the TCPRoute might have been deleted with TCP
+        // connection(s) still open, so they'd otherwise hang around forever. Scanning the
+        // whole map here would block every other RPC for as long as the scan takes, so the
+        // key is queued and cleaned up incrementally by cleanup_connections instead.
+        self.pending_cleanup
+            .lock()
+            .await
+            .insert(key, PendingCleanup::All);
+        Ok(())
+    }
+
+    /// Removes up to `batch_size` tracked connections that belong to removed backend keys, or
+    /// to backends removed from a key, returning how many were removed. Once a pass scans all of
+    /// the tracked connections, the queued keys are considered clean and dropped from the cleanup
+    /// queue. The scan locks the map a chunk of connections at a time, so that other RPCs aren't
+    /// held back by it.
+    pub async fn cleanup_connections(&self, batch_size: usize) -> Result<usize, Error> {
+        let pending = self.pending_cleanup.lock().await.clone();
+        if pending.is_empty() {
+            return Ok(0);
+        }
+
+        let removed = scan::remove_stale(
+            &self.tcp_conns_map,
+            SCAN_CHUNK_SIZE,
+            batch_size,
+            self.max_scan_visits(),
+            |_, mapping| match pending.get(&mapping.backend_key) {
+                Some(PendingCleanup::All) => true,
+                // connections to backends of port 0 have the port their client connected to
+                Some(PendingCleanup::Backends(backends)) => {
+                    backends.contains(&(mapping.backend.daddr, mapping.backend.dport))
+                        || backends.contains(&(mapping.backend.daddr, 0))
+                }
+                None => false,
+            },
+        )
+        .await?;
+
+        if removed.complete {
+            let mut pending_cleanup = self.pending_cleanup.lock().await;
+            // keys queued again during the pass are kept
+            pending_cleanup.retain(|key, cleanup| pending.get(key) != Some(cleanup));
+        }
+
+        Ok(removed.entries.len())
+    }
+
+    // The number of entries a scan of LB_CONNECTIONS visits at most, enough to go through it twice
+    // when it starts over, see scan::remove_stale.
+    fn max_scan_visits(&self) -> usize {
+        2 * self.limits.max_connections as usize
+    }
+
+    /// Periodically runs cleanup_connections, removing at most `batch_size` connections per
+    /// tick so the tcp connection tracking map is never locked for long.
+    pub async fn run_connection_cleanup(self, interval: Duration, batch_size: usize) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match self.cleanup_connections(batch_size).await {
+                Ok(0) => {}
+                Ok(removed) => debug!("removed {} connections of deleted backends", removed),
+                Err(err) => warn!(
+                    "failed to clean up connections of deleted backends: {}",
+                    err
+                ),
+            }
+        }
+    }
+
+    /// Removes up to `batch_size` tracked connections that have been idle for longer than the
+    /// idle timeout of their protocol, returning how many were removed. The eBPF programs
+    /// expire idle connections when their clients send packets again, this catches the ones
+    /// that are abandoned so they don't hold map entries forever.
+    pub async fn expire_idle_connections(&self, batch_size: usize) -> Result<usize, Error> {
+        let (tcp_timeout, udp_timeout) = {
+            let state_map = self.state_map.lock().await;
+            (
+                state_map.get(&STATE_TCP_IDLE_TIMEOUT, 0)?,
+                state_map.get(&STATE_UDP_IDLE_TIMEOUT, 0)?,
+            )
+        };
+        if tcp_timeout == 0 && udp_timeout == 0 {
+            return Ok(0);
+        }
+
+        let now = monotonic_now_ns();
+        let idle = scan::remove_stale(
+            &self.tcp_conns_map,
+            SCAN_CHUNK_SIZE,
+            batch_size,
+            self.max_scan_visits(),
+            |_, mapping| {
+                let timeout = if mapping.tcp_state.is_some() {
+                    tcp_timeout
+                } else {
+                    udp_timeout
+                };
+                timeout > 0
+                    && now.saturating_sub(mapping.last_seen) > timeout as u64 * 1_000_000_000
+            },
+        )
+        .await?;
+
+        let removed = idle.entries.len();
+        let records = idle
+            .entries
+            .into_iter()
+            .map(|(client_key, mapping)| common::FlowRecord {
+                client_key,
+                backend_key: mapping.backend_key,
+                backend: mapping.backend,
+                packets: mapping.packets,
+                bytes: mapping.bytes,
+            })
+            .collect();
+        self.buffer_flow_records(records).await;
+        Ok(removed)
+    }
+
+    /// Periodically runs expire_idle_connections, removing at most `batch_size` connections
+    /// per tick.
+    pub async fn run_idle_connection_expiry(self, interval: Duration, batch_size: usize) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match self.expire_idle_connections(batch_size).await {
+                Ok(0) => {}
+                Ok(removed) => debug!("expired {} idle connections", removed),
+                Err(err) => warn!("failed to expire idle connections: {}", err),
+            }
+        }
+    }
+
+    /// Reads the records of closed connections the eBPF programs send on the ring buffer and
+    /// buffers them until they are collected.
+    pub async fn run_flow_collection(self, ring_buf: RingBuf<MapData>) {
+        let mut ring_buf = match AsyncFd::new(ring_buf) {
+            Ok(ring_buf) => ring_buf,
+            Err(err) => {
+                warn!("failed to poll the flow records ring buffer: {}", err);
+                return;
+            }
+        };
+        loop {
+            let mut guard = match ring_buf.readable_mut().await {
+                Ok(guard) => guard,
+                Err(err) => {
+                    warn!("failed to poll the flow records ring buffer: {}", err);
+                    return;
+                }
+            };
+            let mut records = Vec::new();
+            while let Some(item) = guard.get_inner_mut().next() {
+                if item.len() < std::mem::size_of::<common::FlowRecord>() {
+                    warn!("ignoring a truncated flow record");
+                    continue;
+                }
+                // SAFETY: the eBPF programs only send FlowRecords on the ring buffer.
+                let record =
+                    unsafe { std::ptr::read_unaligned(item.as_ptr() as *const common::FlowRecord) };
+                records.push(record);
+            }
+            guard.clear_ready();
+            self.buffer_flow_records(records).await;
+        }
+    }
+
+    async fn buffer_flow_records(&self, records: Vec<common::FlowRecord>) {
+        let vip_owners = self.vip_owners.lock().await;
+        let mut buffer = self.flow_records.lock().await;
+        for record in records {
+            if buffer.records.len() >= FLOW_RECORDS_BUFFER_CAPACITY {
+                buffer.records.pop_front();
+                buffer.dropped += 1;
+            }
+            let gateway = vip_owners.get(&record.backend_key).map(|owner| Gateway {
+                namespace: owner.namespace.clone(),
+                name: owner.name.clone(),
+            });
+            buffer.records.push_back(FlowRecord {
+                client_ip: record.client_key.ip,
+                client_port: record.client_key.port,
+                vip: Some(vip_for(&record.backend_key)),
+                backend: Some(Target {
+                    daddr: record.backend.daddr,
+                    dport: record.backend.dport,
+                    ifindex: ifindex_of(&record.backend),
+                    hostname: String::new(),
+                    draining: false,
+                }),
+                packets: record.packets,
+                bytes: record.bytes,
+                gateway,
+            });
+        }
+    }
+
+    /// Reads the packets the eBPF programs copy to the perf buffer of every CPU, and adds them
+    /// to the running capture.
+    pub async fn run_packet_capture(self, mut perf_array: AsyncPerfEventArray<MapData>) {
+        let cpus = match online_cpus() {
+            Ok(cpus) => cpus,
+            Err((_, err)) => {
+                warn!(
+                    "failed to list the cpus to read captured packets from: {}",
+                    err
+                );
+                return;
+            }
+        };
+        for cpu in cpus {
+            let mut buf = match perf_array.open(cpu, Some(CAPTURE_PERF_BUFFER_PAGES)) {
+                Ok(buf) => buf,
+                Err(err) => {
+                    warn!(
+                        "failed to open the packet capture buffer of cpu {}: {}",
+                        cpu, err
+                    );
+                    continue;
+                }
+            };
+            let server = self.clone();
+            tokio::spawn(async move {
+                let mut buffers = (0..CAPTURE_READ_BATCH)
+                    .map(|_| BytesMut::with_capacity(CAPTURE_MAX_SNAPLEN as usize))
+                    .collect::<Vec<_>>();
+                loop {
+                    match buf.read_events(&mut buffers).await {
+                        Ok(events) => {
+                            server
+                                .buffer_captured_packets(&buffers[..events.read], events.lost)
+                                .await
+                        }
+                        Err(err) => {
+                            warn!(
+                                "failed to read the packet capture buffer of cpu {}: {}",
+                                cpu, err
+                            );
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+    }
+
+    async fn buffer_captured_packets(&self, events: &[BytesMut], lost: usize) {
+        let mut capture = self.capture.lock().await;
+        // packets still in flight when a capture ends are ignored
+        let Some(session) = capture.as_mut() else {
+            return;
+        };
+        session.lost += lost as u64;
+
+        // the eBPF programs timestamp packets on the monotonic clock
+        let boot_time = (SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64)
+            .saturating_sub(monotonic_now_ns());
+        for event in events {
+            if session.packets.len() >= session.limit {
+                break;
+            }
+            let header_len = std::mem::size_of::<CaptureHeader>();
+            if event.len() < header_len {
+                warn!("ignoring a truncated captured packet");
+                continue;
+            }
+            // SAFETY: the eBPF programs only send CaptureHeaders, followed by the packet.
+            let header =
+                unsafe { std::ptr::read_unaligned(event.as_ptr() as *const CaptureHeader) };
+            // the perf buffer pads events, so the end of the packet is taken from the header
+            let end = (header_len + header.captured_len as usize).min(event.len());
+            let stage = if header.stage == CAPTURE_STAGE_REWRITTEN {
+                CaptureStage::Rewritten
+            } else {
+                CaptureStage::Received
+            };
+            session.packets.push((
+                CapturedPacket {
+                    timestamp: boot_time + header.timestamp,
+                    len: header.len,
+                    data: event[header_len..end].to_vec(),
+                },
+                CapturedPacketInfo {
+                    stage: stage.into(),
+                    ifindex: header.ifindex,
+                },
+            ));
+        }
+
+        if session.packets.len() >= session.limit {
+            if let Some(done) = session.done.take() {
+                let _ = done.send(());
+            }
+        }
+    }
+
+    // Captures up to `limit` packets of the vip, waiting at most `timeout` for them. Returns
+    // None when another capture is running.
+    async fn capture_packets(
+        &self,
+        key: BackendKey,
+        limit: usize,
+        snaplen: u32,
+        timeout: Duration,
+    ) -> Result<Option<CaptureSession>, Error> {
+        let (done, wait) = oneshot::channel();
+        {
+            let mut capture = self.capture.lock().await;
+            if capture.is_some() {
+                return Ok(None);
+            }
+            *capture = Some(CaptureSession {
+                packets: Vec::with_capacity(limit),
+                limit,
+                lost: 0,
+                done: Some(done),
+            });
+        }
+
+        let mut config = CaptureConfig {
+            backend_key: key,
+            enabled: 1,
+            snaplen,
+        };
+        let started = self.capture_config_map.lock().await.set(0, config, 0);
+        if started.is_ok() {
+            let _ = tokio::time::timeout(timeout, wait).await;
+        }
+        config.enabled = 0;
+        let stopped = self.capture_config_map.lock().await.set(0, config, 0);
+        let session = self.capture.lock().await.take();
+        started?;
+        stopped?;
+        Ok(session)
+    }
+
+    /// Periodically resolves the hostnames of targets again, see refresh_hostnames.
+    pub async fn run_hostname_refresh(self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = self.refresh_hostnames().await {
+                warn!("failed to refresh the hostnames of targets: {}", err);
+            }
+        }
+    }
+
+    /// Resolves the hostnames of targets again and updates the backends of the vips whose
+    /// addresses changed. Vips that were updated while their hostnames were being resolved are
+    /// left alone, as are those whose hostnames fail to resolve. The round-robin position of a
+    /// vip is kept, unless its backends shrank below it.
+    pub async fn refresh_hostnames(&self) -> Result<(), Error> {
+        let snapshot = self.hostname_targets.lock().await.clone();
+        let max_backends = self.limits.max_backends_per_vip as usize;
+        for (key, targets) in snapshot {
+            let backend_list =
+                match backend_list_for(self.resolver.as_ref(), &targets, max_backends).await {
+                    Ok(backend_list) => backend_list,
+                    Err(status) => {
+                        warn!(
+                            "keeping the current backends of vip {}:{}: {}",
+                            Ipv4Addr::from(key.ip),
+                            key.port,
+                            status.message()
+                        );
+                        continue;
+                    }
+                };
+
+            // Updates lock hostname_targets before the maps, so once the maps are locked no
+            // update can come between the check of the targets and the write of the backends,
+            // and hostname_targets can be released before the write.
+            let hostname_targets = self.hostname_targets.lock().await;
+            if hostname_targets.get(&key) != Some(&targets) {
+                continue;
+            }
+            let mut backends_map = self.backends_map.lock().await;
+            let mut backend_slots_map = self.backend_slots_map.lock().await;
+            drop(hostname_targets);
+
+            let current = read_backends(&backends_map, &backend_slots_map, key)?;
+            if current == backend_list {
+                continue;
+            }
+            write_backends(
+                &mut backends_map,
+                &mut backend_slots_map,
+                key,
+                &backend_list,
+            )?;
+            drop(backend_slots_map);
+            {
+                let mut gateway_indexes_map = self.gateway_indexes_map.lock().await;
+                let index = gateway_indexes_map.get(&key, 0).unwrap_or(0);
+                if index >= backend_list.list.backends_len {
+                    gateway_indexes_map.insert(key, 0, 0)?;
+                }
+            }
+            drop(backends_map);
+
+            self.queue_removed_backends(
+                key,
+                Some(&current),
+                &backend_list,
+                targets.removed_backend_policy(),
+            )
+            .await;
+            info!(
+                "target addresses of vip {}:{} changed, backends updated",
+                Ipv4Addr::from(key.ip),
+                key.port
+            );
+        }
+        Ok(())
+    }
+
+    // Detects (and optionally repairs) inconsistencies between the maps: GATEWAY_INDEXES and
+    // LB_CONNECTIONS entries must refer to a BACKENDS entry, and every BACKENDS entry must have
+    // a GATEWAY_INDEXES entry.
+    async fn reconcile_maps(&self, repair: bool) -> Result<ConsistencyReport, Error> {
+        let backends_map = self.backends_map.lock().await;
+        let backend_keys = backends_map
+            .keys()
+            .collect::<Result<HashSet<BackendKey>, MapError>>()?;
+
+        let mut gateway_indexes_map = self.gateway_indexes_map.lock().await;
+        let index_keys = gateway_indexes_map
+            .keys()
+            .collect::<Result<HashSet<BackendKey>, MapError>>()?;
+        let orphaned_indexes: Vec<&BackendKey> = index_keys.difference(&backend_keys).collect();
+        let missing_indexes: Vec<&BackendKey> = backend_keys.difference(&index_keys).collect();
+
+        let mut tcp_conns_map = self.tcp_conns_map.lock().await;
+        let mut orphaned_connections = Vec::new();
+        for item in tcp_conns_map.iter() {
+            let (client_key, mapping) = item?;
+            if !backend_keys.contains(&mapping.backend_key) {
+                orphaned_connections.push(client_key);
+            }
+        }
+
+        if repair {
+            for key in &orphaned_indexes {
+                gateway_indexes_map.remove(key)?;
+            }
+            for key in &missing_indexes {
+                gateway_indexes_map.insert(**key, 0, 0)?;
+            }
+            for client_key in &orphaned_connections {
+                tcp_conns_map.remove(client_key)?;
+            }
+        }
+
+        Ok(ConsistencyReport {
+            orphaned_indexes: orphaned_indexes.len() as u32,
+            missing_indexes: missing_indexes.len() as u32,
+            orphaned_connections: orphaned_connections.len() as u32,
+            repaired: repair,
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl Backends for BackendService {
+    async fn get_interface_index(
+        &self,
+        request: Request<PodIp>,
+    ) -> Result<Response<InterfaceIndexConfirmation>, Status> {
+        let pod = request.into_inner();
+        let ip = pod.ip;
+        let ip_addr = std::net::Ipv4Addr::from(ip);
+
+        let ifindex = match if_index_for_routing_ip(ip_addr) {
+            Ok(ifindex) => ifindex,
+            Err(err) => {
+                return Err(error_status(
+                    Code::Internal,
+                    ErrorCode::InterfaceIndexNotFound,
+                    err,
+                ))
+            }
+        };
+
+        Ok(Response::new(InterfaceIndexConfirmation { ifindex }))
+    }
+
+    async fn update(&self, request: Request<Targets>) -> Result<Response<Confirmation>, Status> {
+        let gateway = gateway_from_metadata(&request);
+        let targets = request.into_inner();
+
+        let vip = match targets.vip.clone() {
+            Some(vip) => vip,
+            None => {
+                return Err(error_status(
+                    Code::InvalidArgument,
+                    ErrorCode::MissingVip,
+                    "missing vip ip and port",
+                ))
+            }
+        };
+
+        let key = backend_key_for(&vip);
+        self.check_port_range(&key).await?;
+
+        if targets.full_nat {
+            let snat_ip = self
+                .state_map
+                .lock()
+                .await
+                .get(&STATE_SNAT_IP, 0)
+                .map_err(|err| {
+                    error_status(
+                        Code::Internal,
+                        ErrorCode::MapUpdateFailed,
+                        format!("failed to read the SNAT address: {}", err),
+                    )
+                })?;
+            if snat_ip == 0 {
+                return Err(error_status(
+                    Code::FailedPrecondition,
+                    ErrorCode::SnatNotConfigured,
+                    "full NAT requires the dataplane to be started with --snat-ip",
+                ));
+            }
+        }
+
+        let backend_list = backend_list_for(
+            self.resolver.as_ref(),
+            &targets,
+            self.limits.max_backends_per_vip as usize,
+        )
+        .await?;
+        let count = backend_list.list.backends_len;
+
+        // the owner is checked and recorded under the same lock, so that two Gateways can't both
+        // take a vip that has no owner yet
+        let mut vip_owners = self.vip_owners.lock().await;
+        check_owner(&vip_owners, &key, &gateway)?;
+
+        let mut hostname_targets = self.hostname_targets.lock().await;
+        let mut result = self
+            .insert_and_reset_index(key, backend_list, targets.removed_backend_policy())
+            .await;
+        if result.is_ok() {
+            result = self.insert_port_range(key).await;
+        }
+        match result {
+            Ok(_) => {
+                if has_hostnames(&targets) {
+                    hostname_targets.insert(key, targets);
+                } else {
+                    hostname_targets.remove(&key);
+                }
+                drop(hostname_targets);
+
+                if let Some(gateway) = gateway {
+                    vip_owners.insert(key, gateway.clone());
+                    info!(
+                        "vip {}:{} updated for gateway {}",
+                        Ipv4Addr::from(vip.ip),
+                        vip.port,
+                        gateway
+                    );
+                }
+                Ok(Response::new(Confirmation {
+                    confirmation: format!(
+                        "success, vip {}:{} was updated with {} backends",
+                        Ipv4Addr::from(vip.ip),
+                        vip.port,
+                        count,
+                    ),
+                }))
+            }
+            Err(err) => Err(error_status(
+                Code::Internal,
+                ErrorCode::MapUpdateFailed,
+                format!("failure: {}", err),
+            )),
+        }
+    }
+
+    async fn delete(&self, request: Request<Vip>) -> Result<Response<Confirmation>, Status> {
+        let gateway = gateway_from_metadata(&request);
+        let vip = request.into_inner();
+
+        let key = backend_key_for(&vip);
+
+        let addr_ddn = Ipv4Addr::from(vip.ip);
+
+        let mut vip_owners = self.vip_owners.lock().await;
+        check_owner(&vip_owners, &key, &gateway)?;
+        let result = self.remove(key).await;
+        if result.is_ok() || result.as_ref().is_err_and(is_missing_key_error) {
+            vip_owners.remove(&key);
+        }
+        drop(vip_owners);
+
+        match result {
+            Ok(()) => {
+                if let Some(gateway) = gateway {
+                    info!(
+                        "vip {}:{} deleted for gateway {}",
+                        addr_ddn, vip.port, gateway
+                    );
+                }
+                Ok(Response::new(Confirmation {
+                    confirmation: format!("success, vip {}:{} was deleted", addr_ddn, vip.port),
+                }))
+            }
+            Err(err) if is_missing_key_error(&err) => Ok(Response::new(Confirmation {
+                confirmation: format!("success, vip {}:{} did not exist", addr_ddn, vip.port),
+            })),
+            Err(err) => Err(error_status(
+                Code::Internal,
+                ErrorCode::MapUpdateFailed,
+                format!("failure: {}", err),
+            )),
+        }
+    }
+
+    async fn delete_by_gateway(
+        &self,
+        request: Request<Gateway>,
+    ) -> Result<Response<Confirmation>, Status> {
+        let gateway = request.into_inner();
+        let gateway = NamespacedName::new(gateway.namespace, gateway.name);
+
+        let keys: Vec<BackendKey> = self
+            .vip_owners
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, owner)| **owner == gateway)
+            .map(|(key, _)| *key)
+            .collect();
+
+        for key in &keys {
+            match self.remove(*key).await {
+                Ok(()) => {}
+                Err(err) if is_missing_key_error(&err) => {}
+                Err(err) => {
+                    return Err(error_status(
+                        Code::Internal,
+                        ErrorCode::MapUpdateFailed,
+                        format!("failure: {}", err),
+                    ))
+                }
+            }
+            self.vip_owners.lock().await.remove(key);
+            info!(
+                "vip {}:{} deleted for gateway {}",
+                Ipv4Addr::from(key.ip),
+                key.port,
+                gateway
+            );
+        }
+
+        Ok(Response::new(Confirmation {
+            confirmation: format!(
+                "success, {} vips were deleted for gateway {}",
+                keys.len(),
+                gateway
+            ),
+        }))
+    }
+
+    async fn check_consistency(
+        &self,
+        request: Request<ConsistencyCheck>,
+    ) -> Result<Response<ConsistencyReport>, Status> {
+        let repair = request.into_inner().repair;
+
+        match self.reconcile_maps(repair).await {
+            Ok(report) => {
+                if report.orphaned_indexes > 0
+                    || report.missing_indexes > 0
+                    || report.orphaned_connections > 0
+                {
+                    info!(
+                        "map inconsistencies found (repaired: {}): {} orphaned indexes, {} missing indexes, {} orphaned connections",
+                        repair,
+                        report.orphaned_indexes,
+                        report.missing_indexes,
+                        report.orphaned_connections
+                    );
+                }
+                Ok(Response::new(report))
+            }
+            Err(err) => Err(error_status(
+                Code::Internal,
+                ErrorCode::MapUpdateFailed,
+                format!("failure: {}", err),
+            )),
+        }
+    }
+
+    async fn detach_program(
+        &self,
+        request: Request<Program>,
+    ) -> Result<Response<Confirmation>, Status> {
+        let name = request.into_inner().name;
+
+        let program = match self.attached_programs.lock().await.remove(&name) {
+            Some(program) => program,
+            None => {
+                return Err(error_status(
+                    Code::NotFound,
+                    ErrorCode::ProgramNotAttached,
+                    format!("program {} is not attached", name),
+                ))
+            }
+        };
+
+        match program.link.detach() {
+            Ok(()) => {
+                info!("program {} detached", name);
+                Ok(Response::new(Confirmation {
+                    confirmation: format!("success, program {} was detached", name),
+                }))
+            }
+            Err(err) => Err(error_status(
+                Code::Internal,
+                ErrorCode::ProgramDetachFailed,
+                format!("failed to detach program {}: {}", name, err),
+            )),
+        }
+    }
+
+    async fn probe(&self, request: Request<ProbeRequest>) -> Result<Response<ProbeResult>, Status> {
+        let probe_request = request.into_inner();
+        let protocol = probe_request.protocol();
+
+        let vip = match probe_request.vip {
+            Some(vip) => vip,
+            None => {
+                return Err(error_status(
+                    Code::InvalidArgument,
+                    ErrorCode::MissingVip,
+                    "missing vip ip and port",
+                ))
+            }
+        };
+        let destination = (Ipv4Addr::from(vip.ip), vip.port as u16);
+        let client = match (probe_request.client_ip, probe_request.client_port) {
+            (0, 0) => (probe::DEFAULT_CLIENT_IP, probe::DEFAULT_CLIENT_PORT),
+            (ip, port) => (Ipv4Addr::from(ip), port as u16),
+        };
+
+        let attached_programs = self.attached_programs.lock().await;
+        let ingress = match attached_programs.get("tc_ingress") {
+            Some(program) => program,
+            None => {
+                return Err(error_status(
+                    Code::FailedPrecondition,
+                    ErrorCode::ProgramNotAttached,
+                    "program tc_ingress is not attached",
+                ))
+            }
+        };
+
+        // The ingress program tracks the synthetic client like any other, so its entry is
+        // removed afterwards unless it belongs to a real connection.
+        let client_key = ClientKey {
+            ip: u32::from(client.0),
+            port: client.1 as u32,
+        };
+        let mut tcp_conns_map = self.tcp_conns_map.lock().await;
+        let tracked = tcp_conns_map.get(&client_key, 0).is_ok();
+        // It also moves the round-robin position of the vip on, and may pin the client to its
+        // backend, which are put back too so that probes don't change where clients go.
+        let key = backend_key_for(&Vip {
+            protocol: protocol as i32,
+            ..vip.clone()
+        });
+        let mut gateway_indexes_map = self.gateway_indexes_map.lock().await;
+        let index = gateway_indexes_map.get(&key, 0).ok();
+        let affinity_key = AffinityKey {
+            client_ip: client_key.ip,
+            backend_key: key,
+        };
+        let mut affinities_map = self.affinities_map.lock().await;
+        let pinned = affinities_map.get(&affinity_key, 0).ok();
+
+        let tos = probe_request.tos as u8;
+        let mut packet = probe::build_packet(protocol, client, destination);
+        probe::set_tos(&mut packet, tos);
+        let output = probe::test_run(&ingress.fd, &packet);
+
+        if !tracked {
+            let _ = tcp_conns_map.remove(&client_key);
+        }
+        if let Some(index) = index {
+            let _ = gateway_indexes_map.insert(key, index, 0);
+        }
+        let _ = match pinned {
+            Some(affinity) => affinities_map.insert(affinity_key, affinity, 0),
+            None => affinities_map.remove(&affinity_key),
+        };
+
+        let output = match output {
+            Ok(output) => output,
+            Err(err) => {
+                return Err(error_status(
+                    Code::Internal,
+                    ErrorCode::ProbeFailed,
+                    format!("failure: {}", err),
+                ))
+            }
+        };
+        let (daddr, dport) = probe::packet_destination(&output.data).ok_or_else(|| {
+            error_status(
+                Code::Internal,
+                ErrorCode::ProbeFailed,
+                "the datapath returned a truncated packet",
+            )
+        })?;
+
+        Ok(Response::new(ProbeResult {
+            retval: output.retval as i32,
+            daddr: daddr.into(),
+            dport: dport as u32,
+            rewritten: (daddr, dport) != destination,
+            tos: probe::packet_tos(&output.data).unwrap_or_default() as u32,
+            checksum_valid: probe::ipv4_checksum_valid(&output.data),
+        }))
+    }
+
+    async fn swap(&self, request: Request<VipPair>) -> Result<Response<Confirmation>, Status> {
+        let gateway = gateway_from_metadata(&request);
+        let pair = request.into_inner();
+
+        let (first, second) = match (pair.first, pair.second) {
+            (Some(first), Some(second)) => (first, second),
+            _ => {
+                return Err(error_status(
+                    Code::InvalidArgument,
+                    ErrorCode::MissingVip,
+                    "missing vip ip and port",
+                ))
+            }
+        };
+        let first_key = backend_key_for(&first);
+        let second_key = backend_key_for(&second);
+        self.check_vip_owner(&first_key, &gateway).await?;
+        self.check_vip_owner(&second_key, &gateway).await?;
+
+        match self.swap_backends(first_key, second_key).await {
+            Ok(()) => Ok(Response::new(Confirmation {
+                confirmation: format!(
+                    "success, backends of vips {}:{} and {}:{} were swapped",
+                    Ipv4Addr::from(first.ip),
+                    first.port,
+                    Ipv4Addr::from(second.ip),
+                    second.port
+                ),
+            })),
+            Err(err) => Err(error_status(
+                Code::Internal,
+                ErrorCode::MapUpdateFailed,
+                format!("failure: {}", err),
+            )),
+        }
+    }
+
+    async fn drain_node(
+        &self,
+        request: Request<DrainRequest>,
+    ) -> Result<Response<DrainStatus>, Status> {
+        let draining = request.into_inner().draining;
+
+        match self.drain(draining).await {
+            Ok(status) => {
+                if let Some(draining) = draining {
+                    info!(
+                        "draining {}, {} connections active",
+                        if draining { "started" } else { "stopped" },
+                        status.active_connections
+                    );
+                }
+                Ok(Response::new(status))
+            }
+            Err(err) => Err(error_status(
+                Code::Internal,
+                ErrorCode::MapUpdateFailed,
+                format!("failure: {}", err),
+            )),
+        }
+    }
+
+    async fn list(&self, request: Request<ListRequest>) -> Result<Response<TargetsList>, Status> {
+        let request = request.into_inner();
+        let after = match request.page_token.as_str() {
+            "" => None,
+            token => Some(parse_page_token(token).ok_or_else(|| {
+                error_status(
+                    Code::InvalidArgument,
+                    ErrorCode::InvalidPageToken,
+                    format!("invalid page token {}", token),
+                )
+            })?),
+        };
+
+        match self.list_targets(request.page_size as usize, after).await {
+            Ok((targets, next)) => Ok(Response::new(TargetsList {
+                targets,
+                next_page_token: next.as_ref().map(page_token).unwrap_or_default(),
+            })),
+            Err(err) => Err(error_status(
+                Code::Internal,
+                ErrorCode::MapUpdateFailed,
+                format!("failure: {}", err),
+            )),
+        }
+    }
+
+    async fn activate(
+        &self,
+        _request: Request<ActivateRequest>,
+    ) -> Result<Response<Confirmation>, Status> {
+        let mut state_map = self.state_map.lock().await;
+        let was_standby = state_map
+            .get(&STATE_STANDBY, 0)
+            .is_ok_and(|standby| standby == 1);
+        if let Err(err) = state_map.set(STATE_STANDBY, 0, 0) {
+            return Err(error_status(
+                Code::Internal,
+                ErrorCode::MapUpdateFailed,
+                format!("failure: {}", err),
+            ));
+        }
+
+        let confirmation = if was_standby {
+            info!("dataplane activated");
+            "success, dataplane activated"
+        } else {
+            "success, dataplane was already active"
+        };
+        Ok(Response::new(Confirmation {
+            confirmation: confirmation.to_string(),
+        }))
+    }
+
+    async fn get_dataplane_info(
+        &self,
+        _request: Request<DataplaneInfoRequest>,
+    ) -> Result<Response<DataplaneInfo>, Status> {
+        match self.check_occupancy().await {
+            Ok(info) => Ok(Response::new(info)),
+            Err(err) => Err(error_status(
+                Code::Internal,
+                ErrorCode::MapUpdateFailed,
+                format!("failure: {}", err),
+            )),
+        }
+    }
+
+    async fn collect_flows(
+        &self,
+        _request: Request<CollectFlowsRequest>,
+    ) -> Result<Response<FlowRecords>, Status> {
+        let mut buffer = self.flow_records.lock().await;
+        let records = buffer.records.drain(..).collect();
+        let dropped = std::mem::take(&mut buffer.dropped);
+        if dropped > 0 {
+            warn!("dropped {} flow records since the last collection", dropped);
+        }
+        Ok(Response::new(FlowRecords { records, dropped }))
+    }
+
+    async fn get_gateway_index(
+        &self,
+        request: Request<Vip>,
+    ) -> Result<Response<GatewayIndex>, Status> {
+        let vip = request.into_inner();
+        let key = backend_key_for(&vip);
+
+        let result = self.gateway_indexes_map.lock().await.get(&key, 0);
+        match result {
+            Ok(index) => Ok(Response::new(GatewayIndex {
+                vip: Some(vip),
+                index: index as u32,
+            })),
+            Err(MapError::KeyNotFound) => Err(error_status(
+                Code::NotFound,
+                ErrorCode::VipNotFound,
+                format!("vip {}:{} not found", Ipv4Addr::from(vip.ip), vip.port),
+            )),
+            Err(err) => Err(error_status(
+                Code::Internal,
+                ErrorCode::MapUpdateFailed,
+                format!("failure: {}", err),
+            )),
+        }
+    }
+
+    async fn set_gateway_index(
+        &self,
+        request: Request<GatewayIndex>,
+    ) -> Result<Response<Confirmation>, Status> {
+        let gateway = gateway_from_metadata(&request);
+        let request = request.into_inner();
+        let vip = match request.vip {
+            Some(vip) => vip,
+            None => {
+                return Err(error_status(
+                    Code::InvalidArgument,
+                    ErrorCode::MissingVip,
+                    "missing vip ip and port",
+                ))
+            }
+        };
+        let key = backend_key_for(&vip);
+        let addr_ddn = Ipv4Addr::from(vip.ip);
+        self.check_vip_owner(&key, &gateway).await?;
+
+        let out_of_range = |backends_len| {
+            error_status(
+                Code::InvalidArgument,
+                ErrorCode::GatewayIndexOutOfRange,
+                format!(
+                    "index {} is out of range for the {} backends of vip {}:{}",
+                    request.index, backends_len, addr_ddn, vip.port
+                ),
+            )
+        };
+        let index = u16::try_from(request.index).map_err(|_| out_of_range(u16::MAX))?;
+
+        match BackendService::set_gateway_index(self, key, index).await {
+            Ok(backends_len) if index >= backends_len => Err(out_of_range(backends_len)),
+            Ok(_) => Ok(Response::new(Confirmation {
+                confirmation: format!(
+                    "success, index of vip {}:{} was set to {}",
+                    addr_ddn, vip.port, index
+                ),
+            })),
+            Err(MapError::KeyNotFound) => Err(error_status(
+                Code::NotFound,
+                ErrorCode::VipNotFound,
+                format!("vip {}:{} not found", addr_ddn, vip.port),
+            )),
+            Err(err) => Err(error_status(
+                Code::Internal,
+                ErrorCode::MapUpdateFailed,
+                format!("failure: {}", err),
+            )),
+        }
+    }
+
+    async fn capture_packets(
+        &self,
+        request: Request<CaptureRequest>,
+    ) -> Result<Response<PacketCapture>, Status> {
+        let request = request.into_inner();
+        let vip = match request.vip {
+            Some(vip) => vip,
+            None => {
+                return Err(error_status(
+                    Code::InvalidArgument,
+                    ErrorCode::MissingVip,
+                    "missing vip ip and port",
+                ))
+            }
+        };
+        let key = backend_key_for(&vip);
+        let addr_ddn = Ipv4Addr::from(vip.ip);
+
+        if self.backends_map.lock().await.get(&key, 0).is_err() {
+            return Err(error_status(
+                Code::NotFound,
+                ErrorCode::VipNotFound,
+                format!("vip {}:{} not found", addr_ddn, vip.port),
+            ));
+        }
+
+        let count = match request.count {
+            0 => DEFAULT_CAPTURE_COUNT,
+            count => count.min(MAX_CAPTURE_COUNT),
+        };
+        let snaplen = match request.snaplen {
+            0 => CAPTURE_MAX_SNAPLEN,
+            snaplen => snaplen.min(CAPTURE_MAX_SNAPLEN),
+        };
+        let timeout = match request.timeout_seconds {
+            0 => DEFAULT_CAPTURE_TIMEOUT,
+            seconds => Duration::from_secs(seconds as u64).min(MAX_CAPTURE_TIMEOUT),
+        };
+
+        // every packet is captured as received and once rewritten
+        let limit = 2 * count as usize;
+        match BackendService::capture_packets(self, key, limit, snaplen, timeout).await {
+            Ok(Some(session)) => {
+                let mut packets = session.packets;
+                // the packets of each CPU are read separately
+                packets.sort_by_key(|(packet, _)| packet.timestamp);
+                let (packets, infos): (Vec<_>, Vec<_>) = packets.into_iter().unzip();
+                info!(
+                    "captured {} packets of vip {}:{}",
+                    packets.len(),
+                    addr_ddn,
+                    vip.port
+                );
+                Ok(Response::new(PacketCapture {
+                    pcap: write_pcap(&packets, snaplen),
+                    packets: infos,
+                    lost: session.lost,
+                }))
+            }
+            Ok(None) => Err(error_status(
+                Code::Unavailable,
+                ErrorCode::CaptureInProgress,
+                "another packet capture is running",
+            )),
+            Err(err) => Err(error_status(
+                Code::Internal,
+                ErrorCode::MapUpdateFailed,
+                format!("failure: {}", err),
+            )),
+        }
+    }
+
+    async fn get_vip_stats(
+        &self,
+        _request: Request<VipStatsRequest>,
+    ) -> Result<Response<VipStatsList>, Status> {
+        match self.vip_stats().await {
+            Ok(stats) => Ok(Response::new(VipStatsList { stats })),
+            Err(err) => Err(error_status(
+                Code::Internal,
+                ErrorCode::MapUpdateFailed,
+                format!("failed to read the vip stats: {}", err),
+            )),
+        }
+    }
+
+    async fn set_runtime_config(
+        &self,
+        request: Request<RuntimeConfig>,
+    ) -> Result<Response<RuntimeConfig>, Status> {
+        let update = request.into_inner();
+        let invalid = |setting, value| {
+            error_status(
+                Code::InvalidArgument,
+                ErrorCode::InvalidRuntimeConfig,
+                format!("unsupported {} {}", setting, value),
+            )
+        };
+        if let Some(level) = update.log_level {
+            LogLevel::try_from(level).map_err(|_| invalid("log level", level))?;
+        }
+        if let Some(policy) = update.drop_policy {
+            DropPolicy::try_from(policy).map_err(|_| invalid("drop policy", policy))?;
+        }
+        if let Some(mss) = update.tcp_mss_clamp {
+            if mss != 0 && !(TCP_MIN_MSS..=u16::MAX as u32).contains(&mss) {
+                return Err(error_status(
+                    Code::InvalidArgument,
+                    ErrorCode::InvalidRuntimeConfig,
+                    format!(
+                        "unsupported TCP MSS clamp {}, it must be 0 or between {} and {}",
+                        mss,
+                        TCP_MIN_MSS,
+                        u16::MAX
+                    ),
+                ));
+            }
+        }
+
+        match self.runtime_config(&update).await {
+            Ok(config) => {
+                info!("runtime config updated: {:?}", config);
+                Ok(Response::new(config))
+            }
+            Err(err) => Err(error_status(
+                Code::Internal,
+                ErrorCode::MapUpdateFailed,
+                format!("failure: {}", err),
+            )),
+        }
+    }
+
+    async fn get_drop_stats(
+        &self,
+        _request: Request<DropStatsRequest>,
+    ) -> Result<Response<DropStats>, Status> {
+        match self.drop_stats().await {
+            Ok(stats) => Ok(Response::new(stats)),
+            Err(err) => Err(error_status(
+                Code::Internal,
+                ErrorCode::MapUpdateFailed,
+                format!("failed to read the drop stats: {}", err),
+            )),
+        }
+    }
+
+    async fn set_acl(&self, request: Request<Acl>) -> Result<Response<Confirmation>, Status> {
+        let gateway = gateway_from_metadata(&request);
+        let acl = request.into_inner();
+        let vip = match acl.vip.clone() {
+            Some(vip) => vip,
+            None => {
+                return Err(error_status(
+                    Code::InvalidArgument,
+                    ErrorCode::MissingVip,
+                    "missing vip ip and port",
+                ))
+            }
+        };
+        let key = backend_key_for(&vip);
+        let addr_ddn = Ipv4Addr::from(vip.ip);
+        self.check_vip_owner(&key, &gateway).await?;
+
+        if acl.cidrs.len() > MAX_ACL_CIDRS as usize {
+            return Err(error_status(
+                Code::InvalidArgument,
+                ErrorCode::InvalidAcl,
+                format!(
+                    "the ACL of vip {}:{} has {} CIDRs, more than the maximum of {}",
+                    addr_ddn,
+                    vip.port,
+                    acl.cidrs.len(),
+                    MAX_ACL_CIDRS
+                ),
+            ));
+        }
+        if let Err(cidr) = acl_entries(key, &acl) {
+            return Err(error_status(
+                Code::InvalidArgument,
+                ErrorCode::InvalidAcl,
+                format!(
+                    "{}/{} of the ACL of vip {}:{} is not a CIDR",
+                    Ipv4Addr::from(cidr.ip),
+                    cidr.prefix_len,
+                    addr_ddn,
+                    vip.port
+                ),
+            ));
+        }
+
+        // the vip can't be removed while its ACL is set, which would leave the ACL behind
+        let backends_map = self.backends_map.lock().await;
+        match backends_map.get(&key, 0) {
+            Ok(_) => {}
+            Err(MapError::KeyNotFound) => {
+                return Err(error_status(
+                    Code::NotFound,
+                    ErrorCode::VipNotFound,
+                    format!("vip {}:{} not found", addr_ddn, vip.port),
+                ))
+            }
+            Err(err) => {
+                return Err(error_status(
+                    Code::Internal,
+                    ErrorCode::MapUpdateFailed,
+                    format!("failure: {}", err),
+                ))
+            }
+        }
+        let cidrs = acl.cidrs.len();
+        let mode = acl.mode();
+        let result = self.replace_acl(key, Some(acl)).await;
+        drop(backends_map);
+
+        match result {
+            Ok(()) => Ok(Response::new(Confirmation {
+                confirmation: format!(
+                    "success, the {} of vip {}:{} has {} CIDRs",
+                    mode.as_str_name().to_lowercase(),
+                    addr_ddn,
+                    vip.port,
+                    cidrs
+                ),
+            })),
+            Err(err) => Err(error_status(
+                Code::Internal,
+                ErrorCode::MapUpdateFailed,
+                format!("failure: {}", err),
+            )),
+        }
+    }
+
+    async fn get_acl(&self, request: Request<Vip>) -> Result<Response<Acl>, Status> {
+        let vip = request.into_inner();
+        let key = backend_key_for(&vip);
+
+        // vips without an ACL allow every client, as an empty denylist does
+        let acl = self.acls.lock().await.get(&key).cloned();
+        Ok(Response::new(acl.unwrap_or(Acl {
+            vip: Some(vip),
+            mode: AclMode::Denylist as i32,
+            cidrs: Vec::new(),
+        })))
+    }
+
+    async fn delete_acl(&self, request: Request<Vip>) -> Result<Response<Confirmation>, Status> {
+        let gateway = gateway_from_metadata(&request);
+        let vip = request.into_inner();
+        let key = backend_key_for(&vip);
+        let addr_ddn = Ipv4Addr::from(vip.ip);
+        self.check_vip_owner(&key, &gateway).await?;
+
+        match self.replace_acl(key, None).await {
+            Ok(()) => Ok(Response::new(Confirmation {
+                confirmation: format!("success, vip {}:{} allows every client", addr_ddn, vip.port),
+            })),
+            Err(err) => Err(error_status(
+                Code::Internal,
+                ErrorCode::MapUpdateFailed,
+                format!("failure: {}", err),
+            )),
+        }
+    }
+
+    async fn drain_backend(
+        &self,
+        request: Request<TargetRef>,
+    ) -> Result<Response<Confirmation>, Status> {
+        self.drain_targets(request, true).await
+    }
+
+    async fn undrain_backend(
+        &self,
+        request: Request<TargetRef>,
+    ) -> Result<Response<Confirmation>, Status> {
+        self.drain_targets(request, false).await
+    }
+}
+
+/// Returns the current time on the clock of bpf_ktime_get_ns, which the eBPF programs stamp
+/// tracked connections and captured packets with.
+pub fn monotonic_now_ns() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: ts is a valid timespec for clock_gettime to write to.
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
 }
@@ -0,0 +1,257 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! TLS of the API server.
+//!
+//! The TLS config of Tonic doesn't let us choose the versions and cipher suites of TLS, nor
+//! reload certificates, so the API server terminates TLS itself with rustls, the way Tonic does,
+//! and serves the established TLS streams.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, info, warn};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::mpsc,
+    time::timeout,
+};
+use tokio_rustls::{
+    rustls::{
+        crypto::{ring, CryptoProvider},
+        server::WebPkiClientVerifier,
+        version::TLS13,
+        RootCertStore, ServerConfig, SupportedCipherSuite, SupportedProtocolVersion,
+        DEFAULT_VERSIONS,
+    },
+    server::TlsStream,
+    TlsAcceptor,
+};
+use tokio_stream::{wrappers::UnboundedReceiverStream, Stream, StreamExt};
+
+use crate::config::{TLSOptions, TLSPaths, TLSVersion};
+
+/// The ALPN protocol of gRPC, which clients must negotiate.
+pub const ALPN_H2: &[u8] = b"h2";
+
+// The versions of TLS accepted with a minimum version of TLS 1.3.
+static TLS13_VERSIONS: &[&SupportedProtocolVersion] = &[&TLS13];
+
+// How long clients have to complete their handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Returns the versions of TLS accepted with the options.
+pub fn protocol_versions(options: &TLSOptions) -> &'static [&'static SupportedProtocolVersion] {
+    match options.min_tls_version {
+        TLSVersion::V1_2 => DEFAULT_VERSIONS,
+        TLSVersion::V1_3 => TLS13_VERSIONS,
+    }
+}
+
+/// Returns the cipher suites accepted with the options, in the order of preference of rustls.
+///
+/// Cipher suites are named by their IANA names, like TLS_AES_128_GCM_SHA256, or by those of
+/// rustls, like TLS13_AES_128_GCM_SHA256. Unknown cipher suites and those of versions older than
+/// the minimum version are errors, so that a typo doesn't silently weaken the configuration.
+pub fn cipher_suites(options: &TLSOptions) -> Result<Vec<SupportedCipherSuite>> {
+    let versions = protocol_versions(options);
+    let supported = ring::ALL_CIPHER_SUITES;
+    if options.cipher_suites.is_empty() {
+        return Ok(supported
+            .iter()
+            .filter(|suite| versions.contains(&suite.version()))
+            .copied()
+            .collect());
+    }
+
+    let mut suites = Vec::new();
+    for name in &options.cipher_suites {
+        let Some(suite) = supported
+            .iter()
+            .find(|suite| suite_names(suite).any(|n| n.eq_ignore_ascii_case(name.trim())))
+        else {
+            return Err(anyhow!("Unsupported TLS cipher suite {}", name));
+        };
+        if !versions.contains(&suite.version()) {
+            return Err(anyhow!(
+                "TLS cipher suite {} is of a version older than the minimum version",
+                name
+            ));
+        }
+        suites.push(suite.suite());
+    }
+    Ok(supported
+        .iter()
+        .filter(|suite| suites.contains(&suite.suite()))
+        .copied()
+        .collect())
+}
+
+// The names of the cipher suite: that of rustls and, for TLS 1.3, its IANA name.
+fn suite_names(suite: &SupportedCipherSuite) -> impl Iterator<Item = String> {
+    let name = suite.suite().as_str().unwrap_or_default();
+    let iana = name
+        .strip_prefix("TLS13_")
+        .map(|name| format!("TLS_{}", name));
+    [name.to_string()].into_iter().chain(iana)
+}
+
+/// Returns the cryptography of rustls restricted to the cipher suites of the options.
+pub fn crypto_provider(options: &TLSOptions) -> Result<Arc<CryptoProvider>> {
+    Ok(Arc::new(CryptoProvider {
+        cipher_suites: cipher_suites(options)?,
+        ..ring::default_provider()
+    }))
+}
+
+/// Returns the acceptor of TLS connections with the certificates of the files, restricted to
+/// the versions and cipher suites of the options.
+pub fn acceptor(paths: &TLSPaths, options: &TLSOptions) -> Result<TlsAcceptor> {
+    // TLS implementation drawn from Tonic.
+    // See: https://github.com/hyperium/tonic/blob/master/tonic/src/transport/service/tls.rs
+    let provider = crypto_provider(options)?;
+
+    let cert = fs::read(&paths.server_certificate).with_context(|| {
+        format!(
+            "Failed to read certificate from {:?}",
+            paths.server_certificate
+        )
+    })?;
+    let key = fs::read(&paths.server_private_key)
+        .with_context(|| format!("Failed to read key from {:?}", paths.server_private_key))?;
+    let cert = rustls_pemfile::certs(&mut cert.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid certificate in {:?}", paths.server_certificate))?;
+    let key = rustls_pemfile::private_key(&mut key.as_slice())
+        .ok()
+        .flatten()
+        .ok_or_else(|| anyhow!("No private key in {:?}", paths.server_private_key))?;
+
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(protocol_versions(options))?;
+    let builder = match &paths.client_certificate_authority_root {
+        None => builder.with_no_client_auth(),
+        Some(client_ca_path) => {
+            let client_ca_cert = fs::read(client_ca_path)
+                .with_context(|| format!("Failed to read client CA from {:?}", client_ca_path))?;
+            let mut client_ca_roots = RootCertStore::empty();
+            for cert in rustls_pemfile::certs(&mut client_ca_cert.as_slice()) {
+                client_ca_roots.add(cert?)?;
+            }
+            let verifier =
+                WebPkiClientVerifier::builder_with_provider(client_ca_roots.into(), provider)
+                    .build()?;
+            builder.with_client_cert_verifier(verifier)
+        }
+    };
+    let mut server_config = builder.with_single_cert(cert, key)?;
+    server_config.alpn_protocols.push(ALPN_H2.into());
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// Replaces the acceptor with one with the new certificates when the files change, checking
+/// them every interval. Connections established before keep their certificates.
+///
+/// Kubelet updates the volumes of Secrets atomically, by pointing the `..data` symlink their
+/// files link through at a new directory, so the files are told apart by the paths they resolve
+/// to as well as their modification times. When the new certificates can't be loaded, the
+/// previous ones are kept and loading them is retried at the next check.
+pub async fn watch(
+    paths: TLSPaths,
+    options: TLSOptions,
+    acceptor: Arc<RwLock<TlsAcceptor>>,
+    interval: Duration,
+) {
+    let mut versions = file_versions(&paths);
+    let mut ticker = tokio::time::interval(interval);
+    // the first tick completes immediately
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let current = file_versions(&paths);
+        if current == versions {
+            continue;
+        }
+        match self::acceptor(&paths, &options) {
+            Ok(reloaded) => {
+                *acceptor.write().unwrap() = reloaded;
+                versions = current;
+                info!("reloaded the TLS certificates of the API server");
+            }
+            Err(err) => warn!(
+                "failed to reload TLS certificates, keeping the previous ones: {:#}",
+                err
+            ),
+        }
+    }
+}
+
+// What tells the versions of a file apart: the path it resolves to through symlinks, its
+// modification time and length. None when it can't be read.
+type FileVersion = Option<(PathBuf, SystemTime, u64)>;
+
+fn file_versions(paths: &TLSPaths) -> Vec<FileVersion> {
+    [&paths.server_certificate, &paths.server_private_key]
+        .into_iter()
+        .chain(&paths.client_certificate_authority_root)
+        .map(|path| file_version(path))
+        .collect()
+}
+
+fn file_version(path: &Path) -> FileVersion {
+    let resolved = fs::canonicalize(path).ok()?;
+    let metadata = fs::metadata(&resolved).ok()?;
+    Some((resolved, metadata.modified().ok()?, metadata.len()))
+}
+
+/// Returns the TLS streams of the connections of `incoming`, once their handshake completed.
+///
+/// Handshakes run concurrently, so that a slow client doesn't hold the others back, and the
+/// connections whose handshake failed or timed out are dropped. Each connection is accepted with
+/// the current acceptor, see watch.
+pub fn incoming<S, IO, E>(
+    incoming: S,
+    acceptor: Arc<RwLock<TlsAcceptor>>,
+) -> UnboundedReceiverStream<io::Result<TlsStream<IO>>>
+where
+    S: Stream<Item = Result<IO, E>> + Send + 'static,
+    IO: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    E: std::fmt::Display + Send + 'static,
+{
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        tokio::pin!(incoming);
+        while let Some(stream) = incoming.next().await {
+            // the server stopped
+            if tx.is_closed() {
+                return;
+            }
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    debug!("failed to accept a connection: {}", err);
+                    continue;
+                }
+            };
+            let (acceptor, tx) = (acceptor.read().unwrap().clone(), tx.clone());
+            tokio::spawn(async move {
+                match timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        let _ = tx.send(Ok(stream));
+                    }
+                    Ok(Err(err)) => debug!("TLS handshake failed: {}", err),
+                    Err(_) => debug!("TLS handshake timed out"),
+                }
+            });
+        }
+    });
+    UnboundedReceiverStream::new(rx)
+}
@@ -0,0 +1,159 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+use std::{
+    convert::Infallible,
+    future::{ready, Ready},
+    sync::Arc,
+    time::Duration,
+};
+
+use api_server::metrics::{
+    Histogram, RpcMetrics, RpcMetricsService, RPC_LATENCY_BUCKETS, UNKNOWN_METHOD,
+};
+use api_server::server::{ERROR_CODE_METADATA_KEY, GATEWAY_METADATA_KEY};
+use tonic::{
+    body::{empty_body, BoxBody},
+    codegen::{http, Context, Poll, Service},
+    Code,
+};
+
+#[test]
+fn test_histogram_buckets_and_exemplars() {
+    let mut histogram = Histogram::new(&[0.1, 1.0]);
+    histogram.observe(0.05, Some("default/a"));
+    histogram.observe(0.5, None);
+    histogram.observe(0.7, Some("default/b"));
+    histogram.observe(5.0, Some("other/c"));
+    assert_eq!(histogram.count(), 4);
+    assert_eq!(histogram.exemplar(Some(0.1)).unwrap().gateway, "default/a");
+    // the last observation of the bucket with a Gateway
+    assert_eq!(histogram.exemplar(Some(1.0)).unwrap().gateway, "default/b");
+    assert_eq!(histogram.exemplar(None).unwrap().gateway, "other/c");
+    assert_eq!(histogram.exemplar(Some(2.0)), None);
+
+    let mut out = String::new();
+    histogram.render("rpc", "method=\"Update\"", &mut out);
+    assert_eq!(
+        out,
+        "rpc_bucket{method=\"Update\",le=\"0.1\"} 1 # {gateway=\"default/a\"} 0.05\n\
+         rpc_bucket{method=\"Update\",le=\"1.0\"} 3 # {gateway=\"default/b\"} 0.7\n\
+         rpc_bucket{method=\"Update\",le=\"+Inf\"} 4 # {gateway=\"other/c\"} 5\n\
+         rpc_sum{method=\"Update\"} 6.25\n\
+         rpc_count{method=\"Update\"} 4\n"
+    );
+}
+
+#[test]
+fn test_rpc_metrics_observe() {
+    let metrics = RpcMetrics::default();
+    metrics.observe(
+        "Update",
+        Code::Ok,
+        None,
+        Duration::from_millis(2),
+        Some("default/gw"),
+    );
+    metrics.observe(
+        "Update",
+        Code::InvalidArgument,
+        Some("BLX-1001"),
+        Duration::from_millis(1),
+        Some("not a gateway"),
+    );
+    metrics.observe("Nope", Code::Unimplemented, None, Duration::ZERO, None);
+
+    assert_eq!(metrics.requests("Update", Code::Ok), 1);
+    assert_eq!(metrics.requests("Update", Code::InvalidArgument), 1);
+    assert_eq!(metrics.requests("Nope", Code::Unimplemented), 0);
+    assert_eq!(metrics.requests(UNKNOWN_METHOD, Code::Unimplemented), 1);
+
+    let latency = metrics.latency("Update").unwrap();
+    assert_eq!(latency.count(), 2);
+    assert_eq!(
+        latency.exemplar(Some(0.0025)).unwrap().gateway,
+        "default/gw"
+    );
+    // invalid Gateways aren't exemplars
+    assert_eq!(latency.exemplar(Some(0.001)), None);
+
+    let rendered = metrics.render();
+    assert!(rendered.contains(
+        "blixt_dataplane_rpc_requests_total{method=\"Update\",code=\"InvalidArgument\"} 1\n"
+    ));
+    assert!(rendered.contains(
+        "blixt_dataplane_rpc_errors_total{method=\"Update\",error_code=\"BLX-1001\"} 1\n"
+    ));
+    assert!(rendered.contains("blixt_dataplane_rpc_duration_seconds_count{method=\"Update\"} 2\n"));
+    assert!(rendered.ends_with("# EOF\n"));
+    assert_eq!(
+        rendered
+            .lines()
+            .filter(|line| line
+                .starts_with("blixt_dataplane_rpc_duration_seconds_bucket{method=\"Update\""))
+            .count(),
+        RPC_LATENCY_BUCKETS.len() + 1
+    );
+}
+
+// A service that answers every request with the status it was created with.
+#[derive(Clone)]
+struct StatusService(Option<(Code, &'static str)>);
+
+impl Service<http::Request<()>> for StatusService {
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _req: http::Request<()>) -> Self::Future {
+        let mut response = http::Response::builder();
+        if let Some((code, error_code)) = self.0 {
+            response = response
+                .header("grpc-status", (code as i32).to_string())
+                .header(ERROR_CODE_METADATA_KEY, error_code);
+        }
+        ready(Ok(response.body(empty_body()).unwrap()))
+    }
+}
+
+fn request(method: &str) -> http::Request<()> {
+    http::Request::builder()
+        .uri(format!("/backends.backends/{}", method))
+        .header(GATEWAY_METADATA_KEY, "default/gw")
+        .body(())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_rpc_metrics_service() {
+    let metrics = Arc::new(RpcMetrics::default());
+
+    let mut service = RpcMetricsService::new(StatusService(None), metrics.clone());
+    service.call(request("Update")).await.unwrap();
+    let mut service = RpcMetricsService::new(
+        StatusService(Some((Code::NotFound, "BLX-1003"))),
+        metrics.clone(),
+    );
+    service.call(request("Delete")).await.unwrap();
+
+    assert_eq!(metrics.requests("Update", Code::Ok), 1);
+    assert_eq!(metrics.requests("Delete", Code::NotFound), 1);
+    assert!(metrics.render().contains(
+        "blixt_dataplane_rpc_errors_total{method=\"Delete\",error_code=\"BLX-1003\"} 1\n"
+    ));
+    let latency = metrics.latency("Update").unwrap();
+    assert!(RPC_LATENCY_BUCKETS
+        .iter()
+        .map(|bound| Some(*bound))
+        .chain([None])
+        .any(|bound| latency
+            .exemplar(bound)
+            .is_some_and(|exemplar| exemplar.gateway == "default/gw")));
+}
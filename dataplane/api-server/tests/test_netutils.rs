@@ -0,0 +1,42 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+use anyhow::Error;
+use api_server::netutils::{flags_warnings, ETH_FLAG_LRO};
+
+// see include/uapi/linux/ethtool.h
+const ETH_FLAG_RXHASH: u32 = 1 << 28;
+
+#[test]
+fn test_flags_warnings() {
+    assert!(flags_warnings("eth0", Ok(0)).is_empty());
+    assert!(flags_warnings("eth0", Ok(ETH_FLAG_RXHASH)).is_empty());
+
+    let warnings = flags_warnings("eth0", Ok(ETH_FLAG_LRO | ETH_FLAG_RXHASH));
+    assert_eq!(warnings.len(), 1);
+    assert!(
+        warnings[0].starts_with("large-receive-offload is enabled on eth0"),
+        "{}",
+        warnings[0]
+    );
+    assert!(warnings[0].contains("`ethtool -K eth0 lro off`"));
+}
+
+#[test]
+fn test_flags_warnings_errors() {
+    // devices without offload flags
+    let unsupported = Error::from(std::io::Error::from_raw_os_error(libc::EOPNOTSUPP));
+    assert!(flags_warnings("veth0", Err(unsupported)).is_empty());
+
+    let denied = Error::from(std::io::Error::from_raw_os_error(libc::EPERM));
+    let warnings = flags_warnings("eth0", Err(denied));
+    assert_eq!(warnings.len(), 1);
+    assert!(
+        warnings[0].starts_with("could not read offload settings of eth0: "),
+        "{}",
+        warnings[0]
+    );
+}
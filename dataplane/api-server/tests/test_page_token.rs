@@ -0,0 +1,65 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+use api_server::server::{page_token, parse_page_token};
+use common::{BackendKey, IPPROTO_SCTP, IPPROTO_TCP, IPPROTO_UDP};
+
+fn key(ip: u32, port: u32, port_end: u32, proto: u32) -> BackendKey {
+    BackendKey {
+        ip,
+        port,
+        port_end,
+        proto,
+    }
+}
+
+fn fields(key: &BackendKey) -> (u32, u32, u32, u32) {
+    (key.ip, key.port, key.port_end, key.proto)
+}
+
+#[test]
+fn test_page_token_round_trip() {
+    for key in [
+        key(0, 0, 0, 0),
+        key(0xac12_0064, 80, 0, IPPROTO_TCP),
+        key(0xac12_0064, 8000, 8100, IPPROTO_UDP),
+        key(0x0a00_0001, 9000, 0, IPPROTO_SCTP),
+        key(u32::MAX, u32::MAX, u32::MAX, 0xff),
+    ] {
+        let token = page_token(&key);
+        assert_eq!(token.len(), 26, "{}", token);
+        let parsed = parse_page_token(&token).unwrap();
+        assert_eq!(fields(&parsed), fields(&key), "{}", token);
+    }
+}
+
+#[test]
+fn test_page_token_format() {
+    let token = page_token(&key(0xac12_0064, 80, 0, IPPROTO_TCP));
+    assert_eq!(token, "ac120064000000500000000006");
+}
+
+#[test]
+fn test_parse_invalid_page_token() {
+    let token = page_token(&key(0xac12_0064, 80, 0, IPPROTO_TCP));
+    for invalid in [
+        String::new(),
+        // one character short or long
+        token[..25].to_string(),
+        format!("{}0", token),
+        // not hexadecimal
+        format!("{}zz", &token[..24]),
+        // a sign, which from_str_radix would accept
+        format!("+{}", &token[1..]),
+        // 26 bytes, but not as many characters
+        format!("{}é", &token[..24]),
+    ] {
+        assert!(parse_page_token(&invalid).is_none(), "{:?}", invalid);
+    }
+    // hexadecimal digits of either case are the same key
+    let parsed = parse_page_token(&token.to_uppercase()).unwrap();
+    assert_eq!(fields(&parsed), (0xac12_0064, 80, 0, IPPROTO_TCP));
+}
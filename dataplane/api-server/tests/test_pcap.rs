@@ -0,0 +1,79 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+use api_server::pcap::{write_pcap, write_pcap_header, write_pcap_record, CapturedPacket};
+
+fn u32_at(pcap: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(pcap[offset..offset + 4].try_into().unwrap())
+}
+
+#[test]
+fn test_write_pcap_header() {
+    let pcap = write_pcap(&[], 1518);
+    assert_eq!(pcap.len(), 24);
+    // nanosecond resolution, version 2.4
+    assert_eq!(u32_at(&pcap, 0), 0xa1b2_3c4d);
+    assert_eq!(&pcap[4..8], &[2, 0, 4, 0]);
+    assert_eq!(u32_at(&pcap, 16), 1518);
+    // ethernet
+    assert_eq!(u32_at(&pcap, 20), 1);
+}
+
+#[test]
+fn test_write_pcap_records() {
+    let packets = vec![
+        CapturedPacket {
+            timestamp: 1_700_000_000_123_456_789,
+            len: 60,
+            data: vec![0xaa; 60],
+        },
+        CapturedPacket {
+            timestamp: 1_700_000_001_000_000_000,
+            len: 1500,
+            data: vec![0xbb; 64],
+        },
+    ];
+    let pcap = write_pcap(&packets, 64);
+    assert_eq!(pcap.len(), 24 + 16 + 60 + 16 + 64);
+
+    let first = 24;
+    assert_eq!(u32_at(&pcap, first), 1_700_000_000);
+    assert_eq!(u32_at(&pcap, first + 4), 123_456_789);
+    assert_eq!(u32_at(&pcap, first + 8), 60);
+    assert_eq!(u32_at(&pcap, first + 12), 60);
+    assert_eq!(&pcap[first + 16..first + 76], &[0xaa; 60]);
+
+    // a truncated packet records both its captured and original length
+    let second = first + 16 + 60;
+    assert_eq!(u32_at(&pcap, second), 1_700_000_001);
+    assert_eq!(u32_at(&pcap, second + 4), 0);
+    assert_eq!(u32_at(&pcap, second + 8), 64);
+    assert_eq!(u32_at(&pcap, second + 12), 1500);
+    assert_eq!(&pcap[second + 16..], &[0xbb; 64]);
+}
+
+#[test]
+fn test_write_pcap_stream() {
+    let packets = vec![
+        CapturedPacket {
+            timestamp: 1_700_000_000_123_456_789,
+            len: 60,
+            data: vec![0xaa; 60],
+        },
+        CapturedPacket {
+            timestamp: 1_700_000_001_000_000_000,
+            len: 1500,
+            data: vec![0xbb; 128],
+        },
+    ];
+    // a pcap file streamed packet by packet is the same as one written at once
+    let mut pcap = Vec::new();
+    write_pcap_header(&mut pcap, 128);
+    for packet in &packets {
+        write_pcap_record(&mut pcap, packet);
+    }
+    assert_eq!(pcap, write_pcap(&packets, 128));
+}
@@ -0,0 +1,102 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+use std::net::Ipv4Addr;
+
+use api_server::backends::Protocol;
+use api_server::probe::{
+    build_packet, ipv4_checksum_valid, packet_destination, packet_tos, set_tos,
+};
+use common::sctp::crc32c;
+
+#[test]
+fn test_build_packet() {
+    let client = (Ipv4Addr::new(192, 0, 2, 1), 40000);
+    let destination = (Ipv4Addr::new(172, 18, 0, 100), 8080);
+
+    let tcp = build_packet(Protocol::Tcp, client, destination);
+    assert_eq!(tcp.len(), 14 + 20 + 20);
+    assert_eq!(tcp[14 + 9], 6);
+    assert_eq!(packet_destination(&tcp), Some(destination));
+
+    let udp = build_packet(Protocol::Udp, client, destination);
+    assert_eq!(udp.len(), 14 + 20 + 8);
+    assert_eq!(udp[14 + 9], 17);
+    assert_eq!(packet_destination(&udp), Some(destination));
+
+    let sctp = build_packet(Protocol::Sctp, client, destination);
+    assert_eq!(sctp.len(), 14 + 20 + 12 + 20);
+    assert_eq!(sctp[14 + 9], 132);
+    assert_eq!(packet_destination(&sctp), Some(destination));
+}
+
+#[test]
+fn test_build_packet_sctp_checksum() {
+    let mut packet = build_packet(
+        Protocol::Sctp,
+        (Ipv4Addr::new(10, 0, 0, 1), 1234),
+        (Ipv4Addr::new(10, 0, 0, 2), 38412),
+    );
+    let sctp = &mut packet[34..];
+    // an INIT chunk
+    assert_eq!(sctp[12], 1);
+    let checksum = u32::from_le_bytes(sctp[8..12].try_into().unwrap());
+    sctp[8..12].copy_from_slice(&[0; 4]);
+    assert_eq!(crc32c(sctp), checksum);
+}
+
+#[test]
+fn test_build_packet_ipv4_checksum() {
+    let packet = build_packet(
+        Protocol::Tcp,
+        (Ipv4Addr::new(10, 0, 0, 1), 1234),
+        (Ipv4Addr::new(10, 0, 0, 2), 80),
+    );
+
+    // summing a header including its checksum yields all ones
+    let mut sum: u32 = packet[14..34]
+        .chunks(2)
+        .map(|word| u32::from(u16::from_be_bytes([word[0], word[1]])))
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    assert_eq!(sum, 0xffff);
+}
+
+#[test]
+fn test_packet_destination_truncated() {
+    assert_eq!(packet_destination(&[0u8; 20]), None);
+}
+
+#[test]
+fn test_set_tos() {
+    let mut packet = build_packet(
+        Protocol::Udp,
+        (Ipv4Addr::new(10, 0, 0, 1), 1234),
+        (Ipv4Addr::new(10, 0, 0, 2), 443),
+    );
+    assert_eq!(packet_tos(&packet), Some(0));
+    assert!(ipv4_checksum_valid(&packet));
+
+    // DSCP EF with ECN CE, as sent by congestion-controlled UDP protocols like QUIC
+    set_tos(&mut packet, 0xb8 | 0x03);
+    assert_eq!(packet_tos(&packet), Some(0xbb));
+    assert!(ipv4_checksum_valid(&packet));
+}
+
+#[test]
+fn test_ipv4_checksum_valid() {
+    let mut packet = build_packet(
+        Protocol::Tcp,
+        (Ipv4Addr::new(10, 0, 0, 1), 1234),
+        (Ipv4Addr::new(10, 0, 0, 2), 80),
+    );
+    // flipping the ECN bits without updating the checksum is detected
+    packet[14 + 1] ^= 0x03;
+    assert!(!ipv4_checksum_valid(&packet));
+    assert!(!ipv4_checksum_valid(&[0u8; 20]));
+}
@@ -0,0 +1,112 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use api_server::backends::{Target, Targets};
+use api_server::resolver::{has_hostnames, resolve_target, Resolver};
+
+// Resolves hostnames to fixed addresses, and fails to resolve any other hostname.
+struct StubResolver(HashMap<&'static str, Vec<SocketAddr>>);
+
+#[tonic::async_trait]
+impl Resolver for StubResolver {
+    async fn lookup(&self, hostname: &str, port: u16) -> std::io::Result<Vec<SocketAddr>> {
+        let addrs = self
+            .0
+            .get(hostname)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "unknown hostname"))?;
+        Ok(addrs
+            .iter()
+            .map(|addr| SocketAddr::new(addr.ip(), port))
+            .collect())
+    }
+}
+
+fn resolver() -> StubResolver {
+    let v4 = SocketAddr::from((Ipv4Addr::new(10, 244, 0, 2), 0));
+    let v6 = SocketAddr::from((Ipv6Addr::LOCALHOST, 0));
+    StubResolver(HashMap::from([
+        ("backend.example", vec![v4]),
+        ("dual-stack.example", vec![v6, v4]),
+        ("v6-only.example", vec![v6]),
+    ]))
+}
+
+fn target(hostname: &str) -> Target {
+    Target {
+        hostname: hostname.to_string(),
+        dport: 8080,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_resolve_target() {
+    let resolver = resolver();
+
+    let resolved = resolve_target(&resolver, &target("backend.example"))
+        .await
+        .unwrap();
+    assert_eq!(resolved.daddr, u32::from(Ipv4Addr::new(10, 244, 0, 2)));
+    assert_eq!(resolved.hostname, "backend.example");
+    assert_eq!(resolved.dport, 8080);
+
+    // the first IPv4 address is used
+    let resolved = resolve_target(&resolver, &target("dual-stack.example"))
+        .await
+        .unwrap();
+    assert_eq!(resolved.daddr, u32::from(Ipv4Addr::new(10, 244, 0, 2)));
+
+    // targets without a hostname are kept as they are
+    let by_address = Target {
+        daddr: Ipv4Addr::new(10, 244, 0, 3).into(),
+        dport: 8080,
+        ..Default::default()
+    };
+    assert_eq!(
+        resolve_target(&resolver, &by_address).await.unwrap(),
+        by_address
+    );
+}
+
+#[tokio::test]
+async fn test_resolve_target_failures() {
+    let resolver = resolver();
+
+    let err = resolve_target(&resolver, &target("v6-only.example"))
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "no IPv4 address found for v6-only.example");
+    assert!(resolve_target(&resolver, &target("unknown.example"))
+        .await
+        .is_err());
+}
+
+#[test]
+fn test_has_hostnames() {
+    let by_address = Target {
+        daddr: Ipv4Addr::new(10, 244, 0, 3).into(),
+        dport: 8080,
+        ..Default::default()
+    };
+    let mut targets = Targets {
+        targets: vec![by_address.clone()],
+        ..Default::default()
+    };
+    assert!(!has_hostnames(&targets));
+
+    targets.targets.push(target("backend.example"));
+    assert!(has_hostnames(&targets));
+
+    // a mirror specified by hostname is resolved again too
+    targets.targets = vec![by_address];
+    targets.mirror = Some(target("backend.example"));
+    assert!(has_hostnames(&targets));
+
+    assert!(!has_hostnames(&Targets::default()));
+}
@@ -0,0 +1,84 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use api_server::scan::remove_stale;
+use tokio::sync::Mutex;
+
+fn map(len: u32) -> Mutex<BTreeMap<u32, u32>> {
+    Mutex::new((0..len).map(|key| (key, key)).collect())
+}
+
+#[tokio::test]
+async fn test_remove_stale() {
+    let map = map(1000);
+    let removed = remove_stale(&map, 64, usize::MAX, usize::MAX, |key, _| key % 2 == 0)
+        .await
+        .unwrap();
+    assert!(removed.complete);
+    assert_eq!(removed.entries.len(), 500);
+    // the last keys of chunks are removed too
+    let map = map.lock().await;
+    assert_eq!(map.len(), 500);
+    assert!(map.keys().all(|key| key % 2 == 1));
+}
+
+#[tokio::test]
+async fn test_remove_stale_limit() {
+    let map = map(1000);
+    let removed = remove_stale(&map, 64, 10, usize::MAX, |key, _| key % 2 == 0)
+        .await
+        .unwrap();
+    assert!(!removed.complete);
+    let keys: Vec<u32> = removed.entries.iter().map(|(key, _)| *key).collect();
+    assert_eq!(keys, (0..20).step_by(2).collect::<Vec<_>>());
+    assert_eq!(map.lock().await.len(), 990);
+
+    // the scan stops at max_visits
+    let removed = remove_stale(&map, 64, usize::MAX, 128, |_, _| false)
+        .await
+        .unwrap();
+    assert!(!removed.complete);
+}
+
+#[tokio::test]
+async fn test_remove_stale_takes_turns() {
+    const LEN: usize = 10_000;
+    const CHUNK_SIZE: usize = 100;
+
+    let map = Arc::new(map(LEN as u32));
+    let visited = Arc::new(AtomicUsize::new(0));
+    let scan = tokio::spawn({
+        let (map, visited) = (map.clone(), visited.clone());
+        async move {
+            remove_stale(&map, CHUNK_SIZE, usize::MAX, usize::MAX, |_, _| {
+                visited.fetch_add(1, Ordering::Relaxed);
+                false
+            })
+            .await
+        }
+    });
+
+    // other users of the map, like RPCs, get it between the chunks of the scan rather than
+    // once it's over
+    let mut seen = Vec::new();
+    for _ in 0..5 {
+        tokio::task::yield_now().await;
+        let guard = map.lock().await;
+        seen.push(visited.load(Ordering::Relaxed));
+        drop(guard);
+    }
+    assert!(seen.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", seen);
+    assert!(seen.iter().all(|visited| *visited < LEN), "{:?}", seen);
+    assert_eq!(seen[0] % CHUNK_SIZE, 0);
+
+    let removed = scan.await.unwrap().unwrap();
+    assert!(removed.complete);
+    assert_eq!(visited.load(Ordering::Relaxed), LEN);
+}
@@ -1,10 +1,17 @@
 use anyhow::Result;
-use api_server::config::{MutualTLSConfig, ServerOnlyTLSConfig, TLSConfig};
-use api_server::setup_tls;
+use api_server::config::{
+    MutualTLSConfig, SecretTLSConfig, ServerOnlyTLSConfig, TLSConfig, TLSOptions, TLSVersion,
+};
+use api_server::{setup_tls, tls};
 use rcgen::{generate_simple_self_signed, Certificate, CertificateParams};
 use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use tempfile::tempdir;
-use tonic::transport::Server;
+use tokio_rustls::rustls::{pki_types::ServerName, ClientConfig, RootCertStore};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
 
 #[tokio::test]
 async fn test_tls_self_signed_cert() -> Result<()> {
@@ -28,13 +35,11 @@ async fn test_tls_self_signed_cert() -> Result<()> {
     let tls_config = Some(TLSConfig::TLS(ServerOnlyTLSConfig {
         server_certificate_path: cert_path.clone(),
         server_private_key_path: key_path.clone(),
+        options: TLSOptions::default(),
     }));
 
-    // Prepare a dummy server builder
-    let builder = Server::builder();
-
     // Run the setup_tls function and ensure no error is thrown
-    let result = setup_tls(builder, &tls_config);
+    let result = setup_tls(&tls_config);
     assert!(
         result.is_ok(),
         "setup_tls should succeed with valid self-signed certs"
@@ -58,10 +63,10 @@ async fn test_tls_missing_cert() -> Result<()> {
     let tls_config = Some(TLSConfig::TLS(ServerOnlyTLSConfig {
         server_certificate_path: missing_cert_path.clone(),
         server_private_key_path: key_path.clone(),
+        options: TLSOptions::default(),
     }));
 
-    let builder = Server::builder();
-    let result = setup_tls(builder, &tls_config);
+    let result = setup_tls(&tls_config);
 
     // Assert that the result is an error
     assert!(
@@ -88,10 +93,10 @@ async fn test_tls_missing_key() -> Result<()> {
     let tls_config = Some(TLSConfig::TLS(ServerOnlyTLSConfig {
         server_certificate_path: cert_path.clone(),
         server_private_key_path: missing_key_path.clone(),
+        options: TLSOptions::default(),
     }));
 
-    let builder = Server::builder();
-    let result = setup_tls(builder, &tls_config);
+    let result = setup_tls(&tls_config);
 
     // Assert that the result is an error
     assert!(
@@ -131,13 +136,11 @@ async fn test_mtls_self_signed_cert() -> Result<()> {
         server_certificate_path: cert_path.clone(),
         server_private_key_path: key_path.clone(),
         client_certificate_authority_root_path: ca_cert_path.clone(),
+        options: TLSOptions::default(),
     }));
 
-    // Prepare a dummy server builder
-    let builder = Server::builder();
-
     // Run the setup_tls function and ensure no error is thrown
-    let result = setup_tls(builder, &tls_config);
+    let result = setup_tls(&tls_config);
     assert!(
         result.is_ok(),
         "setup_tls should succeed with valid self-signed certs"
@@ -168,10 +171,10 @@ async fn test_mtls_invalid_ca_cert() -> Result<()> {
         server_certificate_path: cert_path.clone(),
         server_private_key_path: key_path.clone(),
         client_certificate_authority_root_path: invalid_ca_cert_path.clone(),
+        options: TLSOptions::default(),
     }));
 
-    let builder = Server::builder();
-    let result = setup_tls(builder, &tls_config);
+    let result = setup_tls(&tls_config);
 
     // Assert that the result is an error
     assert!(
@@ -203,10 +206,10 @@ async fn test_mtls_missing_ca_cert() -> Result<()> {
         server_certificate_path: cert_path.clone(),
         server_private_key_path: key_path.clone(),
         client_certificate_authority_root_path: missing_ca_cert_path.clone(),
+        options: TLSOptions::default(),
     }));
 
-    let builder = Server::builder();
-    let result = setup_tls(builder, &tls_config);
+    let result = setup_tls(&tls_config);
 
     // Assert that the result is an error
     assert!(
@@ -215,3 +218,202 @@ async fn test_mtls_missing_ca_cert() -> Result<()> {
     );
     Ok(())
 }
+
+#[tokio::test]
+async fn test_tls_min_version_and_cipher_suites() -> Result<()> {
+    let temp_dir = tempdir().unwrap();
+
+    let cert = generate_simple_self_signed(vec!["localhost".into()])?;
+    let cert_path = temp_dir.path().join("server.crt");
+    let key_path = temp_dir.path().join("server.key");
+    fs::write(&cert_path, cert.serialize_pem()?.as_bytes())?;
+    fs::write(&key_path, cert.serialize_private_key_pem().as_bytes())?;
+
+    let tls_config = |min_tls_version, cipher_suites: &[&str]| {
+        Some(TLSConfig::TLS(ServerOnlyTLSConfig {
+            server_certificate_path: cert_path.clone(),
+            server_private_key_path: key_path.clone(),
+            options: TLSOptions {
+                min_tls_version,
+                cipher_suites: cipher_suites.iter().map(|name| name.to_string()).collect(),
+            },
+        }))
+    };
+
+    assert!(setup_tls(&tls_config(TLSVersion::V1_3, &[]))?.is_some());
+    assert!(setup_tls(&tls_config(
+        TLSVersion::V1_2,
+        &[
+            "TLS_AES_256_GCM_SHA384",
+            "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384"
+        ]
+    ))?
+    .is_some());
+    assert!(
+        setup_tls(&tls_config(TLSVersion::V1_2, &["TLS_RSA_WITH_RC4_128_SHA"])).is_err(),
+        "setup_tls should fail with an unsupported cipher suite"
+    );
+    assert!(
+        setup_tls(&tls_config(
+            TLSVersion::V1_3,
+            &["TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256"]
+        ))
+        .is_err(),
+        "setup_tls should fail with a cipher suite older than the minimum version"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_cipher_suites() {
+    let options = |min_tls_version, cipher_suites: &[&str]| TLSOptions {
+        min_tls_version,
+        cipher_suites: cipher_suites.iter().map(|name| name.to_string()).collect(),
+    };
+    let names = |options| {
+        tls::cipher_suites(&options)
+            .unwrap()
+            .iter()
+            .map(|suite| suite.suite().as_str().unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    // all the cipher suites of the accepted versions by default
+    assert!(names(options(TLSVersion::V1_2, &[]))
+        .iter()
+        .any(|name| name.starts_with("TLS_ECDHE_")));
+    assert!(names(options(TLSVersion::V1_3, &[]))
+        .iter()
+        .all(|name| name.starts_with("TLS13_")));
+
+    // IANA and rustls names, in any case, in the order of preference of rustls
+    assert_eq!(
+        names(options(
+            TLSVersion::V1_2,
+            &[
+                "tls_ecdhe_rsa_with_aes_128_gcm_sha256",
+                "TLS13_AES_128_GCM_SHA256",
+                "TLS_AES_256_GCM_SHA384",
+            ]
+        )),
+        [
+            "TLS13_AES_256_GCM_SHA384",
+            "TLS13_AES_128_GCM_SHA256",
+            "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256",
+        ]
+    );
+    assert!(tls::cipher_suites(&options(TLSVersion::V1_2, &["nope"])).is_err());
+}
+
+// Writes the files of a kubernetes.io/tls Secret to a new directory of the volume, and points
+// the volume's ..data symlink at it, the way kubelet updates the volumes of Secrets.
+fn write_secret(volume: &Path, version: &str, cert: &Certificate) -> Result<()> {
+    let dir = volume.join(version);
+    fs::create_dir(&dir)?;
+    fs::write(dir.join("tls.crt"), cert.serialize_pem()?)?;
+    fs::write(dir.join("tls.key"), cert.serialize_private_key_pem())?;
+    fs::write(dir.join("ca.crt"), cert.serialize_pem()?)?;
+
+    let data = volume.join("..data_tmp");
+    symlink(version, &data)?;
+    fs::rename(&data, volume.join("..data"))?;
+    for key in ["tls.crt", "tls.key", "ca.crt"] {
+        if !volume.join(key).exists() {
+            symlink(Path::new("..data").join(key), volume.join(key))?;
+        }
+    }
+    Ok(())
+}
+
+// Returns whether a client that only trusts the certificate completes a handshake with the
+// acceptor.
+async fn trusted(acceptor: &Arc<RwLock<TlsAcceptor>>, cert: &Certificate) -> Result<bool> {
+    let mut roots = RootCertStore::empty();
+    roots.add(cert.serialize_der()?.into())?;
+    let client_config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let connector = TlsConnector::from(Arc::new(client_config));
+
+    let (client_io, server_io) = tokio::io::duplex(16 * 1024);
+    let acceptor = acceptor.read().unwrap().clone();
+    let (client, _) = tokio::join!(
+        connector.connect(ServerName::try_from("localhost")?, client_io),
+        acceptor.accept(server_io)
+    );
+    Ok(client.is_ok())
+}
+
+#[tokio::test]
+async fn test_secret_tls() -> Result<()> {
+    let volume = tempdir().unwrap();
+    let cert = generate_simple_self_signed(vec!["localhost".into()])?;
+    write_secret(volume.path(), "..2024_01_01_00_00_00.1", &cert)?;
+
+    let secret_tls = |client_ca_secret_dir: Option<&Path>| {
+        TLSConfig::SecretTLS(SecretTLSConfig {
+            server_secret_dir: volume.path().to_path_buf(),
+            client_ca_secret_dir: client_ca_secret_dir.map(Path::to_path_buf),
+            check_interval: 10,
+            options: TLSOptions::default(),
+        })
+    };
+    let paths = secret_tls(Some(volume.path())).paths();
+    assert_eq!(paths.server_certificate, volume.path().join("tls.crt"));
+    assert_eq!(paths.server_private_key, volume.path().join("tls.key"));
+    assert_eq!(
+        paths.client_certificate_authority_root,
+        Some(volume.path().join("ca.crt"))
+    );
+
+    assert!(setup_tls(&Some(secret_tls(None)))?.is_some());
+    assert!(setup_tls(&Some(secret_tls(Some(volume.path()))))?.is_some());
+    assert!(
+        setup_tls(&Some(secret_tls(Some(&volume.path().join("missing"))))).is_err(),
+        "setup_tls should fail when the client CA Secret is missing"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_secret_tls_reload() -> Result<()> {
+    let volume = tempdir().unwrap();
+    let first = generate_simple_self_signed(vec!["localhost".into()])?;
+    write_secret(volume.path(), "..2024_01_01_00_00_00.1", &first)?;
+
+    let tls_config = TLSConfig::SecretTLS(SecretTLSConfig {
+        server_secret_dir: volume.path().to_path_buf(),
+        client_ca_secret_dir: None,
+        check_interval: 1,
+        options: TLSOptions::default(),
+    });
+    let paths = tls_config.paths();
+    let acceptor = Arc::new(RwLock::new(setup_tls(&Some(tls_config))?.unwrap()));
+    tokio::spawn(tls::watch(
+        paths,
+        TLSOptions::default(),
+        acceptor.clone(),
+        Duration::from_millis(50),
+    ));
+    assert!(trusted(&acceptor, &first).await?);
+
+    // a Secret updated with an invalid key keeps the previous certificates
+    let dir = volume.path().join("..2024_01_01_00_00_00.2");
+    fs::create_dir(&dir)?;
+    fs::write(dir.join("tls.crt"), first.serialize_pem()?)?;
+    fs::write(dir.join("tls.key"), "not a key")?;
+    symlink("..2024_01_01_00_00_00.2", volume.path().join("..data_tmp"))?;
+    fs::rename(
+        volume.path().join("..data_tmp"),
+        volume.path().join("..data"),
+    )?;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(trusted(&acceptor, &first).await?);
+
+    let second = generate_simple_self_signed(vec!["localhost".into()])?;
+    write_secret(volume.path(), "..2024_01_01_00_00_00.3", &second)?;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(trusted(&acceptor, &second).await?);
+    assert!(!trusted(&acceptor, &first).await?);
+    Ok(())
+}
@@ -0,0 +1,38 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! Access control lists of vips as prefixes of the VIP_ACLS LPM trie. Every CIDR of the ACL of a
+//! vip is an entry with the action of the ACL, and allowlists also have an entry denying every
+//! other client. The datapath looks up the address of the client of each packet and drops it when
+//! the most specific entry denies it.
+
+use crate::{AclKey, BackendKey};
+
+/// Returns the prefix length and data of the key of the CIDR, whose address is in host byte
+/// order, in the ACL of the vip. None when it isn't a CIDR: its prefix is longer than an address
+/// or the address has bits set past it.
+pub fn cidr_key(backend_key: BackendKey, ip: u32, prefix_len: u32) -> Option<(u32, AclKey)> {
+    if prefix_len > 32 || ip.checked_shl(prefix_len).unwrap_or(0) != 0 {
+        return None;
+    }
+    Some((
+        AclKey::FIXED_PREFIX_LEN + prefix_len,
+        AclKey::new(backend_key, ip),
+    ))
+}
+
+/// Returns the prefix length and data of the key matching every client of the vip, which
+/// allowlists deny.
+pub fn any_client_key(backend_key: BackendKey) -> (u32, AclKey) {
+    (AclKey::FIXED_PREFIX_LEN, AclKey::new(backend_key, 0))
+}
+
+/// Returns the prefix length and data of the key the client, whose address is in host byte order,
+/// is looked up by.
+#[inline(always)]
+pub fn client_key(backend_key: BackendKey, ip: u32) -> (u32, AclKey) {
+    (AclKey::FIXED_PREFIX_LEN + 32, AclKey::new(backend_key, ip))
+}
@@ -0,0 +1,67 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! The draining backends of vips, a bit per backend index in the draining bitmap of their
+//! BackendList. Draining backends aren't assigned new connections, while the connections they
+//! already have are still sent to them, so that they can be removed once those are done.
+
+use crate::{DRAINING_WORDS, MAX_BACKENDS_PER_VIP};
+
+// Every backend index has a bit.
+const _: () = assert!(DRAINING_WORDS * 64 >= MAX_BACKENDS_PER_VIP);
+
+/// Returns whether the backend at `index` is draining.
+#[inline(always)]
+pub fn is_draining(draining: &[u64; DRAINING_WORDS], index: u16) -> bool {
+    draining
+        .get(index as usize / 64)
+        .is_some_and(|word| (word >> (index % 64)) & 1 == 1)
+}
+
+/// Sets whether the backend at `index` is draining. Indexes past the bitmap are ignored.
+pub fn set_draining(draining: &mut [u64; DRAINING_WORDS], index: u16, value: bool) {
+    if let Some(word) = draining.get_mut(index as usize / 64) {
+        let bit = 1 << (index % 64);
+        if value {
+            *word |= bit;
+        } else {
+            *word &= !bit;
+        }
+    }
+}
+
+/// Returns the index of the first backend from `index` on that isn't draining, among
+/// `backends_len` backends, going back to the first backend after the last one. None when
+/// `index` is past the backends or all of them are draining.
+#[inline(always)]
+pub fn next_undrained(
+    draining: &[u64; DRAINING_WORDS],
+    index: u16,
+    backends_len: u16,
+) -> Option<u16> {
+    let len = (backends_len as usize).min(MAX_BACKENDS_PER_VIP);
+    if index as usize >= len {
+        return None;
+    }
+    // the words from that of index to the last one, then from the first one back to that of
+    // index, whose bits before index weren't looked at yet
+    let mut start = index as usize;
+    for _ in 0..=DRAINING_WORDS {
+        let word = start / 64;
+        let undrained = !*draining.get(word)? & (u64::MAX << (start % 64));
+        if undrained != 0 {
+            let found = word * 64 + undrained.trailing_zeros() as usize;
+            if found < len {
+                return Some(found as u16);
+            }
+        }
+        start = (word + 1) * 64;
+        if start >= len {
+            start = 0;
+        }
+    }
+    None
+}
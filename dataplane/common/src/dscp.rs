@@ -0,0 +1,21 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! Marking of the packets of vips with a differentiated services code point (DSCP), see RFC
+//! 2474, so that the network between the dataplane and the backends can prioritize them. The
+//! DSCP is the upper 6 bits of the TOS byte of the ip header, whose lower 2 bits are the ECN
+//! field of RFC 3168.
+
+/// The largest DSCP, which is 6 bits long.
+pub const DSCP_MAX: u8 = 0x3f;
+
+const ECN_MASK: u8 = 0x03;
+
+/// Returns the TOS byte of a packet marked with `dscp`, keeping its ECN field.
+#[inline(always)]
+pub fn marked_tos(tos: u8, dscp: u8) -> u8 {
+    ((dscp & DSCP_MAX) << 2) | (tos & ECN_MASK)
+}
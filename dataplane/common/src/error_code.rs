@@ -0,0 +1,126 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+use core::fmt;
+
+/// Stable identifiers for the errors blixt surfaces to users in logs, gRPC responses and
+/// status conditions, so that they can be searched for and documented in runbooks.
+///
+/// Codes in the 1xxx range originate in the dataplane, codes in the 2xxx range originate in the
+/// controlplane. Codes must never be reused or renumbered once released.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[repr(u16)]
+pub enum ErrorCode {
+    /// A VIP was programmed with more backends than fit in a BackendList.
+    BackendsCapacityExceeded = 1001,
+    /// Inserting into or removing from a BPF map failed.
+    MapUpdateFailed = 1002,
+    /// A request to the dataplane API was missing a VIP.
+    MissingVip = 1003,
+    /// No network interface could be found to route to a backend.
+    InterfaceIndexNotFound = 1004,
+    /// A VIP is owned by a different Gateway than the one the request was made on behalf of, or
+    /// the request to modify it was made on behalf of no Gateway.
+    GatewayOwnershipConflict = 1005,
+    /// A request referred to an eBPF program that is not attached.
+    ProgramNotAttached = 1006,
+    /// Running a synthetic packet through the datapath failed.
+    ProbeFailed = 1007,
+    /// The hostname of a target could not be resolved.
+    HostnameResolutionFailed = 1008,
+    /// A list request carried a page token that wasn't issued by the dataplane.
+    InvalidPageToken = 1009,
+    /// A request referred to a VIP that the dataplane doesn't have.
+    VipNotFound = 1010,
+    /// A request set the round-robin position of a VIP past its backends.
+    GatewayIndexOutOfRange = 1011,
+    /// A packet capture was requested while another one is running.
+    CaptureInProgress = 1012,
+    /// Full NAT was requested for a VIP but the dataplane has no SNAT address.
+    SnatNotConfigured = 1013,
+    /// A VIP was configured with QUIC connection IDs longer than QUIC allows.
+    QuicConnectionIdTooLong = 1014,
+    /// A VIP was configured with a range of ports that is empty or overlaps another VIP's.
+    InvalidPortRange = 1015,
+    /// A runtime configuration of the dataplane had a value it doesn't support.
+    InvalidRuntimeConfig = 1016,
+    /// The ACL of a VIP had an address that isn't a CIDR, or too many of them.
+    InvalidAcl = 1017,
+    /// A request referred to a target that the vip doesn't have.
+    BackendNotFound = 1018,
+    /// The PROXY protocol was requested for a VIP that isn't TCP.
+    ProxyProtocolNotSupported = 1019,
+    /// A VIP was configured with a DSCP that doesn't fit its 6 bits.
+    InvalidDscp = 1020,
+    /// An SCTP VIP was configured with full NAT or a mirror, which SCTP VIPs don't support.
+    SctpNotSupported = 1021,
+    /// Detaching an attached eBPF program failed.
+    ProgramDetachFailed = 1022,
+    /// A resource has an invalid or unsupported configuration.
+    InvalidConfig = 2002,
+    /// The LoadBalancer Service for a Gateway is missing or not ready.
+    LoadBalancerNotReady = 2003,
+    /// The Gateway API CRDs are not installed in the cluster.
+    CRDNotFound = 2004,
+    /// A Gateway requested an address of an unsupported type.
+    UnsupportedAddress = 2005,
+    /// A request to the Kubernetes API failed.
+    KubeApiFailed = 2006,
+    /// A Gateway requested an address that an older Gateway already requested.
+    AddressInUse = 2007,
+}
+
+impl ErrorCode {
+    /// Returns the numeric value of the code.
+    pub const fn code(&self) -> u16 {
+        *self as u16
+    }
+}
+
+impl TryFrom<u16> for ErrorCode {
+    type Error = u16;
+
+    fn try_from(code: u16) -> Result<Self, Self::Error> {
+        Ok(match code {
+            1001 => ErrorCode::BackendsCapacityExceeded,
+            1002 => ErrorCode::MapUpdateFailed,
+            1003 => ErrorCode::MissingVip,
+            1004 => ErrorCode::InterfaceIndexNotFound,
+            1005 => ErrorCode::GatewayOwnershipConflict,
+            1006 => ErrorCode::ProgramNotAttached,
+            1007 => ErrorCode::ProbeFailed,
+            1008 => ErrorCode::HostnameResolutionFailed,
+            1009 => ErrorCode::InvalidPageToken,
+            1010 => ErrorCode::VipNotFound,
+            1011 => ErrorCode::GatewayIndexOutOfRange,
+            1012 => ErrorCode::CaptureInProgress,
+            1013 => ErrorCode::SnatNotConfigured,
+            1014 => ErrorCode::QuicConnectionIdTooLong,
+            1015 => ErrorCode::InvalidPortRange,
+            1016 => ErrorCode::InvalidRuntimeConfig,
+            1017 => ErrorCode::InvalidAcl,
+            1018 => ErrorCode::BackendNotFound,
+            1019 => ErrorCode::ProxyProtocolNotSupported,
+            1020 => ErrorCode::InvalidDscp,
+            1021 => ErrorCode::SctpNotSupported,
+            1022 => ErrorCode::ProgramDetachFailed,
+            2002 => ErrorCode::InvalidConfig,
+            2003 => ErrorCode::LoadBalancerNotReady,
+            2004 => ErrorCode::CRDNotFound,
+            2005 => ErrorCode::UnsupportedAddress,
+            2006 => ErrorCode::KubeApiFailed,
+            2007 => ErrorCode::AddressInUse,
+            _ => return Err(code),
+        })
+    }
+}
+
+// Formats the code as e.g. "BLX-1001".
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BLX-{}", self.code())
+    }
+}
@@ -0,0 +1,27 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! IPv4 fragments, see RFC 791. Only the first fragment of a datagram carries its UDP header, so
+//! the later ones are sent where the first one was, by the FragmentKey of the datagram.
+
+// The "more fragments" flag and the fragment offset of the frag_off field of the IPv4 header,
+// in host byte order.
+const IP_MF: u16 = 0x2000;
+const IP_OFFSET: u16 = 0x1fff;
+
+/// Returns whether the packet with the `frag_off` field (in host byte order) is the first
+/// fragment of a datagram that was fragmented.
+#[inline(always)]
+pub fn is_first_fragment(frag_off: u16) -> bool {
+    frag_off & IP_MF != 0 && frag_off & IP_OFFSET == 0
+}
+
+/// Returns whether the packet with the `frag_off` field (in host byte order) is a fragment of a
+/// datagram other than its first, which has no UDP or TCP header.
+#[inline(always)]
+pub fn is_later_fragment(frag_off: u16) -> bool {
+    frag_off & IP_OFFSET != 0
+}
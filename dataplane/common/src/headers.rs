@@ -0,0 +1,44 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! The lengths of the headers of packets that the eBPF programs access directly, which have to be
+//! in the linear data of their skb. Headers are sized for their options, since the IHL of ip
+//! headers and the data offset of TCP headers can be up to 15 words.
+
+/// The length of an ethernet header.
+pub const ETH_HDR_LEN: usize = 14;
+/// The most VLAN tags between the ethernet and ip headers that are parsed, those of QinQ frames.
+pub const MAX_VLAN_TAGS: usize = 2;
+/// A VLAN tag is its tag control information, followed by the ethertype of what follows the tag.
+pub const VLAN_TAG_LEN: usize = 4;
+/// The longest an IPv4 header can be, with options.
+pub const IPV4_MAX_HEADER_LEN: usize = 60;
+/// The longest a TCP header can be, with options.
+pub const TCP_MAX_HEADER_LEN: usize = 60;
+
+const ICMP_HDR_LEN: usize = 8;
+// the ports both TCP and UDP headers start with, which are all of them that are sure to be quoted
+// by ICMP errors
+const L4_PORTS_LEN: usize = 4;
+
+/// The most bytes past the ip header that are accessed directly: a TCP header with options, or
+/// the header of an ICMP error and the ip header and ports of the packet it quotes.
+pub const MAX_L4_HEADERS_LEN: usize = max(
+    TCP_MAX_HEADER_LEN,
+    ICMP_HDR_LEN + IPV4_MAX_HEADER_LEN + L4_PORTS_LEN,
+);
+
+/// The bytes at the start of packets that the headers accessed directly are within.
+pub const MAX_HEADERS_LEN: usize =
+    ETH_HDR_LEN + MAX_VLAN_TAGS * VLAN_TAG_LEN + IPV4_MAX_HEADER_LEN + MAX_L4_HEADERS_LEN;
+
+const fn max(a: usize, b: usize) -> usize {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
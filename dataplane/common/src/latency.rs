@@ -0,0 +1,64 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! Histograms of the time the backends of a vip take to answer new connections: from the SYN of
+//! a TCP connection until its SYN-ACK, and from the first datagram of a UDP flow until its first
+//! reply, as seen by the dataplane. The bounds of the buckets are powers of two of microseconds,
+//! which covers latencies from a microsecond to seconds with few buckets.
+
+/// The number of buckets of a histogram. Bucket i counts the latencies of at most 2^i
+/// microseconds, save for the last one which counts all those above the previous bucket.
+pub const LATENCY_BUCKETS: usize = 24;
+
+/// The latencies of the connections of a vip, per CPU in its VIP_STATS entry.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct LatencyHistogram {
+    // the number of latencies in each bucket, without those of the smaller buckets
+    pub buckets: [u64; LATENCY_BUCKETS],
+    pub sum_ns: u64,
+}
+
+impl LatencyHistogram {
+    /// Records a latency of `ns` nanoseconds.
+    #[inline(always)]
+    pub fn observe(&mut self, ns: u64) {
+        if let Some(count) = self.buckets.get_mut(latency_bucket(ns)) {
+            *count += 1;
+        }
+        self.sum_ns += ns;
+    }
+
+    /// Returns the number of latencies recorded.
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// Adds the latencies of `other`, e.g. those recorded on another CPU.
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        for (count, other_count) in self.buckets.iter_mut().zip(other.buckets.iter()) {
+            *count += other_count;
+        }
+        self.sum_ns += other.sum_ns;
+    }
+}
+
+/// Returns the bucket of a latency of `ns` nanoseconds.
+#[inline(always)]
+pub fn latency_bucket(ns: u64) -> usize {
+    let us = ns.div_ceil(1000);
+    if us <= 1 {
+        return 0;
+    }
+    // the smallest i such that 2^i >= us
+    let bucket = (u64::BITS - (us - 1).leading_zeros()) as usize;
+    bucket.min(LATENCY_BUCKETS - 1)
+}
+
+/// Returns the upper bound, in microseconds, of the bucket, None for the last one.
+pub fn latency_bucket_bound(bucket: usize) -> Option<u64> {
+    (bucket < LATENCY_BUCKETS - 1).then(|| 1 << bucket)
+}
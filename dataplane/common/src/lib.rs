@@ -4,12 +4,158 @@ Copyright 2023 The Kubernetes Authors.
 SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
 */
 
-#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub mod acl;
+pub mod drain;
+pub mod dscp;
+mod error_code;
+pub mod fragment;
+pub mod headers;
+pub mod latency;
+pub mod maglev;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod mss;
+#[cfg(feature = "std")]
+mod namespaced_name;
+pub mod port_range;
+pub mod proxy;
+pub mod rate_limit;
+pub mod sctp;
+pub mod slots;
+
+pub use error_code::ErrorCode;
+
+#[cfg(feature = "std")]
+pub use namespaced_name::{NamespacedName, NamespacedNameError};
 
 pub const BACKENDS_ARRAY_CAPACITY: usize = 128;
 pub const BPF_MAPS_CAPACITY: u32 = 128;
+// The maximum number of backends of a vip. The first BACKENDS_ARRAY_CAPACITY are in its
+// BackendList, the others in BACKEND_SLOTS entries of up to BACKENDS_ARRAY_CAPACITY each.
+pub const MAX_BACKENDS_PER_VIP: usize = 1024;
+// The number of words of the draining bitmap of a BackendList, a bit per backend of the vip.
+pub const DRAINING_WORDS: usize = MAX_BACKENDS_PER_VIP / 64;
+// The number of entries of a Maglev lookup table, a prime much larger than
+// BACKENDS_ARRAY_CAPACITY so that backends get close to even shares of it. Vips with more
+// backends than that get coarser shares.
+pub const MAGLEV_TABLE_SIZE: usize = 4093;
+// The most VIP_PORT_RANGES prefixes a range of ports is split into.
+pub const MAX_PORT_RANGE_PREFIXES: u32 = 30;
+// The most CIDRs of the ACL of a vip, each of which is a VIP_ACLS entry along with the entry of
+// allowlists denying every other client.
+pub const MAX_ACL_CIDRS: u32 = 256;
 
-#[derive(Copy, Clone, Debug, Default)]
+// The actions of VIP_ACLS entries on the clients they match.
+pub const ACL_ACTION_ALLOW: u32 = 0;
+pub const ACL_ACTION_DENY: u32 = 1;
+
+/// The label a dataplane pod carries with the port its API server listens on, so that the
+/// controlplane doesn't need to assume it.
+pub const DATAPLANE_API_PORT_LABEL: &str = "dataplane.blixt.gateway.networking.k8s.io/api-port";
+
+// The ip protocols of vips.
+pub const IPPROTO_TCP: u32 = 6;
+pub const IPPROTO_UDP: u32 = 17;
+pub const IPPROTO_SCTP: u32 = 132;
+
+// Indexes of the values in the DATAPLANE_STATE map. A value of 1 means enabled.
+pub const STATE_DRAINING: u32 = 0;
+// Only 1 in this many per-packet log lines is emitted. 0 and 1 mean every line is emitted.
+pub const STATE_LOG_SAMPLE_RATE: u32 = 1;
+// While in standby, packets pass through the ingress program untouched.
+pub const STATE_STANDBY: u32 = 2;
+// Seconds after which tracked TCP (or UDP) connections that saw no packets are no longer
+// tracked. 0 means they are tracked until they are closed.
+pub const STATE_TCP_IDLE_TIMEOUT: u32 = 3;
+pub const STATE_UDP_IDLE_TIMEOUT: u32 = 4;
+// The node address the traffic of vips with full NAT is sent to their backends from, 0 when
+// full NAT isn't configured.
+pub const STATE_SNAT_IP: u32 = 5;
+// A bitmask of the FEATURE_* flags of the optional work the programs do for every packet.
+pub const STATE_FEATURES: u32 = 6;
+// The most verbose LOG_LEVEL_* of the per-packet log lines that are emitted.
+pub const STATE_LOG_LEVEL: u32 = 7;
+// The DROP_POLICY_* of the packets of vips without backends.
+pub const STATE_DROP_POLICY: u32 = 8;
+// The MSS that TCP SYN packets advertising a larger one are clamped to, 0 when they aren't.
+pub const STATE_TCP_MSS_CLAMP: u32 = 9;
+pub const DATAPLANE_STATE_LEN: u32 = 10;
+
+// Records of the traffic of closed connections are sent to FLOW_RECORDS.
+pub const FEATURE_FLOW_RECORDS: u32 = 1 << 0;
+// The traffic of vips is counted in VIP_STATS.
+pub const FEATURE_VIP_STATS: u32 = 1 << 1;
+pub const FEATURES_DEFAULT: u32 = FEATURE_FLOW_RECORDS | FEATURE_VIP_STATS;
+
+pub const LOG_LEVEL_OFF: u32 = 0;
+pub const LOG_LEVEL_INFO: u32 = 1;
+// Also emits the lines that detail how the backend of new connections is selected.
+pub const LOG_LEVEL_DEBUG: u32 = 2;
+
+// New TCP connections are reset and UDP packets get an ICMP port unreachable error.
+pub const DROP_POLICY_REJECT: u32 = 0;
+// Packets are silently dropped.
+pub const DROP_POLICY_DROP: u32 = 1;
+// Packets are passed to the node, as if the vip didn't exist.
+pub const DROP_POLICY_PASS: u32 = 2;
+
+// The source ports of full NAT connections, above the default ip_local_port_range so that they
+// don't collide with the node's own connections.
+pub const SNAT_PORT_MIN: u16 = 61000;
+pub const SNAT_PORT_MAX: u16 = 65535;
+
+// Indexes of the per-CPU counters in the LOG_STATS map.
+pub const LOG_STATS_EMITTED: u32 = 0;
+pub const LOG_STATS_SUPPRESSED: u32 = 1;
+pub const LOG_STATS_LEN: u32 = 2;
+
+// Indexes of the per-CPU counters in the DROP_STATS map, of the packets to vips that weren't load
+// balanced by reason. Depending on the reason, they are dropped or passed on unmodified.
+pub const DROP_STATS_NO_BACKENDS: u32 = 0;
+pub const DROP_STATS_BACKEND_OUT_OF_RANGE: u32 = 1;
+pub const DROP_STATS_REWRITE_FAILED: u32 = 2;
+pub const DROP_STATS_NO_ROUTE: u32 = 3;
+pub const DROP_STATS_REDIRECT_FAILED: u32 = 4;
+pub const DROP_STATS_ACL_DENIED: u32 = 5;
+pub const DROP_STATS_RATE_LIMITED: u32 = 6;
+pub const DROP_STATS_LEN: u32 = 7;
+
+// The size in bytes of the FLOW_RECORDS ring buffer, a power of 2 multiple of the page size.
+pub const FLOW_RECORDS_BYTE_SIZE: u32 = 256 * 1024;
+
+// The size in bytes of the ERROR_EVENTS ring buffer, a power of 2 multiple of the page size.
+pub const ERROR_EVENTS_BYTE_SIZE: u32 = 64 * 1024;
+
+// The programs that report ErrorEvents.
+pub const PROGRAM_TC_INGRESS: u32 = 0;
+pub const PROGRAM_TC_EGRESS: u32 = 1;
+
+// The indexes of the programs of each protocol in the TAIL_CALLS program array, which tc_ingress
+// and tc_egress tail call into by the protocol of the packet.
+pub const TAIL_CALL_INGRESS_TCP: u32 = 0;
+pub const TAIL_CALL_INGRESS_UDP: u32 = 1;
+pub const TAIL_CALL_INGRESS_SCTP: u32 = 2;
+pub const TAIL_CALL_EGRESS_ICMP: u32 = 3;
+pub const TAIL_CALL_EGRESS_TCP: u32 = 4;
+pub const TAIL_CALL_EGRESS_UDP: u32 = 5;
+pub const TAIL_CALL_EGRESS_SCTP: u32 = 6;
+pub const TAIL_CALLS_LEN: u32 = 7;
+
+// Stages of the datapath at which packets are captured: as received, and once rewritten to be
+// redirected to their backend.
+pub const CAPTURE_STAGE_RECEIVED: u32 = 0;
+pub const CAPTURE_STAGE_REWRITTEN: u32 = 1;
+/// The maximum number of bytes captured of each packet, a full ethernet frame.
+pub const CAPTURE_MAX_SNAPLEN: u32 = 1518;
+
+// The size in bytes of the PACKET_SAMPLES ring buffer, a power of 2 multiple of the page size.
+pub const PACKET_SAMPLES_BYTE_SIZE: u32 = 256 * 1024;
+/// The number of bytes sampled of each packet, enough for its headers.
+pub const SAMPLE_HEADERS_LEN: usize = 128;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 #[repr(C)]
 pub struct Backend {
     pub daddr: u32,
@@ -20,27 +166,195 @@ pub struct Backend {
 #[cfg(feature = "user")]
 unsafe impl aya::Pod for Backend {}
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[repr(C)]
 pub struct BackendKey {
     pub ip: u32,
     pub port: u32,
+    // port_end is the last port of a vip that covers the range of ports from port, 0 for a vip
+    // of a single port. The vip of ports 0 to 65535 covers all ports of its address.
+    pub port_end: u32,
+    // proto is the ip protocol of the vip, IPPROTO_TCP, IPPROTO_UDP or IPPROTO_SCTP, so that
+    // listeners of different protocols on the same address and port are separate vips.
+    pub proto: u32,
 }
 
 #[cfg(feature = "user")]
 unsafe impl aya::Pod for BackendKey {}
 
-#[derive(Copy, Clone, Debug)]
+// PortRangeKey is the data of the VIP_PORT_RANGES LPM trie keys, whose values are the keys of the
+// vips of ranges of ports. Its fields are in network byte order so that a prefix of the key is the
+// vip address and protocol followed by a prefix of the port.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct PortRangeKey {
+    pub ip: u32,
+    pub proto: u16,
+    pub port: u16,
+}
+
+impl PortRangeKey {
+    // The number of bits of the address and protocol, which every prefix includes.
+    pub const FIXED_PREFIX_LEN: u32 = 48;
+
+    // Builds the key of a port of the address and protocol, all in host byte order.
+    #[inline(always)]
+    pub fn new(ip: u32, proto: u32, port: u16) -> PortRangeKey {
+        PortRangeKey {
+            ip: ip.to_be(),
+            proto: (proto as u16).to_be(),
+            port: port.to_be(),
+        }
+    }
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for PortRangeKey {}
+
+// AclKey is the data of the VIP_ACLS LPM trie keys, whose values are the ACL_ACTION_ of the
+// clients they match. A prefix of the key is the vip followed by a prefix of the client address,
+// which is in network byte order, see common::acl.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct AclKey {
+    pub backend_key: BackendKey,
+    pub client_ip: u32,
+}
+
+impl AclKey {
+    // The number of bits of the vip, which every prefix includes.
+    pub const FIXED_PREFIX_LEN: u32 = 128;
+
+    // Builds the key of the client address, in host byte order, of the vip.
+    #[inline(always)]
+    pub fn new(backend_key: BackendKey, client_ip: u32) -> AclKey {
+        AclKey {
+            backend_key,
+            client_ip: client_ip.to_be(),
+        }
+    }
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for AclKey {}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[repr(C)]
 pub struct BackendList {
     pub backends: [Backend; BACKENDS_ARRAY_CAPACITY],
-    // backends_len is the length of the backends array
+    // backends_len is the number of backends of the vip, those past the backends array are in
+    // its BACKEND_SLOTS entries
     pub backends_len: u16,
+    // mirror is a backend that receives a copy of every packet, when has_mirror is 1. The map
+    // value is shared with the eBPF programs, so it has no Options whose layout isn't defined.
+    pub mirror: Backend,
+    pub has_mirror: u8,
+    // maglev is the lookup table of the vip when it uses consistent hashing instead of round
+    // robin, when has_maglev is 1: the backend of a client is the backends index at the hash of
+    // its 5-tuple.
+    pub maglev: [u16; MAGLEV_TABLE_SIZE],
+    pub has_maglev: u8,
+    // affinity_timeout is the number of seconds new connections from a client ip are sent to
+    // the backend its first connection was sent to, 0 when the vip has no session affinity.
+    pub affinity_timeout: u32,
+    // full_nat is 1 to rewrite the source of the vip's traffic to the node's SNAT address, so
+    // that backends without a route back to its clients can reply.
+    pub full_nat: u8,
+    // proxy_protocol is 1 to insert a PROXY protocol header before the data of the vip's TCP
+    // connections, see common::proxy.
+    pub proxy_protocol: u8,
+    // quic_cid_len is the length of the connection IDs of short header QUIC packets to the vip,
+    // whose UDP packets go to a backend by their QUIC connection ID when it's set.
+    pub quic_cid_len: u8,
+    // rate_limit drops the packets each client ip sends to the vip over the limit, when
+    // has_rate_limit is 1.
+    pub rate_limit: RateLimit,
+    pub has_rate_limit: u8,
+    // dscp is the DSCP the vip's packets are marked with on their way to its backends, when
+    // has_dscp is 1, see common::dscp.
+    pub dscp: u8,
+    pub has_dscp: u8,
+    // draining has the bit of each backend index that isn't assigned new connections, while
+    // those it has continue, see common::drain. draining_len is the number of those backends.
+    pub draining: [u64; DRAINING_WORDS],
+    pub draining_len: u16,
+}
+
+// RateLimit is the number of packets per second each client ip of a vip may send, and the number
+// of packets it may send at once after being idle. See common::rate_limit.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct RateLimit {
+    pub packets_per_second: u32,
+    pub burst: u32,
+}
+
+impl BackendList {
+    // Returns the mirror of the vip, if it has one.
+    #[inline(always)]
+    pub fn mirror(&self) -> Option<Backend> {
+        (self.has_mirror != 0).then_some(self.mirror)
+    }
+
+    // Returns the maglev lookup table of the vip, if it uses consistent hashing.
+    #[inline(always)]
+    pub fn maglev(&self) -> Option<&[u16; MAGLEV_TABLE_SIZE]> {
+        (self.has_maglev != 0).then_some(&self.maglev)
+    }
+
+    // Returns the session affinity timeout of the vip in seconds, if it has session affinity.
+    #[inline(always)]
+    pub fn affinity_timeout(&self) -> Option<u32> {
+        (self.affinity_timeout != 0).then_some(self.affinity_timeout)
+    }
+
+    #[inline(always)]
+    pub fn full_nat(&self) -> bool {
+        self.full_nat != 0
+    }
+
+    #[inline(always)]
+    pub fn proxy_protocol(&self) -> bool {
+        self.proxy_protocol != 0
+    }
+
+    // Returns the rate limit of the clients of the vip, if it has one.
+    #[inline(always)]
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        (self.has_rate_limit != 0).then_some(self.rate_limit)
+    }
+
+    // Returns the DSCP the packets of the vip are marked with, if they are.
+    #[inline(always)]
+    pub fn dscp(&self) -> Option<u8> {
+        (self.has_dscp != 0).then_some(self.dscp)
+    }
 }
 
 #[cfg(feature = "user")]
 unsafe impl aya::Pod for BackendList {}
 
+// BackendSlotKey identifies an overflow slot of a vip: slot n holds the backends from index
+// n * BACKENDS_ARRAY_CAPACITY, starting at 1 since the BackendList holds the first ones.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[repr(C)]
+pub struct BackendSlotKey {
+    pub backend_key: BackendKey,
+    pub slot: u32,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for BackendSlotKey {}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct BackendSlot {
+    pub backends: [Backend; BACKENDS_ARRAY_CAPACITY],
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for BackendSlot {}
+
 #[derive(Copy, Clone, Debug)]
 #[repr(C)]
 pub struct ClientKey {
@@ -51,6 +365,72 @@ pub struct ClientKey {
 #[cfg(feature = "user")]
 unsafe impl aya::Pod for ClientKey {}
 
+// AffinityKey identifies a client of a vip with session affinity, by its ip address only so that
+// all of its connections share the entry.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct AffinityKey {
+    pub client_ip: u32,
+    pub backend_key: BackendKey,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for AffinityKey {}
+
+// Affinity is the backend a client of a vip is pinned to, until expires_at (in nanoseconds of
+// bpf_ktime_get_ns). The index is where the backend was in the vip's backends, the pin no longer
+// applies once another backend is found there.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct Affinity {
+    pub backend: Backend,
+    pub index: u16,
+    pub expires_at: u64,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for Affinity {}
+
+// RateLimitKey identifies a client of a vip with a rate limit, by its ip address only so that all
+// of its connections share the bucket.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct RateLimitKey {
+    pub client_ip: u32,
+    pub backend_key: BackendKey,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for RateLimitKey {}
+
+// TokenBucket is the tokens a client of a vip with a rate limit has left, in billionths of a
+// packet, as of updated_at (in nanoseconds of bpf_ktime_get_ns).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct TokenBucket {
+    pub tokens: u64,
+    pub updated_at: u64,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for TokenBucket {}
+
+// The longest connection ID QUIC allows.
+pub const QUIC_MAX_CID_LEN: usize = 20;
+
+// QuicKey identifies a QUIC connection of a vip by a connection ID its client sends packets to,
+// the first cid_len bytes of cid.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct QuicKey {
+    pub backend_key: BackendKey,
+    pub cid_len: u32,
+    pub cid: [u8; QUIC_MAX_CID_LEN],
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for QuicKey {}
+
 // TCPState contains variants that represent the current phase of the TCP connection at a point in
 // time during the connection's termination.
 #[derive(Copy, Clone, Debug, Default)]
@@ -74,7 +454,180 @@ pub struct LoadBalancerMapping {
     pub backend: Backend,
     pub backend_key: BackendKey,
     pub tcp_state: Option<TCPState>,
+    // packets and bytes count the traffic forwarded on the connection, in both directions
+    pub packets: u64,
+    pub bytes: u64,
+    // last_seen is the bpf_ktime_get_ns of the last packet of the connection
+    pub last_seen: u64,
+    // snat_port is the source port the connection is sent to its backend from with full NAT,
+    // 0 otherwise
+    pub snat_port: u32,
+    // vip_port is the port the client connected to, which replies are sent from. It's only
+    // different from backend_key.port for vips of a range of ports.
+    pub vip_port: u32,
+    // proxy_seq is the sequence number of the first byte the client sends on a connection to a
+    // vip with the PROXY protocol, which the header is inserted before. None for other
+    // connections, and those that weren't tracked from their SYN.
+    pub proxy_seq: Option<u32>,
+    // request_ns is the bpf_ktime_get_ns the SYN of the connection, or the first datagram of
+    // the UDP flow, was forwarded at, until its first reply is measured, see common::latency.
+    // 0 afterwards.
+    pub request_ns: u64,
+    // backend_index is the index of the backend in those of the vip when the connection was
+    // assigned it. The backends of the vip may have changed since.
+    pub backend_index: u16,
 }
 
 #[cfg(feature = "user")]
 unsafe impl aya::Pod for LoadBalancerMapping {}
+
+// SnatKey identifies a full NAT connection as its backend sees it: from snat_port of the node's
+// SNAT address to the backend's address and port, over the ip protocol proto.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct SnatKey {
+    pub backend_ip: u32,
+    pub backend_port: u32,
+    pub snat_port: u32,
+    pub proto: u32,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for SnatKey {}
+
+// Snat is the client and vip of a full NAT connection, that the backend's replies are sent back
+// to and from. It's only current while the client's LB_CONNECTIONS entry has the same
+// snat_port, stale entries are reused by new connections.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct Snat {
+    pub client_key: ClientKey,
+    pub backend_key: BackendKey,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for Snat {}
+
+// FragmentKey identifies the fragments of an IPv4 datagram by the fields they are reassembled
+// by: the client's and vip's addresses, the ip protocol and the datagram's identification.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct FragmentKey {
+    pub src_addr: u32,
+    pub dst_addr: u32,
+    pub id: u16,
+    pub proto: u16,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for FragmentKey {}
+
+// Fragment is where the first fragment of a datagram was sent, that its later fragments, which
+// have no UDP header to be load balanced by, are sent to as well. snat_ip is the address they
+// are sent from with full NAT, 0 otherwise.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct Fragment {
+    pub backend_key: BackendKey,
+    pub backend: Backend,
+    pub snat_ip: u32,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for Fragment {}
+
+// VipStats counts the traffic a vip load balanced and the latency of its backends, per CPU in
+// the VIP_STATS map.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct VipStats {
+    pub packets: u64,
+    pub bytes: u64,
+    // latency is how long the backends of the vip took to answer new connections.
+    pub latency: latency::LatencyHistogram,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for VipStats {}
+
+// CaptureConfig selects the packets the eBPF programs copy to userspace, in the single entry of
+// the CAPTURE_CONFIG map.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct CaptureConfig {
+    pub backend_key: BackendKey,
+    // enabled is 1 while a capture of the vip is running
+    pub enabled: u32,
+    // snaplen is the maximum number of bytes copied of each packet
+    pub snaplen: u32,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for CaptureConfig {}
+
+// CaptureHeader precedes the bytes of each packet sent on the PACKET_CAPTURES perf event array.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct CaptureHeader {
+    // timestamp is the bpf_ktime_get_ns at which the packet was captured
+    pub timestamp: u64,
+    // len is the length of the packet, of which the first captured_len bytes follow
+    pub len: u32,
+    pub captured_len: u32,
+    pub stage: u32,
+    // ifindex is the interface the packet was received on, or is redirected to once rewritten
+    pub ifindex: u32,
+}
+
+// SampleConfig selects the packets the eBPF programs sample to the PACKET_SAMPLES ring buffer, in
+// the single entry of the SAMPLE_CONFIG map. Unlike captures, which are requested through the
+// API, sampling is set up by the loader and runs for as long as the dataplane does.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct SampleConfig {
+    // the vip of the sampled packets, by its address, (first) port and proto
+    pub ip: u32,
+    pub port: u32,
+    pub proto: u32,
+    // rate is the N of every Nth packet of the vip that's sampled, none when 0
+    pub rate: u32,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for SampleConfig {}
+
+// PacketSample is sent on the PACKET_SAMPLES ring buffer for each sampled packet, with the first
+// header.captured_len bytes of the packet in data.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct PacketSample {
+    pub header: CaptureHeader,
+    pub data: [u8; SAMPLE_HEADERS_LEN],
+}
+
+// FlowRecord is sent on the FLOW_RECORDS ring buffer when a tracked connection is closed, with
+// the traffic it carried.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct FlowRecord {
+    pub client_key: ClientKey,
+    pub backend_key: BackendKey,
+    pub backend: Backend,
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+// ErrorEvent is sent on the ERROR_EVENTS ring buffer when a helper fails a program handling a
+// packet, which is then passed on as is. Packets that aren't for a vip or too short to parse
+// aren't errors. Addresses are those of the packet as it was when handling
+// failed, 0 when it has no IPv4 header.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct ErrorEvent {
+    pub program: u32,
+    pub proto: u32,
+    pub src_addr: u32,
+    pub dst_addr: u32,
+    // error is the negative errno the helper failed with
+    pub error: i64,
+}
@@ -0,0 +1,84 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! Maglev consistent hashing, see https://research.google/pubs/pub44824/. Userspace populates
+//! the lookup table of a vip from its backends, and the datapath picks the table entry at the
+//! hash of a packet's 5-tuple. Both only depend on their inputs, so every dataplane (and every
+//! restart of one) maps a client to the same backend.
+
+use crate::{Backend, MAGLEV_TABLE_SIZE, MAX_BACKENDS_PER_VIP};
+
+// The splitmix64 finalizer, a cheap hash with good avalanche.
+#[inline(always)]
+fn mix64(mut x: u64) -> u64 {
+    x ^= x >> 30;
+    x = x.wrapping_mul(0xbf58476d1ce4e5b9);
+    x ^= x >> 27;
+    x = x.wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+/// Hashes the 5-tuple of a packet, with addresses and ports in host byte order.
+#[inline(always)]
+pub fn flow_hash(src_ip: u32, src_port: u16, dst_ip: u32, dst_port: u16, proto: u8) -> u64 {
+    let addresses = ((src_ip as u64) << 32) | dst_ip as u64;
+    let ports = ((proto as u64) << 32) | ((src_port as u64) << 16) | dst_port as u64;
+    mix64(addresses ^ mix64(ports))
+}
+
+/// Returns the index of the lookup table entry of a flow hash.
+#[inline(always)]
+pub fn table_index(hash: u64) -> usize {
+    (hash % MAGLEV_TABLE_SIZE as u64) as usize
+}
+
+/// Populates a lookup table whose entries are indexes into `backends`, each backend getting
+/// an almost even share of them. Adding or removing a backend only moves about the share of
+/// that backend to others. Backends are identified by address and port, so the order they
+/// are listed in doesn't matter.
+pub fn populate(backends: &[Backend]) -> [u16; MAGLEV_TABLE_SIZE] {
+    let n = backends.len().min(MAX_BACKENDS_PER_VIP);
+    if n == 0 {
+        return [0; MAGLEV_TABLE_SIZE];
+    }
+    let m = MAGLEV_TABLE_SIZE as u64;
+
+    let mut order = [0u16; MAX_BACKENDS_PER_VIP];
+    for (i, slot) in order.iter_mut().enumerate().take(n) {
+        *slot = i as u16;
+    }
+    order[..n].sort_unstable_by_key(|&i| (backends[i as usize].daddr, backends[i as usize].dport));
+
+    // each backend's preference list is the permutation offset + j * skip (mod m)
+    let mut offset = [0u64; MAX_BACKENDS_PER_VIP];
+    let mut skip = [0u64; MAX_BACKENDS_PER_VIP];
+    for (j, &i) in order[..n].iter().enumerate() {
+        let backend = &backends[i as usize];
+        let key = ((backend.daddr as u64) << 32) | backend.dport as u64;
+        offset[j] = mix64(key) % m;
+        skip[j] = mix64(!key) % (m - 1) + 1;
+    }
+
+    // the backends take turns claiming their next preferred entry that is still free
+    let mut table = [u16::MAX; MAGLEV_TABLE_SIZE];
+    let mut next = [0u64; MAX_BACKENDS_PER_VIP];
+    let mut filled = 0;
+    loop {
+        for j in 0..n {
+            let mut entry = ((offset[j] + next[j] * skip[j]) % m) as usize;
+            while table[entry] != u16::MAX {
+                next[j] += 1;
+                entry = ((offset[j] + next[j] * skip[j]) % m) as usize;
+            }
+            table[entry] = order[j];
+            next[j] += 1;
+            filled += 1;
+            if filled == MAGLEV_TABLE_SIZE {
+                return table;
+            }
+        }
+    }
+}
@@ -0,0 +1,198 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! Metrics shared by the controlplane and the API of the dataplane: histograms with fixed
+//! buckets, rendered in the Prometheus and OpenMetrics text formats, and the HTTP endpoint they
+//! are served on.
+
+use std::{fmt::Write, sync::Arc};
+
+use log::debug;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// The content type of the Prometheus text format.
+pub const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+/// The content type of the OpenMetrics text format.
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// The longest the request line of a request for the metrics can be.
+pub const MAX_REQUEST_LINE_LEN: usize = 8192;
+
+const EXEMPLAR_LABEL: &str = "gateway";
+
+/// The last observation of a bucket of a histogram, made for the request of a Gateway.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Exemplar {
+    pub gateway: String,
+    pub value: f64,
+}
+
+/// A histogram with fixed buckets, and an exemplar for each of them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    bounds: &'static [f64],
+    // the count of each bucket, without those of the smaller buckets, and of +Inf last
+    counts: Vec<u64>,
+    exemplars: Vec<Option<Exemplar>>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    pub fn new(bounds: &'static [f64]) -> Self {
+        Histogram {
+            bounds,
+            counts: vec![0; bounds.len() + 1],
+            exemplars: vec![None; bounds.len() + 1],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    /// Records an observation of `value`, made for the request of the Gateway if there's one.
+    pub fn observe(&mut self, value: f64, gateway: Option<&str>) {
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.bounds.len());
+        self.counts[bucket] += 1;
+        if let Some(gateway) = gateway {
+            self.exemplars[bucket] = Some(Exemplar {
+                gateway: gateway.to_string(),
+                value,
+            });
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    /// Returns the number of observations.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the exemplar of the bucket with the upper bound, `None` for +Inf.
+    pub fn exemplar(&self, bound: Option<f64>) -> Option<&Exemplar> {
+        let bucket = match bound {
+            Some(bound) => self.bounds.iter().position(|b| *b == bound)?,
+            None => self.bounds.len(),
+        };
+        self.exemplars[bucket].as_ref()
+    }
+
+    /// Writes the samples of the histogram with the labels, which are written before `le` and
+    /// may be empty, with cumulative buckets. Exemplars are only part of the OpenMetrics text
+    /// format, histograms rendered in the Prometheus one shouldn't observe Gateways.
+    pub fn render(&self, name: &str, labels: &str, out: &mut String) {
+        let les = self
+            .bounds
+            .iter()
+            .map(|bound| format!("{:?}", bound))
+            .chain(["+Inf".to_string()]);
+        let separator = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for ((le, count), exemplar) in les.zip(&self.counts).zip(&self.exemplars) {
+            cumulative += count;
+            let _ = write!(
+                out,
+                "{}_bucket{{{}{}le=\"{}\"}} {}",
+                name, labels, separator, le, cumulative
+            );
+            if let Some(exemplar) = exemplar {
+                let _ = write!(
+                    out,
+                    " # {{{}=\"{}\"}} {}",
+                    EXEMPLAR_LABEL, exemplar.gateway, exemplar.value
+                );
+            }
+            out.push('\n');
+        }
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", labels)
+        };
+        let _ = writeln!(out, "{}_sum{} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{} {}", name, labels, self.count);
+    }
+}
+
+/// Serves the metrics `render` returns over HTTP on `GET /metrics`, with the content type, until
+/// the listener fails.
+pub async fn serve<F>(
+    listener: TcpListener,
+    content_type: &'static str,
+    render: F,
+) -> std::io::Result<()>
+where
+    F: Fn() -> String + Send + Sync + 'static,
+{
+    let render = Arc::new(render);
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let render = render.clone();
+        tokio::spawn(async move {
+            if let Err(error) = serve_metrics(stream, content_type, render.as_ref()).await {
+                debug!("failed to serve metrics to {}: {}", peer, error);
+            }
+        });
+    }
+}
+
+async fn serve_metrics(
+    mut stream: TcpStream,
+    content_type: &str,
+    render: &(dyn Fn() -> String + Send + Sync),
+) -> std::io::Result<()> {
+    let request_line = read_request_line(&mut stream).await?;
+    let (status, body) = match request_line {
+        Some(line) => match line.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+            ["GET", "/metrics"] => ("200 OK", render()),
+            _ => ("404 Not Found", String::new()),
+        },
+        None => ("400 Bad Request", String::new()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await
+}
+
+/// Reads the request line of an HTTP request, which is all that's needed of the request, however
+/// many reads it takes to arrive. Returns None when the stream ends before it does or it's longer
+/// than MAX_REQUEST_LINE_LEN.
+pub async fn read_request_line(
+    stream: &mut (impl AsyncRead + Unpin),
+) -> std::io::Result<Option<String>> {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    loop {
+        if let Some(end) = request.iter().position(|byte| *byte == b'\n') {
+            if end > MAX_REQUEST_LINE_LEN {
+                return Ok(None);
+            }
+            let line = String::from_utf8_lossy(&request[..end]);
+            return Ok(Some(line.trim_end().to_string()));
+        }
+        if request.len() > MAX_REQUEST_LINE_LEN {
+            return Ok(None);
+        }
+        let len = stream.read(&mut buf).await?;
+        if len == 0 {
+            return Ok(None);
+        }
+        request.extend_from_slice(&buf[..len]);
+    }
+}
@@ -0,0 +1,60 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! Clamping of the maximum segment size (MSS) that TCP SYN packets advertise, see RFC 9293
+//! 3.7.1. A lower MSS keeps the segments sent in reply small enough for backends behind tunnels
+//! or links with a lower MTU, whose ICMP errors may never make it back to the sender.
+
+/// The most bytes of options a TCP header can have.
+pub const TCP_MAX_OPTIONS_LEN: usize = 40;
+/// The lowest MSS the MSS can be clamped to, the one Linux enforces.
+pub const TCP_MIN_MSS: u32 = 88;
+
+const TCPOPT_EOL: u8 = 0;
+pub(crate) const TCPOPT_NOP: u8 = 1;
+const TCPOPT_MSS: u8 = 2;
+const TCPOLEN_MSS: usize = 4;
+
+/// Returns the offset of the value of the MSS option in the first `len` bytes of the options of
+/// a TCP header, if they have a well formed one.
+#[inline(always)]
+pub fn mss_offset(options: &[u8; TCP_MAX_OPTIONS_LEN], len: usize) -> Option<usize> {
+    option_offset(options, len, TCPOPT_MSS, TCPOLEN_MSS).map(|offset| offset + 2)
+}
+
+/// Returns the offset of the option of the kind in the first `len` bytes of the options of a
+/// TCP header, if they have a well formed one, which is `opt_len` bytes long.
+#[inline(always)]
+pub fn option_offset(
+    options: &[u8; TCP_MAX_OPTIONS_LEN],
+    len: usize,
+    kind: u8,
+    opt_len: usize,
+) -> Option<usize> {
+    let len = len.min(TCP_MAX_OPTIONS_LEN);
+    let mut i = 0;
+    // options are at least a byte long, so this many steps reach the end of any of them
+    for _ in 0..TCP_MAX_OPTIONS_LEN {
+        if i >= len {
+            return None;
+        }
+        match *options.get(i)? {
+            TCPOPT_EOL => return None,
+            TCPOPT_NOP => i += 1,
+            option_kind => {
+                let option_len = *options.get(i + 1)? as usize;
+                if option_kind == kind {
+                    return (option_len == opt_len && i + opt_len <= len).then_some(i);
+                }
+                if option_len < 2 {
+                    return None;
+                }
+                i += option_len;
+            }
+        }
+    }
+    None
+}
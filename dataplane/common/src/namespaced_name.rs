@@ -0,0 +1,89 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+use std::{fmt, str::FromStr};
+
+#[cfg(feature = "k8s")]
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+/// The name and namespace which together identify a namespaced Kubernetes object.
+///
+/// The string form is `namespace/name`, matching the format used by kubectl and client-go.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NamespacedName {
+    pub name: String,
+    pub namespace: String,
+}
+
+impl NamespacedName {
+    pub fn new(namespace: impl Into<String>, name: impl Into<String>) -> Self {
+        NamespacedName {
+            name: name.into(),
+            namespace: namespace.into(),
+        }
+    }
+}
+
+impl fmt::Display for NamespacedName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.namespace, self.name)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NamespacedNameError {
+    MissingName,
+    MissingNamespace,
+    Malformed(String),
+}
+
+impl fmt::Display for NamespacedNameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NamespacedNameError::MissingName => write!(f, "object name not found"),
+            NamespacedNameError::MissingNamespace => write!(f, "object namespace not found"),
+            NamespacedNameError::Malformed(value) => {
+                write!(
+                    f,
+                    "invalid namespaced name {:?}, expected namespace/name",
+                    value
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for NamespacedNameError {}
+
+impl FromStr for NamespacedName {
+    type Err = NamespacedNameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('/') {
+            Some((namespace, name))
+                if !namespace.is_empty() && !name.is_empty() && !name.contains('/') =>
+            {
+                Ok(NamespacedName::new(namespace, name))
+            }
+            _ => Err(NamespacedNameError::Malformed(s.to_string())),
+        }
+    }
+}
+
+#[cfg(feature = "k8s")]
+impl TryFrom<&ObjectMeta> for NamespacedName {
+    type Error = NamespacedNameError;
+
+    fn try_from(meta: &ObjectMeta) -> Result<Self, Self::Error> {
+        let name = meta.name.clone().ok_or(NamespacedNameError::MissingName)?;
+        let namespace = meta
+            .namespace
+            .clone()
+            .ok_or(NamespacedNameError::MissingNamespace)?;
+        Ok(NamespacedName { name, namespace })
+    }
+}
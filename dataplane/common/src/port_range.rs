@@ -0,0 +1,48 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! Ranges of ports as prefixes of the VIP_PORT_RANGES LPM trie. Userspace splits the range of a
+//! vip into the fewest aligned blocks of ports, each of which is a single prefix, and the datapath
+//! looks up the port of a packet that no vip has a port of its own for.
+
+/// Returns the prefixes that cover the ports from `start` to `end` (inclusive), as the first port
+/// of each prefix and the number of leading bits of the port it fixes. Ranges are split into at
+/// most MAX_PORT_RANGE_PREFIXES prefixes, the range of all ports is a single prefix of 0 bits.
+pub fn prefixes(start: u16, end: u16) -> Prefixes {
+    Prefixes {
+        next: start as u32,
+        end: end as u32,
+    }
+}
+
+/// An iterator over the prefixes of a range of ports, see [`prefixes`].
+#[derive(Clone, Debug)]
+pub struct Prefixes {
+    next: u32,
+    end: u32,
+}
+
+impl Iterator for Prefixes {
+    type Item = (u16, u32);
+
+    fn next(&mut self) -> Option<(u16, u32)> {
+        if self.next > self.end {
+            return None;
+        }
+        // the largest block of ports aligned on the next port that doesn't go past the end
+        let mut bits = if self.next == 0 {
+            16
+        } else {
+            self.next.trailing_zeros().min(16)
+        };
+        while self.next + (1 << bits) - 1 > self.end {
+            bits -= 1;
+        }
+        let prefix = (self.next as u16, 16 - bits);
+        self.next += 1 << bits;
+        Some(prefix)
+    }
+}
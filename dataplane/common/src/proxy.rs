@@ -0,0 +1,98 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! Version 2 of the PROXY protocol, see
+//! https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt. On the TCP connections of vips
+//! that use it, the dataplane inserts the header before the first byte the client sends, so that
+//! backends behind more NAT learn the address of the client. The backend then sees every later
+//! byte of the client PROXY_V2_HEADER_LEN bytes further in the stream, and the sequence and
+//! acknowledgment numbers of the connection are shifted by as much.
+//!
+//! The SYN of the client has its SACK-permitted option cleared, so that the connection doesn't
+//! use selective acknowledgments, whose blocks the backend would send shifted too.
+
+use crate::mss::{option_offset, TCPOPT_NOP, TCP_MAX_OPTIONS_LEN};
+
+/// The length of the header of a TCP connection over IPv4.
+pub const PROXY_V2_HEADER_LEN: usize = 28;
+
+const SIGNATURE: [u8; 12] = [
+    0x0d, 0x0a, 0x0d, 0x0a, 0x00, 0x0d, 0x0a, 0x51, 0x55, 0x49, 0x54, 0x0a,
+];
+// version 2, PROXY command
+const VERSION_COMMAND: u8 = 0x21;
+// AF_INET, STREAM
+const FAMILY_TCP4: u8 = 0x11;
+// the length of the addresses and ports that follow the first 16 bytes
+const TCP4_ADDRESSES_LEN: u16 = 12;
+
+/// The length of the SACK-permitted option of TCP SYNs.
+pub const TCPOLEN_SACK_PERM: usize = 2;
+const TCPOPT_SACK_PERM: u8 = 4;
+
+/// Returns the header of a TCP connection from the client to the vip, whose addresses and ports
+/// are in host byte order.
+#[inline(always)]
+pub fn proxy_v2_header(
+    client_ip: u32,
+    client_port: u16,
+    vip_ip: u32,
+    vip_port: u16,
+) -> [u8; PROXY_V2_HEADER_LEN] {
+    let mut header = [0; PROXY_V2_HEADER_LEN];
+    header[..12].copy_from_slice(&SIGNATURE);
+    header[12] = VERSION_COMMAND;
+    header[13] = FAMILY_TCP4;
+    header[14..16].copy_from_slice(&TCP4_ADDRESSES_LEN.to_be_bytes());
+    header[16..20].copy_from_slice(&client_ip.to_be_bytes());
+    header[20..24].copy_from_slice(&vip_ip.to_be_bytes());
+    header[24..26].copy_from_slice(&client_port.to_be_bytes());
+    header[26..28].copy_from_slice(&vip_port.to_be_bytes());
+    header
+}
+
+/// Returns the sequence number the backend sees for the segment the client sent with `seq`, on
+/// a connection whose header was inserted before the byte at `first_seq`.
+#[inline(always)]
+pub fn backend_seq(seq: u32, first_seq: u32) -> u32 {
+    if seq_after(seq, first_seq) {
+        seq.wrapping_add(PROXY_V2_HEADER_LEN as u32)
+    } else {
+        seq
+    }
+}
+
+/// Returns the acknowledgment number the client expects for the `ack` the backend sent, on a
+/// connection whose header was inserted before the byte at `first_seq`. Acknowledging part of
+/// the header acknowledges none of the client's bytes.
+#[inline(always)]
+pub fn client_ack(ack: u32, first_seq: u32) -> u32 {
+    let acked = ack.wrapping_sub(first_seq) as i32;
+    if acked <= 0 {
+        ack
+    } else if acked < PROXY_V2_HEADER_LEN as i32 {
+        first_seq
+    } else {
+        ack.wrapping_sub(PROXY_V2_HEADER_LEN as u32)
+    }
+}
+
+/// Replaces the SACK-permitted option in the first `len` bytes of the options of a TCP header
+/// with NOPs, if they have a well formed one, returning its offset.
+#[inline(always)]
+pub fn clear_sack_permitted(options: &mut [u8; TCP_MAX_OPTIONS_LEN], len: usize) -> Option<usize> {
+    let offset = option_offset(options, len, TCPOPT_SACK_PERM, TCPOLEN_SACK_PERM)?;
+    options
+        .get_mut(offset..offset + TCPOLEN_SACK_PERM)?
+        .fill(TCPOPT_NOP);
+    Some(offset)
+}
+
+// Returns whether the sequence number a is after b, which it wraps around to.
+#[inline(always)]
+fn seq_after(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
@@ -0,0 +1,37 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! Token buckets of the clients of vips with a rate limit. Each client ip has a bucket of up to
+//! `burst` tokens, which refills at `packets_per_second` tokens per second, and every packet of
+//! the client takes a token: those that find the bucket empty are over the limit. Tokens are
+//! counted in billionths, so that the bucket refills by the nanosecond without a division.
+
+use crate::{RateLimit, TokenBucket};
+
+/// A token, in the unit of the tokens of buckets.
+pub const TOKEN: u64 = 1_000_000_000;
+
+/// Takes the token of a packet of a client, whose bucket was last updated as `bucket`, at `now`
+/// in nanoseconds. Returns the bucket after the packet, and whether the packet is within the
+/// limit. Clients without a bucket get a full one.
+#[inline(always)]
+pub fn take_token(bucket: Option<TokenBucket>, limit: &RateLimit, now: u64) -> (TokenBucket, bool) {
+    let capacity = (limit.burst.max(1) as u64).saturating_mul(TOKEN);
+    let tokens = match bucket {
+        Some(bucket) => {
+            let elapsed = now.saturating_sub(bucket.updated_at);
+            let refill = elapsed.saturating_mul(limit.packets_per_second as u64);
+            bucket.tokens.saturating_add(refill).min(capacity)
+        }
+        None => capacity,
+    };
+    let allowed = tokens >= TOKEN;
+    let bucket = TokenBucket {
+        tokens: if allowed { tokens - TOKEN } else { tokens },
+        updated_at: now,
+    };
+    (bucket, allowed)
+}
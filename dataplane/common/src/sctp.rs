@@ -0,0 +1,138 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! The headers and checksum of SCTP packets.
+//!
+//! Unlike those of TCP and UDP, the checksum of SCTP packets is a CRC32c of the packet alone,
+//! without a pseudo header: rewriting the addresses of a packet leaves it as is, while rewriting
+//! its ports changes it in a way that depends on the length of the packet. The CRC being linear,
+//! the new checksum is computed from the old one and the ports, see update_checksum, without
+//! reading the rest of the packet, which the eBPF programs can't afford.
+
+// The CRC32c polynomial, bit reversed as the checksum is computed least significant bit first.
+const CRC32C_POLY: u32 = 0x82f6_3b78;
+
+// The most bits of the length of an SCTP packet, which is at most that of an IPv4 packet.
+const LEN_BITS: usize = 16;
+
+// X_POW_2K[k] is x^(2^k) modulo the polynomial, bit reversed.
+const X_POW_2K: [u32; LEN_BITS + 3] = x_pow_2k();
+
+/// The common header SCTP packets start with, followed by their chunks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct SctpHdr {
+    pub source: u16,
+    pub dest: u16,
+    pub verification_tag: u32,
+    // the CRC32c of the packet, computed with this field zeroed, in little endian
+    pub checksum: u32,
+}
+
+impl SctpHdr {
+    pub const LEN: usize = 12;
+}
+
+/// The header every chunk of an SCTP packet starts with.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct ChunkHdr {
+    pub type_: u8,
+    pub flags: u8,
+    pub length: u16,
+}
+
+// The types of the chunks the dataplane follows associations by, see RFC 9260.
+pub const CHUNK_INIT: u8 = 1;
+pub const CHUNK_INIT_ACK: u8 = 2;
+pub const CHUNK_ABORT: u8 = 6;
+pub const CHUNK_SHUTDOWN_COMPLETE: u8 = 14;
+
+/// Returns whether a packet whose first chunk is of the type is the last of its association.
+#[inline(always)]
+pub fn ends_association(chunk_type: u8) -> bool {
+    chunk_type == CHUNK_ABORT || chunk_type == CHUNK_SHUTDOWN_COMPLETE
+}
+
+/// Returns the CRC32c of the data, which is the checksum of an SCTP packet when the data is the
+/// packet with its checksum zeroed.
+pub fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0;
+    for byte in data {
+        crc = crc32c_byte(crc, *byte);
+    }
+    !crc
+}
+
+/// Returns the checksum of an SCTP packet of `len` bytes after its ports, the first 4 bytes of
+/// the packet, changed from `old_ports` to `new_ports`.
+///
+/// The checksums of two packets of the same length differ by the CRC, without its initial value
+/// and final inversion, of the difference of the packets: here the difference of the ports
+/// followed by `len - 4` zeros, whose CRC is that of the ports multiplied by x^(8 * (len - 4)).
+#[inline(always)]
+pub fn update_checksum(checksum: u32, old_ports: [u8; 4], new_ports: [u8; 4], len: u16) -> u32 {
+    let mut diff = 0;
+    for i in 0..4 {
+        diff = crc32c_byte(diff, old_ports[i] ^ new_ports[i]);
+    }
+
+    // x^(8 * zeros) is the product of the x^(2^k) of the bits of 8 * zeros
+    let zeros = len.saturating_sub(4) as u32;
+    for bit in 0..LEN_BITS {
+        if zeros & (1 << bit) != 0 {
+            diff = mul_mod(X_POW_2K[bit + 3], diff);
+        }
+    }
+    checksum ^ diff
+}
+
+#[inline(always)]
+const fn crc32c_byte(mut crc: u32, byte: u8) -> u32 {
+    crc ^= byte as u32;
+    let mut i = 0;
+    while i < 8 {
+        crc = if crc & 1 != 0 {
+            (crc >> 1) ^ CRC32C_POLY
+        } else {
+            crc >> 1
+        };
+        i += 1;
+    }
+    crc
+}
+
+// Multiplies the bit reversed polynomials a and b modulo the CRC32c polynomial.
+#[inline(always)]
+const fn mul_mod(a: u32, mut b: u32) -> u32 {
+    let mut product = 0;
+    let mut i = 0;
+    while i < 32 {
+        if a & (1 << (31 - i)) != 0 {
+            product ^= b;
+        }
+        b = if b & 1 != 0 {
+            (b >> 1) ^ CRC32C_POLY
+        } else {
+            b >> 1
+        };
+        i += 1;
+    }
+    product
+}
+
+const fn x_pow_2k() -> [u32; LEN_BITS + 3] {
+    let mut table = [0; LEN_BITS + 3];
+    // x^1, the bit of x^0 being the most significant one
+    let mut x = 1 << 30;
+    let mut k = 0;
+    while k < table.len() {
+        table[k] = x;
+        x = mul_mod(x, x);
+        k += 1;
+    }
+    table
+}
@@ -0,0 +1,58 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! Where the backends of a vip are kept: the first BACKENDS_ARRAY_CAPACITY in its BackendList,
+//! the others in BACKEND_SLOTS entries of up to BACKENDS_ARRAY_CAPACITY each. Userspace lays the
+//! backends out and the datapath finds them with the same arithmetic, whose results are bounded
+//! by the capacities so that the datapath's array accesses can be proven in bounds whatever they
+//! are set to.
+
+use crate::{BACKENDS_ARRAY_CAPACITY, MAX_BACKENDS_PER_VIP};
+
+// Backend indexes are u16s, and the first backends are always in the BackendList.
+const _: () = assert!(BACKENDS_ARRAY_CAPACITY > 0);
+const _: () = assert!(BACKENDS_ARRAY_CAPACITY <= MAX_BACKENDS_PER_VIP);
+const _: () = assert!(MAX_BACKENDS_PER_VIP <= u16::MAX as usize);
+
+/// Where a backend of a vip is: at `offset` of its BackendList when `slot` is 0, or else of its
+/// BACKEND_SLOTS entry `slot`. The offset is always less than BACKENDS_ARRAY_CAPACITY.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub slot: u32,
+    pub offset: usize,
+}
+
+/// Returns the position of the backend at `index` of a vip with `backends_len` backends, None
+/// past its backends.
+#[inline(always)]
+pub fn position(index: u16, backends_len: u16) -> Option<Position> {
+    let index = index as usize;
+    if index >= backends_len as usize || index >= MAX_BACKENDS_PER_VIP {
+        return None;
+    }
+    Some(Position {
+        slot: (index / BACKENDS_ARRAY_CAPACITY) as u32,
+        offset: index % BACKENDS_ARRAY_CAPACITY,
+    })
+}
+
+/// Returns the number of BACKEND_SLOTS entries of a vip with `backends_len` backends.
+#[inline(always)]
+pub fn overflow_slots(backends_len: u16) -> u32 {
+    (backends_len as usize)
+        .div_ceil(BACKENDS_ARRAY_CAPACITY)
+        .saturating_sub(1) as u32
+}
+
+/// Returns the round-robin index that follows `index` among `backends_len` backends, going back
+/// to the first backend after the last one.
+#[inline(always)]
+pub fn next_index(index: u16, backends_len: u16) -> u16 {
+    match index.checked_add(1) {
+        Some(next) if next < backends_len => next,
+        _ => 0,
+    }
+}
@@ -0,0 +1,32 @@
+use common::acl::{any_client_key, cidr_key, client_key};
+use common::{AclKey, BackendKey, IPPROTO_TCP};
+
+const VIP: BackendKey = BackendKey {
+    ip: 0xc000_020a,
+    port: 8080,
+    port_end: 0,
+    proto: IPPROTO_TCP,
+};
+
+#[test]
+fn test_cidr_key() {
+    let (prefix_len, key) = cidr_key(VIP, 0x0a00_0000, 8).unwrap();
+    assert_eq!(prefix_len, AclKey::FIXED_PREFIX_LEN + 8);
+    assert_eq!(key.backend_key, VIP);
+    assert_eq!(key.client_ip.to_ne_bytes(), [10, 0, 0, 0]);
+
+    assert_eq!(
+        cidr_key(VIP, 0x0a00_0001, 32),
+        Some(client_key(VIP, 0x0a00_0001))
+    );
+    assert_eq!(cidr_key(VIP, 0, 0), Some(any_client_key(VIP)));
+}
+
+#[test]
+fn test_cidr_key_invalid() {
+    // bits set past the prefix
+    assert_eq!(cidr_key(VIP, 0x0a00_0001, 8), None);
+    assert_eq!(cidr_key(VIP, 0x0a00_0000, 0), None);
+    // prefix longer than an address
+    assert_eq!(cidr_key(VIP, 0x0a00_0001, 33), None);
+}
@@ -0,0 +1,81 @@
+use common::drain::{is_draining, next_undrained, set_draining};
+use common::{DRAINING_WORDS, MAX_BACKENDS_PER_VIP};
+
+fn draining(indexes: &[u16]) -> [u64; DRAINING_WORDS] {
+    let mut draining = [0; DRAINING_WORDS];
+    for index in indexes {
+        set_draining(&mut draining, *index, true);
+    }
+    draining
+}
+
+#[test]
+fn test_set_draining() {
+    let mut bitmap = draining(&[0, 63, 64, 1023]);
+    for index in [0, 63, 64, 1023] {
+        assert!(is_draining(&bitmap, index));
+    }
+    assert!(!is_draining(&bitmap, 1));
+    assert!(!is_draining(&bitmap, 65));
+    // past the bitmap
+    assert!(!is_draining(&bitmap, u16::MAX));
+    set_draining(&mut bitmap, u16::MAX, true);
+
+    set_draining(&mut bitmap, 64, false);
+    assert!(!is_draining(&bitmap, 64));
+    assert!(is_draining(&bitmap, 63));
+}
+
+#[test]
+fn test_next_undrained_skips_draining() {
+    let bitmap = draining(&[1, 2]);
+    assert_eq!(next_undrained(&bitmap, 0, 4), Some(0));
+    assert_eq!(next_undrained(&bitmap, 1, 4), Some(3));
+    assert_eq!(next_undrained(&bitmap, 3, 4), Some(3));
+}
+
+#[test]
+fn test_next_undrained_wraps_around() {
+    let bitmap = draining(&[2, 3]);
+    assert_eq!(next_undrained(&bitmap, 2, 4), Some(0));
+
+    // across words, back to a backend before the word of the index
+    let bitmap = draining(&(65..300).collect::<Vec<_>>());
+    assert_eq!(next_undrained(&bitmap, 70, 300), Some(0));
+    assert_eq!(next_undrained(&bitmap, 70, 301), Some(300));
+}
+
+#[test]
+fn test_next_undrained_all_draining() {
+    let bitmap = draining(&(0..MAX_BACKENDS_PER_VIP as u16).collect::<Vec<_>>());
+    assert_eq!(
+        next_undrained(&bitmap, 0, MAX_BACKENDS_PER_VIP as u16),
+        None
+    );
+    assert_eq!(
+        next_undrained(&bitmap, 517, MAX_BACKENDS_PER_VIP as u16),
+        None
+    );
+
+    let bitmap = draining(&[0, 1, 2]);
+    assert_eq!(next_undrained(&bitmap, 1, 3), None);
+}
+
+#[test]
+fn test_next_undrained_past_backends() {
+    let bitmap = draining(&[]);
+    assert_eq!(next_undrained(&bitmap, 0, 0), None);
+    assert_eq!(next_undrained(&bitmap, 3, 3), None);
+    assert_eq!(next_undrained(&bitmap, 2, 3), Some(2));
+}
+
+#[test]
+fn test_next_undrained_bounded() {
+    // every backend from the index on is looked at, whatever the draining backends
+    for len in [1, 64, 65, 300, MAX_BACKENDS_PER_VIP as u16] {
+        let bitmap = draining(&(0..len - 1).collect::<Vec<_>>());
+        for index in 0..len {
+            assert_eq!(next_undrained(&bitmap, index, len), Some(len - 1));
+        }
+    }
+}
@@ -0,0 +1,17 @@
+use common::dscp::{marked_tos, DSCP_MAX};
+
+#[test]
+fn test_marked_tos() {
+    // EF, the DSCP of voice traffic
+    assert_eq!(marked_tos(0, 46), 0xb8);
+    assert_eq!(marked_tos(0xb8, 0), 0);
+    assert_eq!(marked_tos(0, DSCP_MAX), 0xfc);
+}
+
+#[test]
+fn test_marked_tos_keeps_ecn() {
+    // ECT(0), ECT(1) and CE
+    assert_eq!(marked_tos(0x02, 46), 0xba);
+    assert_eq!(marked_tos(0x01, 46), 0xb9);
+    assert_eq!(marked_tos(0xff, 10), 0x2b);
+}
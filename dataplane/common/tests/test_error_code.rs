@@ -0,0 +1,33 @@
+use common::ErrorCode;
+
+#[test]
+fn test_display() {
+    assert_eq!(ErrorCode::BackendsCapacityExceeded.to_string(), "BLX-1001");
+    assert_eq!(ErrorCode::SctpNotSupported.to_string(), "BLX-1021");
+    assert_eq!(ErrorCode::InvalidConfig.to_string(), "BLX-2002");
+}
+
+#[test]
+fn test_try_from_round_trip() {
+    let mut codes = 0;
+    for value in 0..=u16::MAX {
+        let Ok(code) = ErrorCode::try_from(value) else {
+            continue;
+        };
+        codes += 1;
+        assert_eq!(code.code(), value);
+        assert_eq!(code.to_string(), format!("BLX-{}", value));
+        assert_eq!(ErrorCode::try_from(code.code()), Ok(code));
+    }
+    // every variant can be parsed back, and nothing else
+    assert_eq!(codes, 22 + 6);
+}
+
+#[test]
+fn test_try_from_unknown() {
+    assert_eq!(ErrorCode::try_from(0), Err(0));
+    assert_eq!(ErrorCode::try_from(1000), Err(1000));
+    // unused codes aren't parsed, even between used ones
+    assert_eq!(ErrorCode::try_from(2001), Err(2001));
+    assert_eq!(ErrorCode::try_from(3001), Err(3001));
+}
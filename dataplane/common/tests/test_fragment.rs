@@ -0,0 +1,28 @@
+use common::fragment::{is_first_fragment, is_later_fragment};
+
+// the "don't fragment" flag
+const IP_DF: u16 = 0x4000;
+
+#[test]
+fn test_unfragmented() {
+    for frag_off in [0, IP_DF] {
+        assert!(!is_first_fragment(frag_off));
+        assert!(!is_later_fragment(frag_off));
+    }
+}
+
+#[test]
+fn test_first_fragment() {
+    assert!(is_first_fragment(0x2000));
+    assert!(!is_later_fragment(0x2000));
+}
+
+#[test]
+fn test_later_fragments() {
+    // a middle fragment, with more fragments after it
+    assert!(is_later_fragment(0x2000 | 185));
+    assert!(!is_first_fragment(0x2000 | 185));
+    // the last fragment
+    assert!(is_later_fragment(370));
+    assert!(!is_first_fragment(370));
+}
@@ -0,0 +1,29 @@
+use common::headers::{
+    ETH_HDR_LEN, IPV4_MAX_HEADER_LEN, MAX_HEADERS_LEN, MAX_VLAN_TAGS, TCP_MAX_HEADER_LEN,
+    VLAN_TAG_LEN,
+};
+use common::mss::TCP_MAX_OPTIONS_LEN;
+
+// the offset of the ip header of QinQ frames, the furthest one
+const MAX_L3_OFFSET: usize = ETH_HDR_LEN + MAX_VLAN_TAGS * VLAN_TAG_LEN;
+
+#[test]
+fn test_max_headers_len_tcp_options() {
+    // the last option of a TCP header with the most options, behind an ip header with the most
+    // options, such as an MSS option the clamp rewrites
+    let options_end = MAX_L3_OFFSET + IPV4_MAX_HEADER_LEN + 20 + TCP_MAX_OPTIONS_LEN;
+    assert_eq!(TCP_MAX_HEADER_LEN, 20 + TCP_MAX_OPTIONS_LEN);
+    assert!(options_end <= MAX_HEADERS_LEN);
+}
+
+#[test]
+fn test_max_headers_len_icmp_errors() {
+    // an ICMP error with ip options, quoting a packet with ip options, up to its ports
+    let ports_end = MAX_L3_OFFSET + IPV4_MAX_HEADER_LEN + 8 + IPV4_MAX_HEADER_LEN + 4;
+    assert!(ports_end <= MAX_HEADERS_LEN);
+}
+
+#[test]
+fn test_max_headers_len() {
+    assert_eq!(MAX_HEADERS_LEN, 22 + 60 + 72);
+}
@@ -0,0 +1,45 @@
+use common::latency::{latency_bucket, latency_bucket_bound, LatencyHistogram, LATENCY_BUCKETS};
+
+#[test]
+fn test_latency_bucket() {
+    assert_eq!(latency_bucket(0), 0);
+    assert_eq!(latency_bucket(1_000), 0);
+    assert_eq!(latency_bucket(1_001), 1);
+    assert_eq!(latency_bucket(2_000), 1);
+    assert_eq!(latency_bucket(3_000), 2);
+    // a millisecond is at most 1024 microseconds
+    assert_eq!(latency_bucket(1_000_000), 10);
+    assert_eq!(latency_bucket(1_024_000), 10);
+    assert_eq!(latency_bucket(1_024_001), 11);
+}
+
+#[test]
+fn test_latency_bucket_overflow() {
+    let last = LATENCY_BUCKETS - 1;
+    assert_eq!(latency_bucket(1_000 << (last - 1)), last - 1);
+    assert_eq!(latency_bucket((1_000 << (last - 1)) + 1), last);
+    assert_eq!(latency_bucket(u64::MAX), last);
+}
+
+#[test]
+fn test_latency_bucket_bound() {
+    assert_eq!(latency_bucket_bound(0), Some(1));
+    assert_eq!(latency_bucket_bound(10), Some(1024));
+    assert_eq!(latency_bucket_bound(LATENCY_BUCKETS - 2), Some(1 << 22));
+    assert_eq!(latency_bucket_bound(LATENCY_BUCKETS - 1), None);
+}
+
+#[test]
+fn test_observe_and_merge() {
+    let mut histogram = LatencyHistogram::default();
+    histogram.observe(500);
+    histogram.observe(1_500_000);
+    let mut other = LatencyHistogram::default();
+    other.observe(800);
+
+    histogram.merge(&other);
+    assert_eq!(histogram.count(), 3);
+    assert_eq!(histogram.buckets[0], 2);
+    assert_eq!(histogram.buckets[11], 1);
+    assert_eq!(histogram.sum_ns, 1_501_300);
+}
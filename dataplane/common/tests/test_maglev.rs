@@ -0,0 +1,94 @@
+use common::maglev::{flow_hash, populate, table_index};
+use common::{Backend, MAGLEV_TABLE_SIZE};
+
+fn backends(count: u32) -> Vec<Backend> {
+    (0..count)
+        .map(|i| Backend {
+            daddr: 0x0a000001 + i,
+            dport: 8080,
+            ifindex: 1,
+        })
+        .collect()
+}
+
+#[test]
+fn test_populate_even_shares() {
+    let backends = backends(10);
+    let table = populate(&backends);
+
+    let mut shares = [0usize; 10];
+    for entry in table {
+        shares[entry as usize] += 1;
+    }
+    let even = MAGLEV_TABLE_SIZE / 10;
+    for share in shares {
+        assert!(share.abs_diff(even) <= 1, "uneven shares: {:?}", shares);
+    }
+}
+
+#[test]
+fn test_populate_ignores_order() {
+    let backends = backends(5);
+    let mut reversed = backends.clone();
+    reversed.reverse();
+
+    let table = populate(&backends);
+    let reversed_table = populate(&reversed);
+    for (entry, reversed_entry) in table.iter().zip(reversed_table.iter()) {
+        assert_eq!(
+            backends[*entry as usize],
+            reversed[*reversed_entry as usize]
+        );
+    }
+}
+
+#[test]
+fn test_populate_minimal_disruption() {
+    let before = backends(10);
+    let after = backends(11);
+
+    let table_before = populate(&before);
+    let table_after = populate(&after);
+    let moved = table_before
+        .iter()
+        .zip(table_after.iter())
+        .filter(|(b, a)| before[**b as usize] != after[**a as usize])
+        .count();
+    // ideally 1/11th of the entries move to the new backend, Maglev gets close to that
+    assert!(
+        moved < MAGLEV_TABLE_SIZE / 11 * 2,
+        "{} of {} entries moved",
+        moved,
+        MAGLEV_TABLE_SIZE
+    );
+}
+
+#[test]
+fn test_populate_empty() {
+    assert!(populate(&[]).iter().all(|entry| *entry == 0));
+}
+
+#[test]
+fn test_flow_hash() {
+    let hash = flow_hash(0xc0000201, 40000, 0xac120064, 8080, 6);
+    assert_eq!(hash, flow_hash(0xc0000201, 40000, 0xac120064, 8080, 6));
+    assert_ne!(hash, flow_hash(0xc0000201, 40001, 0xac120064, 8080, 6));
+    assert_ne!(hash, flow_hash(0xc0000201, 40000, 0xac120064, 8080, 17));
+    assert!(table_index(hash) < MAGLEV_TABLE_SIZE);
+}
+
+#[test]
+fn test_populate_past_backends_array_capacity() {
+    let backends = backends(300);
+    let table = populate(&backends);
+
+    let mut shares = vec![0usize; backends.len()];
+    for entry in table {
+        shares[entry as usize] += 1;
+    }
+    assert!(
+        shares.iter().all(|share| *share > 0),
+        "backends without a share: {:?}",
+        shares
+    );
+}
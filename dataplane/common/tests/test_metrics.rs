@@ -0,0 +1,83 @@
+#![cfg(feature = "metrics")]
+
+use std::time::Duration;
+
+use common::metrics::{
+    read_request_line, serve, Histogram, MAX_REQUEST_LINE_LEN, PROMETHEUS_CONTENT_TYPE,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+#[test]
+fn test_histogram_render_without_labels() {
+    let mut histogram = Histogram::new(&[1.0, 5.0]);
+    histogram.observe(0.5, None);
+    histogram.observe(3.0, None);
+    histogram.observe(10.0, None);
+
+    let mut out = String::new();
+    histogram.render("test_seconds", "", &mut out);
+    assert_eq!(
+        out,
+        "test_seconds_bucket{le=\"1.0\"} 1\n\
+         test_seconds_bucket{le=\"5.0\"} 2\n\
+         test_seconds_bucket{le=\"+Inf\"} 3\n\
+         test_seconds_sum 13.5\n\
+         test_seconds_count 3\n"
+    );
+}
+
+#[tokio::test]
+async fn test_read_request_line() {
+    let mut request: &[u8] = b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    assert_eq!(
+        read_request_line(&mut request).await.unwrap().as_deref(),
+        Some("GET /metrics HTTP/1.1")
+    );
+
+    // the stream ended before the request line did
+    let mut request: &[u8] = b"GET /metrics";
+    assert_eq!(read_request_line(&mut request).await.unwrap(), None);
+
+    let long = format!("GET /{} HTTP/1.1\r\n", "a".repeat(MAX_REQUEST_LINE_LEN));
+    assert_eq!(read_request_line(&mut long.as_bytes()).await.unwrap(), None);
+}
+
+async fn get(addr: std::net::SocketAddr, segments: &[&str]) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.set_nodelay(true).unwrap();
+    for segment in segments {
+        stream.write_all(segment.as_bytes()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[tokio::test]
+async fn test_serve_request_in_segments() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(serve(listener, PROMETHEUS_CONTENT_TYPE, || {
+        "metric 1\n".to_string()
+    }));
+
+    let response = get(
+        addr,
+        &["GET /met", "rics HTTP/1.1\r\n", "Host: localhost\r\n\r\n"],
+    )
+    .await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.contains("Content-Type: text/plain; version=0.0.4\r\n"));
+    assert!(response.ends_with("\r\n\r\nmetric 1\n"));
+
+    let response = get(addr, &["GET /other HTTP/1.1\r\n\r\n"]).await;
+    assert!(
+        response.starts_with("HTTP/1.1 404 Not Found\r\n"),
+        "{}",
+        response
+    );
+}
@@ -0,0 +1,44 @@
+use common::mss::{mss_offset, TCP_MAX_OPTIONS_LEN};
+
+fn options(bytes: &[u8]) -> [u8; TCP_MAX_OPTIONS_LEN] {
+    let mut options = [0; TCP_MAX_OPTIONS_LEN];
+    options[..bytes.len()].copy_from_slice(bytes);
+    options
+}
+
+#[test]
+fn test_mss_first() {
+    // MSS 1460, SACK permitted, timestamps, NOP, window scale: the options of a Linux SYN
+    let syn = options(&[
+        2, 4, 0x05, 0xb4, 4, 2, 8, 10, 0, 0, 0, 1, 0, 0, 0, 0, 1, 3, 3, 7,
+    ]);
+    assert_eq!(mss_offset(&syn, 20), Some(2));
+}
+
+#[test]
+fn test_mss_after_other_options() {
+    let syn = options(&[1, 1, 4, 2, 2, 4, 0x05, 0xb4]);
+    assert_eq!(mss_offset(&syn, 8), Some(6));
+}
+
+#[test]
+fn test_no_mss() {
+    let syn = options(&[4, 2, 1, 3, 3, 7, 0, 0]);
+    assert_eq!(mss_offset(&syn, 8), None);
+    // the end of the option list
+    let syn = options(&[0, 0, 0, 0, 2, 4, 0x05, 0xb4]);
+    assert_eq!(mss_offset(&syn, 8), None);
+}
+
+#[test]
+fn test_malformed_options() {
+    // an MSS option past the options of the header
+    let syn = options(&[1, 1, 2, 4, 0x05, 0xb4]);
+    assert_eq!(mss_offset(&syn, 4), None);
+    // an MSS option of the wrong length
+    let syn = options(&[2, 3, 0x05, 0]);
+    assert_eq!(mss_offset(&syn, 4), None);
+    // an option shorter than its kind and length
+    let syn = options(&[4, 0, 2, 4, 0x05, 0xb4]);
+    assert_eq!(mss_offset(&syn, 6), None);
+}
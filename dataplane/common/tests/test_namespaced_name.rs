@@ -0,0 +1,30 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+#![cfg(feature = "std")]
+
+use common::{NamespacedName, NamespacedNameError};
+
+#[test]
+fn test_namespaced_name_round_trip() {
+    let key = NamespacedName::new("blixt-system", "my-gateway");
+    assert_eq!(key.to_string(), "blixt-system/my-gateway");
+
+    let parsed: NamespacedName = key.to_string().parse().unwrap();
+    assert_eq!(parsed, key);
+}
+
+#[test]
+fn test_namespaced_name_malformed() {
+    for value in ["", "my-gateway", "/my-gateway", "blixt-system/", "a/b/c"] {
+        assert_eq!(
+            value.parse::<NamespacedName>(),
+            Err(NamespacedNameError::Malformed(value.to_string())),
+            "{:?} should not parse",
+            value
+        );
+    }
+}
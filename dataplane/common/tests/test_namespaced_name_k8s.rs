@@ -0,0 +1,44 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+#![cfg(feature = "k8s")]
+
+use common::{NamespacedName, NamespacedNameError};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+fn meta(namespace: Option<&str>, name: Option<&str>) -> ObjectMeta {
+    ObjectMeta {
+        namespace: namespace.map(str::to_string),
+        name: name.map(str::to_string),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_namespaced_name_from_object_meta() {
+    let meta = meta(Some("blixt-system"), Some("my-gateway"));
+    assert_eq!(
+        NamespacedName::try_from(&meta),
+        Ok(NamespacedName::new("blixt-system", "my-gateway"))
+    );
+}
+
+#[test]
+fn test_namespaced_name_from_incomplete_object_meta() {
+    assert_eq!(
+        NamespacedName::try_from(&meta(Some("blixt-system"), None)),
+        Err(NamespacedNameError::MissingName)
+    );
+    assert_eq!(
+        NamespacedName::try_from(&meta(None, Some("my-gateway"))),
+        Err(NamespacedNameError::MissingNamespace)
+    );
+    // the name is reported missing first
+    assert_eq!(
+        NamespacedName::try_from(&meta(None, None)),
+        Err(NamespacedNameError::MissingName)
+    );
+}
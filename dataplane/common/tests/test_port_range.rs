@@ -0,0 +1,46 @@
+use common::port_range::prefixes;
+use common::MAX_PORT_RANGE_PREFIXES;
+
+// Returns the ports covered by the prefixes, in order.
+fn covered(start: u16, end: u16) -> Vec<u32> {
+    prefixes(start, end)
+        .flat_map(|(port, bits)| port as u32..port as u32 + (1 << (16 - bits)))
+        .collect()
+}
+
+#[test]
+fn test_prefixes_single_port() {
+    assert_eq!(prefixes(8080, 8080).collect::<Vec<_>>(), vec![(8080, 16)]);
+}
+
+#[test]
+fn test_prefixes_all_ports() {
+    assert_eq!(prefixes(0, u16::MAX).collect::<Vec<_>>(), vec![(0, 0)]);
+}
+
+#[test]
+fn test_prefixes_node_port_range() {
+    assert_eq!(
+        prefixes(30000, 32767).collect::<Vec<_>>(),
+        vec![(30000, 12), (30016, 10), (30080, 9), (30208, 7), (30720, 5)]
+    );
+    assert_eq!(covered(30000, 32767), (30000..=32767).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_prefixes_cover_exactly_the_range() {
+    for (start, end) in [(1, 65534), (1, 65535), (0, 1), (65535, 65535), (1000, 1999)] {
+        assert_eq!(
+            covered(start, end),
+            (start as u32..=end as u32).collect::<Vec<_>>(),
+            "{}-{}",
+            start,
+            end
+        );
+    }
+}
+
+#[test]
+fn test_prefixes_bounded() {
+    assert_eq!(prefixes(1, 65534).count(), MAX_PORT_RANGE_PREFIXES as usize);
+}
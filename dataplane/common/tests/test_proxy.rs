@@ -0,0 +1,89 @@
+use common::mss::TCP_MAX_OPTIONS_LEN;
+use common::proxy::{
+    backend_seq, clear_sack_permitted, client_ack, proxy_v2_header, PROXY_V2_HEADER_LEN,
+};
+
+const LEN: u32 = PROXY_V2_HEADER_LEN as u32;
+
+#[test]
+fn test_proxy_v2_header() {
+    let header = proxy_v2_header(0xc0a80001, 51234, 0x0a000001, 8080);
+    assert_eq!(
+        header,
+        [
+            // signature
+            0x0d, 0x0a, 0x0d, 0x0a, 0x00, 0x0d, 0x0a, 0x51, 0x55, 0x49, 0x54, 0x0a,
+            // version 2 PROXY, TCP over IPv4, 12 bytes of addresses
+            0x21, 0x11, 0x00, 0x0c, // 192.168.0.1 to 10.0.0.1
+            0xc0, 0xa8, 0x00, 0x01, 0x0a, 0x00, 0x00, 0x01, // 51234 to 8080
+            0xc8, 0x22, 0x1f, 0x90,
+        ]
+    );
+}
+
+#[test]
+fn test_backend_seq() {
+    let first_seq = 1000;
+    // the handshake and the segment the header is inserted in
+    assert_eq!(backend_seq(999, first_seq), 999);
+    assert_eq!(backend_seq(1000, first_seq), 1000);
+    // the bytes after the first one
+    assert_eq!(backend_seq(1100, first_seq), 1100 + LEN);
+    // across the wrap around of sequence numbers
+    assert_eq!(backend_seq(10, u32::MAX - 10), 10 + LEN);
+}
+
+#[test]
+fn test_client_ack() {
+    let first_seq = 1000;
+    // the SYN-ACK
+    assert_eq!(client_ack(1000, first_seq), 1000);
+    // part of the header
+    assert_eq!(client_ack(1000 + LEN - 1, first_seq), 1000);
+    // the header and the bytes of the client
+    assert_eq!(client_ack(1000 + LEN, first_seq), 1000);
+    assert_eq!(client_ack(1100 + LEN, first_seq), 1100);
+    // across the wrap around of sequence numbers
+    assert_eq!(client_ack(10 + LEN, u32::MAX - 10), 10);
+}
+
+fn options(bytes: &[u8]) -> [u8; TCP_MAX_OPTIONS_LEN] {
+    let mut options = [0; TCP_MAX_OPTIONS_LEN];
+    options[..bytes.len()].copy_from_slice(bytes);
+    options
+}
+
+#[test]
+fn test_clear_sack_permitted() {
+    // MSS 1460, SACK permitted, timestamps, NOP, window scale: the options of a Linux SYN
+    let mut syn = options(&[
+        2, 4, 0x05, 0xb4, 4, 2, 8, 10, 0, 0, 0, 1, 0, 0, 0, 0, 1, 3, 3, 7,
+    ]);
+    assert_eq!(clear_sack_permitted(&mut syn, 20), Some(4));
+    assert_eq!(
+        syn,
+        options(&[2, 4, 0x05, 0xb4, 1, 1, 8, 10, 0, 0, 0, 1, 0, 0, 0, 0, 1, 3, 3, 7])
+    );
+    // once cleared, there's no SACK-permitted option left
+    assert_eq!(clear_sack_permitted(&mut syn, 20), None);
+}
+
+#[test]
+fn test_clear_sack_permitted_unaligned() {
+    let mut syn = options(&[1, 4, 2, 1, 2, 4, 0x05, 0xb4]);
+    assert_eq!(clear_sack_permitted(&mut syn, 8), Some(1));
+    assert_eq!(syn, options(&[1, 1, 1, 1, 2, 4, 0x05, 0xb4]));
+}
+
+#[test]
+fn test_clear_sack_permitted_missing() {
+    let mut syn = options(&[2, 4, 0x05, 0xb4]);
+    assert_eq!(clear_sack_permitted(&mut syn, 4), None);
+    assert_eq!(syn, options(&[2, 4, 0x05, 0xb4]));
+    // past the options of the header
+    let mut syn = options(&[2, 4, 0x05, 0xb4, 4, 2]);
+    assert_eq!(clear_sack_permitted(&mut syn, 4), None);
+    // of the wrong length
+    let mut syn = options(&[4, 3, 0, 1]);
+    assert_eq!(clear_sack_permitted(&mut syn, 4), None);
+}
@@ -0,0 +1,63 @@
+use common::rate_limit::{take_token, TOKEN};
+use common::{RateLimit, TokenBucket};
+
+const SECOND: u64 = 1_000_000_000;
+
+const LIMIT: RateLimit = RateLimit {
+    packets_per_second: 10,
+    burst: 3,
+};
+
+#[test]
+fn test_take_token_allows_burst() {
+    let mut bucket = None;
+    for _ in 0..LIMIT.burst {
+        let (next, allowed) = take_token(bucket, &LIMIT, SECOND);
+        assert!(allowed);
+        bucket = Some(next);
+    }
+    let (next, allowed) = take_token(bucket, &LIMIT, SECOND);
+    assert!(!allowed);
+    assert_eq!(
+        next,
+        TokenBucket {
+            tokens: 0,
+            updated_at: SECOND
+        }
+    );
+}
+
+#[test]
+fn test_take_token_refills_at_rate() {
+    let empty = TokenBucket {
+        tokens: 0,
+        updated_at: SECOND,
+    };
+    // a tenth of a second refills a token at 10 packets per second
+    assert!(!take_token(Some(empty), &LIMIT, SECOND + SECOND / 10 - 1).1);
+    let (bucket, allowed) = take_token(Some(empty), &LIMIT, SECOND + SECOND / 10);
+    assert!(allowed);
+    assert_eq!(bucket.tokens, 0);
+}
+
+#[test]
+fn test_take_token_caps_at_burst() {
+    let empty = TokenBucket {
+        tokens: 0,
+        updated_at: 0,
+    };
+    let (bucket, allowed) = take_token(Some(empty), &LIMIT, u64::MAX);
+    assert!(allowed);
+    assert_eq!(bucket.tokens, (LIMIT.burst as u64 - 1) * TOKEN);
+}
+
+#[test]
+fn test_take_token_clock_going_back() {
+    let bucket = TokenBucket {
+        tokens: TOKEN / 2,
+        updated_at: SECOND,
+    };
+    let (bucket, allowed) = take_token(Some(bucket), &LIMIT, 0);
+    assert!(!allowed);
+    assert_eq!(bucket.tokens, TOKEN / 2);
+}
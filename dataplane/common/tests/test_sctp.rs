@@ -0,0 +1,65 @@
+use common::sctp::{crc32c, ends_association, update_checksum, CHUNK_ABORT, CHUNK_INIT};
+
+// Returns an SCTP packet of len bytes from the ports, with a valid checksum.
+fn packet(source: u16, dest: u16, len: usize) -> Vec<u8> {
+    let mut packet: Vec<u8> = (0..len).map(|i| (i * 7 + 3) as u8).collect();
+    packet[0..2].copy_from_slice(&source.to_be_bytes());
+    packet[2..4].copy_from_slice(&dest.to_be_bytes());
+    packet[8..12].copy_from_slice(&[0; 4]);
+    let checksum = crc32c(&packet);
+    packet[8..12].copy_from_slice(&checksum.to_le_bytes());
+    packet
+}
+
+fn checksum(packet: &[u8]) -> u32 {
+    u32::from_le_bytes(packet[8..12].try_into().unwrap())
+}
+
+fn ports(packet: &[u8]) -> [u8; 4] {
+    packet[0..4].try_into().unwrap()
+}
+
+#[test]
+fn test_crc32c() {
+    // the check value of CRC-32C
+    assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+    assert_eq!(crc32c(&[]), 0);
+}
+
+#[test]
+fn test_update_checksum() {
+    for len in [12, 16, 28, 100, 1452, 9000, 65515] {
+        let old = packet(40000, 8080, len);
+        let new = packet(40000, 38412, len);
+        assert_eq!(
+            update_checksum(checksum(&old), ports(&old), ports(&new), len as u16),
+            checksum(&new),
+            "packet of {} bytes",
+            len
+        );
+
+        // both ports, as rewritten by the replies of backends
+        let new = packet(9, 65535, len);
+        assert_eq!(
+            update_checksum(checksum(&old), ports(&old), ports(&new), len as u16),
+            checksum(&new),
+            "packet of {} bytes",
+            len
+        );
+    }
+}
+
+#[test]
+fn test_update_checksum_unchanged_ports() {
+    let packet = packet(40000, 8080, 64);
+    assert_eq!(
+        update_checksum(checksum(&packet), ports(&packet), ports(&packet), 64),
+        checksum(&packet)
+    );
+}
+
+#[test]
+fn test_ends_association() {
+    assert!(ends_association(CHUNK_ABORT));
+    assert!(!ends_association(CHUNK_INIT));
+}
@@ -0,0 +1,65 @@
+use common::slots::{next_index, overflow_slots, position, Position};
+use common::{BACKENDS_ARRAY_CAPACITY, MAX_BACKENDS_PER_VIP};
+
+#[test]
+fn test_position_in_backend_list() {
+    assert_eq!(position(0, 1), Some(Position { slot: 0, offset: 0 }));
+    assert_eq!(
+        position(
+            BACKENDS_ARRAY_CAPACITY as u16 - 1,
+            BACKENDS_ARRAY_CAPACITY as u16
+        ),
+        Some(Position {
+            slot: 0,
+            offset: BACKENDS_ARRAY_CAPACITY - 1
+        })
+    );
+}
+
+#[test]
+fn test_position_in_slots() {
+    let len = MAX_BACKENDS_PER_VIP as u16;
+    assert_eq!(
+        position(BACKENDS_ARRAY_CAPACITY as u16, len),
+        Some(Position { slot: 1, offset: 0 })
+    );
+    assert_eq!(
+        position(len - 1, len),
+        Some(Position {
+            slot: overflow_slots(len),
+            offset: (MAX_BACKENDS_PER_VIP - 1) % BACKENDS_ARRAY_CAPACITY
+        })
+    );
+}
+
+#[test]
+fn test_position_bounded() {
+    for len in [0, 1, BACKENDS_ARRAY_CAPACITY as u16, u16::MAX] {
+        for index in 0..=u16::MAX {
+            match position(index, len) {
+                Some(position) => {
+                    assert!(index < len && (index as usize) < MAX_BACKENDS_PER_VIP);
+                    assert!(position.offset < BACKENDS_ARRAY_CAPACITY);
+                    assert!(position.slot <= overflow_slots(len));
+                }
+                None => assert!(index >= len || index as usize >= MAX_BACKENDS_PER_VIP),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_overflow_slots() {
+    assert_eq!(overflow_slots(0), 0);
+    assert_eq!(overflow_slots(BACKENDS_ARRAY_CAPACITY as u16), 0);
+    assert_eq!(overflow_slots(BACKENDS_ARRAY_CAPACITY as u16 + 1), 1);
+}
+
+#[test]
+fn test_next_index() {
+    assert_eq!(next_index(0, 3), 1);
+    assert_eq!(next_index(2, 3), 0);
+    assert_eq!(next_index(0, 0), 0);
+    assert_eq!(next_index(5, 3), 0);
+    assert_eq!(next_index(u16::MAX, u16::MAX), 0);
+}
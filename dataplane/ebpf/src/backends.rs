@@ -0,0 +1,199 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! Selection of the backend of new connections, shared by the TCP and UDP ingress programs.
+//!
+//! Every array access below is at a position returned by common::slots, which is in bounds for
+//! any BACKENDS_ARRAY_CAPACITY, and still goes through get() because the verifier can't tell.
+//! Nothing here indexes arrays directly, so that changing the capacity can't produce accesses
+//! the verifier rejects.
+
+use aya_ebpf::{helpers::bpf_ktime_get_ns, maps::lpm_trie::Key};
+
+use crate::{
+    AFFINITIES, BACKENDS, BACKEND_SLOTS, GATEWAY_INDEXES, RATE_LIMITS, VIP_ACLS, VIP_PORT_RANGES,
+};
+use common::{
+    acl::client_key,
+    drain::{is_draining, next_undrained},
+    maglev::table_index,
+    rate_limit::take_token,
+    slots, Affinity, AffinityKey, Backend, BackendKey, BackendList, BackendSlotKey,
+    LoadBalancerMapping, PortRangeKey, RateLimitKey, ACL_ACTION_DENY,
+};
+
+// Returns the vip a packet to the address and port (in host byte order) is for, along with its
+// backends: the vip of that port when there is one, or else the vip of the range of ports it's in.
+#[inline(always)]
+pub fn vip_backends(ip: u32, port: u16, proto: u32) -> Option<(BackendKey, &'static BackendList)> {
+    let backend_key = BackendKey {
+        ip,
+        port: port as u32,
+        port_end: 0,
+        proto,
+    };
+    if let Some(backend_list) = unsafe { BACKENDS.get(&backend_key) } {
+        return Some((backend_key, backend_list));
+    }
+    let range_key = Key::new(
+        PortRangeKey::FIXED_PREFIX_LEN + 16,
+        PortRangeKey::new(ip, proto, port),
+    );
+    let backend_key = *unsafe { VIP_PORT_RANGES.get(&range_key) }?;
+    let backend_list = unsafe { BACKENDS.get(&backend_key) }?;
+    Some((backend_key, backend_list))
+}
+
+// Returns whether the ACL of the vip denies the client, whose address is in host byte order.
+// Vips without an ACL allow every client.
+#[inline(always)]
+pub fn acl_denies(backend_key: &BackendKey, client_ip: u32) -> bool {
+    let (prefix_len, data) = client_key(*backend_key, client_ip);
+    let key = Key::new(prefix_len, data);
+    matches!(unsafe { VIP_ACLS.get(&key) }, Some(&ACL_ACTION_DENY))
+}
+
+// Takes the token of a packet of the client from its bucket, and returns whether the packet is
+// over the rate limit of the vip. Buckets are updated without synchronization, so packets of a
+// client handled on several CPUs at once may take the same token.
+#[inline(always)]
+pub fn rate_limited(backend_key: &BackendKey, backend_list: &BackendList, client_ip: u32) -> bool {
+    let Some(limit) = backend_list.rate_limit() else {
+        return false;
+    };
+    let key = RateLimitKey {
+        client_ip,
+        backend_key: *backend_key,
+    };
+    let bucket = unsafe { RATE_LIMITS.get(&key) }.copied();
+    let (bucket, allowed) = take_token(bucket, &limit, unsafe { bpf_ktime_get_ns() });
+    // a client whose bucket can't be written is limited by its last written one
+    let _ = unsafe { RATE_LIMITS.insert(&key, &bucket, 0) };
+    !allowed
+}
+
+// Returns the backend with the port the client connected to when it has none, as backends of
+// vips of a range of ports keep the port of each connection.
+#[inline(always)]
+pub fn backend_for_port(mut backend: Backend, vip_port: u32) -> Backend {
+    if backend.dport == 0 {
+        backend.dport = vip_port;
+    }
+    backend
+}
+
+// Returns the backend of the vip at the index, from its BACKEND_SLOTS entry when the index is
+// past the backends array.
+#[inline(always)]
+pub fn backend_at(
+    backend_key: &BackendKey,
+    backend_list: &BackendList,
+    index: u16,
+) -> Option<Backend> {
+    let position = slots::position(index, backend_list.backends_len)?;
+    if position.slot == 0 {
+        return backend_list.backends.get(position.offset).copied();
+    }
+    let slot_key = BackendSlotKey {
+        backend_key: *backend_key,
+        slot: position.slot,
+    };
+    let slot = unsafe { BACKEND_SLOTS.get(&slot_key) }?;
+    slot.backends.get(position.offset).copied()
+}
+
+// Returns the first backend from the round-robin index of the vip on that isn't draining, along
+// with its index, or the first backend of the vip when that one can't be read, e.g. while its
+// BACKEND_SLOTS entry is being written. None when the index is past the backends, so that a
+// "zero-value" Backend is never used, or when all of them are draining.
+#[inline(always)]
+pub fn round_robin_backend(
+    backend_key: &BackendKey,
+    backend_list: &BackendList,
+    index: u16,
+) -> Option<(u16, Backend)> {
+    let index = next_undrained(&backend_list.draining, index, backend_list.backends_len)?;
+    let backend = backend_at(backend_key, backend_list, index)
+        .or_else(|| backend_at(backend_key, backend_list, 0))?;
+    Some((index, backend))
+}
+
+// Moves the round-robin index of the vip past the backend at index.
+#[inline(always)]
+pub fn advance_round_robin(
+    backend_key: &BackendKey,
+    backend_list: &BackendList,
+    index: u16,
+) -> Result<(), i64> {
+    let next = slots::next_index(index, backend_list.backends_len);
+    unsafe { GATEWAY_INDEXES.insert(backend_key, &next, 0_u64) }
+}
+
+// Returns the backend of a flow by its hash, along with its index, when the backends use
+// consistent hashing, see common::maglev.
+#[inline(always)]
+pub fn maglev_backend(
+    backend_key: &BackendKey,
+    backend_list: &BackendList,
+    hash: u64,
+) -> Option<(u16, Backend)> {
+    let table = backend_list.maglev()?;
+    let index = *table.get(table_index(hash))?;
+    Some((index, backend_at(backend_key, backend_list, index)?))
+}
+
+// Returns the backend the client is pinned to when the vip uses session affinity, along with its
+// index, unless the pin expired or the backend was removed from the vip or is draining since.
+#[inline(always)]
+pub fn affinity_backend(backend_list: &BackendList, key: &AffinityKey) -> Option<(u16, Backend)> {
+    backend_list.affinity_timeout()?;
+    let affinity = unsafe { AFFINITIES.get(key) }?;
+    if affinity.expires_at <= unsafe { bpf_ktime_get_ns() } {
+        return None;
+    }
+    let backend = backend_at(&key.backend_key, backend_list, affinity.index)?;
+    (backend == affinity.backend && !is_draining(&backend_list.draining, affinity.index))
+        .then_some((affinity.index, backend))
+}
+
+// Returns the backend of the tracked connection, along with its index, when the vip still has it
+// at that index and it's draining: draining backends aren't assigned new connections, but keep
+// serving those they have.
+#[inline(always)]
+pub fn draining_backend(
+    backend_key: &BackendKey,
+    backend_list: &BackendList,
+    mapping: &LoadBalancerMapping,
+    vip_port: u32,
+) -> Option<(u16, Backend)> {
+    let index = mapping.backend_index;
+    if mapping.backend_key != *backend_key || !is_draining(&backend_list.draining, index) {
+        return None;
+    }
+    let backend = backend_for_port(backend_at(backend_key, backend_list, index)?, vip_port);
+    (backend == mapping.backend).then_some((index, backend))
+}
+
+// Pins the client to the backend at index for the affinity timeout of the vip, if it uses
+// session affinity. The pin isn't extended by later connections, so clients are eventually
+// load balanced again.
+#[inline(always)]
+pub fn pin_affinity(
+    backend_list: &BackendList,
+    key: &AffinityKey,
+    index: u16,
+    backend: Backend,
+) -> Result<(), i64> {
+    let Some(timeout) = backend_list.affinity_timeout() else {
+        return Ok(());
+    };
+    let affinity = Affinity {
+        backend,
+        index,
+        expires_at: unsafe { bpf_ktime_get_ns() } + timeout as u64 * 1_000_000_000,
+    };
+    unsafe { AFFINITIES.insert(key, &affinity, 0_u64) }
+}
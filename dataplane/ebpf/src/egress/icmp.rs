@@ -6,39 +6,67 @@ SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
 
 use core::mem;
 
-use aya_ebpf::{bindings::TC_ACT_PIPE, helpers::bpf_csum_diff, programs::TcContext};
-use aya_log_ebpf::info;
+use aya_ebpf::{
+    bindings::TC_ACT_PIPE,
+    helpers::{bpf_csum_diff, bpf_l4_csum_replace},
+    programs::TcContext,
+};
 use common::ClientKey;
-use network_types::{eth::EthHdr, icmp::IcmpHdr, ip::Ipv4Hdr};
+use memoffset::offset_of;
+use network_types::{
+    icmp::IcmpHdr,
+    ip::{IpProto, Ipv4Hdr},
+};
 
 use crate::{
-    utils::{csum_fold_helper, ptr_at},
+    utils::{csum_fold_helper, ptr_at, record_flow, tracked_connection, L4Ports},
     LB_CONNECTIONS,
 };
 
 const ICMP_PROTO_TYPE_UNREACH: u8 = 3;
 
-pub fn handle_icmp_egress(ctx: TcContext) -> Result<i32, i64> {
-    let ip_hdr: *mut Ipv4Hdr = unsafe { ptr_at(&ctx, EthHdr::LEN)? };
+// The ip header is at l3_offset, past the ethernet header and any VLAN tags.
+pub fn handle_icmp_egress(ctx: TcContext, l3_offset: usize) -> Result<i32, i64> {
+    let ip_hdr: *mut Ipv4Hdr = unsafe { ptr_at(&ctx, l3_offset)? };
 
-    let icmp_header_offset = EthHdr::LEN + Ipv4Hdr::LEN;
+    let icmp_header_offset = l3_offset + Ipv4Hdr::LEN;
+    let icmp_csum_offset = (icmp_header_offset + offset_of!(IcmpHdr, checksum)) as u32;
 
     let icmp_hdr: *mut IcmpHdr = unsafe { ptr_at(&ctx, icmp_header_offset)? };
 
-    // We only care about redirecting port unreachable messages currently so a
-    // UDP client can tell when the server is shutdown
+    // We only care about redirecting destination unreachable messages currently, so that a
+    // UDP client can tell when the server is shut down, a TCP client that its connection was
+    // refused, and both can discover the path MTU to the backend (fragmentation needed).
     if unsafe { (*icmp_hdr).type_ } != ICMP_PROTO_TYPE_UNREACH {
         return Ok(TC_ACT_PIPE);
     }
 
+    // The error carries the header of the packet the client sent the backend, whose source
+    // port identifies the tracked connection. Only the ports are sure to be quoted of TCP and
+    // SCTP.
+    let icmp_inner_ip_hdr: *mut Ipv4Hdr =
+        unsafe { ptr_at(&ctx, icmp_header_offset + IcmpHdr::LEN) }?;
+    let proto = unsafe { (*icmp_inner_ip_hdr).proto };
+    if proto != IpProto::Udp && proto != IpProto::Tcp && proto != IpProto::Sctp {
+        return Ok(TC_ACT_PIPE);
+    }
+    let icmp_inner_ports: *mut L4Ports =
+        unsafe { ptr_at(&ctx, icmp_header_offset + IcmpHdr::LEN + Ipv4Hdr::LEN) }?;
+
     let dest_addr = unsafe { (*ip_hdr).dst_addr };
     let client_key = &ClientKey {
-        ip: dest_addr.to_be(),
-        port: 0,
+        ip: u32::from_be(dest_addr),
+        port: (u16::from_be(unsafe { (*icmp_inner_ports).source })) as u32,
     };
-    let lb_mapping = unsafe { LB_CONNECTIONS.get(client_key) }.ok_or(TC_ACT_PIPE)?;
+    let Some(lb_mapping) = tracked_connection(client_key) else {
+        return Ok(TC_ACT_PIPE);
+    };
+    // the client may have a connection of the other protocol from the same port
+    if lb_mapping.backend_key.proto != proto as u32 {
+        return Ok(TC_ACT_PIPE);
+    }
 
-    info!(
+    sampled_info!(
         &ctx,
         "Received a ICMP Unreachable packet destined for svc ip: {:i} ",
         u32::from_be(dest_addr)
@@ -61,10 +89,7 @@ pub fn handle_icmp_egress(ctx: TcContext) -> Result<i32, i64> {
     } as u64;
     unsafe { (*ip_hdr).check = csum_fold_helper(full_cksum) };
 
-    // Get inner ipheader since we need to update that as well
-    let icmp_inner_ip_hdr: *mut Ipv4Hdr =
-        unsafe { ptr_at(&ctx, icmp_header_offset + IcmpHdr::LEN) }?;
-
+    // The inner ip header needs to be updated as well
     unsafe {
         (*icmp_inner_ip_hdr).dst_addr = lb_mapping.backend_key.ip.to_be();
         (*icmp_inner_ip_hdr).check = 0;
@@ -81,7 +106,34 @@ pub fn handle_icmp_egress(ctx: TcContext) -> Result<i32, i64> {
     } as u64;
     unsafe { (*icmp_inner_ip_hdr).check = csum_fold_helper(full_cksum) };
 
-    unsafe { LB_CONNECTIONS.remove(client_key)? };
+    // and so does the port the client sent the packet to, which the ICMP checksum covers
+    let backend_port = unsafe { (*icmp_inner_ports).dest };
+    let vip_port = (lb_mapping.vip_port as u16).to_be();
+    unsafe { (*icmp_inner_ports).dest = vip_port };
+    let ret = unsafe {
+        bpf_l4_csum_replace(
+            ctx.skb.skb,
+            icmp_csum_offset,
+            backend_port as u64,
+            vip_port as u64,
+            mem::size_of::<u16>() as u64,
+        )
+    };
+    if ret != 0 {
+        return Ok(TC_ACT_PIPE);
+    }
+
+    let mut mapping = lb_mapping;
+    mapping.packets += 1;
+    mapping.bytes += ctx.len() as u64;
+    if proto == IpProto::Udp {
+        record_flow(client_key, &mapping);
+        unsafe { LB_CONNECTIONS.remove(client_key)? };
+    } else {
+        // the connection or association isn't over, e.g. it continues with smaller segments
+        // after the path MTU was discovered, and is closed by the client if it was refused
+        unsafe { LB_CONNECTIONS.insert(client_key, &mapping, 0)? };
+    }
 
     Ok(TC_ACT_PIPE)
 }
@@ -5,4 +5,6 @@ SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
 */
 
 pub mod icmp;
+pub mod sctp;
 pub mod tcp;
+pub mod udp;
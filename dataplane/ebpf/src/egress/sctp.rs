@@ -0,0 +1,100 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+use aya_ebpf::{
+    bindings::{TC_ACT_PIPE, TC_ACT_SHOT},
+    helpers::bpf_ktime_get_ns,
+    programs::TcContext,
+};
+use aya_log_ebpf::debug;
+use network_types::ip::Ipv4Hdr;
+
+use common::{
+    sctp::{ends_association, ChunkHdr, SctpHdr, CHUNK_INIT_ACK},
+    ClientKey, DROP_STATS_REWRITE_FAILED, IPPROTO_SCTP,
+};
+
+use crate::{
+    utils::{
+        count_drop, count_vip_packet, ptr_at, record_flow, record_reply_latency,
+        set_fragment_ip_src, set_sctp_ports, tracked_connection,
+    },
+    LB_CONNECTIONS,
+};
+
+// Sends the packets of the backends of SCTP associations back to their clients from the vip
+// they sent to, see ingress::sctp::handle_sctp_ingress.
+//
+// The ip header is at l3_offset, past the ethernet header and any VLAN tags.
+pub fn handle_sctp_egress(ctx: TcContext, l3_offset: usize) -> Result<i32, i64> {
+    let sctp_offset = l3_offset + Ipv4Hdr::LEN;
+    let ip_hdr: *const Ipv4Hdr = unsafe { ptr_at(&ctx, l3_offset)? };
+    let sctp_hdr: *const SctpHdr = unsafe { ptr_at(&ctx, sctp_offset)? };
+    let chunk_hdr: *const ChunkHdr = unsafe { ptr_at(&ctx, sctp_offset + SctpHdr::LEN)? };
+
+    let client_key = ClientKey {
+        ip: u32::from_be(unsafe { (*ip_hdr).dst_addr }),
+        port: u16::from_be(unsafe { (*sctp_hdr).dest }) as u32,
+    };
+    let Some(mut lb_mapping) = tracked_connection(&client_key) else {
+        return Ok(TC_ACT_PIPE);
+    };
+
+    // only the backend of the association answers it
+    let saddr = unsafe { (*ip_hdr).src_addr };
+    let sport = unsafe { (*sctp_hdr).source };
+    if lb_mapping.backend_key.proto != IPPROTO_SCTP
+        || lb_mapping.backend.daddr != u32::from_be(saddr)
+        || lb_mapping.backend.dport != u16::from_be(sport) as u32
+    {
+        return Ok(TC_ACT_PIPE);
+    }
+
+    sampled_info!(
+        &ctx,
+        "Received SCTP packet for tracked IP {:i}:{} setting source to VIP {:i}:{}",
+        client_key.ip,
+        client_key.port as u16,
+        lb_mapping.backend_key.ip,
+        lb_mapping.vip_port,
+    );
+
+    // the INIT ACK of the backend answers the INIT of the client
+    let chunk_type = unsafe { (*chunk_hdr).type_ };
+    if chunk_type == CHUNK_INIT_ACK {
+        record_reply_latency(&client_key, &mut lb_mapping);
+    }
+
+    let sctp_len = u16::from_be(unsafe { (*ip_hdr).tot_len }).saturating_sub(Ipv4Hdr::LEN as u16);
+    let dest = unsafe { (*sctp_hdr).dest };
+    if set_fragment_ip_src(&ctx, l3_offset, &saddr, lb_mapping.backend_key.ip.to_be()) != 0
+        || set_sctp_ports(
+            &ctx,
+            sctp_offset,
+            sctp_len,
+            (lb_mapping.vip_port as u16).to_be(),
+            dest,
+        )
+        .is_err()
+    {
+        count_drop(DROP_STATS_REWRITE_FAILED);
+        debug!(&ctx, "Failed to rewrite the source of an SCTP packet");
+        return Ok(TC_ACT_SHOT);
+    }
+
+    lb_mapping.packets += 1;
+    lb_mapping.bytes += ctx.len() as u64;
+    lb_mapping.last_seen = unsafe { bpf_ktime_get_ns() };
+    if ends_association(chunk_type) {
+        record_flow(&client_key, &lb_mapping);
+        let _ = unsafe { LB_CONNECTIONS.remove(&client_key) };
+    } else {
+        unsafe { LB_CONNECTIONS.insert(&client_key, &lb_mapping, 0)? };
+    }
+    count_vip_packet(&lb_mapping.backend_key, ctx.len() as u64);
+
+    Ok(TC_ACT_PIPE)
+}
@@ -8,23 +8,27 @@ use core::mem;
 
 use aya_ebpf::{
     bindings::{TC_ACT_OK, TC_ACT_PIPE},
-    helpers::bpf_csum_diff,
+    helpers::{bpf_csum_diff, bpf_ktime_get_ns},
     programs::TcContext,
 };
 use aya_log_ebpf::info;
-use common::ClientKey;
-use network_types::{eth::EthHdr, ip::Ipv4Hdr, tcp::TcpHdr};
+use common::{proxy::client_ack, ClientKey};
+use network_types::{ip::Ipv4Hdr, tcp::TcpHdr};
 
 use crate::{
-    utils::{csum_fold_helper, ptr_at, update_tcp_conns},
+    utils::{
+        count_vip_packet, csum_fold_helper, ptr_at, record_flow, record_reply_latency,
+        tracked_connection, update_tcp_conns,
+    },
     LB_CONNECTIONS,
 };
 
-pub fn handle_tcp_egress(ctx: TcContext) -> Result<i32, i64> {
+// The ip header is at l3_offset, past the ethernet header and any VLAN tags.
+pub fn handle_tcp_egress(ctx: TcContext, l3_offset: usize) -> Result<i32, i64> {
     // gather the TCP header
-    let ip_hdr: *mut Ipv4Hdr = unsafe { ptr_at(&ctx, EthHdr::LEN)? };
+    let ip_hdr: *mut Ipv4Hdr = unsafe { ptr_at(&ctx, l3_offset)? };
 
-    let tcp_header_offset = EthHdr::LEN + Ipv4Hdr::LEN;
+    let tcp_header_offset = l3_offset + Ipv4Hdr::LEN;
 
     let tcp_hdr: *mut TcpHdr = unsafe { ptr_at(&ctx, tcp_header_offset)? };
 
@@ -36,15 +40,17 @@ pub fn handle_tcp_egress(ctx: TcContext) -> Result<i32, i64> {
         ip: u32::from_be(client_addr),
         port: u16::from_be(dest_port) as u32,
     };
-    let lb_mapping = unsafe { LB_CONNECTIONS.get(&client_key) }.ok_or(TC_ACT_PIPE)?;
+    let Some(lb_mapping) = tracked_connection(&client_key) else {
+        return Ok(TC_ACT_PIPE);
+    };
 
-    info!(
+    sampled_info!(
         &ctx,
         "Received TCP packet destined for tracked IP {:i}:{} setting source IP to VIP {:i}:{}",
         u32::from_be(client_addr),
         u16::from_be(dest_port),
         lb_mapping.backend_key.ip,
-        lb_mapping.backend_key.port,
+        lb_mapping.vip_port,
     );
 
     // TODO: connection tracking cleanup https://github.com/kubernetes-sigs/blixt/issues/85
@@ -53,9 +59,18 @@ pub fn handle_tcp_egress(ctx: TcContext) -> Result<i32, i64> {
         (*ip_hdr).src_addr = lb_mapping.backend_key.ip.to_be();
     };
     // SNAT the port
-    unsafe { (*tcp_hdr).source = u16::from_be(lb_mapping.backend_key.port as u16) };
+    unsafe { (*tcp_hdr).source = u16::from_be(lb_mapping.vip_port as u16) };
+    // the client never sent the PROXY protocol header the backend acknowledges
+    if let Some(first_seq) = lb_mapping.proxy_seq {
+        unsafe {
+            if (*tcp_hdr).ack() == 1 {
+                (*tcp_hdr).ack_seq =
+                    client_ack(u32::from_be((*tcp_hdr).ack_seq), first_seq).to_be();
+            }
+        }
+    }
 
-    if (ctx.data() + EthHdr::LEN + Ipv4Hdr::LEN) > ctx.data_end() {
+    if (ctx.data() + l3_offset + Ipv4Hdr::LEN) > ctx.data_end() {
         info!(&ctx, "Iphdr is out of bounds");
         return Ok(TC_ACT_OK);
     }
@@ -75,16 +90,25 @@ pub fn handle_tcp_egress(ctx: TcContext) -> Result<i32, i64> {
 
     let tcp_hdr_ref = unsafe { tcp_hdr.as_ref().ok_or(TC_ACT_OK)? };
 
+    let mut mapping = lb_mapping;
+    mapping.packets += 1;
+    mapping.bytes += ctx.len() as u64;
+    mapping.last_seen = unsafe { bpf_ktime_get_ns() };
+
     // If the packet has the RST flag set, it means the connection is being terminated, so remove it
     // from our map.
     if tcp_hdr_ref.rst() == 1 {
+        record_flow(&client_key, &mapping);
         unsafe {
             LB_CONNECTIONS.remove(&client_key)?;
         }
     }
-
-    let mut mapping = *lb_mapping;
+    // the SYN-ACK of the backend answers the SYN of the client
+    if tcp_hdr_ref.syn() == 1 && tcp_hdr_ref.ack() == 1 {
+        record_reply_latency(&client_key, &mut mapping);
+    }
     update_tcp_conns(tcp_hdr_ref, &client_key, &mut mapping)?;
+    count_vip_packet(&mapping.backend_key, ctx.len() as u64);
 
     Ok(TC_ACT_PIPE)
 }
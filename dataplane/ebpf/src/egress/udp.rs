@@ -0,0 +1,98 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+use aya_ebpf::{
+    bindings::{TC_ACT_PIPE, TC_ACT_SHOT},
+    helpers::bpf_ktime_get_ns,
+    programs::TcContext,
+};
+use aya_log_ebpf::debug;
+use memoffset::offset_of;
+use network_types::{ip::Ipv4Hdr, udp::UdpHdr};
+
+use common::{ClientKey, DROP_STATS_REWRITE_FAILED};
+
+use crate::{
+    utils::{
+        count_drop, count_vip_packet, ptr_at, record_reply_latency, set_ipv4_ip_src, set_ipv4_port,
+        tracked_connection,
+    },
+    LB_CONNECTIONS,
+};
+
+// Sends the replies of the backends of UDP flows load balanced without full NAT back to their
+// clients from the vip they sent to, which clients would otherwise reject for coming from the
+// backend's own address. Replies of full NAT flows are handled on ingress, see
+// ingress::snat::handle_snat_reply.
+//
+// The ip header is at l3_offset, past the ethernet header and any VLAN tags.
+pub fn handle_udp_egress(ctx: TcContext, l3_offset: usize) -> Result<i32, i64> {
+    let udp_offset = l3_offset + Ipv4Hdr::LEN;
+    let ip_hdr: *const Ipv4Hdr = unsafe { ptr_at(&ctx, l3_offset)? };
+    let udp_hdr: *const UdpHdr = unsafe { ptr_at(&ctx, udp_offset)? };
+
+    let client_key = ClientKey {
+        ip: u32::from_be(unsafe { (*ip_hdr).dst_addr }),
+        port: u16::from_be(unsafe { (*udp_hdr).dest }) as u32,
+    };
+    let Some(mut lb_mapping) = tracked_connection(&client_key) else {
+        return Ok(TC_ACT_PIPE);
+    };
+
+    // only the backend of the flow answers it, rather than the client's other UDP traffic
+    let saddr = unsafe { (*ip_hdr).src_addr };
+    let sport = unsafe { (*udp_hdr).source };
+    if lb_mapping.tcp_state.is_some()
+        || lb_mapping.backend.daddr != u32::from_be(saddr)
+        || lb_mapping.backend.dport != u16::from_be(sport) as u32
+    {
+        return Ok(TC_ACT_PIPE);
+    }
+
+    sampled_info!(
+        &ctx,
+        "Received UDP reply for tracked IP {:i}:{} setting source to VIP {:i}:{}",
+        client_key.ip,
+        client_key.port as u16,
+        lb_mapping.backend_key.ip,
+        lb_mapping.vip_port,
+    );
+
+    record_reply_latency(&client_key, &mut lb_mapping);
+
+    let udp_csum_offset = (udp_offset + offset_of!(UdpHdr, check)) as u32;
+    if set_ipv4_ip_src(
+        &ctx,
+        l3_offset,
+        udp_csum_offset,
+        &saddr,
+        lb_mapping.backend_key.ip.to_be(),
+    ) != 0
+        || set_ipv4_port(
+            &ctx,
+            udp_csum_offset,
+            (udp_offset + offset_of!(UdpHdr, source)) as u32,
+            &sport,
+            (lb_mapping.vip_port as u16).to_be(),
+        ) != 0
+    {
+        count_drop(DROP_STATS_REWRITE_FAILED);
+        debug!(&ctx, "Failed to rewrite the source of a UDP reply");
+        return Ok(TC_ACT_SHOT);
+    }
+
+    // UDP has no connection state, so only the counters of the flow change
+    if let Some(mapping) = unsafe { LB_CONNECTIONS.get_ptr_mut(&client_key) } {
+        unsafe {
+            (*mapping).packets += 1;
+            (*mapping).bytes += ctx.len() as u64;
+            (*mapping).last_seen = bpf_ktime_get_ns();
+        }
+    }
+    count_vip_packet(&lb_mapping.backend_key, ctx.len() as u64);
+
+    Ok(TC_ACT_PIPE)
+}
@@ -0,0 +1,97 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+use aya_ebpf::{
+    bindings::{TC_ACT_PIPE, TC_ACT_SHOT},
+    programs::TcContext,
+};
+use aya_log_ebpf::debug;
+
+use network_types::ip::Ipv4Hdr;
+
+use crate::{
+    utils::{
+        capture_packet, count_drop, count_vip_packet, next_hop, ptr_at, redirect_to, set_dscp,
+        set_fragment_ip_dst, set_fragment_ip_src,
+    },
+    BACKENDS, FRAGMENTS,
+};
+use common::{
+    Fragment, FragmentKey, CAPTURE_STAGE_RECEIVED, CAPTURE_STAGE_REWRITTEN,
+    DROP_STATS_REWRITE_FAILED,
+};
+
+// Returns the key of the datagram the fragment belongs to, as the client sent it.
+#[inline(always)]
+pub fn fragment_key(ip_hdr: &Ipv4Hdr) -> FragmentKey {
+    FragmentKey {
+        src_addr: u32::from_be(ip_hdr.src_addr),
+        dst_addr: u32::from_be(ip_hdr.dst_addr),
+        id: u16::from_be(ip_hdr.id),
+        proto: ip_hdr.proto as u16,
+    }
+}
+
+// Records where the first fragment of a datagram was sent, so that its later fragments follow.
+#[inline(always)]
+pub fn track_fragments(key: &FragmentKey, fragment: &Fragment) -> Result<(), i64> {
+    unsafe { FRAGMENTS.insert(key, fragment, 0) }
+}
+
+// Sends a later fragment of a UDP datagram to the backend its first fragment was sent to. The
+// fragments of datagrams whose first fragment wasn't load balanced, or arrived after them, are
+// passed on unmodified.
+pub fn handle_udp_fragment(ctx: TcContext, l3_offset: usize) -> Result<i32, i64> {
+    let ip_hdr: *const Ipv4Hdr = unsafe { ptr_at(&ctx, l3_offset)? };
+    let key = fragment_key(unsafe { &*ip_hdr });
+    let Some(fragment) = (unsafe { FRAGMENTS.get(&key) }).copied() else {
+        debug!(&ctx, "Fragment of an untracked datagram, passing it on");
+        return Ok(TC_ACT_PIPE);
+    };
+    let daddr = unsafe { (*ip_hdr).dst_addr };
+    let saddr = unsafe { (*ip_hdr).src_addr };
+
+    capture_packet(
+        &ctx,
+        &fragment.backend_key,
+        CAPTURE_STAGE_RECEIVED,
+        unsafe { (*ctx.skb.skb).ifindex },
+    );
+
+    // the UDP checksum, which covers the whole datagram, was updated with the first fragment
+    let ret = set_fragment_ip_dst(&ctx, l3_offset, &daddr, fragment.backend.daddr.to_be());
+    if ret != 0 {
+        count_drop(DROP_STATS_REWRITE_FAILED);
+        return Ok(TC_ACT_SHOT);
+    }
+    if fragment.snat_ip != 0 {
+        let ret = set_fragment_ip_src(&ctx, l3_offset, &saddr, fragment.snat_ip.to_be());
+        if ret != 0 {
+            count_drop(DROP_STATS_REWRITE_FAILED);
+            return Ok(TC_ACT_SHOT);
+        }
+    }
+    let dscp = unsafe { BACKENDS.get(&fragment.backend_key) }.and_then(|list| list.dscp());
+    if set_dscp(&ctx, l3_offset, dscp).is_err() {
+        count_drop(DROP_STATS_REWRITE_FAILED);
+        return Ok(TC_ACT_SHOT);
+    }
+    count_vip_packet(&fragment.backend_key, ctx.len() as u64);
+
+    let Some(hop) = next_hop(&ctx, l3_offset, fragment.backend.ifindex) else {
+        debug!(&ctx, "No route to the backend");
+        return Ok(TC_ACT_PIPE);
+    };
+
+    capture_packet(
+        &ctx,
+        &fragment.backend_key,
+        CAPTURE_STAGE_REWRITTEN,
+        hop.ifindex,
+    );
+
+    Ok(redirect_to(&hop) as i32)
+}
@@ -4,5 +4,11 @@ Copyright 2023 The Kubernetes Authors.
 SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
 */
 
+pub mod fragment;
+pub mod proxy;
+pub mod quic;
+pub mod reject;
+pub mod sctp;
+pub mod snat;
 pub mod tcp;
 pub mod udp;
@@ -0,0 +1,273 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+use core::{mem, ptr};
+
+use aya_ebpf::{
+    bindings::{bpf_adj_room_mode::BPF_ADJ_ROOM_NET, BPF_F_PSEUDO_HDR},
+    helpers::{bpf_csum_diff, bpf_l3_csum_replace, bpf_l4_csum_replace, bpf_skb_store_bytes},
+    programs::TcContext,
+};
+use aya_ebpf_cty::{c_long, c_void};
+use memoffset::offset_of;
+use network_types::{ip::Ipv4Hdr, tcp::TcpHdr};
+
+use common::{
+    headers::TCP_MAX_HEADER_LEN,
+    mss::TCP_MAX_OPTIONS_LEN,
+    proxy::{
+        backend_seq, clear_sack_permitted, client_ack, proxy_v2_header, PROXY_V2_HEADER_LEN,
+        TCPOLEN_SACK_PERM,
+    },
+    BackendKey, ClientKey,
+};
+
+use crate::utils::ptr_at;
+
+// Prepares a segment the client sent on a connection with the PROXY protocol for its backend:
+// the segment that carries the first byte of the client gets the header inserted before its
+// data, every time the client sends it, and those after it are shifted past the header. The SYN
+// has its SACK-permitted option cleared, see clear_sack. Fails when the packet can't be
+// rewritten, which must then be dropped.
+//
+// The ip header is at l3_offset, past the ethernet header and any VLAN tags.
+pub fn proxy_segment(
+    ctx: &TcContext,
+    l3_offset: usize,
+    client_key: &ClientKey,
+    backend_key: &BackendKey,
+    vip_port: u32,
+    first_seq: u32,
+) -> Result<(), c_long> {
+    let tcp_offset = l3_offset + Ipv4Hdr::LEN;
+    let ip_hdr: *const Ipv4Hdr = unsafe { ptr_at(ctx, l3_offset)? };
+    let tcp_hdr: *const TcpHdr = unsafe { ptr_at(ctx, tcp_offset)? };
+    let (tot_len, seq, tcp_len, syn) = unsafe {
+        (
+            u16::from_be((*ip_hdr).tot_len),
+            u32::from_be((*tcp_hdr).seq),
+            (*tcp_hdr).doff() as usize * 4,
+            (*tcp_hdr).syn(),
+        )
+    };
+    if syn == 1 {
+        return clear_sack(ctx, tcp_offset, tcp_len);
+    }
+    let payload_len = (tot_len as usize).saturating_sub(Ipv4Hdr::LEN + tcp_len);
+
+    if seq == first_seq && payload_len > 0 {
+        let header = proxy_v2_header(
+            client_key.ip,
+            client_key.port as u16,
+            backend_key.ip,
+            vip_port as u16,
+        );
+        return insert_header(ctx, l3_offset, tot_len, tcp_len, &header);
+    }
+
+    let new_seq = backend_seq(seq, first_seq);
+    if new_seq == seq {
+        return Ok(());
+    }
+    replace_u32(
+        ctx,
+        (tcp_offset + offset_of!(TcpHdr, check)) as u32,
+        (tcp_offset + offset_of!(TcpHdr, seq)) as u32,
+        seq.to_be(),
+        new_seq.to_be(),
+    )
+}
+
+// Rewrites the acknowledgment of a segment the backend sent on a connection with the PROXY
+// protocol for its client, which never sent the header. The TCP header is at tcp_offset. There
+// are no SACK blocks to rewrite, since the SYN of the client didn't permit them.
+pub fn proxy_reply(ctx: &TcContext, tcp_offset: usize, first_seq: u32) -> Result<(), c_long> {
+    let tcp_hdr: *const TcpHdr = unsafe { ptr_at(ctx, tcp_offset)? };
+    let (ack, ack_seq) = unsafe { ((*tcp_hdr).ack(), u32::from_be((*tcp_hdr).ack_seq)) };
+    if ack == 0 {
+        return Ok(());
+    }
+
+    let new_ack_seq = client_ack(ack_seq, first_seq);
+    if new_ack_seq == ack_seq {
+        return Ok(());
+    }
+    replace_u32(
+        ctx,
+        (tcp_offset + offset_of!(TcpHdr, check)) as u32,
+        (tcp_offset + offset_of!(TcpHdr, ack_seq)) as u32,
+        ack_seq.to_be(),
+        new_ack_seq.to_be(),
+    )
+}
+
+// Clears the SACK-permitted option of the SYN of the client, so that the backend never sends SACK
+// blocks: their edges are sequence numbers of the client shifted by the header, like the
+// acknowledgments proxy_reply rewrites, but they're at any offset of the options of any reply.
+// The TCP header is at tcp_offset.
+#[inline(always)]
+fn clear_sack(ctx: &TcContext, tcp_offset: usize, tcp_len: usize) -> Result<(), c_long> {
+    let options_len = tcp_len.saturating_sub(TcpHdr::LEN).min(TCP_MAX_OPTIONS_LEN);
+    if options_len == 0 {
+        return Ok(());
+    }
+    let mut options = [0u8; TCP_MAX_OPTIONS_LEN];
+    ctx.load_bytes(tcp_offset + TcpHdr::LEN, &mut options[..options_len])?;
+    let mut new_options = options;
+    let Some(offset) = clear_sack_permitted(&mut new_options, options_len) else {
+        return Ok(());
+    };
+    let Some(nops) = new_options.get(offset..offset + TCPOLEN_SACK_PERM) else {
+        return Ok(());
+    };
+
+    // the options start at an offset of the segment that's a multiple of 4, so the difference
+    // of their sums adds to the checksum as is, wherever in them the option is
+    let diff = unsafe {
+        bpf_csum_diff(
+            options.as_ptr() as *mut u32,
+            TCP_MAX_OPTIONS_LEN as u32,
+            new_options.as_ptr() as *mut u32,
+            TCP_MAX_OPTIONS_LEN as u32,
+            0,
+        )
+    };
+    let ret = unsafe {
+        bpf_l4_csum_replace(
+            ctx.skb.skb,
+            (tcp_offset + offset_of!(TcpHdr, check)) as u32,
+            0,
+            diff as u64,
+            0,
+        )
+    };
+    if ret != 0 {
+        return Err(ret);
+    }
+    store_bytes(ctx, tcp_offset + TcpHdr::LEN + offset, nops)
+}
+
+// Inserts the header between the TCP header and the data of the segment: room is made after the
+// ip header, which the TCP header is moved before. VLAN tagged packets aren't supported by
+// bpf_skb_adjust_room, and the packets GRO aggregated would be segmented again with the header
+// taken for part of the TCP header of each segment, so neither can be rewritten.
+#[inline(always)]
+fn insert_header(
+    ctx: &TcContext,
+    l3_offset: usize,
+    tot_len: u16,
+    tcp_len: usize,
+    header: &[u8; PROXY_V2_HEADER_LEN],
+) -> Result<(), c_long> {
+    if unsafe { (*ctx.skb.skb).gso_segs } > 1 {
+        return Err(-1);
+    }
+    if !(TcpHdr::LEN..=TCP_MAX_HEADER_LEN).contains(&tcp_len) {
+        return Err(-1);
+    }
+
+    let tcp_offset = l3_offset + Ipv4Hdr::LEN;
+    let mut tcp_hdr = [0u8; TCP_MAX_HEADER_LEN];
+    ctx.load_bytes(tcp_offset, &mut tcp_hdr[..tcp_len])?;
+
+    ctx.adjust_room(PROXY_V2_HEADER_LEN as i32, BPF_ADJ_ROOM_NET, 0)?;
+    store_bytes(ctx, tcp_offset, &tcp_hdr[..tcp_len])?;
+    store_bytes(ctx, tcp_offset + tcp_len, header)?;
+
+    // the ip header has the length of the packet, and the TCP pseudo header the length of the
+    // segment, which both grow by the length of the header
+    let new_tot_len = tot_len + PROXY_V2_HEADER_LEN as u16;
+    let ret = unsafe {
+        bpf_l3_csum_replace(
+            ctx.skb.skb,
+            (l3_offset + offset_of!(Ipv4Hdr, check)) as u32,
+            tot_len.to_be() as u64,
+            new_tot_len.to_be() as u64,
+            mem::size_of::<u16>() as u64,
+        )
+    };
+    if ret != 0 {
+        return Err(ret);
+    }
+    store_bytes(
+        ctx,
+        l3_offset + offset_of!(Ipv4Hdr, tot_len),
+        &new_tot_len.to_be_bytes(),
+    )?;
+
+    let tcp_csum_offset = (tcp_offset + offset_of!(TcpHdr, check)) as u32;
+    let segment_len = tot_len - Ipv4Hdr::LEN as u16;
+    let new_segment_len = new_tot_len - Ipv4Hdr::LEN as u16;
+    let ret = unsafe {
+        bpf_l4_csum_replace(
+            ctx.skb.skb,
+            tcp_csum_offset,
+            segment_len.to_be() as u64,
+            new_segment_len.to_be() as u64,
+            BPF_F_PSEUDO_HDR as u64 | mem::size_of::<u16>() as u64,
+        )
+    };
+    if ret != 0 {
+        return Err(ret);
+    }
+    // the header starts at an offset of the segment that's a multiple of 4, so its sum adds to
+    // the checksum as is
+    let diff = unsafe {
+        bpf_csum_diff(
+            ptr::null_mut(),
+            0,
+            header.as_ptr() as *mut u32,
+            PROXY_V2_HEADER_LEN as u32,
+            0,
+        )
+    };
+    let ret = unsafe { bpf_l4_csum_replace(ctx.skb.skb, tcp_csum_offset, 0, diff as u64, 0) };
+    if ret != 0 {
+        return Err(ret);
+    }
+    Ok(())
+}
+
+// Replaces the 32 bits at offset with new, which the TCP checksum at csum_offset is updated for.
+#[inline(always)]
+fn replace_u32(
+    ctx: &TcContext,
+    csum_offset: u32,
+    offset: u32,
+    old: u32,
+    new: u32,
+) -> Result<(), c_long> {
+    let ret = unsafe {
+        bpf_l4_csum_replace(
+            ctx.skb.skb,
+            csum_offset,
+            old as u64,
+            new as u64,
+            mem::size_of::<u32>() as u64,
+        )
+    };
+    if ret != 0 {
+        return Err(ret);
+    }
+    store_bytes(ctx, offset as usize, &new.to_ne_bytes())
+}
+
+#[inline(always)]
+fn store_bytes(ctx: &TcContext, offset: usize, bytes: &[u8]) -> Result<(), c_long> {
+    let ret = unsafe {
+        bpf_skb_store_bytes(
+            ctx.skb.skb,
+            offset as u32,
+            bytes.as_ptr() as *const c_void,
+            bytes.len() as u32,
+            0,
+        )
+    };
+    if ret != 0 {
+        return Err(ret);
+    }
+    Ok(())
+}
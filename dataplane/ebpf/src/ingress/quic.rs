@@ -0,0 +1,87 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+use aya_ebpf::{helpers::bpf_ktime_get_ns, programs::TcContext};
+
+use network_types::udp::UdpHdr;
+
+use crate::{backends::backend_at, idle_timeout, QUIC_CONNECTIONS};
+use common::{Affinity, Backend, BackendKey, BackendList, QuicKey, QUIC_MAX_CID_LEN};
+
+const QUIC_LONG_HEADER: u8 = 0x80;
+// Set in the first byte of every QUIC v1 packet but version negotiation ones.
+const QUIC_FIXED_BIT: u8 = 0x40;
+// The destination connection ID length of long header packets follows the first byte and the
+// version.
+const QUIC_LONG_DCID_LEN_OFF: usize = 5;
+
+// Returns the key of the QUIC connection the UDP packet belongs to: the destination connection
+// ID of long header packets, which carry its length, or the first cid_len bytes of short header
+// ones. None when the packet isn't QUIC or has no connection ID. The UDP header of the packet is
+// at udp_offset.
+#[inline(always)]
+pub fn quic_key(
+    ctx: &TcContext,
+    udp_offset: usize,
+    backend_key: &BackendKey,
+    cid_len: u8,
+) -> Option<QuicKey> {
+    let quic_offset = udp_offset + UdpHdr::LEN;
+    let first: u8 = ctx.load(quic_offset).ok()?;
+    if first & QUIC_FIXED_BIT == 0 {
+        return None;
+    }
+
+    let (offset, len) = if first & QUIC_LONG_HEADER != 0 {
+        let dcid_len_offset = quic_offset + QUIC_LONG_DCID_LEN_OFF;
+        let len: u8 = ctx.load(dcid_len_offset).ok()?;
+        (dcid_len_offset + 1, len as usize)
+    } else {
+        (quic_offset + 1, cid_len as usize)
+    };
+    if len == 0 || len > QUIC_MAX_CID_LEN {
+        return None;
+    }
+
+    let mut key = QuicKey {
+        backend_key: *backend_key,
+        cid_len: len as u32,
+        cid: [0; QUIC_MAX_CID_LEN],
+    };
+    // a truncated connection ID isn't one
+    if ctx.load_bytes(offset, &mut key.cid[..len]).ok()? != len {
+        return None;
+    }
+    Some(key)
+}
+
+// Returns the backend the QUIC connection is bound to, along with its index, unless the binding
+// expired or the backend was removed from the vip since.
+#[inline(always)]
+pub fn quic_backend(backend_list: &BackendList, key: &QuicKey) -> Option<(u16, Backend)> {
+    let binding = unsafe { QUIC_CONNECTIONS.get(key) }?;
+    if binding.expires_at <= unsafe { bpf_ktime_get_ns() } {
+        return None;
+    }
+    let backend = backend_at(&key.backend_key, backend_list, binding.index)?;
+    (backend == binding.backend).then_some((binding.index, backend))
+}
+
+// Binds the QUIC connection to the backend at index, for as long as idle UDP flows are tracked.
+// Every packet extends the binding, so that the connection IDs a client still uses stay bound.
+#[inline(always)]
+pub fn bind_quic_connection(key: &QuicKey, index: u16, backend: Backend) -> Result<(), i64> {
+    let expires_at = match idle_timeout(false) {
+        0 => u64::MAX,
+        timeout => timeout + unsafe { bpf_ktime_get_ns() },
+    };
+    let binding = Affinity {
+        backend,
+        index,
+        expires_at,
+    };
+    unsafe { QUIC_CONNECTIONS.insert(key, &binding, 0_u64) }
+}
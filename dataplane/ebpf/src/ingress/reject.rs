@@ -0,0 +1,228 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! Replies to clients of vips that have no backends, so that they fail fast instead of waiting
+//! for a timeout: TCP connections are reset and UDP datagrams get an ICMP port unreachable
+//! error, as the host would reply for a port nothing listens on. The packet of the client is
+//! turned into the reply in place, a copy of which is sent back out of the interface it came in
+//! from.
+
+use core::{mem, ptr};
+
+use aya_ebpf::{
+    bindings::{TC_ACT_OK, TC_ACT_SHOT},
+    helpers::{bpf_clone_redirect, bpf_csum_diff, bpf_skb_change_tail, bpf_skb_store_bytes},
+    programs::TcContext,
+};
+use aya_ebpf_cty::c_void;
+use aya_log_ebpf::debug;
+
+use network_types::{
+    eth::EthHdr,
+    icmp::IcmpHdr,
+    ip::{IpProto, Ipv4Hdr},
+    tcp::TcpHdr,
+    udp::UdpHdr,
+};
+
+use crate::utils::{csum_fold_helper, ptr_at};
+
+const ICMP_DEST_UNREACH: u8 = 3;
+const ICMP_PORT_UNREACH: u8 = 3;
+
+const REPLY_TTL: u8 = 64;
+
+// The headers a reset is made of, after those of the link: the headers of the segment it
+// answers, without its payload.
+const RST_LEN: usize = Ipv4Hdr::LEN + TcpHdr::LEN;
+// The headers of the datagram an ICMP error carries: its IP header and first 8 bytes.
+const ICMP_QUOTE_LEN: usize = Ipv4Hdr::LEN + UdpHdr::LEN;
+
+// The TCP pseudo-header the checksum of a segment covers, along with the segment.
+#[repr(C)]
+struct PseudoHdr {
+    src_addr: u32,
+    dst_addr: u32,
+    proto_len: u32,
+}
+
+// Turns the TCP segment into the reset of its connection and sends it back to the client, as
+// per RFC 9293 3.10.7.1 for a connection that doesn't exist. The ip header is at l3_offset, the
+// VLAN tags in front of it are kept.
+pub fn reject_tcp(ctx: &TcContext, l3_offset: usize) -> Result<i32, i64> {
+    let ip_hdr: *mut Ipv4Hdr = unsafe { ptr_at(ctx, l3_offset)? };
+    let tcp_hdr: *mut TcpHdr = unsafe { ptr_at(ctx, l3_offset + Ipv4Hdr::LEN)? };
+    let (ip, tcp) = unsafe { (*ip_hdr, *tcp_hdr) };
+
+    // a reset is never answered
+    if tcp.rst() == 1 {
+        return Ok(TC_ACT_OK);
+    }
+    let payload_len = (u16::from_be(ip.tot_len) as u32)
+        .saturating_sub(Ipv4Hdr::LEN as u32 + tcp.doff() as u32 * 4);
+
+    if unsafe { bpf_skb_change_tail(ctx.skb.skb, (l3_offset + RST_LEN) as u32, 0) } != 0 {
+        return Ok(TC_ACT_SHOT);
+    }
+    let eth_hdr: *mut EthHdr = unsafe { ptr_at(ctx, 0)? };
+    let ip_hdr: *mut Ipv4Hdr = unsafe { ptr_at(ctx, l3_offset)? };
+    let tcp_hdr: *mut TcpHdr = unsafe { ptr_at(ctx, l3_offset + Ipv4Hdr::LEN)? };
+
+    unsafe {
+        reverse_eth(eth_hdr);
+        reply_ipv4(ip_hdr, &ip, IpProto::Tcp, Ipv4Hdr::LEN + TcpHdr::LEN);
+
+        let mut reset = tcp;
+        reset.source = tcp.dest;
+        reset.dest = tcp.source;
+        reset.set_doff(5);
+        reset.set_fin(0);
+        reset.set_syn(0);
+        reset.set_rst(1);
+        reset.set_psh(0);
+        reset.set_urg(0);
+        reset.set_ece(0);
+        reset.set_cwr(0);
+        if tcp.ack() == 1 {
+            reset.seq = tcp.ack_seq;
+            reset.ack_seq = 0;
+            reset.set_ack(0);
+        } else {
+            let acked = payload_len + tcp.syn() as u32 + tcp.fin() as u32;
+            reset.seq = 0;
+            reset.ack_seq = u32::from_be(tcp.seq).wrapping_add(acked).to_be();
+            reset.set_ack(1);
+        }
+        reset.window = 0;
+        reset.urg_ptr = 0;
+        reset.check = 0;
+        *tcp_hdr = reset;
+
+        let mut pseudo = PseudoHdr {
+            src_addr: (*ip_hdr).src_addr,
+            dst_addr: (*ip_hdr).dst_addr,
+            proto_len: ((IpProto::Tcp as u32) << 16 | TcpHdr::LEN as u32).to_be(),
+        };
+        let seed = bpf_csum_diff(
+            mem::MaybeUninit::zeroed().assume_init(),
+            0,
+            &mut pseudo as *mut PseudoHdr as *mut u32,
+            mem::size_of::<PseudoHdr>() as u32,
+            0,
+        );
+        let csum = bpf_csum_diff(
+            mem::MaybeUninit::zeroed().assume_init(),
+            0,
+            tcp_hdr as *mut u32,
+            TcpHdr::LEN as u32,
+            seed as u32,
+        );
+        (*tcp_hdr).check = csum_fold_helper(csum as u64);
+    }
+
+    Ok(send_back(ctx))
+}
+
+// Turns the UDP datagram into an ICMP port unreachable error and sends it back to the client.
+// The error quotes the IP and UDP headers of the datagram, as per RFC 792. The ip header is at
+// l3_offset, the VLAN tags in front of it are kept.
+pub fn reject_udp(ctx: &TcContext, l3_offset: usize) -> Result<i32, i64> {
+    let icmp_offset = l3_offset + Ipv4Hdr::LEN;
+    let quote_offset = icmp_offset + IcmpHdr::LEN;
+
+    // the quoted headers are moved behind those of the error, which are written over them
+    let mut quote = [0u8; ICMP_QUOTE_LEN];
+    if ctx.load_bytes(l3_offset, &mut quote).is_err()
+        || unsafe { bpf_skb_change_tail(ctx.skb.skb, (quote_offset + ICMP_QUOTE_LEN) as u32, 0) }
+            != 0
+        || unsafe {
+            bpf_skb_store_bytes(
+                ctx.skb.skb,
+                quote_offset as u32,
+                quote.as_ptr() as *const c_void,
+                ICMP_QUOTE_LEN as u32,
+                0,
+            )
+        } != 0
+    {
+        return Ok(TC_ACT_SHOT);
+    }
+    let eth_hdr: *mut EthHdr = unsafe { ptr_at(ctx, 0)? };
+    let ip_hdr: *mut Ipv4Hdr = unsafe { ptr_at(ctx, l3_offset)? };
+    let icmp_hdr: *mut IcmpHdr = unsafe { ptr_at(ctx, icmp_offset)? };
+    let quoted_ip_hdr: *const Ipv4Hdr = unsafe { ptr_at(ctx, quote_offset)? };
+    // the whole error is covered by its checksum
+    let icmp_msg: *mut [u8; IcmpHdr::LEN + ICMP_QUOTE_LEN] = unsafe { ptr_at(ctx, icmp_offset)? };
+
+    unsafe {
+        reverse_eth(eth_hdr);
+        reply_ipv4(
+            ip_hdr,
+            &*quoted_ip_hdr,
+            IpProto::Icmp,
+            Ipv4Hdr::LEN + IcmpHdr::LEN + ICMP_QUOTE_LEN,
+        );
+
+        (*icmp_hdr).type_ = ICMP_DEST_UNREACH;
+        (*icmp_hdr).code = ICMP_PORT_UNREACH;
+        (*icmp_hdr).checksum = 0;
+        (*icmp_hdr).un.gateway = 0;
+        let csum = bpf_csum_diff(
+            mem::MaybeUninit::zeroed().assume_init(),
+            0,
+            icmp_msg as *mut u32,
+            (IcmpHdr::LEN + ICMP_QUOTE_LEN) as u32,
+            0,
+        );
+        (*icmp_hdr).checksum = csum_fold_helper(csum as u64);
+    }
+
+    Ok(send_back(ctx))
+}
+
+// Swaps the addresses of the ethernet header, to send the packet back to where it came from.
+#[inline(always)]
+unsafe fn reverse_eth(eth_hdr: *mut EthHdr) {
+    let src_addr = (*eth_hdr).src_addr;
+    (*eth_hdr).src_addr = (*eth_hdr).dst_addr;
+    (*eth_hdr).dst_addr = src_addr;
+}
+
+// Writes the IP header of a reply of `len` bytes to the client that sent `request`.
+#[inline(always)]
+unsafe fn reply_ipv4(ip_hdr: *mut Ipv4Hdr, request: &Ipv4Hdr, proto: IpProto, len: usize) {
+    let mut reply = *request;
+    reply.tos = 0;
+    reply.tot_len = (len as u16).to_be();
+    reply.id = 0;
+    reply.frag_off = 0;
+    reply.ttl = REPLY_TTL;
+    reply.proto = proto;
+    reply.src_addr = request.dst_addr;
+    reply.dst_addr = request.src_addr;
+    reply.check = 0;
+    ptr::write(ip_hdr, reply);
+
+    let csum = bpf_csum_diff(
+        mem::MaybeUninit::zeroed().assume_init(),
+        0,
+        ip_hdr as *mut u32,
+        Ipv4Hdr::LEN as u32,
+        0,
+    );
+    (*ip_hdr).check = csum_fold_helper(csum as u64);
+}
+
+// Sends a copy of the packet out of the interface it was received on, the packet itself is
+// dropped.
+#[inline(always)]
+fn send_back(ctx: &TcContext) -> i32 {
+    let ifindex = unsafe { (*ctx.skb.skb).ifindex };
+    if unsafe { bpf_clone_redirect(ctx.skb.skb, ifindex, 0) } != 0 {
+        debug!(ctx, "Failed to send the reply back to the client");
+    }
+    TC_ACT_SHOT
+}
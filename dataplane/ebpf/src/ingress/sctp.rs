@@ -0,0 +1,222 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+use aya_ebpf::{
+    bindings::{TC_ACT_PIPE, TC_ACT_SHOT},
+    helpers::bpf_ktime_get_ns,
+    programs::TcContext,
+};
+use aya_log_ebpf::debug;
+
+use network_types::ip::{IpProto, Ipv4Hdr};
+
+use crate::{
+    backends::{
+        acl_denies, advance_round_robin, affinity_backend, backend_for_port, maglev_backend,
+        pin_affinity, rate_limited, round_robin_backend, vip_backends,
+    },
+    drop_policy,
+    ingress::reject::reject_udp,
+    is_draining,
+    utils::{
+        capture_packet, count_drop, count_vip_packet, next_hop, ptr_at, record_flow, redirect_to,
+        set_dscp, set_fragment_ip_dst, set_sctp_ports, tracked_connection,
+    },
+    GATEWAY_INDEXES, LB_CONNECTIONS,
+};
+use common::{
+    fragment::{is_first_fragment, is_later_fragment},
+    maglev::flow_hash,
+    sctp::{ends_association, ChunkHdr, SctpHdr, CHUNK_INIT},
+    AffinityKey, ClientKey, LoadBalancerMapping, CAPTURE_STAGE_RECEIVED, CAPTURE_STAGE_REWRITTEN,
+    DROP_POLICY_DROP, DROP_POLICY_PASS, DROP_STATS_ACL_DENIED, DROP_STATS_BACKEND_OUT_OF_RANGE,
+    DROP_STATS_NO_BACKENDS, DROP_STATS_RATE_LIMITED, DROP_STATS_REWRITE_FAILED,
+};
+
+// Load balances the associations of SCTP clients to vips, which are tracked by the client's
+// address and port like UDP flows, from the INIT chunk that starts them until the ABORT or
+// SHUTDOWN COMPLETE chunk that ends them. Only the primary path of multi-homed associations is
+// load balanced, and vips with full NAT or a mirror aren't supported for SCTP. Fragmented
+// packets pass through, their checksum covering the fragments that don't have the header.
+//
+// The ip header is at l3_offset, past the ethernet header and any VLAN tags.
+pub fn handle_sctp_ingress(ctx: TcContext, l3_offset: usize) -> Result<i32, i64> {
+    let ip_hdr: *const Ipv4Hdr = unsafe { ptr_at(&ctx, l3_offset)? };
+    let frag_off = u16::from_be(unsafe { (*ip_hdr).frag_off });
+    if is_first_fragment(frag_off) || is_later_fragment(frag_off) {
+        return Ok(TC_ACT_PIPE);
+    }
+
+    let sctp_offset = l3_offset + Ipv4Hdr::LEN;
+    let sctp_hdr: *const SctpHdr = unsafe { ptr_at(&ctx, sctp_offset)? };
+    let chunk_hdr: *const ChunkHdr = unsafe { ptr_at(&ctx, sctp_offset + SctpHdr::LEN)? };
+    let chunk_type = unsafe { (*chunk_hdr).type_ };
+    let sctp_len = u16::from_be(unsafe { (*ip_hdr).tot_len }).saturating_sub(Ipv4Hdr::LEN as u16);
+
+    let original_daddr = unsafe { (*ip_hdr).dst_addr };
+    let source = unsafe { (*sctp_hdr).source };
+
+    // the port the client sent the packet to
+    let vip_port = u16::from_be(unsafe { (*sctp_hdr).dest }) as u32;
+    let Some((backend_key, backend_list)) = vip_backends(
+        u32::from_be(original_daddr),
+        vip_port as u16,
+        IpProto::Sctp as u32,
+    ) else {
+        return Ok(TC_ACT_PIPE);
+    };
+    let client_ip = u32::from_be(unsafe { (*ip_hdr).src_addr });
+    if acl_denies(&backend_key, client_ip) {
+        count_drop(DROP_STATS_ACL_DENIED);
+        debug!(&ctx, "Client denied by the ACL of the vip, dropping packet");
+        return Ok(TC_ACT_SHOT);
+    }
+    if rate_limited(&backend_key, backend_list, client_ip) {
+        count_drop(DROP_STATS_RATE_LIMITED);
+        debug!(
+            &ctx,
+            "Client over the rate limit of the vip, dropping packet"
+        );
+        return Ok(TC_ACT_SHOT);
+    }
+    let client_key = ClientKey {
+        ip: client_ip,
+        port: u16::from_be(source) as u32,
+    };
+
+    sampled_info!(
+        &ctx,
+        "Received an SCTP packet destined for svc ip: {:i} at Port: {} ",
+        backend_key.ip,
+        vip_port as u16,
+    );
+
+    // the packets of a tracked association go to its backend, even once it's draining
+    let tracked =
+        tracked_connection(&client_key).filter(|mapping| mapping.backend_key == backend_key);
+    let (backend_index, backend) = match &tracked {
+        Some(mapping) => (mapping.backend_index, mapping.backend),
+        None => {
+            // while draining, only associations we've already seen are served
+            if is_draining() {
+                debug!(&ctx, "Draining, dropping packet from new association");
+                return Ok(TC_ACT_SHOT);
+            }
+            if backend_list.draining_len >= backend_list.backends_len {
+                count_drop(DROP_STATS_NO_BACKENDS);
+                debug!(&ctx, "No backends for the vip, refusing new association");
+                return match drop_policy() {
+                    DROP_POLICY_DROP => Ok(TC_ACT_SHOT),
+                    DROP_POLICY_PASS => Ok(TC_ACT_PIPE),
+                    // SCTP takes ICMP port unreachable errors for an ABORT, see RFC 9260
+                    _ => reject_udp(&ctx, l3_offset),
+                };
+            }
+            let Some(backend_index) = (unsafe { GATEWAY_INDEXES.get(&backend_key) }) else {
+                return Ok(TC_ACT_PIPE);
+            };
+            sampled_debug!(&ctx, "Destination backend index: {}", *backend_index);
+            sampled_debug!(&ctx, "Backends length: {}", backend_list.backends_len);
+
+            // with session affinity every association of a client goes to the backend it's
+            // pinned to, with consistent hashing to the backend at the association's hash
+            let affinity_key = AffinityKey {
+                client_ip: client_key.ip,
+                backend_key,
+            };
+            let hash = flow_hash(
+                client_key.ip,
+                client_key.port as u16,
+                backend_key.ip,
+                vip_port as u16,
+                IpProto::Sctp as u8,
+            );
+            let (index, backend) = if let Some(pinned) =
+                affinity_backend(backend_list, &affinity_key)
+            {
+                pinned
+            } else if let Some((i, bk)) = maglev_backend(&backend_key, backend_list, hash) {
+                pin_affinity(backend_list, &affinity_key, i, bk)?;
+                (i, bk)
+            } else {
+                let Some((i, bk)) = round_robin_backend(&backend_key, backend_list, *backend_index)
+                else {
+                    count_drop(DROP_STATS_BACKEND_OUT_OF_RANGE);
+                    return Ok(TC_ACT_PIPE);
+                };
+                pin_affinity(backend_list, &affinity_key, i, bk)?;
+                advance_round_robin(&backend_key, backend_list, i)?;
+                (i, bk)
+            };
+            (index, backend_for_port(backend, vip_port))
+        }
+    };
+
+    capture_packet(&ctx, &backend_key, CAPTURE_STAGE_RECEIVED, unsafe {
+        (*ctx.skb.skb).ifindex
+    });
+
+    // the checksum of SCTP doesn't cover the addresses, only the ports need it updated
+    if set_fragment_ip_dst(&ctx, l3_offset, &original_daddr, backend.daddr.to_be()) != 0
+        || set_sctp_ports(
+            &ctx,
+            sctp_offset,
+            sctp_len,
+            source,
+            (backend.dport as u16).to_be(),
+        )
+        .is_err()
+        || set_dscp(&ctx, l3_offset, backend_list.dscp()).is_err()
+    {
+        count_drop(DROP_STATS_REWRITE_FAILED);
+        return Ok(TC_ACT_SHOT);
+    }
+
+    // Record the packet's source and destination in our connection tracking map, so that the
+    // replies of the backend are sent back to the client as coming from the vip. The latency of
+    // the association is measured from its INIT chunk until the INIT ACK of the backend.
+    let now = unsafe { bpf_ktime_get_ns() };
+    let (packets, bytes, request_ns) = tracked.map_or((0, 0, 0), |mapping| {
+        (mapping.packets, mapping.bytes, mapping.request_ns)
+    });
+    let lb_mapping = LoadBalancerMapping {
+        backend,
+        backend_key,
+        tcp_state: None,
+        packets: packets + 1,
+        bytes: bytes + ctx.len() as u64,
+        last_seen: now,
+        snat_port: 0,
+        vip_port,
+        proxy_seq: None,
+        request_ns: if chunk_type == CHUNK_INIT {
+            now
+        } else {
+            request_ns
+        },
+        backend_index,
+    };
+    if ends_association(chunk_type) {
+        record_flow(&client_key, &lb_mapping);
+        let _ = unsafe { LB_CONNECTIONS.remove(&client_key) };
+    } else {
+        unsafe { LB_CONNECTIONS.insert(&client_key, &lb_mapping, 0_u64)? };
+    }
+    count_vip_packet(&backend_key, ctx.len() as u64);
+
+    let Some(hop) = next_hop(&ctx, l3_offset, backend.ifindex) else {
+        debug!(&ctx, "No route to the backend");
+        return Ok(TC_ACT_PIPE);
+    };
+
+    capture_packet(&ctx, &backend_key, CAPTURE_STAGE_REWRITTEN, hop.ifindex);
+
+    let action = redirect_to(&hop);
+
+    sampled_info!(&ctx, "redirect action: {}", action);
+
+    Ok(action as i32)
+}
@@ -0,0 +1,219 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+use aya_ebpf::{bindings::TC_ACT_OK, programs::TcContext};
+use aya_ebpf_cty::c_long;
+use aya_log_ebpf::debug;
+
+use memoffset::offset_of;
+use network_types::{
+    ip::{IpProto, Ipv4Hdr},
+    tcp::TcpHdr,
+    udp::UdpHdr,
+};
+
+use crate::{
+    ingress::proxy::proxy_reply,
+    snat_ip,
+    utils::{
+        clamp_mss, ptr_at, record_reply_latency, set_ipv4_ip_dst, set_ipv4_ip_src, set_ipv4_port,
+        tracked_connection, L4Ports,
+    },
+    SNAT_CONNECTIONS,
+};
+use common::{
+    maglev::flow_hash, Backend, BackendKey, ClientKey, LoadBalancerMapping, Snat, SnatKey,
+    SNAT_PORT_MAX, SNAT_PORT_MIN,
+};
+
+// The ports of new connections are looked for at this many places of the range before giving up.
+const SNAT_PORT_ATTEMPTS: u64 = 8;
+
+// Returns the source port a new full NAT connection of the client to the backend is sent from,
+// after recording it in SNAT_CONNECTIONS. None when no port was free.
+pub fn allocate_snat_port(
+    client_key: &ClientKey,
+    backend_key: &BackendKey,
+    backend: &Backend,
+    proto: IpProto,
+) -> Option<u32> {
+    let hash = flow_hash(
+        client_key.ip,
+        client_key.port as u16,
+        backend.daddr,
+        backend.dport as u16,
+        proto as u8,
+    );
+    let range = (SNAT_PORT_MAX - SNAT_PORT_MIN) as u64 + 1;
+    let snat = Snat {
+        client_key: *client_key,
+        backend_key: *backend_key,
+    };
+
+    for attempt in 0..SNAT_PORT_ATTEMPTS {
+        let key = SnatKey {
+            backend_ip: backend.daddr,
+            backend_port: backend.dport,
+            snat_port: SNAT_PORT_MIN as u32 + ((hash + attempt) % range) as u32,
+            proto: proto as u32,
+        };
+        // the ports of connections that are no longer tracked are free again
+        if current_snat(&key).is_some() {
+            continue;
+        }
+        if unsafe { SNAT_CONNECTIONS.insert(&key, &snat, 0) }.is_ok() {
+            return Some(key.snat_port);
+        }
+    }
+    None
+}
+
+// Rewrites the source of a packet the client sends to its backend to snat_port of the node's
+// SNAT address. The ip header is at l3_offset.
+pub fn snat_packet(
+    ctx: &TcContext,
+    l3_offset: usize,
+    l4_csum_offset: u32,
+    client_key: &ClientKey,
+    snat_port: u32,
+) -> c_long {
+    let ret = set_ipv4_ip_src(
+        ctx,
+        l3_offset,
+        l4_csum_offset,
+        &client_key.ip.to_be(),
+        snat_ip().to_be(),
+    );
+    if ret != 0 {
+        return ret;
+    }
+
+    set_ipv4_port(
+        ctx,
+        l4_csum_offset,
+        (l3_offset + Ipv4Hdr::LEN + offset_of!(L4Ports, source)) as u32,
+        &(client_key.port as u16).to_be(),
+        (snat_port as u16).to_be(),
+    )
+}
+
+// Sends the replies of backends to full NAT connections back to their clients, from the vip
+// they connected to. Returns None for any other packet, which is load balanced as usual.
+pub fn handle_snat_reply(
+    ctx: &TcContext,
+    l3_offset: usize,
+    proto: IpProto,
+) -> Result<Option<i32>, i64> {
+    let snat_ip = snat_ip();
+    if snat_ip == 0 {
+        return Ok(None);
+    }
+
+    let ip_hdr: *const Ipv4Hdr = unsafe { ptr_at(ctx, l3_offset)? };
+    let daddr = unsafe { (*ip_hdr).dst_addr };
+    if u32::from_be(daddr) != snat_ip {
+        return Ok(None);
+    }
+    let saddr = unsafe { (*ip_hdr).src_addr };
+
+    let l4_offset = l3_offset + Ipv4Hdr::LEN;
+    let l4_csum_offset = match proto {
+        IpProto::Tcp => l4_offset + offset_of!(TcpHdr, check),
+        IpProto::Udp => l4_offset + offset_of!(UdpHdr, check),
+        _ => return Ok(None),
+    } as u32;
+    let ports: *const L4Ports = unsafe { ptr_at(ctx, l4_offset)? };
+    let sport = unsafe { (*ports).source };
+    let dport = unsafe { (*ports).dest };
+
+    let key = SnatKey {
+        backend_ip: u32::from_be(saddr),
+        backend_port: u16::from_be(sport) as u32,
+        snat_port: u16::from_be(dport) as u32,
+        proto: proto as u32,
+    };
+    let (snat, mut mapping) = match current_snat(&key) {
+        Some(current) => current,
+        None => return Ok(None),
+    };
+
+    // the SYN-ACK of a TCP connection, or any reply of a UDP flow, answers its first packet
+    let answers_request = match proto {
+        IpProto::Tcp => {
+            let tcp_hdr: *const TcpHdr = unsafe { ptr_at(ctx, l4_offset)? };
+            unsafe { (*tcp_hdr).syn() == 1 && (*tcp_hdr).ack() == 1 }
+        }
+        _ => true,
+    };
+    if answers_request {
+        record_reply_latency(&snat.client_key, &mut mapping);
+    }
+
+    sampled_info!(
+        ctx,
+        "Received a reply to full NAT port {} from backend {:i}",
+        key.snat_port as u16,
+        key.backend_ip,
+    );
+
+    // the kernel routes the rewritten reply to the client, like the backend had sent it
+    if set_ipv4_ip_src(
+        ctx,
+        l3_offset,
+        l4_csum_offset,
+        &saddr,
+        snat.backend_key.ip.to_be(),
+    ) != 0
+        || set_ipv4_ip_dst(
+            ctx,
+            l3_offset,
+            l4_csum_offset,
+            &daddr,
+            snat.client_key.ip.to_be(),
+        ) != 0
+        || set_ipv4_port(
+            ctx,
+            l4_csum_offset,
+            (l4_offset + offset_of!(L4Ports, source)) as u32,
+            &sport,
+            (mapping.vip_port as u16).to_be(),
+        ) != 0
+        || set_ipv4_port(
+            ctx,
+            l4_csum_offset,
+            (l4_offset + offset_of!(L4Ports, dest)) as u32,
+            &dport,
+            (snat.client_key.port as u16).to_be(),
+        ) != 0
+    {
+        debug!(ctx, "Failed to rewrite a full NAT reply");
+    }
+    if let IpProto::Tcp = proto {
+        if clamp_mss(ctx, l4_offset).is_err() {
+            debug!(ctx, "Failed to clamp the MSS of a full NAT reply");
+        }
+        if let Some(first_seq) = mapping.proxy_seq {
+            if proxy_reply(ctx, l4_offset, first_seq).is_err() {
+                debug!(
+                    ctx,
+                    "Failed to rewrite a full NAT reply for the PROXY protocol"
+                );
+            }
+        }
+    }
+    Ok(Some(TC_ACT_OK))
+}
+
+// Returns the client and vip of the full NAT connection along with its tracked connection,
+// unless its client is no longer tracked with the connection's port and backend.
+fn current_snat(key: &SnatKey) -> Option<(Snat, LoadBalancerMapping)> {
+    let snat = *unsafe { SNAT_CONNECTIONS.get(key) }?;
+    let mapping = tracked_connection(&snat.client_key)?;
+    (mapping.snat_port == key.snat_port
+        && mapping.backend.daddr == key.backend_ip
+        && mapping.backend.dport == key.backend_port)
+        .then_some((snat, mapping))
+}
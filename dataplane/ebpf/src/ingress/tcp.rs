@@ -4,28 +4,52 @@ Copyright 2023 The Kubernetes Authors.
 SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
 */
 
-use core::mem;
-
-use aya_ebpf::{bindings::TC_ACT_OK, helpers::bpf_redirect_neigh, programs::TcContext};
+use aya_ebpf::{
+    bindings::{TC_ACT_OK, TC_ACT_SHOT},
+    helpers::bpf_ktime_get_ns,
+    programs::TcContext,
+};
 use aya_log_ebpf::{debug, info};
 
 use memoffset::offset_of;
-use network_types::{eth::EthHdr, ip::Ipv4Hdr, tcp::TcpHdr};
+use network_types::{
+    ip::{IpProto, Ipv4Hdr},
+    tcp::TcpHdr,
+};
 
 use crate::{
-    utils::{ptr_at, set_ipv4_dest_port, set_ipv4_ip_dst, update_tcp_conns},
+    backends::{
+        acl_denies, advance_round_robin, affinity_backend, backend_for_port, maglev_backend,
+        pin_affinity, rate_limited, round_robin_backend, vip_backends,
+    },
+    drop_policy,
+    ingress::{
+        proxy::proxy_segment,
+        reject::reject_tcp,
+        snat::{allocate_snat_port, snat_packet},
+    },
+    is_draining, snat_ip,
+    utils::{
+        capture_packet, clamp_mss, count_drop, count_vip_packet, is_hairpin, mirror_packet,
+        next_hop, ptr_at, record_flow, redirect_to, set_dscp, set_ipv4_ip_dst, set_ipv4_port,
+        tracked_connection, update_tcp_conns,
+    },
     BACKENDS, GATEWAY_INDEXES, LB_CONNECTIONS,
 };
 use common::{
-    Backend, BackendKey, ClientKey, LoadBalancerMapping, TCPState, BACKENDS_ARRAY_CAPACITY,
+    maglev::flow_hash, AffinityKey, Backend, BackendKey, ClientKey, LoadBalancerMapping, TCPState,
+    CAPTURE_STAGE_RECEIVED, CAPTURE_STAGE_REWRITTEN, DROP_POLICY_DROP, DROP_POLICY_PASS,
+    DROP_STATS_ACL_DENIED, DROP_STATS_BACKEND_OUT_OF_RANGE, DROP_STATS_NO_BACKENDS,
+    DROP_STATS_RATE_LIMITED, DROP_STATS_REWRITE_FAILED,
 };
 
-const TCP_CSUM_OFF: u32 = (EthHdr::LEN + Ipv4Hdr::LEN + offset_of!(TcpHdr, check)) as u32;
-
-pub fn handle_tcp_ingress(ctx: TcContext) -> Result<i32, i64> {
-    let ip_hdr: *mut Ipv4Hdr = unsafe { ptr_at(&ctx, EthHdr::LEN)? };
+// The ip header is at l3_offset, past the ethernet header and any VLAN tags.
+pub fn handle_tcp_ingress(ctx: TcContext, l3_offset: usize) -> Result<i32, i64> {
+    let ip_hdr: *mut Ipv4Hdr = unsafe { ptr_at(&ctx, l3_offset)? };
 
-    let tcp_header_offset = EthHdr::LEN + Ipv4Hdr::LEN;
+    let tcp_header_offset = l3_offset + Ipv4Hdr::LEN;
+    let tcp_csum_offset = (tcp_header_offset + offset_of!(TcpHdr, check)) as u32;
+    let tcp_dport_offset = (tcp_header_offset + offset_of!(TcpHdr, dest)) as u32;
 
     let tcp_hdr: *mut TcpHdr = unsafe { ptr_at(&ctx, tcp_header_offset) }?;
 
@@ -45,121 +69,300 @@ pub fn handle_tcp_ingress(ctx: TcContext) -> Result<i32, i64> {
     let mut new_conn = false;
     // The state of this TCP connection.
     let mut tcp_state = Some(TCPState::default());
+    // The traffic forwarded on this TCP connection before this packet.
+    let mut packets = 0;
+    let mut bytes = 0;
+    // The source port of this connection with full NAT, 0 without.
+    let mut snat_port = 0;
+    // The port the client connected to.
+    let vip_port = u16::from_be(original_dport) as u32;
+    // The sequence number of the first byte of the client with the PROXY protocol.
+    let mut proxy_seq = None;
+    // When the SYN of this connection was forwarded, until the backend answered it.
+    let mut request_ns = 0;
+    // The index of the backend in those of the vip when the connection was assigned it.
+    let backend_index: u16;
 
     // Try to find the backend previously used for this connection. If not found, it means that
-    // this is a new connection, so assign it the next backend in line.
-    if let Some(val) = unsafe { LB_CONNECTIONS.get(&client_key) } {
+    // this is a new connection, so assign it the backend its client is pinned to with session
+    // affinity, or else the next backend in line, or the backend at the hash of the connection
+    // with consistent hashing.
+    if let Some(val) = tracked_connection(&client_key) {
         backend = val.backend;
         backend_key = val.backend_key;
         tcp_state = val.tcp_state;
+        packets = val.packets;
+        bytes = val.bytes;
+        snat_port = val.snat_port;
+        proxy_seq = val.proxy_seq;
+        request_ns = val.request_ns;
+        backend_index = val.backend_index;
+
+        // the ACL of the vip may have changed since the connection was established
+        if acl_denies(&backend_key, client_key.ip) {
+            count_drop(DROP_STATS_ACL_DENIED);
+            return Ok(TC_ACT_SHOT);
+        }
+        if unsafe { BACKENDS.get(&backend_key) }
+            .is_some_and(|list| rate_limited(&backend_key, list, client_key.ip))
+        {
+            count_drop(DROP_STATS_RATE_LIMITED);
+            return Ok(TC_ACT_SHOT);
+        }
     } else {
         new_conn = true;
 
-        backend_key = BackendKey {
-            ip: u32::from_be(original_daddr),
-            port: (u16::from_be(original_dport)) as u32,
-        };
-        let backend_list = unsafe { BACKENDS.get(&backend_key) }.ok_or(TC_ACT_OK)?;
-        let backend_index = unsafe { GATEWAY_INDEXES.get(&backend_key) }.ok_or(TC_ACT_OK)?;
-
-        debug!(&ctx, "Destination backend index: {}", *backend_index);
-        debug!(&ctx, "Backends length: {}", backend_list.backends_len);
-
-        // this check asserts that we don't use a "zero-value" Backend
-        if backend_list.backends_len <= *backend_index {
+        let Some((key, backend_list)) = vip_backends(
+            u32::from_be(original_daddr),
+            u16::from_be(original_dport),
+            IpProto::Tcp as u32,
+        ) else {
             return Ok(TC_ACT_OK);
+        };
+        if acl_denies(&key, client_key.ip) {
+            count_drop(DROP_STATS_ACL_DENIED);
+            debug!(
+                &ctx,
+                "Client denied by the ACL of the vip, dropping new connection"
+            );
+            return Ok(TC_ACT_SHOT);
         }
-        // the bpf verifier is aware of variables that are used as an index for
-        // an array and requires that we check the array boundaries against
-        // the index to ensure our access is in-bounds.
-        if *backend_index as usize >= BACKENDS_ARRAY_CAPACITY {
+        if rate_limited(&key, backend_list, client_key.ip) {
+            count_drop(DROP_STATS_RATE_LIMITED);
+            debug!(
+                &ctx,
+                "Client over the rate limit of the vip, dropping new connection"
+            );
+            return Ok(TC_ACT_SHOT);
+        }
+        // draining backends aren't assigned new connections
+        if backend_list.draining_len >= backend_list.backends_len {
+            count_drop(DROP_STATS_NO_BACKENDS);
+            debug!(&ctx, "No backends for the vip, refusing new connection");
+            return match drop_policy() {
+                DROP_POLICY_DROP => Ok(TC_ACT_SHOT),
+                DROP_POLICY_PASS => Ok(TC_ACT_OK),
+                _ => reject_tcp(&ctx, l3_offset),
+            };
+        }
+        backend_key = key;
+        let Some(round_robin_index) = (unsafe { GATEWAY_INDEXES.get(&backend_key) }) else {
             return Ok(TC_ACT_OK);
+        };
+
+        // while draining, existing connections are allowed to finish but new ones are dropped
+        if is_draining() {
+            debug!(&ctx, "Draining, dropping new connection");
+            return Ok(TC_ACT_SHOT);
         }
 
-        backend = backend_list.backends[0];
-        if let Some(val) = backend_list.backends.get(*backend_index as usize) {
-            backend = *val;
+        let affinity_key = AffinityKey {
+            client_ip: client_key.ip,
+            backend_key,
+        };
+        if let Some((index, pinned)) = affinity_backend(backend_list, &affinity_key) {
+            backend = pinned;
+            backend_index = index;
+        } else if backend_list.maglev().is_some() {
+            let hash = flow_hash(
+                client_key.ip,
+                client_key.port as u16,
+                backend_key.ip,
+                vip_port as u16,
+                IpProto::Tcp as u8,
+            );
+            let Some((index, bk)) = maglev_backend(&backend_key, backend_list, hash) else {
+                count_drop(DROP_STATS_BACKEND_OUT_OF_RANGE);
+                return Ok(TC_ACT_OK);
+            };
+            backend = bk;
+            backend_index = index;
+            pin_affinity(backend_list, &affinity_key, index, backend)?;
         } else {
-            debug!(
-                &ctx,
-                "Failed to find backend in backends_list at index {}, falling back to 0th index; backends_len: {} ",
-                *backend_index,
-                backend_list.backends_len
-            )
+            sampled_debug!(&ctx, "Destination backend index: {}", *round_robin_index);
+            sampled_debug!(&ctx, "Backends length: {}", backend_list.backends_len);
+
+            let Some((index, bk)) =
+                round_robin_backend(&backend_key, backend_list, *round_robin_index)
+            else {
+                count_drop(DROP_STATS_BACKEND_OUT_OF_RANGE);
+                return Ok(TC_ACT_OK);
+            };
+            backend = bk;
+            backend_index = index;
+            pin_affinity(backend_list, &affinity_key, index, backend)?;
+            advance_round_robin(&backend_key, backend_list, index)?;
         }
 
-        // move the index to the next backend in our list
-        let mut next = *backend_index + 1;
-        if next >= backend_list.backends_len {
-            next = 0;
+        backend = backend_for_port(backend, vip_port);
+
+        // the first byte of the client is the one after its SYN
+        if backend_list.proxy_protocol() && unsafe { (*tcp_hdr).syn() } == 1 {
+            proxy_seq = Some(u32::from_be(unsafe { (*tcp_hdr).seq }).wrapping_add(1));
         }
-        unsafe {
-            GATEWAY_INDEXES.insert(&backend_key, &next, 0_u64)?;
+
+        // hairpinned connections are full NATed too, so that their replies come back to the
+        // dataplane
+        if snat_ip() != 0 && (backend_list.full_nat() || is_hairpin(&ctx, l3_offset, &backend)) {
+            match allocate_snat_port(&client_key, &backend_key, &backend, IpProto::Tcp) {
+                Some(port) => snat_port = port,
+                None => {
+                    debug!(&ctx, "No full NAT port left, dropping new connection");
+                    return Ok(TC_ACT_SHOT);
+                }
+            }
         }
     }
 
-    info!(
+    sampled_info!(
         &ctx,
         "Received a TCP packet destined for svc ip: {:i} at Port: {} ",
         u32::from_be(original_daddr),
         u16::from_be(unsafe { (*tcp_hdr).dest })
     );
 
-    if (ctx.data() + EthHdr::LEN + Ipv4Hdr::LEN) > ctx.data_end() {
+    if (ctx.data() + l3_offset + Ipv4Hdr::LEN) > ctx.data_end() {
         info!(&ctx, "Iphdr is out of bounds");
         return Ok(TC_ACT_OK);
     }
 
     let tcp_hdr_ref = unsafe { tcp_hdr.as_ref().ok_or(TC_ACT_OK)? };
-
-    // If the packet has the RST flag set, it means the connection is being terminated, so remove it
-    // from our map.
-    if tcp_hdr_ref.rst() == 1 {
-        unsafe {
-            LB_CONNECTIONS.remove(&client_key)?;
-        }
+    let now = unsafe { bpf_ktime_get_ns() };
+    if new_conn && tcp_hdr_ref.syn() == 1 && tcp_hdr_ref.ack() == 0 {
+        request_ns = now;
     }
 
     let mut lb_mapping = LoadBalancerMapping {
         backend,
         backend_key,
         tcp_state,
+        packets: packets + 1,
+        bytes: bytes + ctx.len() as u64,
+        last_seen: now,
+        snat_port,
+        vip_port,
+        proxy_seq,
+        request_ns,
+        backend_index,
     };
 
-    update_tcp_conns(tcp_hdr_ref, &client_key, &mut lb_mapping)?;
+    // If the packet has the RST flag set, it means the connection is being terminated, so remove it
+    // from our map. That's all of its tracking: the state machine would record its flow again in
+    // TimeWait, or insert it back in FinWait1. The RST itself still goes to the backend.
+    let rst = tcp_hdr_ref.rst() == 1;
+    if rst {
+        if !new_conn {
+            record_flow(&client_key, &lb_mapping);
+            unsafe {
+                LB_CONNECTIONS.remove(&client_key)?;
+            }
+        }
+    } else {
+        update_tcp_conns(tcp_hdr_ref, &client_key, &mut lb_mapping)?;
+    }
+    count_vip_packet(&backend_key, ctx.len() as u64);
+
+    capture_packet(&ctx, &backend_key, CAPTURE_STAGE_RECEIVED, unsafe {
+        (*ctx.skb.skb).ifindex
+    });
+
+    if clamp_mss(&ctx, l3_offset + Ipv4Hdr::LEN).is_err() {
+        debug!(&ctx, "Failed to clamp the MSS of a SYN");
+    }
+
+    if let Some(first_seq) = proxy_seq {
+        if proxy_segment(
+            &ctx,
+            l3_offset,
+            &client_key,
+            &backend_key,
+            vip_port,
+            first_seq,
+        )
+        .is_err()
+        {
+            count_drop(DROP_STATS_REWRITE_FAILED);
+            debug!(&ctx, "Failed to rewrite a segment for the PROXY protocol");
+            return Ok(TC_ACT_SHOT);
+        }
+    }
+
+    // the destination the packet is currently addressed to
+    let mut daddr = original_daddr;
+    let mut dport = original_dport;
+
+    if let Some(mirror) = unsafe { BACKENDS.get(&backend_key) }.and_then(|list| list.mirror()) {
+        let mirror = backend_for_port(mirror, vip_port);
+        let ret = mirror_packet(
+            &ctx,
+            l3_offset,
+            tcp_csum_offset,
+            tcp_dport_offset,
+            &mut daddr,
+            &mut dport,
+            &mirror,
+        );
+        // the packet still goes to its backend when it can't be mirrored
+        if ret != 0 {
+            debug!(&ctx, "Failed to mirror the packet");
+        }
+    }
 
     let backend_ip = backend.daddr.to_be();
-    let ret = set_ipv4_ip_dst(&ctx, TCP_CSUM_OFF, &original_daddr, backend_ip);
+    let ret = set_ipv4_ip_dst(&ctx, l3_offset, tcp_csum_offset, &daddr, backend_ip);
     if ret != 0 {
-        return Ok(TC_ACT_OK);
+        count_drop(DROP_STATS_REWRITE_FAILED);
+        return Ok(TC_ACT_SHOT);
     }
 
     let backend_port = (backend.dport as u16).to_be();
-    let ret = set_ipv4_dest_port(&ctx, TCP_CSUM_OFF, &original_dport, backend_port);
+    let ret = set_ipv4_port(
+        &ctx,
+        tcp_csum_offset,
+        tcp_dport_offset,
+        &dport,
+        backend_port,
+    );
     if ret != 0 {
-        return Ok(TC_ACT_OK);
+        count_drop(DROP_STATS_REWRITE_FAILED);
+        return Ok(TC_ACT_SHOT);
     }
 
-    let action = unsafe {
-        bpf_redirect_neigh(
-            backend.ifindex as u32,
-            mem::MaybeUninit::zeroed().assume_init(),
-            0,
-            0,
-        )
+    if snat_port != 0 {
+        let ret = snat_packet(&ctx, l3_offset, tcp_csum_offset, &client_key, snat_port);
+        if ret != 0 {
+            count_drop(DROP_STATS_REWRITE_FAILED);
+            return Ok(TC_ACT_SHOT);
+        }
+    }
+
+    let dscp = unsafe { BACKENDS.get(&backend_key) }.and_then(|list| list.dscp());
+    if set_dscp(&ctx, l3_offset, dscp).is_err() {
+        count_drop(DROP_STATS_REWRITE_FAILED);
+        return Ok(TC_ACT_SHOT);
+    }
+
+    let Some(hop) = next_hop(&ctx, l3_offset, backend.ifindex) else {
+        debug!(&ctx, "No route to the backend");
+        return Ok(TC_ACT_OK);
     };
 
-    // If the connection is new, then record it in our map for future tracking.
-    if new_conn {
+    capture_packet(&ctx, &backend_key, CAPTURE_STAGE_REWRITTEN, hop.ifindex);
+
+    let action = redirect_to(&hop);
+
+    // If the connection is new, then record it in our map for future tracking, unless it's
+    // already reset.
+    if new_conn && !rst {
         unsafe {
             LB_CONNECTIONS.insert(&client_key, &lb_mapping, 0_u64)?;
         }
 
         // since this is a new connection, there is nothing else to do, so exit early
-        info!(&ctx, "redirect action: {}", action);
+        sampled_info!(&ctx, "redirect action: {}", action);
         return Ok(action as i32);
     }
 
-    info!(&ctx, "redirect action: {}", action);
+    sampled_info!(&ctx, "redirect action: {}", action);
     Ok(action as i32)
 }
@@ -4,128 +4,309 @@ Copyright 2023 The Kubernetes Authors.
 SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
 */
 
-use core::mem;
-
-use aya_ebpf::{bindings::TC_ACT_PIPE, helpers::bpf_redirect_neigh, programs::TcContext};
+use aya_ebpf::{
+    bindings::{TC_ACT_PIPE, TC_ACT_SHOT},
+    helpers::bpf_ktime_get_ns,
+    programs::TcContext,
+};
 use aya_log_ebpf::{debug, info};
 
 use memoffset::offset_of;
-use network_types::{eth::EthHdr, ip::Ipv4Hdr, udp::UdpHdr};
+use network_types::{
+    ip::{IpProto, Ipv4Hdr},
+    udp::UdpHdr,
+};
 
 use crate::{
-    utils::{ptr_at, set_ipv4_dest_port, set_ipv4_ip_dst},
-    BACKENDS, GATEWAY_INDEXES, LB_CONNECTIONS,
+    backends::{
+        acl_denies, advance_round_robin, affinity_backend, backend_for_port, draining_backend,
+        maglev_backend, pin_affinity, rate_limited, round_robin_backend, vip_backends,
+    },
+    drop_policy,
+    ingress::{
+        fragment::{fragment_key, handle_udp_fragment, track_fragments},
+        quic::{bind_quic_connection, quic_backend, quic_key},
+        reject::reject_udp,
+        snat::{allocate_snat_port, snat_packet},
+    },
+    is_draining, snat_ip,
+    utils::{
+        capture_packet, count_drop, count_vip_packet, is_hairpin, mirror_packet, next_hop, ptr_at,
+        redirect_to, set_dscp, set_ipv4_ip_dst, set_ipv4_port, tracked_connection,
+    },
+    GATEWAY_INDEXES, LB_CONNECTIONS,
+};
+use common::{
+    fragment::{is_first_fragment, is_later_fragment},
+    maglev::flow_hash,
+    AffinityKey, ClientKey, Fragment, LoadBalancerMapping, CAPTURE_STAGE_RECEIVED,
+    CAPTURE_STAGE_REWRITTEN, DROP_POLICY_DROP, DROP_POLICY_PASS, DROP_STATS_ACL_DENIED,
+    DROP_STATS_BACKEND_OUT_OF_RANGE, DROP_STATS_NO_BACKENDS, DROP_STATS_RATE_LIMITED,
+    DROP_STATS_REWRITE_FAILED,
 };
-use common::{BackendKey, ClientKey, LoadBalancerMapping, BACKENDS_ARRAY_CAPACITY};
 
-const UDP_CSUM_OFF: u32 = (EthHdr::LEN + Ipv4Hdr::LEN + offset_of!(UdpHdr, check)) as u32;
+// The ip header is at l3_offset, past the ethernet header and any VLAN tags.
+pub fn handle_udp_ingress(ctx: TcContext, l3_offset: usize) -> Result<i32, i64> {
+    let ip_hdr: *mut Ipv4Hdr = unsafe { ptr_at(&ctx, l3_offset)? };
 
-pub fn handle_udp_ingress(ctx: TcContext) -> Result<i32, i64> {
-    let ip_hdr: *mut Ipv4Hdr = unsafe { ptr_at(&ctx, EthHdr::LEN)? };
+    // only the first fragment of a datagram has its UDP header, the later ones follow it
+    let frag_off = u16::from_be(unsafe { (*ip_hdr).frag_off });
+    if is_later_fragment(frag_off) {
+        return handle_udp_fragment(ctx, l3_offset);
+    }
+    let first_fragment = is_first_fragment(frag_off).then(|| fragment_key(unsafe { &*ip_hdr }));
 
-    let udp_header_offset = EthHdr::LEN + Ipv4Hdr::LEN;
+    let udp_header_offset = l3_offset + Ipv4Hdr::LEN;
+    let udp_csum_offset = (udp_header_offset + offset_of!(UdpHdr, check)) as u32;
+    let udp_dport_offset = (udp_header_offset + offset_of!(UdpHdr, dest)) as u32;
 
     let udp_hdr: *mut UdpHdr = unsafe { ptr_at(&ctx, udp_header_offset) }?;
 
     let original_daddr = unsafe { (*ip_hdr).dst_addr };
     let original_dport = unsafe { (*udp_hdr).dest };
 
-    let backend_key = BackendKey {
-        ip: u32::from_be(original_daddr),
-        port: (u16::from_be(original_dport)) as u32,
+    // the port the client sent the packet to
+    let vip_port = u16::from_be(original_dport) as u32;
+    let Some((backend_key, backend_list)) = vip_backends(
+        u32::from_be(original_daddr),
+        vip_port as u16,
+        IpProto::Udp as u32,
+    ) else {
+        return Ok(TC_ACT_PIPE);
     };
-    let backend_list = unsafe { BACKENDS.get(&backend_key) }.ok_or(TC_ACT_PIPE)?;
-    let backend_index = unsafe { GATEWAY_INDEXES.get(&backend_key) }.ok_or(TC_ACT_PIPE)?;
+    let client_ip = u32::from_be(unsafe { (*ip_hdr).src_addr });
+    if acl_denies(&backend_key, client_ip) {
+        count_drop(DROP_STATS_ACL_DENIED);
+        debug!(&ctx, "Client denied by the ACL of the vip, dropping packet");
+        return Ok(TC_ACT_SHOT);
+    }
+    if rate_limited(&backend_key, backend_list, client_ip) {
+        count_drop(DROP_STATS_RATE_LIMITED);
+        debug!(
+            &ctx,
+            "Client over the rate limit of the vip, dropping packet"
+        );
+        return Ok(TC_ACT_SHOT);
+    }
+    let Some(backend_index) = (unsafe { GATEWAY_INDEXES.get(&backend_key) }) else {
+        return Ok(TC_ACT_PIPE);
+    };
+
+    // UDP flows are tracked by the client's address and port, so that the flows of clients
+    // behind the same address are told apart.
+    let client_key = ClientKey {
+        ip: client_ip,
+        port: (u16::from_be(unsafe { (*udp_hdr).source })) as u32,
+    };
+
+    let tracked = tracked_connection(&client_key);
 
-    info!(
+    // while draining, only flows we've already seen are served
+    if is_draining() && tracked.is_none() {
+        debug!(&ctx, "Draining, dropping packet from new flow");
+        return Ok(TC_ACT_SHOT);
+    }
+
+    sampled_info!(
         &ctx,
         "Received a UDP packet destined for svc ip: {:i} at Port: {} ",
         backend_key.ip,
-        backend_key.port as u16,
+        vip_port as u16,
     );
-    debug!(&ctx, "Destination backend index: {}", *backend_index);
-    debug!(&ctx, "Backends length: {}", backend_list.backends_len);
+    sampled_debug!(&ctx, "Destination backend index: {}", *backend_index);
+    sampled_debug!(&ctx, "Backends length: {}", backend_list.backends_len);
 
-    // this check asserts that we don't use a "zero-value" Backend
-    if backend_list.backends_len <= *backend_index {
-        return Ok(TC_ACT_PIPE);
+    // with session affinity every packet of a client goes to the backend it's pinned to
+    let affinity_key = AffinityKey {
+        client_ip: client_key.ip,
+        backend_key,
+    };
+    let pinned = affinity_backend(backend_list, &affinity_key);
+    // with QUIC connection IDs every packet of a QUIC connection goes to the backend its
+    // connection ID is bound to, including those sent after the client moved to another address
+    let quic = match backend_list.quic_cid_len {
+        0 => None,
+        cid_len => quic_key(&ctx, udp_header_offset, &backend_key, cid_len),
+    };
+    let bound = quic
+        .as_ref()
+        .and_then(|key| quic_backend(backend_list, key));
+    // draining backends aren't assigned new flows, but the flows and QUIC connections they have
+    // continue
+    let draining = tracked
+        .as_ref()
+        .and_then(|mapping| draining_backend(&backend_key, backend_list, mapping, vip_port));
+    if bound.is_none()
+        && draining.is_none()
+        && backend_list.draining_len >= backend_list.backends_len
+    {
+        count_drop(DROP_STATS_NO_BACKENDS);
+        debug!(&ctx, "No backends for the vip, refusing packet");
+        return match drop_policy() {
+            DROP_POLICY_DROP => Ok(TC_ACT_SHOT),
+            DROP_POLICY_PASS => Ok(TC_ACT_PIPE),
+            _ => reject_udp(&ctx, l3_offset),
+        };
     }
-    // this check is to make the verifier happy
-    if *backend_index as usize >= BACKENDS_ARRAY_CAPACITY {
-        return Ok(TC_ACT_PIPE);
+    // with consistent hashing every packet of a flow goes to the backend at the flow's hash
+    let hash = flow_hash(
+        client_key.ip,
+        client_key.port as u16,
+        backend_key.ip,
+        vip_port as u16,
+        IpProto::Udp as u8,
+    );
+    let (index, backend) = if let Some(selected) = bound.or(draining).or(pinned) {
+        selected
+    } else if let Some((i, bk)) = maglev_backend(&backend_key, backend_list, hash) {
+        pin_affinity(backend_list, &affinity_key, i, bk)?;
+        (i, bk)
+    } else {
+        let Some((i, bk)) = round_robin_backend(&backend_key, backend_list, *backend_index) else {
+            count_drop(DROP_STATS_BACKEND_OUT_OF_RANGE);
+            return Ok(TC_ACT_PIPE);
+        };
+        pin_affinity(backend_list, &affinity_key, i, bk)?;
+        (i, bk)
+    };
+    if let Some(key) = &quic {
+        bind_quic_connection(key, index, backend)?;
     }
+    let backend = backend_for_port(backend, vip_port);
 
-    let mut backend = backend_list.backends[0];
-    match backend_list.backends.get(*backend_index as usize) {
-        Some(bk) => backend = *bk,
-        None => {
-            debug!(
-                &ctx,
-                "Failed to find backend in backends_list at index {}, falling back to 0th index; backends_len: {} ",
-                *backend_index,
-                backend_list.backends_len
-            )
+    // with full NAT a flow keeps its source port for as long as it's sent to the same backend.
+    // Hairpinned flows are full NATed too, so that their replies come back to the dataplane.
+    let mut snat_port = 0;
+    if snat_ip() != 0 && (backend_list.full_nat() || is_hairpin(&ctx, l3_offset, &backend)) {
+        snat_port = match tracked {
+            Some(mapping) if mapping.snat_port != 0 && mapping.backend == backend => {
+                mapping.snat_port
+            }
+            _ => match allocate_snat_port(&client_key, &backend_key, &backend, IpProto::Udp) {
+                Some(port) => port,
+                None => {
+                    debug!(&ctx, "No full NAT port left, dropping packet from new flow");
+                    return Ok(TC_ACT_SHOT);
+                }
+            },
+        };
+    }
+
+    capture_packet(&ctx, &backend_key, CAPTURE_STAGE_RECEIVED, unsafe {
+        (*ctx.skb.skb).ifindex
+    });
+
+    // the destination the packet is currently addressed to
+    let mut daddr = original_daddr;
+    let mut dport = original_dport;
+
+    if let Some(mirror) = backend_list.mirror() {
+        let mirror = backend_for_port(mirror, vip_port);
+        let ret = mirror_packet(
+            &ctx,
+            l3_offset,
+            udp_csum_offset,
+            udp_dport_offset,
+            &mut daddr,
+            &mut dport,
+            &mirror,
+        );
+        // the packet still goes to its backend when it can't be mirrored
+        if ret != 0 {
+            debug!(&ctx, "Failed to mirror the packet");
         }
     }
 
     unsafe {
-        // DNAT the ip address
-        (*ip_hdr).dst_addr = backend.daddr.to_be();
-        // DNAT the port
-        (*udp_hdr).dest = (backend.dport as u16).to_be();
-
-        // Record the packet's source and destination in our connection tracking map.
-        let client_key = ClientKey {
-            ip: u32::from_be((*ip_hdr).src_addr),
-            // The only reason we're tracking UDP packets is to be able to allow ICMP egress
-            // traffic. Since ICMP is a L3 protocol, an ICMP packet's header does not have access to
-            // the UDP port and operates solely based on the IP address.
-            port: 0,
-        };
+        // Record the packet's source and destination in our connection tracking map, so that
+        // ICMP errors of the backend can be sent back to the client as coming from the vip.
+        // UDP has no connection state, so the counters accumulate until the client is no
+        // longer tracked
+        // the latency of the flow is measured from its first datagram to its first reply
+        let now = bpf_ktime_get_ns();
+        let (packets, bytes, request_ns) = tracked.map_or((0, 0, now), |mapping| {
+            (mapping.packets, mapping.bytes, mapping.request_ns)
+        });
         let lb_mapping = LoadBalancerMapping {
             backend,
             backend_key,
             tcp_state: None,
+            packets: packets + 1,
+            bytes: bytes + ctx.len() as u64,
+            last_seen: now,
+            snat_port,
+            vip_port,
+            proxy_seq: None,
+            request_ns,
+            backend_index: index,
         };
         LB_CONNECTIONS.insert(&client_key, &lb_mapping, 0_u64)?;
     };
+    count_vip_packet(&backend_key, ctx.len() as u64);
 
-    if (ctx.data() + EthHdr::LEN + Ipv4Hdr::LEN) > ctx.data_end() {
+    if (ctx.data() + l3_offset + Ipv4Hdr::LEN) > ctx.data_end() {
         info!(&ctx, "Iphdr is out of bounds");
         return Ok(TC_ACT_PIPE);
     }
 
     let backend_ip = backend.daddr.to_be();
-    let ret = set_ipv4_ip_dst(&ctx, UDP_CSUM_OFF, &original_daddr, backend_ip);
+    let ret = set_ipv4_ip_dst(&ctx, l3_offset, udp_csum_offset, &daddr, backend_ip);
     if ret != 0 {
-        return Ok(TC_ACT_PIPE);
+        count_drop(DROP_STATS_REWRITE_FAILED);
+        return Ok(TC_ACT_SHOT);
     }
 
     let backend_port = (backend.dport as u16).to_be();
-    let ret = set_ipv4_dest_port(&ctx, UDP_CSUM_OFF, &original_dport, backend_port);
+    let ret = set_ipv4_port(
+        &ctx,
+        udp_csum_offset,
+        udp_dport_offset,
+        &dport,
+        backend_port,
+    );
     if ret != 0 {
-        return Ok(TC_ACT_PIPE);
+        count_drop(DROP_STATS_REWRITE_FAILED);
+        return Ok(TC_ACT_SHOT);
     }
 
-    let action = unsafe {
-        bpf_redirect_neigh(
-            backend.ifindex as u32,
-            mem::MaybeUninit::zeroed().assume_init(),
-            0,
-            0,
-        )
-    };
+    if snat_port != 0 {
+        let ret = snat_packet(&ctx, l3_offset, udp_csum_offset, &client_key, snat_port);
+        if ret != 0 {
+            count_drop(DROP_STATS_REWRITE_FAILED);
+            return Ok(TC_ACT_SHOT);
+        }
+    }
 
-    // move the index to the next backend in our list
-    let mut next = *backend_index + 1;
-    if next >= backend_list.backends_len {
-        next = 0;
+    if set_dscp(&ctx, l3_offset, backend_list.dscp()).is_err() {
+        count_drop(DROP_STATS_REWRITE_FAILED);
+        return Ok(TC_ACT_SHOT);
     }
-    unsafe {
-        GATEWAY_INDEXES.insert(&backend_key, &next, 0_u64)?;
+
+    if let Some(key) = &first_fragment {
+        let fragment = Fragment {
+            backend_key,
+            backend,
+            snat_ip: if snat_port != 0 { snat_ip() } else { 0 },
+        };
+        track_fragments(key, &fragment)?;
+    }
+
+    let Some(hop) = next_hop(&ctx, l3_offset, backend.ifindex) else {
+        debug!(&ctx, "No route to the backend");
+        return Ok(TC_ACT_PIPE);
+    };
+
+    capture_packet(&ctx, &backend_key, CAPTURE_STAGE_REWRITTEN, hop.ifindex);
+
+    let action = redirect_to(&hop);
+
+    // move the index to the next backend in our list, unless it wasn't used
+    if backend_list.maglev().is_none() && pinned.is_none() && bound.is_none() && draining.is_none()
+    {
+        advance_round_robin(&backend_key, backend_list, index)?;
     }
 
-    info!(&ctx, "redirect action: {}", action);
+    sampled_info!(&ctx, "redirect action: {}", action);
 
     Ok(action as i32)
 }
@@ -7,6 +7,25 @@ SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
 #![no_std]
 #![no_main]
 
+// Like info!, but only emits 1 in STATE_LOG_SAMPLE_RATE lines, for logging on every packet.
+macro_rules! sampled_info {
+    ($($arg:tt)*) => {
+        if $crate::should_log(common::LOG_LEVEL_INFO) {
+            aya_log_ebpf::info!($($arg)*);
+        }
+    };
+}
+
+// Like debug!, but sampled like sampled_info! and only emitted at LOG_LEVEL_DEBUG.
+macro_rules! sampled_debug {
+    ($($arg:tt)*) => {
+        if $crate::should_log(common::LOG_LEVEL_DEBUG) {
+            aya_log_ebpf::debug!($($arg)*);
+        }
+    };
+}
+
+mod backends;
 #[allow(non_upper_case_globals)]
 #[allow(non_snake_case)]
 #[allow(non_camel_case_types)]
@@ -16,21 +35,39 @@ mod ingress;
 mod utils;
 
 use aya_ebpf::{
-    bindings::{TC_ACT_OK, TC_ACT_PIPE, TC_ACT_SHOT},
+    bindings::{TC_ACT_OK, TC_ACT_PIPE},
+    helpers::bpf_get_prandom_u32,
     macros::{classifier, map},
-    maps::HashMap,
+    maps::{
+        Array, HashMap, LpmTrie, LruHashMap, PerCpuArray, PerCpuHashMap, PerfEventArray,
+        ProgramArray, RingBuf,
+    },
     programs::TcContext,
 };
 
-use common::{BackendKey, BackendList, ClientKey, LoadBalancerMapping, BPF_MAPS_CAPACITY};
-use egress::{icmp::handle_icmp_egress, tcp::handle_tcp_egress};
-use ingress::{tcp::handle_tcp_ingress, udp::handle_udp_ingress};
-
-use network_types::{
-    eth::{EthHdr, EtherType},
-    ip::{IpProto, Ipv4Hdr},
+use common::{
+    AclKey, Affinity, AffinityKey, BackendKey, BackendList, BackendSlot, BackendSlotKey,
+    CaptureConfig, CaptureHeader, ClientKey, Fragment, FragmentKey, LoadBalancerMapping,
+    PortRangeKey, QuicKey, SampleConfig, Snat, SnatKey, VipStats, BPF_MAPS_CAPACITY,
+    DATAPLANE_STATE_LEN, DROP_POLICY_REJECT, DROP_STATS_LEN, ERROR_EVENTS_BYTE_SIZE,
+    FLOW_RECORDS_BYTE_SIZE, LOG_LEVEL_OFF, LOG_STATS_EMITTED, LOG_STATS_LEN, LOG_STATS_SUPPRESSED,
+    PACKET_SAMPLES_BYTE_SIZE, PROGRAM_TC_EGRESS, PROGRAM_TC_INGRESS, STATE_DRAINING,
+    STATE_DROP_POLICY, STATE_FEATURES, STATE_LOG_LEVEL, STATE_LOG_SAMPLE_RATE, STATE_SNAT_IP,
+    STATE_STANDBY, STATE_TCP_IDLE_TIMEOUT, STATE_TCP_MSS_CLAMP, STATE_UDP_IDLE_TIMEOUT,
+    TAIL_CALLS_LEN, TAIL_CALL_EGRESS_ICMP, TAIL_CALL_EGRESS_SCTP, TAIL_CALL_EGRESS_TCP,
+    TAIL_CALL_EGRESS_UDP, TAIL_CALL_INGRESS_SCTP, TAIL_CALL_INGRESS_TCP, TAIL_CALL_INGRESS_UDP,
+};
+use egress::{
+    icmp::handle_icmp_egress, sctp::handle_sctp_egress, tcp::handle_tcp_egress,
+    udp::handle_udp_egress,
 };
-use utils::ptr_at;
+use ingress::{
+    sctp::handle_sctp_ingress, snat::handle_snat_reply, tcp::handle_tcp_ingress,
+    udp::handle_udp_ingress,
+};
+
+use network_types::ip::{IpProto, Ipv4Hdr};
+use utils::{ipv4_offset, ptr_at, pull_headers, report_error};
 
 // -----------------------------------------------------------------------------
 // Maps
@@ -40,6 +77,22 @@ use utils::ptr_at;
 static mut BACKENDS: HashMap<BackendKey, BackendList> =
     HashMap::<BackendKey, BackendList>::with_max_entries(BPF_MAPS_CAPACITY, 0);
 
+// The backends of vips past the BACKENDS_ARRAY_CAPACITY that fit in their BACKENDS entry.
+#[map(name = "BACKEND_SLOTS")]
+static mut BACKEND_SLOTS: HashMap<BackendSlotKey, BackendSlot> =
+    HashMap::<BackendSlotKey, BackendSlot>::with_max_entries(BPF_MAPS_CAPACITY, 0);
+
+// The vips of ranges of ports, by the prefixes of the ports they cover. Its size is set by the
+// loader to fit the prefixes of a range of every vip.
+#[map(name = "VIP_PORT_RANGES")]
+static mut VIP_PORT_RANGES: LpmTrie<PortRangeKey, BackendKey> =
+    LpmTrie::<PortRangeKey, BackendKey>::with_max_entries(BPF_MAPS_CAPACITY, 0);
+
+// The size of VIP_ACLS is set by the loader, to fit the ACL of every vip.
+#[map(name = "VIP_ACLS")]
+static mut VIP_ACLS: LpmTrie<AclKey, u32> =
+    LpmTrie::<AclKey, u32>::with_max_entries(BPF_MAPS_CAPACITY, 0);
+
 #[map(name = "GATEWAY_INDEXES")]
 static mut GATEWAY_INDEXES: HashMap<BackendKey, u16> =
     HashMap::<BackendKey, u16>::with_max_entries(BPF_MAPS_CAPACITY, 0);
@@ -48,67 +101,363 @@ static mut GATEWAY_INDEXES: HashMap<BackendKey, u16> =
 static mut LB_CONNECTIONS: HashMap<ClientKey, LoadBalancerMapping> =
     HashMap::<ClientKey, LoadBalancerMapping>::with_max_entries(128, 0);
 
+// The clients pinned to a backend of vips with session affinity. The least recently used pins are
+// evicted when it's full, their clients are load balanced again.
+#[map(name = "AFFINITIES")]
+static mut AFFINITIES: LruHashMap<AffinityKey, Affinity> =
+    LruHashMap::<AffinityKey, Affinity>::with_max_entries(BPF_MAPS_CAPACITY, 0);
+
+// The token buckets of the clients of vips with a rate limit, sized by the loader like the
+// tracked connections. Evicted clients get a full bucket again.
+#[map(name = "RATE_LIMITS")]
+static mut RATE_LIMITS: LruHashMap<RateLimitKey, TokenBucket> =
+    LruHashMap::<RateLimitKey, TokenBucket>::with_max_entries(BPF_MAPS_CAPACITY, 0);
+
+#[map(name = "DATAPLANE_STATE")]
+static mut DATAPLANE_STATE: Array<u32> = Array::<u32>::with_max_entries(DATAPLANE_STATE_LEN, 0);
+
+#[map(name = "LOG_STATS")]
+static mut LOG_STATS: PerCpuArray<u64> = PerCpuArray::<u64>::with_max_entries(LOG_STATS_LEN, 0);
+
+#[map(name = "DROP_STATS")]
+static mut DROP_STATS: PerCpuArray<u64> = PerCpuArray::<u64>::with_max_entries(DROP_STATS_LEN, 0);
+
+#[map(name = "VIP_STATS")]
+static mut VIP_STATS: PerCpuHashMap<BackendKey, VipStats> =
+    PerCpuHashMap::<BackendKey, VipStats>::with_max_entries(BPF_MAPS_CAPACITY, 0);
+
+#[map(name = "FLOW_RECORDS")]
+static mut FLOW_RECORDS: RingBuf = RingBuf::with_byte_size(FLOW_RECORDS_BYTE_SIZE, 0);
+
+// Errors the programs failed to handle packets with, see common::ErrorEvent.
+#[map(name = "ERROR_EVENTS")]
+static mut ERROR_EVENTS: RingBuf = RingBuf::with_byte_size(ERROR_EVENTS_BYTE_SIZE, 0);
+
+#[map(name = "CAPTURE_CONFIG")]
+static mut CAPTURE_CONFIG: Array<CaptureConfig> = Array::<CaptureConfig>::with_max_entries(1, 0);
+
+// Copies of the packets of the vip being captured, each sent after a CaptureHeader.
+#[map(name = "PACKET_CAPTURES")]
+static mut PACKET_CAPTURES: PerfEventArray<CaptureHeader> = PerfEventArray::new(0);
+
+// The packet sampling of a vip set by the loader, see common::SampleConfig.
+#[map(name = "SAMPLE_CONFIG")]
+static mut SAMPLE_CONFIG: Array<SampleConfig> = Array::<SampleConfig>::with_max_entries(1, 0);
+
+// The packets of the sampled vip each CPU received, to sample every Nth of them.
+#[map(name = "SAMPLE_COUNTS")]
+static mut SAMPLE_COUNTS: PerCpuArray<u32> = PerCpuArray::<u32>::with_max_entries(1, 0);
+
+// The headers of the sampled packets of the vip, see common::PacketSample.
+#[map(name = "PACKET_SAMPLES")]
+static mut PACKET_SAMPLES: RingBuf = RingBuf::with_byte_size(PACKET_SAMPLES_BYTE_SIZE, 0);
+
+// The backends QUIC connections of vips that are load balanced by connection ID are bound to.
+// Its size is set by the loader to that of LB_CONNECTIONS.
+#[map(name = "QUIC_CONNECTIONS")]
+static mut QUIC_CONNECTIONS: LruHashMap<QuicKey, Affinity> =
+    LruHashMap::<QuicKey, Affinity>::with_max_entries(128, 0);
+
+// The clients of full NAT connections by the source port they are sent to their backend from.
+// Its size is set by the loader to that of LB_CONNECTIONS.
+#[map(name = "SNAT_CONNECTIONS")]
+static mut SNAT_CONNECTIONS: LruHashMap<SnatKey, Snat> =
+    LruHashMap::<SnatKey, Snat>::with_max_entries(128, 0);
+
+// Where the first fragments of UDP datagrams to vips were sent, by their datagram, for their
+// later fragments. Its size is set by the loader to that of LB_CONNECTIONS.
+#[map(name = "FRAGMENTS")]
+static mut FRAGMENTS: LruHashMap<FragmentKey, Fragment> =
+    LruHashMap::<FragmentKey, Fragment>::with_max_entries(128, 0);
+
+// The programs of each protocol, which tc_ingress and tc_egress tail call into so that each
+// program stays small enough for the verifier. Filled in by the loader, see common::TAIL_CALLS_LEN.
+#[map(name = "TAIL_CALLS")]
+static mut TAIL_CALLS: ProgramArray = ProgramArray::with_max_entries(TAIL_CALLS_LEN, 0);
+
+// Returns true if the dataplane is draining and new connections must not be accepted.
+#[inline(always)]
+fn is_draining() -> bool {
+    matches!(unsafe { DATAPLANE_STATE.get(STATE_DRAINING) }, Some(1))
+}
+
+// Returns true if the dataplane is in standby and must not load balance any traffic.
+#[inline(always)]
+fn is_standby() -> bool {
+    matches!(unsafe { DATAPLANE_STATE.get(STATE_STANDBY) }, Some(1))
+}
+
+// Returns the nanoseconds after which idle TCP (or UDP) connections are no longer tracked, 0
+// when they are tracked until they are closed.
+#[inline(always)]
+fn idle_timeout(tcp: bool) -> u64 {
+    let index = if tcp {
+        STATE_TCP_IDLE_TIMEOUT
+    } else {
+        STATE_UDP_IDLE_TIMEOUT
+    };
+    let seconds = unsafe { DATAPLANE_STATE.get(index) }.copied().unwrap_or(0);
+    seconds as u64 * 1_000_000_000
+}
+
+// Returns the address full NAT connections are sent to their backends from, 0 when none is set.
+#[inline(always)]
+fn snat_ip() -> u32 {
+    unsafe { DATAPLANE_STATE.get(STATE_SNAT_IP) }
+        .copied()
+        .unwrap_or(0)
+}
+
+// Returns the MSS that TCP SYN packets advertising a larger one are clamped to, 0 when they
+// aren't.
+#[inline(always)]
+fn tcp_mss_clamp() -> u16 {
+    unsafe { DATAPLANE_STATE.get(STATE_TCP_MSS_CLAMP) }.map_or(0, |mss| *mss as u16)
+}
+
+// Returns true if the FEATURE_* flag is enabled.
+#[inline(always)]
+fn feature_enabled(feature: u32) -> bool {
+    unsafe { DATAPLANE_STATE.get(STATE_FEATURES) }.is_some_and(|features| features & feature != 0)
+}
+
+// Returns the DROP_POLICY_* of the packets of vips without backends.
+#[inline(always)]
+fn drop_policy() -> u32 {
+    unsafe { DATAPLANE_STATE.get(STATE_DROP_POLICY) }
+        .copied()
+        .unwrap_or(DROP_POLICY_REJECT)
+}
+
+// Returns true if a sampled log line of the level should be emitted, and counts the lines of
+// enabled levels either way so userspace can summarize what was suppressed.
+#[inline(always)]
+fn should_log(level: u32) -> bool {
+    let max_level = unsafe { DATAPLANE_STATE.get(STATE_LOG_LEVEL) }
+        .copied()
+        .unwrap_or(LOG_LEVEL_OFF);
+    if level > max_level {
+        return false;
+    }
+    let rate = unsafe { DATAPLANE_STATE.get(STATE_LOG_SAMPLE_RATE) }
+        .copied()
+        .unwrap_or(0);
+    let emit = rate <= 1 || unsafe { bpf_get_prandom_u32() } % rate == 0;
+
+    let index = if emit {
+        LOG_STATS_EMITTED
+    } else {
+        LOG_STATS_SUPPRESSED
+    };
+    if let Some(count) = unsafe { LOG_STATS.get_ptr_mut(index) } {
+        unsafe { *count += 1 };
+    }
+    emit
+}
+
+// -----------------------------------------------------------------------------
+// Tail calls
+// -----------------------------------------------------------------------------
+
+// see include/uapi/asm-generic/errno-base.h
+const ENOENT: i64 = 2;
+
+// Tail calls into the program at the index of TAIL_CALLS, which handles the packet from there on.
+// Only returns when the tail call failed, which bpf_tail_call doesn't tell the reason of, with
+// -ENOENT since it's almost always that there's no program at the index. The programs tail called
+// into find the ip header again rather than being passed its offset, so that the verifier knows
+// its bounds.
+#[inline(always)]
+fn tail_call(ctx: &TcContext, index: u32) -> Result<i32, i64> {
+    let _ = unsafe { TAIL_CALLS.tail_call(ctx, index) };
+    Err(-ENOENT)
+}
+
 // -----------------------------------------------------------------------------
 // Ingress
 // -----------------------------------------------------------------------------
 
 #[classifier]
 pub fn tc_ingress(ctx: TcContext) -> i32 {
-    match try_tc_ingress(ctx) {
-        Ok(ret) => ret,
-        Err(_) => TC_ACT_SHOT,
-    };
+    let skb = ctx.skb.skb;
+    ingress_action(&TcContext::new(skb), try_tc_ingress(ctx))
+}
 
-    // TODO(https://github.com/Kong/blixt/issues/69) better Error reporting framework
-    TC_ACT_OK
+// The action for the packet the ingress programs handled with the result: the one the programs
+// returned, e.g. the redirect to the backend of the packet or the drop of a denied client.
+// Packets that failed to be handled are passed on, the helper errors they failed with are
+// reported on ERROR_EVENTS, not the packets too short to parse.
+#[inline(always)]
+fn ingress_action(ctx: &TcContext, result: Result<i32, i64>) -> i32 {
+    match result {
+        Ok(ret) => ret,
+        Err(err) => {
+            if err < 0 {
+                report_error(ctx, PROGRAM_TC_INGRESS, err);
+            }
+            TC_ACT_OK
+        }
+    }
 }
 
 // Make sure ip_forwarding is enabled on the interface this it attached to
 fn try_tc_ingress(ctx: TcContext) -> Result<i32, i64> {
-    let eth_hdr: *const EthHdr = unsafe { ptr_at(&ctx, 0) }?;
-    match unsafe { *eth_hdr }.ether_type {
-        EtherType::Ipv4 => {
-            let ipv4hdr: *const Ipv4Hdr = unsafe { ptr_at(&ctx, EthHdr::LEN)? };
-            match unsafe { *ipv4hdr }.proto {
-                IpProto::Tcp => handle_tcp_ingress(ctx),
-                IpProto::Udp => handle_udp_ingress(ctx),
-                _ => Ok(TC_ACT_PIPE),
-            }
-        }
+    if is_standby() {
+        return Ok(TC_ACT_PIPE);
+    }
+
+    pull_headers(&ctx)?;
+    // the ip header follows the ethernet header and the VLAN tags of tagged frames
+    let Some(l3_offset) = ipv4_offset(&ctx) else {
+        return Ok(TC_ACT_PIPE);
+    };
+    let ipv4hdr: *const Ipv4Hdr = unsafe { ptr_at(&ctx, l3_offset)? };
+    match unsafe { *ipv4hdr }.proto {
+        IpProto::Tcp => tail_call(&ctx, TAIL_CALL_INGRESS_TCP),
+        IpProto::Udp => tail_call(&ctx, TAIL_CALL_INGRESS_UDP),
+        IpProto::Sctp => tail_call(&ctx, TAIL_CALL_INGRESS_SCTP),
         _ => Ok(TC_ACT_PIPE),
     }
 }
 
+#[classifier]
+pub fn tc_ingress_tcp(ctx: TcContext) -> i32 {
+    let skb = ctx.skb.skb;
+    ingress_action(&TcContext::new(skb), try_tc_ingress_tcp(ctx))
+}
+
+fn try_tc_ingress_tcp(ctx: TcContext) -> Result<i32, i64> {
+    let Some(l3_offset) = ipv4_offset(&ctx) else {
+        return Ok(TC_ACT_PIPE);
+    };
+    if let Some(ret) = handle_snat_reply(&ctx, l3_offset, IpProto::Tcp)? {
+        return Ok(ret);
+    }
+    handle_tcp_ingress(ctx, l3_offset)
+}
+
+#[classifier]
+pub fn tc_ingress_udp(ctx: TcContext) -> i32 {
+    let skb = ctx.skb.skb;
+    ingress_action(&TcContext::new(skb), try_tc_ingress_udp(ctx))
+}
+
+fn try_tc_ingress_udp(ctx: TcContext) -> Result<i32, i64> {
+    let Some(l3_offset) = ipv4_offset(&ctx) else {
+        return Ok(TC_ACT_PIPE);
+    };
+    if let Some(ret) = handle_snat_reply(&ctx, l3_offset, IpProto::Udp)? {
+        return Ok(ret);
+    }
+    handle_udp_ingress(ctx, l3_offset)
+}
+
+// SCTP isn't full NATed, so there are no replies to SNAT connections to handle.
+#[classifier]
+pub fn tc_ingress_sctp(ctx: TcContext) -> i32 {
+    let skb = ctx.skb.skb;
+    ingress_action(&TcContext::new(skb), try_tc_ingress_sctp(ctx))
+}
+
+fn try_tc_ingress_sctp(ctx: TcContext) -> Result<i32, i64> {
+    let Some(l3_offset) = ipv4_offset(&ctx) else {
+        return Ok(TC_ACT_PIPE);
+    };
+    handle_sctp_ingress(ctx, l3_offset)
+}
+
 // -----------------------------------------------------------------------------
 // Egress
 // -----------------------------------------------------------------------------
 
 #[classifier]
 pub fn tc_egress(ctx: TcContext) -> i32 {
-    match try_tc_egress(ctx) {
-        Ok(ret) => ret,
-        Err(_) => TC_ACT_SHOT,
-    };
-
-    // TODO(https://github.com/Kong/blixt/issues/69) better Error reporting framework
-    TC_ACT_OK
+    let skb = ctx.skb.skb;
+    egress_action(&TcContext::new(skb), try_tc_egress(ctx))
 }
 
-fn try_tc_egress(ctx: TcContext) -> Result<i32, i64> {
-    let eth_hdr: *const EthHdr = unsafe { ptr_at(&ctx, 0) }?;
-    match unsafe { *eth_hdr }.ether_type {
-        EtherType::Ipv4 => {
-            let ipv4hdr: *const Ipv4Hdr = unsafe { ptr_at(&ctx, EthHdr::LEN)? };
-            match unsafe { *ipv4hdr }.proto {
-                IpProto::Icmp => handle_icmp_egress(ctx),
-                IpProto::Tcp => handle_tcp_egress(ctx),
-                _ => Ok(TC_ACT_PIPE),
+// The action for the packet the egress programs handled with the result, see ingress_action.
+#[inline(always)]
+fn egress_action(ctx: &TcContext, result: Result<i32, i64>) -> i32 {
+    match result {
+        Ok(ret) => ret,
+        Err(err) => {
+            if err < 0 {
+                report_error(ctx, PROGRAM_TC_EGRESS, err);
             }
+            TC_ACT_OK
         }
+    }
+}
+
+fn try_tc_egress(ctx: TcContext) -> Result<i32, i64> {
+    pull_headers(&ctx)?;
+    // the ip header follows the ethernet header and the VLAN tags of tagged frames
+    let Some(l3_offset) = ipv4_offset(&ctx) else {
+        return Ok(TC_ACT_PIPE);
+    };
+    let ipv4hdr: *const Ipv4Hdr = unsafe { ptr_at(&ctx, l3_offset)? };
+    match unsafe { *ipv4hdr }.proto {
+        IpProto::Icmp => tail_call(&ctx, TAIL_CALL_EGRESS_ICMP),
+        IpProto::Tcp => tail_call(&ctx, TAIL_CALL_EGRESS_TCP),
+        IpProto::Udp => tail_call(&ctx, TAIL_CALL_EGRESS_UDP),
+        IpProto::Sctp => tail_call(&ctx, TAIL_CALL_EGRESS_SCTP),
         _ => Ok(TC_ACT_PIPE),
     }
 }
 
+#[classifier]
+pub fn tc_egress_icmp(ctx: TcContext) -> i32 {
+    let skb = ctx.skb.skb;
+    egress_action(&TcContext::new(skb), try_tc_egress_icmp(ctx))
+}
+
+fn try_tc_egress_icmp(ctx: TcContext) -> Result<i32, i64> {
+    let Some(l3_offset) = ipv4_offset(&ctx) else {
+        return Ok(TC_ACT_PIPE);
+    };
+    handle_icmp_egress(ctx, l3_offset)
+}
+
+#[classifier]
+pub fn tc_egress_tcp(ctx: TcContext) -> i32 {
+    let skb = ctx.skb.skb;
+    egress_action(&TcContext::new(skb), try_tc_egress_tcp(ctx))
+}
+
+fn try_tc_egress_tcp(ctx: TcContext) -> Result<i32, i64> {
+    let Some(l3_offset) = ipv4_offset(&ctx) else {
+        return Ok(TC_ACT_PIPE);
+    };
+    handle_tcp_egress(ctx, l3_offset)
+}
+
+#[classifier]
+pub fn tc_egress_udp(ctx: TcContext) -> i32 {
+    let skb = ctx.skb.skb;
+    egress_action(&TcContext::new(skb), try_tc_egress_udp(ctx))
+}
+
+fn try_tc_egress_udp(ctx: TcContext) -> Result<i32, i64> {
+    let Some(l3_offset) = ipv4_offset(&ctx) else {
+        return Ok(TC_ACT_PIPE);
+    };
+    handle_udp_egress(ctx, l3_offset)
+}
+
+#[classifier]
+pub fn tc_egress_sctp(ctx: TcContext) -> i32 {
+    let skb = ctx.skb.skb;
+    egress_action(&TcContext::new(skb), try_tc_egress_sctp(ctx))
+}
+
+fn try_tc_egress_sctp(ctx: TcContext) -> Result<i32, i64> {
+    let Some(l3_offset) = ipv4_offset(&ctx) else {
+        return Ok(TC_ACT_PIPE);
+    };
+    handle_sctp_egress(ctx, l3_offset)
+}
+
 // -----------------------------------------------------------------------------
 // Panic Implementation
 // -----------------------------------------------------------------------------
@@ -5,8 +5,11 @@ SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
 */
 
 use aya_ebpf::{
-    bindings::TC_ACT_OK,
-    helpers::{bpf_l3_csum_replace, bpf_l4_csum_replace, bpf_skb_store_bytes},
+    bindings::{bpf_fib_lookup as FibLookupParams, TC_ACT_OK, TC_ACT_SHOT},
+    helpers::{
+        bpf_clone_redirect, bpf_fib_lookup, bpf_ktime_get_ns, bpf_l3_csum_replace,
+        bpf_l4_csum_replace, bpf_redirect, bpf_redirect_neigh, bpf_skb_store_bytes,
+    },
     programs::TcContext,
 };
 use aya_ebpf_cty::{c_long, c_void};
@@ -14,15 +17,45 @@ use aya_log_ebpf::info;
 use core::mem;
 use network_types::{eth::EthHdr, ip::Ipv4Hdr, tcp::TcpHdr};
 
-use crate::LB_CONNECTIONS;
-use common::{ClientKey, LoadBalancerMapping, TCPState};
+use crate::{
+    feature_enabled, idle_timeout, tcp_mss_clamp, CAPTURE_CONFIG, DROP_STATS, ERROR_EVENTS,
+    FLOW_RECORDS, LB_CONNECTIONS, PACKET_CAPTURES, PACKET_SAMPLES, SAMPLE_CONFIG, SAMPLE_COUNTS,
+    VIP_STATS,
+};
+use common::{
+    dscp::marked_tos,
+    headers::{MAX_HEADERS_LEN, MAX_VLAN_TAGS, VLAN_TAG_LEN},
+    mss::{mss_offset, TCP_MAX_OPTIONS_LEN},
+    sctp::{update_checksum, SctpHdr},
+    Backend, BackendKey, CaptureHeader, ClientKey, ErrorEvent, FlowRecord, LoadBalancerMapping,
+    PacketSample, TCPState, VipStats, CAPTURE_MAX_SNAPLEN, CAPTURE_STAGE_RECEIVED,
+    DROP_STATS_NO_ROUTE, DROP_STATS_REDIRECT_FAILED, DROP_STATS_REWRITE_FAILED,
+    FEATURE_FLOW_RECORDS, FEATURE_VIP_STATS,
+};
 
 use memoffset::offset_of;
 
-const IP_CSUM_OFF: u32 = (EthHdr::LEN + offset_of!(Ipv4Hdr, check)) as u32;
-const IP_DST_OFF: u32 = (EthHdr::LEN + offset_of!(Ipv4Hdr, dst_addr)) as u32;
 const IS_PSEUDO: u64 = 0x10;
 
+// The ethertypes of IPv4, of 802.1Q VLAN tags and of the outer tags of 802.1ad (QinQ) frames.
+const ETH_P_IP: u16 = 0x0800;
+const ETH_P_8021Q: u16 = 0x8100;
+const ETH_P_8021AD: u16 = 0x88a8;
+// A VLAN tag is its tag control information, followed by the ethertype of what follows the tag.
+const VLAN_ETHER_TYPE_OFF: usize = 2;
+
+const AF_INET: u8 = 2;
+const BPF_FIB_LKUP_RET_SUCCESS: c_long = 0;
+const BPF_FIB_LKUP_RET_NO_NEIGH: c_long = 7;
+
+// The ports both TCP and UDP headers start with, which are all of them that are sure to be
+// quoted by ICMP errors.
+#[repr(C)]
+pub struct L4Ports {
+    pub source: u16,
+    pub dest: u16,
+}
+
 // -----------------------------------------------------------------------------
 // Helper Functions
 // -----------------------------------------------------------------------------
@@ -40,6 +73,39 @@ pub unsafe fn ptr_at<T>(ctx: &TcContext, offset: usize) -> Result<*mut T, i64> {
     Ok((start + offset) as *mut T)
 }
 
+// Pulls the headers of the packet into the linear data of its skb, where ptr_at reaches them,
+// sized for the most options the ip and TCP headers can have.
+// The headers of large packets, like those aggregated by GRO, can be in its paged data instead,
+// and those packets would otherwise bypass the load balancer. Packets with linear headers, most
+// of them, are left as is, since pulling would copy the ones that are cloned.
+#[inline(always)]
+pub fn pull_headers(ctx: &TcContext) -> Result<(), c_long> {
+    let len = MAX_HEADERS_LEN.min(ctx.len() as usize);
+    if ctx.data() + len <= ctx.data_end() {
+        return Ok(());
+    }
+    ctx.pull_data(len as u32)
+}
+
+// Returns the offset of the ip header of an IPv4 packet: past its ethernet header, and the VLAN
+// tags of single tagged or QinQ frames. None for packets of other protocols, or with more tags.
+#[inline(always)]
+pub fn ipv4_offset(ctx: &TcContext) -> Option<usize> {
+    // read as numbers, since the ethertypes of VLAN tags aren't EtherType variants
+    let mut ether_type =
+        u16::from_be(unsafe { *ptr_at::<u16>(ctx, offset_of!(EthHdr, ether_type)).ok()? });
+    let mut offset = EthHdr::LEN;
+    for _ in 0..MAX_VLAN_TAGS {
+        if ether_type != ETH_P_8021Q && ether_type != ETH_P_8021AD {
+            break;
+        }
+        ether_type =
+            u16::from_be(unsafe { *ptr_at::<u16>(ctx, offset + VLAN_ETHER_TYPE_OFF).ok()? });
+        offset += VLAN_TAG_LEN;
+    }
+    (ether_type == ETH_P_IP).then_some(offset)
+}
+
 // Converts a checksum into u16
 #[inline(always)]
 pub fn csum_fold_helper(mut csum: u64) -> u16 {
@@ -121,6 +187,7 @@ pub fn update_tcp_conns(
     if let Some(ref mut tcp_state) = lb_mapping.tcp_state {
         let transitioned = process_tcp_state_transition(hdr, tcp_state);
         if let TCPState::Closed = tcp_state {
+            record_flow(client_key, lb_mapping);
             unsafe {
                 return LB_CONNECTIONS.remove(client_key);
             }
@@ -133,44 +200,300 @@ pub fn update_tcp_conns(
             }
         }
     }
+    // Otherwise only the counters changed, which are updated in place to avoid a map update on
+    // every packet. Connections that aren't tracked yet are inserted by the caller.
+    if let Some(mapping) = unsafe { LB_CONNECTIONS.get_ptr_mut(client_key) } {
+        unsafe {
+            (*mapping).packets = lb_mapping.packets;
+            (*mapping).bytes = lb_mapping.bytes;
+            (*mapping).last_seen = lb_mapping.last_seen;
+        }
+    }
     Ok(())
 }
 
+// Returns the tracked connection of the client, unless it has been idle for longer than the idle
+// timeout of its protocol, in which case it's recorded and no longer tracked, so that the client
+// is load balanced like a new one.
+#[inline(always)]
+pub fn tracked_connection(client_key: &ClientKey) -> Option<LoadBalancerMapping> {
+    let mapping = *unsafe { LB_CONNECTIONS.get(client_key) }?;
+    let timeout = idle_timeout(mapping.tcp_state.is_some());
+    let idle = unsafe { bpf_ktime_get_ns() }.saturating_sub(mapping.last_seen);
+    if timeout == 0 || idle <= timeout {
+        return Some(mapping);
+    }
+    record_flow(client_key, &mapping);
+    let _ = unsafe { LB_CONNECTIONS.remove(client_key) };
+    None
+}
+
+// Counts a packet of the vip in the counters of the current CPU, which userspace sums up.
+#[inline(always)]
+pub fn count_vip_packet(backend_key: &BackendKey, bytes: u64) {
+    if !feature_enabled(FEATURE_VIP_STATS) {
+        return;
+    }
+    if let Some(stats) = unsafe { VIP_STATS.get_ptr_mut(backend_key) } {
+        unsafe {
+            (*stats).packets += 1;
+            (*stats).bytes += bytes;
+        }
+    } else {
+        let stats = VipStats {
+            packets: 1,
+            bytes,
+            ..Default::default()
+        };
+        let _ = unsafe { VIP_STATS.insert(backend_key, &stats, 0) };
+    }
+}
+
+// Records the latency of the first reply of the tracked connection of the client in the
+// histogram of its vip on the current CPU, and forgets when its request was forwarded so that
+// later replies aren't measured.
+#[inline(always)]
+pub fn record_reply_latency(client_key: &ClientKey, lb_mapping: &mut LoadBalancerMapping) {
+    if lb_mapping.request_ns == 0 {
+        return;
+    }
+    let latency = unsafe { bpf_ktime_get_ns() }.saturating_sub(lb_mapping.request_ns);
+    lb_mapping.request_ns = 0;
+    if let Some(mapping) = unsafe { LB_CONNECTIONS.get_ptr_mut(client_key) } {
+        unsafe { (*mapping).request_ns = 0 };
+    }
+
+    if !feature_enabled(FEATURE_VIP_STATS) {
+        return;
+    }
+    if let Some(stats) = unsafe { VIP_STATS.get_ptr_mut(&lb_mapping.backend_key) } {
+        unsafe { (*stats).latency.observe(latency) };
+    } else {
+        let mut stats = VipStats::default();
+        stats.latency.observe(latency);
+        let _ = unsafe { VIP_STATS.insert(&lb_mapping.backend_key, &stats, 0) };
+    }
+}
+
+// Copies the packet to userspace if a capture of the vip is running, at most the configured
+// snaplen of it. When userspace doesn't keep up the copy is lost, but the packet is still
+// forwarded.
+#[inline(always)]
+pub fn capture_packet(ctx: &TcContext, backend_key: &BackendKey, stage: u32, ifindex: u32) {
+    sample_packet(ctx, backend_key, stage, ifindex);
+    let config = match unsafe { CAPTURE_CONFIG.get(0) } {
+        Some(config) if config.enabled == 1 && config.backend_key == *backend_key => config,
+        _ => return,
+    };
+    let len = ctx.len();
+    let header = CaptureHeader {
+        timestamp: unsafe { bpf_ktime_get_ns() },
+        len,
+        captured_len: len.min(config.snaplen).min(CAPTURE_MAX_SNAPLEN),
+        stage,
+        ifindex,
+    };
+    // the upper bits of the flags are the number of bytes of the packet appended to the header
+    unsafe { PACKET_CAPTURES.output(ctx, &header, header.captured_len) };
+}
+
+// Copies the headers of every Nth packet of the vip sampled by the loader to userspace, at both
+// stages: the count of the CPU is only incremented as packets are received, and the program
+// handles the packet on the same CPU until it's rewritten. When the ring buffer is full the
+// sample is lost, but the packet is still forwarded.
+#[inline(always)]
+fn sample_packet(ctx: &TcContext, backend_key: &BackendKey, stage: u32, ifindex: u32) {
+    match unsafe { SAMPLE_CONFIG.get(0) } {
+        Some(config)
+            if config.rate != 0
+                && config.ip == backend_key.ip
+                && config.port == backend_key.port
+                && config.proto == backend_key.proto =>
+        {
+            let Some(count) = (unsafe { SAMPLE_COUNTS.get_ptr_mut(0) }) else {
+                return;
+            };
+            if stage == CAPTURE_STAGE_RECEIVED {
+                unsafe { *count = (*count).wrapping_add(1) };
+            }
+            if unsafe { *count } % config.rate != 0 {
+                return;
+            }
+        }
+        _ => return,
+    }
+
+    let Some(mut entry) = (unsafe { PACKET_SAMPLES.reserve::<PacketSample>(0) }) else {
+        return;
+    };
+    let sample = entry.as_mut_ptr();
+    let Ok(captured_len) = ctx.load_bytes(0, unsafe { &mut (*sample).data }) else {
+        entry.discard(0);
+        return;
+    };
+    unsafe {
+        (*sample).header = CaptureHeader {
+            timestamp: bpf_ktime_get_ns(),
+            len: ctx.len(),
+            captured_len: captured_len as u32,
+            stage,
+            ifindex,
+        }
+    };
+    entry.submit(0);
+}
+
+// Sends a record of the traffic a connection carried to userspace, for accounting. When the
+// ring buffer is full the record is lost, but the packet is still forwarded.
+pub fn record_flow(client_key: &ClientKey, lb_mapping: &LoadBalancerMapping) {
+    if !feature_enabled(FEATURE_FLOW_RECORDS) {
+        return;
+    }
+    let record = FlowRecord {
+        client_key: *client_key,
+        backend_key: lb_mapping.backend_key,
+        backend: lb_mapping.backend,
+        packets: lb_mapping.packets,
+        bytes: lb_mapping.bytes,
+    };
+    let _ = unsafe { FLOW_RECORDS.output(&record, 0) };
+}
+
+// Counts a packet to a vip that wasn't load balanced for the reason, one of the DROP_STATS_
+// indexes.
+#[inline(always)]
+pub fn count_drop(reason: u32) {
+    if let Some(count) = unsafe { DROP_STATS.get_ptr_mut(reason) } {
+        unsafe { *count += 1 };
+    }
+}
+
+// Reports that the program failed to handle the packet on ERROR_EVENTS. Events are lost while the
+// ring buffer is full.
+pub fn report_error(ctx: &TcContext, program: u32, error: i64) {
+    let mut event = ErrorEvent {
+        program,
+        proto: 0,
+        src_addr: 0,
+        dst_addr: 0,
+        error,
+    };
+    let ip_hdr = ipv4_offset(ctx).and_then(|offset| unsafe { ptr_at::<Ipv4Hdr>(ctx, offset) }.ok());
+    if let Some(ip_hdr) = ip_hdr {
+        unsafe {
+            event.proto = (*ip_hdr).proto as u32;
+            event.src_addr = u32::from_be((*ip_hdr).src_addr);
+            event.dst_addr = u32::from_be((*ip_hdr).dst_addr);
+        }
+    }
+    let _ = unsafe { ERROR_EVENTS.output(&event, 0) };
+}
+
 // inspired by https://github.com/torvalds/linux/blob/master/samples/bpf/tcbpf1_kern.c
 // update dst_addr in the ip_hdr
 // recalculate the checksums
-pub fn set_ipv4_ip_dst(ctx: &TcContext, l4_csum_offset: u32, old_ip: &u32, new_dip: u32) -> c_long {
+// Like every rewrite of the datapath, this leaves the type of service byte alone: the DSCP and
+// ECN bits the client sent reach the backend, which ECN capable transports (e.g. QUIC) rely on.
+// The ip header is at l3_offset, past the ethernet header and any VLAN tags.
+pub fn set_ipv4_ip_dst(
+    ctx: &TcContext,
+    l3_offset: usize,
+    l4_csum_offset: u32,
+    old_ip: &u32,
+    new_dip: u32,
+) -> c_long {
+    let ip_offset = l3_offset + offset_of!(Ipv4Hdr, dst_addr);
+    set_ipv4_ip(ctx, l3_offset, l4_csum_offset, ip_offset, old_ip, new_dip)
+}
+
+// update src_addr in the ip_hdr
+// recalculate the checksums
+pub fn set_ipv4_ip_src(
+    ctx: &TcContext,
+    l3_offset: usize,
+    l4_csum_offset: u32,
+    old_ip: &u32,
+    new_sip: u32,
+) -> c_long {
+    let ip_offset = l3_offset + offset_of!(Ipv4Hdr, src_addr);
+    set_ipv4_ip(ctx, l3_offset, l4_csum_offset, ip_offset, old_ip, new_sip)
+}
+
+fn set_ipv4_ip(
+    ctx: &TcContext,
+    l3_offset: usize,
+    l4_csum_offset: u32,
+    ip_offset: usize,
+    old_ip: &u32,
+    new_ip: u32,
+) -> c_long {
     let mut ret: c_long;
     unsafe {
         ret = bpf_l4_csum_replace(
             ctx.skb.skb,
             l4_csum_offset,
             *old_ip as u64,
-            new_dip as u64,
-            IS_PSEUDO | (mem::size_of_val(&new_dip) as u64),
+            new_ip as u64,
+            IS_PSEUDO | (mem::size_of_val(&new_ip) as u64),
         );
     }
     if ret != 0 {
         info!(
             ctx,
-            "Failed to update the TCP checksum after modifying the destination IP"
+            "Failed to update the L4 checksum after modifying an IP address"
         );
         return ret;
     }
 
+    set_ipv4_header_ip(ctx, l3_offset, ip_offset, old_ip, new_ip)
+}
+
+// update dst_addr in the ip_hdr of a fragment without an L4 header, whose checksum was updated
+// along with the first fragment's, or of an SCTP packet, whose checksum doesn't cover it
+// recalculate the ip header checksum
+pub fn set_fragment_ip_dst(
+    ctx: &TcContext,
+    l3_offset: usize,
+    old_ip: &u32,
+    new_dip: u32,
+) -> c_long {
+    let ip_offset = l3_offset + offset_of!(Ipv4Hdr, dst_addr);
+    set_ipv4_header_ip(ctx, l3_offset, ip_offset, old_ip, new_dip)
+}
+
+// update src_addr in the ip_hdr of a fragment without an L4 header, or of an SCTP packet
+// recalculate the ip header checksum
+pub fn set_fragment_ip_src(
+    ctx: &TcContext,
+    l3_offset: usize,
+    old_ip: &u32,
+    new_sip: u32,
+) -> c_long {
+    let ip_offset = l3_offset + offset_of!(Ipv4Hdr, src_addr);
+    set_ipv4_header_ip(ctx, l3_offset, ip_offset, old_ip, new_sip)
+}
+
+fn set_ipv4_header_ip(
+    ctx: &TcContext,
+    l3_offset: usize,
+    ip_offset: usize,
+    old_ip: &u32,
+    new_ip: u32,
+) -> c_long {
+    let mut ret: c_long;
     unsafe {
         ret = bpf_l3_csum_replace(
             ctx.skb.skb,
-            IP_CSUM_OFF,
+            (l3_offset + offset_of!(Ipv4Hdr, check)) as u32,
             *old_ip as u64,
-            new_dip as u64,
-            mem::size_of_val(&new_dip) as u64,
+            new_ip as u64,
+            mem::size_of_val(&new_ip) as u64,
         );
     }
     if ret != 0 {
         info!(
             ctx,
-            "Failed to update the IP header checksum after modifying the destination IP"
+            "Failed to update the IP header checksum after modifying an IP address"
         );
         return ret;
     }
@@ -178,28 +501,26 @@ pub fn set_ipv4_ip_dst(ctx: &TcContext, l4_csum_offset: u32, old_ip: &u32, new_d
     unsafe {
         ret = bpf_skb_store_bytes(
             ctx.skb.skb,
-            IP_DST_OFF,
-            &new_dip as *const u32 as *const c_void,
-            mem::size_of_val(&new_dip) as u32,
+            ip_offset as u32,
+            &new_ip as *const u32 as *const c_void,
+            mem::size_of_val(&new_ip) as u32,
             0,
         );
     }
     if ret != 0 {
-        info!(
-            ctx,
-            "Failed to update the destination IP address in the packet header"
-        );
+        info!(ctx, "Failed to update an IP address in the packet header");
         return ret;
     }
 
     ret
 }
 
-// update destination port in the tcp_hdr
-// recalculate the checksums
-pub fn set_ipv4_dest_port(
+// update the tcp or udp port at port_offset, either port of the header
+// recalculate the checksum
+pub fn set_ipv4_port(
     ctx: &TcContext,
     l4_csum_offset: u32,
+    port_offset: u32,
     old_port: &u16,
     new_port: u16,
 ) -> c_long {
@@ -216,7 +537,7 @@ pub fn set_ipv4_dest_port(
     if ret != 0 {
         info!(
             ctx,
-            "Failed to update the TCP checksum after modifying the destination port"
+            "Failed to update the L4 checksum after modifying a port"
         );
         return ret;
     }
@@ -224,19 +545,340 @@ pub fn set_ipv4_dest_port(
     unsafe {
         ret = bpf_skb_store_bytes(
             ctx.skb.skb,
-            l4_csum_offset,
+            port_offset,
             &new_port as *const u16 as *const c_void,
             mem::size_of_val(&new_port) as u32,
             0,
         );
     }
     if ret != 0 {
-        info!(
-            ctx,
-            "Failed to update the destination port in the packet header"
-        );
+        info!(ctx, "Failed to update a port in the packet header");
         return ret;
     }
 
     ret
 }
+
+// Rewrites the ports of the SCTP header at sctp_offset to source and dest, in network byte order,
+// updating the checksum of the SCTP packet of len bytes, see common::sctp. A zero checksum is left
+// alone: the packet's checksum is offloaded, and computed when it's sent over the rewritten ports.
+#[inline(always)]
+pub fn set_sctp_ports(
+    ctx: &TcContext,
+    sctp_offset: usize,
+    len: u16,
+    source: u16,
+    dest: u16,
+) -> Result<(), c_long> {
+    let sctp_hdr: *mut SctpHdr = unsafe { ptr_at(ctx, sctp_offset)? };
+    let (old_source, old_dest, checksum) =
+        unsafe { ((*sctp_hdr).source, (*sctp_hdr).dest, (*sctp_hdr).checksum) };
+    if (old_source, old_dest) == (source, dest) {
+        return Ok(());
+    }
+
+    let ports = |source: u16, dest: u16| {
+        let (source, dest) = (source.to_ne_bytes(), dest.to_ne_bytes());
+        [source[0], source[1], dest[0], dest[1]]
+    };
+    unsafe {
+        (*sctp_hdr).source = source;
+        (*sctp_hdr).dest = dest;
+        if checksum != 0 {
+            (*sctp_hdr).checksum = update_checksum(
+                u32::from_le(checksum),
+                ports(old_source, old_dest),
+                ports(source, dest),
+                len,
+            )
+            .to_le();
+        }
+    }
+    Ok(())
+}
+
+// Lowers the MSS that a TCP SYN packet advertises to the one of STATE_TCP_MSS_CLAMP when it's
+// larger, so that the segments sent in reply fit the links with a lower MTU on the way. The TCP
+// header is at tcp_offset.
+#[inline(always)]
+pub fn clamp_mss(ctx: &TcContext, tcp_offset: usize) -> Result<(), c_long> {
+    let max_mss = tcp_mss_clamp();
+    if max_mss == 0 {
+        return Ok(());
+    }
+    let tcp_hdr: *const TcpHdr = unsafe { ptr_at(ctx, tcp_offset)? };
+    let (syn, tcp_len) = unsafe { ((*tcp_hdr).syn(), (*tcp_hdr).doff() as usize * 4) };
+    let options_len = tcp_len.saturating_sub(TcpHdr::LEN).min(TCP_MAX_OPTIONS_LEN);
+    if syn == 0 || options_len == 0 {
+        return Ok(());
+    }
+
+    let mut options = [0u8; TCP_MAX_OPTIONS_LEN];
+    ctx.load_bytes(tcp_offset + TcpHdr::LEN, &mut options[..options_len])?;
+    let Some(offset) = mss_offset(&options, options_len) else {
+        return Ok(());
+    };
+    let Some(&[high, low]) = options.get(offset..offset + 2) else {
+        return Ok(());
+    };
+    let mss = u16::from_be_bytes([high, low]);
+    if mss <= max_mss {
+        return Ok(());
+    }
+
+    let ret = unsafe {
+        bpf_l4_csum_replace(
+            ctx.skb.skb,
+            (tcp_offset + offset_of!(TcpHdr, check)) as u32,
+            mss.to_be() as u64,
+            max_mss.to_be() as u64,
+            mem::size_of::<u16>() as u64,
+        )
+    };
+    if ret != 0 {
+        return Err(ret);
+    }
+    let ret = unsafe {
+        bpf_skb_store_bytes(
+            ctx.skb.skb,
+            (tcp_offset + TcpHdr::LEN + offset) as u32,
+            &max_mss.to_be() as *const u16 as *const c_void,
+            mem::size_of::<u16>() as u32,
+            0,
+        )
+    };
+    if ret != 0 {
+        return Err(ret);
+    }
+    Ok(())
+}
+
+// Marks the packet with the DSCP of its vip, if the vip has one, fixing up the ip header
+// checksum. The ip header is at l3_offset.
+#[inline(always)]
+pub fn set_dscp(ctx: &TcContext, l3_offset: usize, dscp: Option<u8>) -> Result<(), c_long> {
+    let Some(dscp) = dscp else {
+        return Ok(());
+    };
+    // the TOS byte is checksummed along with the byte before it, the version and header length
+    let mut word = [0u8; 2];
+    ctx.load_bytes(l3_offset, &mut word)?;
+    let tos = marked_tos(word[1], dscp);
+    if tos == word[1] {
+        return Ok(());
+    }
+    let new_word = [word[0], tos];
+
+    let ret = unsafe {
+        bpf_l3_csum_replace(
+            ctx.skb.skb,
+            (l3_offset + offset_of!(Ipv4Hdr, check)) as u32,
+            u16::from_ne_bytes(word) as u64,
+            u16::from_ne_bytes(new_word) as u64,
+            mem::size_of::<u16>() as u64,
+        )
+    };
+    if ret != 0 {
+        return Err(ret);
+    }
+    let ret = unsafe {
+        bpf_skb_store_bytes(
+            ctx.skb.skb,
+            (l3_offset + offset_of!(Ipv4Hdr, tos)) as u32,
+            &tos as *const u8 as *const c_void,
+            mem::size_of::<u8>() as u32,
+            0,
+        )
+    };
+    if ret != 0 {
+        return Err(ret);
+    }
+    Ok(())
+}
+
+// The interface a packet is sent out of to reach its destination, and whether its ethernet
+// header is already addressed to the next hop.
+pub struct NextHop {
+    pub ifindex: u32,
+    pub resolved: bool,
+}
+
+// Returns the next hop of the packet, which must already be addressed to its backend: the
+// interface of the backend when it was programmed with one, or else the interface the host routes
+// the packet through. Routed packets get the ethernet header of the next hop when its neighbor is
+// known. None when there is no route to the backend.
+//
+// The VLAN tags of the packet are kept, so the backends of tagged packets must be on the same
+// VLAN as their clients.
+#[inline(always)]
+pub fn next_hop(ctx: &TcContext, l3_offset: usize, ifindex: u16) -> Option<NextHop> {
+    if ifindex != 0 {
+        return Some(NextHop {
+            ifindex: ifindex as u32,
+            resolved: false,
+        });
+    }
+
+    let ip_hdr: *const Ipv4Hdr = unsafe { ptr_at(ctx, l3_offset) }.ok()?;
+    let (ret, params) = fib_lookup(ctx, l3_offset, unsafe { (*ip_hdr).dst_addr })?;
+    match ret {
+        BPF_FIB_LKUP_RET_SUCCESS => {
+            let eth_hdr: *mut EthHdr = unsafe { ptr_at(ctx, 0) }.ok()?;
+            unsafe {
+                (*eth_hdr).src_addr = params.smac;
+                (*eth_hdr).dst_addr = params.dmac;
+            }
+            Some(NextHop {
+                ifindex: params.ifindex,
+                resolved: true,
+            })
+        }
+        // the kernel resolves the neighbor when redirecting
+        BPF_FIB_LKUP_RET_NO_NEIGH => Some(NextHop {
+            ifindex: params.ifindex,
+            resolved: false,
+        }),
+        _ => {
+            count_drop(DROP_STATS_NO_ROUTE);
+            None
+        }
+    }
+}
+
+// Looks up the route of the packet in the host's FIB as if it was addressed to daddr, in network
+// byte order.
+#[inline(always)]
+fn fib_lookup(ctx: &TcContext, l3_offset: usize, daddr: u32) -> Option<(c_long, FibLookupParams)> {
+    let ip_hdr: *const Ipv4Hdr = unsafe { ptr_at(ctx, l3_offset) }.ok()?;
+    let mut params: FibLookupParams = unsafe { mem::zeroed() };
+    unsafe {
+        params.family = AF_INET;
+        params.l4_protocol = (*ip_hdr).proto as u8;
+        params.ifindex = (*ctx.skb.skb).ifindex;
+        params.__bindgen_anon_1.tot_len = u16::from_be((*ip_hdr).tot_len);
+        params.__bindgen_anon_2.tos = (*ip_hdr).tos;
+        params.__bindgen_anon_3.ipv4_src = (*ip_hdr).src_addr;
+        params.__bindgen_anon_4.ipv4_dst = daddr;
+    }
+    let ret = unsafe {
+        bpf_fib_lookup(
+            ctx.skb.skb as *mut c_void,
+            &mut params,
+            mem::size_of::<FibLookupParams>() as i32,
+            0,
+        )
+    };
+    Some((ret, params))
+}
+
+// Returns true if the backend is reached through the interface the packet arrived on, i.e. the
+// packet hairpins: the client and the backend are behind the same interface, as when a pod
+// connects to a Gateway whose backend runs on the same node. The backend would then reply to the
+// client directly rather than through the dataplane, so the reply wouldn't come from the vip.
+#[inline(always)]
+pub fn is_hairpin(ctx: &TcContext, l3_offset: usize, backend: &Backend) -> bool {
+    let ingress_ifindex = unsafe { (*ctx.skb.skb).ifindex };
+    if backend.ifindex != 0 {
+        return backend.ifindex as u32 == ingress_ifindex;
+    }
+    match fib_lookup(ctx, l3_offset, backend.daddr.to_be()) {
+        Some((BPF_FIB_LKUP_RET_SUCCESS | BPF_FIB_LKUP_RET_NO_NEIGH, params)) => {
+            params.ifindex == ingress_ifindex
+        }
+        _ => false,
+    }
+}
+
+// Sends the packet out of the interface of the next hop, resolving its neighbor unless the
+// ethernet header is already addressed to it.
+#[inline(always)]
+pub fn redirect_to(hop: &NextHop) -> c_long {
+    let action = if hop.resolved {
+        unsafe { bpf_redirect(hop.ifindex, 0) }
+    } else {
+        unsafe { bpf_redirect_neigh(hop.ifindex, mem::MaybeUninit::zeroed().assume_init(), 0, 0) }
+    };
+    if action == TC_ACT_SHOT as c_long {
+        count_drop(DROP_STATS_REDIRECT_FAILED);
+    }
+    action
+}
+
+// rewrite the destination to the mirror backend and send a copy of the packet to it. The
+// destination port is at dport_offset, with the checksum of the tcp or udp header at
+// l4_csum_offset. daddr and dport are updated to the mirror's as they are rewritten, as the
+// packet itself stays addressed to the mirror. When mirroring fails they are left at what the
+// packet is addressed to, so that the caller still rewrites it to its backend from there.
+// Pointers into the packet must be reloaded afterwards, since cloning invalidates them.
+pub fn mirror_packet(
+    ctx: &TcContext,
+    l3_offset: usize,
+    l4_csum_offset: u32,
+    dport_offset: u32,
+    daddr: &mut u32,
+    dport: &mut u16,
+    mirror: &Backend,
+) -> c_long {
+    let mirror_ip = mirror.daddr.to_be();
+    let ret = set_ipv4_ip_dst(ctx, l3_offset, l4_csum_offset, daddr, mirror_ip);
+    if ret != 0 {
+        count_drop(DROP_STATS_REWRITE_FAILED);
+        return ret;
+    }
+    *daddr = mirror_ip;
+
+    let mirror_port = (mirror.dport as u16).to_be();
+    let ret = set_ipv4_port(ctx, l4_csum_offset, dport_offset, dport, mirror_port);
+    if ret != 0 {
+        count_drop(DROP_STATS_REWRITE_FAILED);
+        return ret;
+    }
+    *dport = mirror_port;
+
+    // The copy is sent as it is, without the neighbor resolution of bpf_redirect_neigh, so its
+    // ethernet header is addressed to the next hop of the mirror before cloning, and the packet
+    // gets its own addresses back afterwards.
+    let Ok(eth_hdr) = (unsafe { ptr_at::<EthHdr>(ctx, 0) }) else {
+        return 0;
+    };
+    let (src_addr, dst_addr) = unsafe { ((*eth_hdr).src_addr, (*eth_hdr).dst_addr) };
+    let Some(ifindex) = mirror_hop(ctx, l3_offset) else {
+        count_drop(DROP_STATS_REDIRECT_FAILED);
+        info!(ctx, "No resolved route to the mirror backend");
+        return 0;
+    };
+    let ret = unsafe { bpf_clone_redirect(ctx.skb.skb, ifindex, 0) };
+    if ret != 0 {
+        count_drop(DROP_STATS_REDIRECT_FAILED);
+        info!(ctx, "Failed to clone the packet to the mirror backend");
+    }
+
+    // cloning invalidated the pointers into the packet
+    if let Ok(eth_hdr) = unsafe { ptr_at::<EthHdr>(ctx, 0) } {
+        unsafe {
+            (*eth_hdr).src_addr = src_addr;
+            (*eth_hdr).dst_addr = dst_addr;
+        }
+    }
+    ret
+}
+
+// Addresses the ethernet header of the packet, already addressed to the mirror backend, to the
+// next hop of the mirror, and returns the interface it's reached through. The mirror is always
+// reached through the host's routes, even when it has an ifindex, since the copy needs the
+// ethernet address of its next hop. None when there is no route to the mirror, or its neighbor
+// isn't resolved yet.
+#[inline(always)]
+fn mirror_hop(ctx: &TcContext, l3_offset: usize) -> Option<u32> {
+    let ip_hdr: *const Ipv4Hdr = unsafe { ptr_at(ctx, l3_offset) }.ok()?;
+    let (ret, params) = fib_lookup(ctx, l3_offset, unsafe { (*ip_hdr).dst_addr })?;
+    if ret != BPF_FIB_LKUP_RET_SUCCESS {
+        return None;
+    }
+    let eth_hdr: *mut EthHdr = unsafe { ptr_at(ctx, 0) }.ok()?;
+    unsafe {
+        (*eth_hdr).src_addr = params.smac;
+        (*eth_hdr).dst_addr = params.dmac;
+    }
+    Some(params.ifindex)
+}
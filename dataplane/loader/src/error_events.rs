@@ -0,0 +1,86 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! Reports of the packets the eBPF programs failed to handle, as they send them on the
+//! ERROR_EVENTS ring buffer.
+
+use std::collections::BTreeMap;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use aya::maps::{MapData, RingBuf};
+use common::{ErrorEvent, PROGRAM_TC_EGRESS, PROGRAM_TC_INGRESS};
+use log::{debug, warn};
+use tokio::io::unix::AsyncFd;
+
+/// Logs every error event at debug level, and how many events of each program and error were
+/// received every interval, when any were.
+pub async fn consume(ring_buf: RingBuf<MapData>, interval: Duration) {
+    let mut ring_buf = match AsyncFd::new(ring_buf) {
+        Ok(ring_buf) => ring_buf,
+        Err(err) => {
+            warn!("failed to poll the error events ring buffer: {}", err);
+            return;
+        }
+    };
+    let mut ticker = tokio::time::interval(interval);
+    // the first tick completes immediately
+    ticker.tick().await;
+
+    let mut counts: BTreeMap<(u32, i64), u64> = BTreeMap::new();
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                for ((program, error), count) in &counts {
+                    warn!(
+                        "{} failed to handle {} packets with error {} in the last {:?}",
+                        program_name(*program),
+                        count,
+                        error,
+                        interval
+                    );
+                }
+                counts.clear();
+            }
+            guard = ring_buf.readable_mut() => {
+                let mut guard = match guard {
+                    Ok(guard) => guard,
+                    Err(err) => {
+                        warn!("failed to poll the error events ring buffer: {}", err);
+                        return;
+                    }
+                };
+                while let Some(item) = guard.get_inner_mut().next() {
+                    if item.len() < std::mem::size_of::<ErrorEvent>() {
+                        warn!("ignoring a truncated error event");
+                        continue;
+                    }
+                    // SAFETY: the eBPF programs only send ErrorEvents on the ring buffer.
+                    let event =
+                        unsafe { std::ptr::read_unaligned(item.as_ptr() as *const ErrorEvent) };
+                    debug!(
+                        "{} failed to handle a packet with error {} (proto: {}, src: {}, dst: {})",
+                        program_name(event.program),
+                        event.error,
+                        event.proto,
+                        Ipv4Addr::from(event.src_addr),
+                        Ipv4Addr::from(event.dst_addr)
+                    );
+                    *counts.entry((event.program, event.error)).or_default() += 1;
+                }
+                guard.clear_ready();
+            }
+        }
+    }
+}
+
+fn program_name(program: u32) -> &'static str {
+    match program {
+        PROGRAM_TC_INGRESS => "tc_ingress",
+        PROGRAM_TC_EGRESS => "tc_egress",
+        _ => "unknown program",
+    }
+}
@@ -0,0 +1,50 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! Periodic summaries of the log lines the eBPF programs sampled out.
+
+use std::time::Duration;
+
+use aya::maps::{MapData, PerCpuArray};
+use common::{LOG_STATS_EMITTED, LOG_STATS_SUPPRESSED};
+use log::{info, warn};
+
+/// Logs how many eBPF log lines were emitted and suppressed every interval, when any were.
+pub async fn summarize(stats: PerCpuArray<MapData, u64>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    // the first tick completes immediately
+    ticker.tick().await;
+
+    let (mut last_emitted, mut last_suppressed) = (0, 0);
+    loop {
+        ticker.tick().await;
+
+        let (emitted, suppressed) = match (
+            total(&stats, LOG_STATS_EMITTED),
+            total(&stats, LOG_STATS_SUPPRESSED),
+        ) {
+            (Ok(emitted), Ok(suppressed)) => (emitted, suppressed),
+            (Err(err), _) | (_, Err(err)) => {
+                warn!("failed to read eBPF log stats: {}", err);
+                continue;
+            }
+        };
+        if suppressed > last_suppressed {
+            info!(
+                "eBPF programs emitted {} and suppressed {} sampled log lines in the last {:?}",
+                emitted - last_emitted,
+                suppressed - last_suppressed,
+                interval
+            );
+        }
+        (last_emitted, last_suppressed) = (emitted, suppressed);
+    }
+}
+
+// Sums the counter across CPUs.
+fn total(stats: &PerCpuArray<MapData, u64>, index: u32) -> Result<u64, aya::maps::MapError> {
+    Ok(stats.get(&index, 0)?.iter().sum())
+}
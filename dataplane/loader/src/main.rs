@@ -4,34 +4,215 @@ Copyright 2023 The Kubernetes Authors.
 SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
 */
 
-use std::net::Ipv4Addr;
+mod error_events;
+mod log_stats;
+mod node_config;
+mod packet_samples;
+mod registration;
+
+use std::borrow::Cow;
+use std::collections::HashMap as StdHashMap;
+use std::fs;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use anyhow::Context;
 use api_server::config::TLSConfig;
+use api_server::netutils::{attach_device_for, if_index_for_name, offload_warnings};
+use api_server::server::{AttachedProgram, MapLimits};
 use api_server::start as start_api_server;
-use aya::maps::HashMap;
+use aya::maps::{
+    perf::AsyncPerfEventArray, Array, HashMap, LpmTrie, MapData, PerCpuArray, PerCpuHashMap,
+    ProgramArray, RingBuf,
+};
 use aya::programs::{tc, SchedClassifier, TcAttachType};
-use aya::{include_bytes_aligned, Ebpf};
+use aya::{include_bytes_aligned, Ebpf, EbpfLoader};
 use aya_log::EbpfLogger;
 use clap::Parser;
-use common::{BackendKey, BackendList, ClientKey, LoadBalancerMapping};
+use common::{
+    mss::TCP_MIN_MSS, AclKey, Affinity, AffinityKey, BackendKey, BackendList, BackendSlot,
+    BackendSlotKey, CaptureConfig, ClientKey, LoadBalancerMapping, PortRangeKey, SampleConfig,
+    VipStats, BACKENDS_ARRAY_CAPACITY, BPF_MAPS_CAPACITY, FEATURES_DEFAULT, IPPROTO_SCTP,
+    IPPROTO_TCP, IPPROTO_UDP, LOG_LEVEL_INFO, MAX_ACL_CIDRS, MAX_BACKENDS_PER_VIP,
+    MAX_PORT_RANGE_PREFIXES, STATE_FEATURES, STATE_LOG_LEVEL, STATE_LOG_SAMPLE_RATE, STATE_SNAT_IP,
+    STATE_STANDBY, STATE_TCP_IDLE_TIMEOUT, STATE_TCP_MSS_CLAMP, STATE_UDP_IDLE_TIMEOUT,
+    TAIL_CALL_EGRESS_ICMP, TAIL_CALL_EGRESS_SCTP, TAIL_CALL_EGRESS_TCP, TAIL_CALL_EGRESS_UDP,
+    TAIL_CALL_INGRESS_SCTP, TAIL_CALL_INGRESS_TCP, TAIL_CALL_INGRESS_UDP,
+};
 use log::{info, warn};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWrite;
+
+use node_config::{labels_from_api, labels_from_file, NodeConfig};
+use packet_samples::SampleVip;
 
 /// Command-line options for the application.
 ///
 /// This struct defines the options available for the command-line interface,
 /// including an interface name (`iface`) and an optional TLS configuration (`tls_config`).
+/// Every option can also be set through the `BLIXT_`-prefixed environment variable named
+/// after it (e.g. `BLIXT_IFACE`), which is convenient when templating DaemonSets.
 #[derive(Debug, Parser)]
 struct Opt {
     /// Name of the network interface to attach the eBPF programs to.
     ///
-    /// By default, this is set to `"lo"` (the loopback interface).
-    #[clap(short, long, default_value = "lo")]
+    /// By default, this is set to `"lo"` (the loopback interface). If the interface is a port
+    /// of a bond or bridge, the programs are attached to the bond or bridge instead.
+    #[clap(short, long, env = "BLIXT_IFACE", default_value = "lo")]
     iface: String,
+    /// Address the API server listens on.
+    ///
+    /// Defaults to --pod-ip, so that the API isn't exposed on the other addresses of the node
+    /// when the dataplane runs on the host network. Without a pod IP, the API server listens on
+    /// every address (`0.0.0.0`).
+    #[clap(long, env = "BLIXT_API_ADDR")]
+    api_addr: Option<Ipv4Addr>,
+    /// Network interface the API server only accepts connections through (SO_BINDTODEVICE).
+    ///
+    /// Connections from the node itself, such as the kubelet's probes, arrive through the
+    /// loopback interface and are refused when this is set to another interface.
+    #[clap(long, env = "BLIXT_API_BIND_DEVICE")]
+    api_bind_device: Option<String>,
+    /// Port the API server listens on. Healthchecks are served on the port after it.
+    #[clap(long, env = "BLIXT_API_PORT", default_value_t = 9874)]
+    api_port: u16,
+    /// Port the metrics of the API server are served on, in the OpenMetrics format, on the
+    /// address of the API server. Metrics aren't served unless this is set.
+    #[clap(long, env = "BLIXT_METRICS_PORT")]
+    metrics_port: Option<u16>,
+    /// Maximum number of VIPs the dataplane can hold.
+    #[clap(long, env = "BLIXT_MAX_VIPS", default_value_t = BPF_MAPS_CAPACITY)]
+    max_vips: u32,
+    /// Maximum number of connections the dataplane can track.
+    #[clap(long, env = "BLIXT_MAX_CONNECTIONS", default_value_t = BPF_MAPS_CAPACITY)]
+    max_connections: u32,
+    /// Maximum number of backends of a VIP.
+    ///
+    /// Backends past the first 128 take up additional map entries, which are allocated for
+    /// every VIP up front.
+    #[clap(
+        long,
+        env = "BLIXT_MAX_BACKENDS_PER_VIP",
+        default_value_t = BACKENDS_ARRAY_CAPACITY as u32,
+        value_parser = clap::value_parser!(u32).range(1..=MAX_BACKENDS_PER_VIP as i64)
+    )]
+    max_backends_per_vip: u32,
+    /// Percentage of --max-vips above which the dataplane reports itself as degraded.
+    #[clap(
+        long,
+        env = "BLIXT_VIPS_HIGH_WATERMARK",
+        default_value_t = 80,
+        value_parser = clap::value_parser!(u32).range(1..=100)
+    )]
+    vips_high_watermark: u32,
+    /// Percentage of --max-connections above which the dataplane reports itself as degraded.
+    #[clap(
+        long,
+        env = "BLIXT_CONNECTIONS_HIGH_WATERMARK",
+        default_value_t = 80,
+        value_parser = clap::value_parser!(u32).range(1..=100)
+    )]
+    connections_high_watermark: u32,
+    /// Don't attach the tc_egress program.
+    ///
+    /// The egress program rewrites the source of replies from backends, which is unnecessary
+    /// when replies don't pass back through this node (e.g. direct server return).
+    #[clap(long, env = "BLIXT_SKIP_EGRESS", action)]
+    skip_egress: bool,
+    /// Emit only 1 in this many of the log lines the eBPF programs write for every packet.
+    ///
+    /// Counts of the lines that were suppressed are logged periodically instead.
+    #[clap(long, env = "BLIXT_LOG_SAMPLE_RATE", default_value_t = 1)]
+    log_sample_rate: u32,
+    /// Interval in seconds between summaries of the suppressed eBPF log lines, and of the
+    /// packets the eBPF programs failed to handle.
+    #[clap(long, env = "BLIXT_LOG_SUMMARY_INTERVAL", default_value_t = 60)]
+    log_summary_interval: u64,
+    /// VIP whose packets are sampled for debugging, as ADDRESS:PORT/PROTOCOL with a protocol of
+    /// tcp (the default), udp or sctp. VIPs of ranges of ports are named by their first port.
+    ///
+    /// The headers of every --packet-sample-rate-th packet of the VIP are written to
+    /// --packet-sample-output as a pcap stream, as received and once rewritten to be redirected
+    /// to their backend, like a tcpdump of the load balancer's decisions.
+    #[clap(long, env = "BLIXT_PACKET_SAMPLE_VIP", value_parser = parse_sample_vip)]
+    packet_sample_vip: Option<SampleVip>,
+    /// Sample 1 in this many packets of --packet-sample-vip, counted on each CPU.
+    #[clap(
+        long,
+        env = "BLIXT_PACKET_SAMPLE_RATE",
+        default_value_t = 100,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    packet_sample_rate: u32,
+    /// File the packet samples are written to, `-` for stdout.
+    #[clap(long, env = "BLIXT_PACKET_SAMPLE_OUTPUT", default_value = "-")]
+    packet_sample_output: PathBuf,
+    /// Seconds after which TCP connections that saw no packets are no longer tracked, and new
+    /// packets of them are load balanced like new connections. 0 disables the timeout.
+    #[clap(long, env = "BLIXT_TCP_IDLE_TIMEOUT", default_value_t = 3600)]
+    tcp_idle_timeout: u32,
+    /// Seconds after which UDP clients that sent no packets are no longer tracked. 0 disables
+    /// the timeout.
+    #[clap(long, env = "BLIXT_UDP_IDLE_TIMEOUT", default_value_t = 120)]
+    udp_idle_timeout: u32,
+    /// Clamp the MSS that TCP SYN packets to vips, and the replies of their full NAT backends,
+    /// advertise to this value, for backends behind tunnels or links with a lower MTU. 0
+    /// disables clamping.
+    #[clap(long, env = "BLIXT_TCP_MSS_CLAMP", default_value_t = 0, value_parser = parse_mss_clamp)]
+    tcp_mss_clamp: u32,
+    /// Address of the node that the traffic of vips with full NAT is sent to their backends
+    /// from, so that backends without a route to the clients reply through the dataplane.
+    /// Full NAT can't be enabled on vips without it.
+    ///
+    /// Connections that hairpin, i.e. whose client and backend are behind the interface the
+    /// dataplane is attached to, are also full NATed when it's set, so that clients such as pods
+    /// of the node can reach backends through the vip.
+    #[clap(long, env = "BLIXT_SNAT_IP")]
+    snat_ip: Option<Ipv4Addr>,
+    /// Start in standby: the programs and maps are loaded and can be programmed, but traffic
+    /// passes through without being load balanced until the Activate RPC is called.
+    ///
+    /// Useful to pre-stage new nodes, or to switch between dataplane versions.
+    #[clap(long, env = "BLIXT_STANDBY", action)]
+    standby: bool,
+    /// Run a synthetic packet through the datapath on startup, against a temporary VIP, to check
+    /// that it's rewritten and redirected on this kernel and interface.
+    ///
+    /// The dataplane reports itself as not serving until the self-test passed.
+    #[clap(long, env = "BLIXT_SELF_TEST", action)]
+    self_test: bool,
+    /// Path to a pre-compiled eBPF object to load.
+    ///
+    /// By default, the object embedded in the loader at build time is used.
+    #[clap(long, env = "BLIXT_EBPF_OBJECT")]
+    ebpf_object: Option<PathBuf>,
+    /// Name of the node the dataplane runs on, whose labels override the options above.
+    ///
+    /// The supported labels are `iface` and `skip-egress`, prefixed with
+    /// `dataplane.blixt.gateway.networking.k8s.io/`. Requires permission to get the Node.
+    #[clap(long, env = "BLIXT_NODE_NAME")]
+    node_name: Option<String>,
+    /// Path to a file with the node's labels in the downward API format, read instead of
+    /// fetching them from the Kubernetes API.
+    #[clap(long, env = "BLIXT_NODE_LABELS_FILE")]
+    node_labels_file: Option<PathBuf>,
+    /// Name of the dataplane's pod, which is labeled with the API port when set.
+    #[clap(long, env = "BLIXT_POD_NAME", requires = "pod_namespace")]
+    pod_name: Option<String>,
+    /// Namespace of the dataplane's pod.
+    #[clap(long, env = "BLIXT_POD_NAMESPACE")]
+    pod_namespace: Option<String>,
+    /// IP address of the dataplane's pod, which the API server listens on unless --api-addr
+    /// is set.
+    #[clap(long, env = "BLIXT_POD_IP")]
+    pod_ip: Option<Ipv4Addr>,
     /// Optional TLS configuration for securing the API server.
     ///
     /// If no TLS configuration is provided, the server will start without TLS.
-    /// You can specify either `tls` for server-only TLS or `mutual-tls` for mutual TLS.
+    /// You can specify either `tls` for server-only TLS, `mutual-tls` for mutual TLS, or
+    /// `secret-tls` for either with the certificates of mounted Kubernetes Secrets, which are
+    /// reloaded when the Secrets are updated.
     #[clap(subcommand)]
     tls_config: Option<TLSConfig>,
 }
@@ -44,6 +225,8 @@ struct Opt {
 /// The program supports an optional TLS configuration, allowing the user to choose between:
 /// - `tls`: Server-only TLS.
 /// - `mutual-tls`: Mutual TLS, where both server and client authenticate with certificates.
+/// - `secret-tls`: TLS, or mutual TLS with a client CA Secret, with the certificates of mounted
+///   Kubernetes Secrets, reloaded when they're updated.
 ///
 /// # Arguments
 ///
@@ -59,55 +242,220 @@ struct Opt {
 /// # Running with a specified interface and server-only TLS config:
 /// $ dataplane --iface eth0 tls --server-certificate-path /path/to/cert --server-private-key-path /path/to/key
 ///
+/// # Running with options (including TLS paths) from the environment:
+/// $ BLIXT_IFACE=eth0 BLIXT_TLS_SERVER_CERTIFICATE_PATH=/path/to/cert BLIXT_TLS_SERVER_PRIVATE_KEY_PATH=/path/to/key dataplane tls
+///
 /// # Running with mutual TLS config:
 /// $ dataplane --iface eth0 mutual-tls --server-certificate-path /path/to/cert --server-private-key-path /path/to/key --client-certificate-authority-root-path /path/to/ca
+///
+/// # Running with mutual TLS config from mounted Secrets:
+/// $ dataplane --iface eth0 secret-tls --server-secret-dir /etc/blixt/tls --client-ca-secret-dir /etc/blixt/client-ca
+///
+/// # Running with TLS 1.3 only, and with a subset of its cipher suites:
+/// $ dataplane --iface eth0 tls --server-certificate-path /path/to/cert --server-private-key-path /path/to/key --min-tls-version 1.3 --cipher-suites TLS_AES_256_GCM_SHA384,TLS_CHACHA20_POLY1305_SHA256
 /// ```
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
-    let opt = Opt::parse();
+    let mut opt = Opt::parse();
 
     env_logger::init();
 
+    let node_config = match (&opt.node_labels_file, &opt.node_name) {
+        (Some(path), _) => NodeConfig::from_labels(&labels_from_file(path)?)?,
+        (None, Some(node_name)) => NodeConfig::from_labels(
+            &labels_from_api(node_name)
+                .await
+                .with_context(|| format!("failed to get the labels of node {}", node_name))?,
+        )?,
+        (None, None) => NodeConfig::default(),
+    };
+    if let Some(iface) = node_config.iface {
+        info!("using interface {} from node labels", iface);
+        opt.iface = iface;
+    }
+    if let Some(skip_egress) = node_config.skip_egress {
+        info!("using skip-egress {} from node labels", skip_egress);
+        opt.skip_egress = skip_egress;
+    }
+
     info!("loading ebpf programs");
 
     #[cfg(debug_assertions)]
-    let mut bpf_program = Ebpf::load(include_bytes_aligned!(
-        "../../target/bpfel-unknown-none/debug/loader"
-    ))?;
+    let embedded_object = include_bytes_aligned!("../../target/bpfel-unknown-none/debug/loader");
     #[cfg(not(debug_assertions))]
-    let mut bpf_program = Ebpf::load(include_bytes_aligned!(
-        "../../target/bpfel-unknown-none/release/loader"
-    ))?;
+    let embedded_object = include_bytes_aligned!("../../target/bpfel-unknown-none/release/loader");
+
+    let ebpf_object: Cow<[u8]> = match &opt.ebpf_object {
+        Some(path) => Cow::Owned(
+            fs::read(path)
+                .with_context(|| format!("failed to read eBPF object {}", path.display()))?,
+        ),
+        None => Cow::Borrowed(embedded_object),
+    };
+    let checksum: String = Sha256::digest(&ebpf_object)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    info!(
+        "loading {} eBPF object (sha256: {})",
+        opt.ebpf_object
+            .as_ref()
+            .map_or("embedded".into(), |path| path.display().to_string()),
+        checksum
+    );
+
+    // a map needs at least one entry, even when no VIP has more than 128 backends
+    let backend_slots = opt.max_vips
+        * (opt.max_backends_per_vip as usize)
+            .div_ceil(BACKENDS_ARRAY_CAPACITY)
+            .saturating_sub(1) as u32;
+    let mut bpf_program = EbpfLoader::new()
+        .set_max_entries("BACKENDS", opt.max_vips)
+        .set_max_entries("BACKEND_SLOTS", backend_slots.max(1))
+        .set_max_entries("VIP_PORT_RANGES", opt.max_vips * MAX_PORT_RANGE_PREFIXES)
+        .set_max_entries("VIP_ACLS", opt.max_vips * (MAX_ACL_CIDRS + 1))
+        .set_max_entries("GATEWAY_INDEXES", opt.max_vips)
+        .set_max_entries("LB_CONNECTIONS", opt.max_connections)
+        .set_max_entries("SNAT_CONNECTIONS", opt.max_connections)
+        .set_max_entries("QUIC_CONNECTIONS", opt.max_connections)
+        .set_max_entries("FRAGMENTS", opt.max_connections)
+        .set_max_entries("RATE_LIMITS", opt.max_connections)
+        .load(&ebpf_object)?;
     if let Err(e) = EbpfLogger::init(&mut bpf_program) {
         warn!("failed to initialize eBPF logger: {}", e);
     }
 
-    info!("attaching tc_ingress program to {}", &opt.iface);
+    let iface = match attach_device_for(&opt.iface) {
+        Ok(iface) => iface,
+        Err(err) => {
+            warn!("failed to inspect interface {}: {}", &opt.iface, err);
+            opt.iface.clone()
+        }
+    };
+    if iface != opt.iface {
+        info!(
+            "{} is a port of {}, attaching to {}",
+            &opt.iface, &iface, &iface
+        );
+    }
+    // the loopback device has no offloads to speak of
+    if iface != "lo" {
+        for warning in offload_warnings(&iface) {
+            warn!("{}", warning);
+        }
+    }
+
+    let self_test_ifindex = if opt.self_test {
+        Some(
+            if_index_for_name(&iface)
+                .with_context(|| format!("failed to get the index of interface {}", &iface))?,
+        )
+    } else {
+        None
+    };
+
+    // The programs of each protocol are in TAIL_CALLS before tc_ingress and tc_egress are attached,
+    // so that no packet is passed on for lack of them. TAIL_CALLS is kept open for as long as the
+    // programs are attached, since the kernel empties program arrays nothing holds open anymore.
+    let mut tail_calls = ProgramArray::try_from(
+        bpf_program
+            .take_map("TAIL_CALLS")
+            .expect("no maps named TAIL_CALLS"),
+    )?;
+    load_tail_calls(
+        &mut bpf_program,
+        &mut tail_calls,
+        &[
+            (TAIL_CALL_INGRESS_TCP, "tc_ingress_tcp"),
+            (TAIL_CALL_INGRESS_UDP, "tc_ingress_udp"),
+            (TAIL_CALL_INGRESS_SCTP, "tc_ingress_sctp"),
+        ],
+    )?;
 
-    let _ = tc::qdisc_add_clsact(&opt.iface);
+    info!("attaching tc_ingress program to {}", &iface);
+
+    let _ = tc::qdisc_add_clsact(&iface);
     let ingress_program: &mut SchedClassifier =
         bpf_program.program_mut("tc_ingress").unwrap().try_into()?;
     ingress_program.load()?;
-    ingress_program
-        .attach(&opt.iface, TcAttachType::Ingress)
+    let ingress_link = ingress_program
+        .attach(&iface, TcAttachType::Ingress)
         .context("failed to attach the ingress TC program")?;
 
-    info!("attaching tc_egress program to {}", &opt.iface);
+    // The programs are handed to the api server so they can be probed and detached at runtime.
+    let mut attached_programs = StdHashMap::new();
+    attached_programs.insert(
+        "tc_ingress".to_string(),
+        AttachedProgram {
+            link: ingress_program.take_link(ingress_link)?,
+            fd: ingress_program.fd()?.try_clone()?,
+        },
+    );
+
+    if opt.skip_egress {
+        info!("skipping tc_egress program");
+    } else {
+        load_tail_calls(
+            &mut bpf_program,
+            &mut tail_calls,
+            &[
+                (TAIL_CALL_EGRESS_ICMP, "tc_egress_icmp"),
+                (TAIL_CALL_EGRESS_TCP, "tc_egress_tcp"),
+                (TAIL_CALL_EGRESS_UDP, "tc_egress_udp"),
+                (TAIL_CALL_EGRESS_SCTP, "tc_egress_sctp"),
+            ],
+        )?;
 
-    let egress_program: &mut SchedClassifier =
-        bpf_program.program_mut("tc_egress").unwrap().try_into()?;
-    egress_program.load()?;
-    egress_program
-        .attach(&opt.iface, TcAttachType::Egress)
-        .context("failed to attach the egress TC program")?;
+        info!("attaching tc_egress program to {}", &iface);
+
+        let egress_program: &mut SchedClassifier =
+            bpf_program.program_mut("tc_egress").unwrap().try_into()?;
+        egress_program.load()?;
+        let egress_link = egress_program
+            .attach(&iface, TcAttachType::Egress)
+            .context("failed to attach the egress TC program")?;
+        attached_programs.insert(
+            "tc_egress".to_string(),
+            AttachedProgram {
+                link: egress_program.take_link(egress_link)?,
+                fd: egress_program.fd()?.try_clone()?,
+            },
+        );
+    }
+
+    if let (Some(pod_name), Some(pod_namespace)) = (&opt.pod_name, &opt.pod_namespace) {
+        match registration::register(pod_namespace, pod_name, opt.api_port).await {
+            Ok(()) => info!("registered dataplane pod {}/{}", pod_namespace, pod_name),
+            Err(err) => warn!("failed to register the dataplane pod: {:#}", err),
+        }
+    }
 
     info!("starting api server");
+    let api_addr = match opt.api_addr.or(opt.pod_ip) {
+        Some(addr) => addr,
+        None => {
+            warn!(
+                "neither --api-addr nor --pod-ip is set, the api server listens on every address"
+            );
+            Ipv4Addr::UNSPECIFIED
+        }
+    };
     info!("Using tls config: {:?}", &opt.tls_config);
     let backends: HashMap<_, BackendKey, BackendList> = HashMap::try_from(
         bpf_program
             .take_map("BACKENDS")
             .expect("no maps named BACKENDS"),
     )?;
+    let backend_slots: HashMap<_, BackendSlotKey, BackendSlot> = HashMap::try_from(
+        bpf_program
+            .take_map("BACKEND_SLOTS")
+            .expect("no maps named BACKEND_SLOTS"),
+    )?;
+    let port_ranges: LpmTrie<_, PortRangeKey, BackendKey> = LpmTrie::try_from(
+        bpf_program
+            .take_map("VIP_PORT_RANGES")
+            .expect("no maps named VIP_PORT_RANGES"),
+    )?;
     let gateway_indexes: HashMap<_, BackendKey, u16> = HashMap::try_from(
         bpf_program
             .take_map("GATEWAY_INDEXES")
@@ -118,13 +466,158 @@ async fn main() -> Result<(), anyhow::Error> {
             .take_map("LB_CONNECTIONS")
             .expect("no maps named LB_CONNECTIONS"),
     )?;
+    let affinities: HashMap<_, AffinityKey, Affinity> = HashMap::try_from(
+        bpf_program
+            .take_map("AFFINITIES")
+            .expect("no maps named AFFINITIES"),
+    )?;
+    let mut state: Array<_, u32> = Array::try_from(
+        bpf_program
+            .take_map("DATAPLANE_STATE")
+            .expect("no maps named DATAPLANE_STATE"),
+    )?;
+    state.set(STATE_FEATURES, FEATURES_DEFAULT, 0)?;
+    state.set(STATE_LOG_LEVEL, LOG_LEVEL_INFO, 0)?;
+    state.set(STATE_LOG_SAMPLE_RATE, opt.log_sample_rate, 0)?;
+    state.set(STATE_TCP_IDLE_TIMEOUT, opt.tcp_idle_timeout, 0)?;
+    state.set(STATE_UDP_IDLE_TIMEOUT, opt.udp_idle_timeout, 0)?;
+    state.set(STATE_TCP_MSS_CLAMP, opt.tcp_mss_clamp, 0)?;
+    if let Some(snat_ip) = opt.snat_ip {
+        state.set(STATE_SNAT_IP, u32::from(snat_ip), 0)?;
+    }
+    if opt.standby {
+        info!("starting in standby, traffic won't be load balanced until activated");
+        state.set(STATE_STANDBY, 1, 0)?;
+    }
+
+    let vip_stats: PerCpuHashMap<_, BackendKey, VipStats> = PerCpuHashMap::try_from(
+        bpf_program
+            .take_map("VIP_STATS")
+            .expect("no maps named VIP_STATS"),
+    )?;
+
+    let drop_stats: PerCpuArray<_, u64> = PerCpuArray::try_from(
+        bpf_program
+            .take_map("DROP_STATS")
+            .expect("no maps named DROP_STATS"),
+    )?;
+
+    let vip_acls: LpmTrie<_, AclKey, u32> = LpmTrie::try_from(
+        bpf_program
+            .take_map("VIP_ACLS")
+            .expect("no maps named VIP_ACLS"),
+    )?;
+
+    let flow_records = RingBuf::try_from(
+        bpf_program
+            .take_map("FLOW_RECORDS")
+            .expect("no maps named FLOW_RECORDS"),
+    )?;
+
+    let capture_config: Array<_, CaptureConfig> = Array::try_from(
+        bpf_program
+            .take_map("CAPTURE_CONFIG")
+            .expect("no maps named CAPTURE_CONFIG"),
+    )?;
+    let packet_captures = AsyncPerfEventArray::try_from(
+        bpf_program
+            .take_map("PACKET_CAPTURES")
+            .expect("no maps named PACKET_CAPTURES"),
+    )?;
+
+    let log_stats: PerCpuArray<_, u64> = PerCpuArray::try_from(
+        bpf_program
+            .take_map("LOG_STATS")
+            .expect("no maps named LOG_STATS"),
+    )?;
+    tokio::spawn(log_stats::summarize(
+        log_stats,
+        Duration::from_secs(opt.log_summary_interval),
+    ));
+
+    let error_events = RingBuf::try_from(
+        bpf_program
+            .take_map("ERROR_EVENTS")
+            .expect("no maps named ERROR_EVENTS"),
+    )?;
+    tokio::spawn(error_events::consume(
+        error_events,
+        Duration::from_secs(opt.log_summary_interval),
+    ));
+
+    if let Some(vip) = opt.packet_sample_vip {
+        let out: Box<dyn AsyncWrite + Send + Unpin> = if opt.packet_sample_output == Path::new("-")
+        {
+            Box::new(tokio::io::stdout())
+        } else {
+            Box::new(
+                tokio::fs::File::create(&opt.packet_sample_output)
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "failed to create packet sample output {}",
+                            opt.packet_sample_output.display()
+                        )
+                    })?,
+            )
+        };
+        let packet_samples = RingBuf::try_from(
+            bpf_program
+                .take_map("PACKET_SAMPLES")
+                .expect("no maps named PACKET_SAMPLES"),
+        )?;
+        let mut sample_config: Array<_, SampleConfig> = Array::try_from(
+            bpf_program
+                .take_map("SAMPLE_CONFIG")
+                .expect("no maps named SAMPLE_CONFIG"),
+        )?;
+        sample_config.set(
+            0,
+            SampleConfig {
+                ip: u32::from(vip.ip),
+                port: vip.port as u32,
+                proto: vip.proto,
+                rate: opt.packet_sample_rate,
+            },
+            0,
+        )?;
+        info!(
+            "sampling 1 in {} packets of vip {}:{} to {}",
+            opt.packet_sample_rate,
+            vip.ip,
+            vip.port,
+            opt.packet_sample_output.display()
+        );
+        tokio::spawn(packet_samples::stream(packet_samples, out));
+    }
 
     start_api_server(
-        Ipv4Addr::new(0, 0, 0, 0),
-        9874,
+        api_addr,
+        opt.api_port,
+        opt.api_bind_device,
+        opt.metrics_port,
         backends,
+        backend_slots,
+        port_ranges,
         gateway_indexes,
         tcp_conns,
+        affinities,
+        state,
+        vip_stats,
+        drop_stats,
+        vip_acls,
+        attached_programs,
+        flow_records,
+        capture_config,
+        packet_captures,
+        MapLimits {
+            max_vips: opt.max_vips,
+            max_connections: opt.max_connections,
+            max_backends_per_vip: opt.max_backends_per_vip,
+            vips_high_watermark: opt.vips_high_watermark,
+            connections_high_watermark: opt.connections_high_watermark,
+        },
+        self_test_ifindex,
         opt.tls_config,
     )
     .await?;
@@ -133,3 +626,56 @@ async fn main() -> Result<(), anyhow::Error> {
 
     Ok(())
 }
+
+// Loads the programs tc_ingress and tc_egress tail call into for each protocol, and adds them to
+// TAIL_CALLS at their index.
+fn load_tail_calls(
+    bpf_program: &mut Ebpf,
+    tail_calls: &mut ProgramArray<MapData>,
+    programs: &[(u32, &str)],
+) -> Result<(), anyhow::Error> {
+    for &(index, name) in programs {
+        let program: &mut SchedClassifier = bpf_program
+            .program_mut(name)
+            .with_context(|| format!("no programs named {}", name))?
+            .try_into()?;
+        program
+            .load()
+            .with_context(|| format!("failed to load the {} program", name))?;
+        tail_calls.set(index, program.fd()?, 0)?;
+    }
+    Ok(())
+}
+
+// Parses an MSS to clamp to, which is 0 or a valid MSS.
+fn parse_mss_clamp(value: &str) -> Result<u32, String> {
+    let mss: u32 = value.parse().map_err(|err| format!("{}", err))?;
+    if mss != 0 && !(TCP_MIN_MSS..=u16::MAX as u32).contains(&mss) {
+        return Err(format!(
+            "the MSS must be 0 or between {} and {}",
+            TCP_MIN_MSS,
+            u16::MAX
+        ));
+    }
+    Ok(mss)
+}
+
+// Parses the vip of packet samples, as ADDRESS:PORT/PROTOCOL with an optional protocol.
+fn parse_sample_vip(value: &str) -> Result<SampleVip, String> {
+    let (addr, proto) = match value.split_once('/') {
+        Some((addr, proto)) => (addr, proto),
+        None => (value, "tcp"),
+    };
+    let addr: SocketAddrV4 = addr.parse().map_err(|err| format!("{}", err))?;
+    let proto = match proto.to_ascii_lowercase().as_str() {
+        "tcp" => IPPROTO_TCP,
+        "udp" => IPPROTO_UDP,
+        "sctp" => IPPROTO_SCTP,
+        _ => return Err(format!("unsupported protocol {}", proto)),
+    };
+    Ok(SampleVip {
+        ip: *addr.ip(),
+        port: addr.port(),
+        proto,
+    })
+}
@@ -0,0 +1,81 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! Node-specific configuration, expressed as labels on the Node the dataplane runs on so that
+//! heterogeneous fleets can be served by a single DaemonSet.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Error};
+use k8s_openapi::api::core::v1::Node;
+use kube::{Api, Client};
+
+/// The prefix of the node labels the dataplane reads its configuration from.
+pub const NODE_LABEL_PREFIX: &str = "dataplane.blixt.gateway.networking.k8s.io/";
+
+/// Overrides of the loader options for this node.
+#[derive(Debug, Default, PartialEq)]
+pub struct NodeConfig {
+    /// Set by the `iface` label.
+    pub iface: Option<String>,
+    /// Set by the `skip-egress` label (`"true"` or `"false"`).
+    pub skip_egress: Option<bool>,
+}
+
+impl NodeConfig {
+    /// Reads the configuration from the labels, ignoring labels without the dataplane prefix.
+    pub fn from_labels(labels: &BTreeMap<String, String>) -> Result<NodeConfig, Error> {
+        let mut config = NodeConfig::default();
+        for (key, value) in labels {
+            match key.strip_prefix(NODE_LABEL_PREFIX) {
+                Some("iface") => config.iface = Some(value.clone()),
+                Some("skip-egress") => {
+                    config.skip_egress = Some(
+                        value
+                            .parse()
+                            .with_context(|| format!("invalid value for label {}", key))?,
+                    )
+                }
+                _ => {}
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Fetches the labels of the node with the Kubernetes API.
+pub async fn labels_from_api(node_name: &str) -> Result<BTreeMap<String, String>, Error> {
+    let client = Client::try_default().await?;
+    let node = Api::<Node>::all(client).get(node_name).await?;
+    Ok(node.metadata.labels.unwrap_or_default())
+}
+
+/// Reads labels from a file in the format of the Kubernetes downward API (`key="value"`, one
+/// per line), e.g. one populated by an init container.
+pub fn labels_from_file(path: &Path) -> Result<BTreeMap<String, String>, Error> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("failed to read labels from {}", path.display()))?;
+    parse_labels(&contents)
+}
+
+/// Parses labels in the format of the Kubernetes downward API.
+pub fn parse_labels(contents: &str) -> Result<BTreeMap<String, String>, Error> {
+    let mut labels = BTreeMap::new();
+    for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+        let (key, value) = line
+            .split_once('=')
+            .with_context(|| format!("malformed label line: {}", line))?;
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+        labels.insert(key.trim().to_string(), value.to_string());
+    }
+    Ok(labels)
+}
@@ -0,0 +1,88 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! Samples of the packets of a vip, as the eBPF programs send them on the PACKET_SAMPLES ring
+//! buffer, streamed as a pcap file for debugging how the vip is load balanced.
+
+use std::net::Ipv4Addr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use api_server::pcap::{write_pcap_header, write_pcap_record, CapturedPacket};
+use api_server::server::monotonic_now_ns;
+use aya::maps::{MapData, RingBuf};
+use common::{PacketSample, SAMPLE_HEADERS_LEN};
+use log::warn;
+use tokio::io::{unix::AsyncFd, AsyncWrite, AsyncWriteExt};
+
+/// The vip whose packets are sampled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SampleVip {
+    pub ip: Ipv4Addr,
+    pub port: u16,
+    pub proto: u32,
+}
+
+/// Writes the sampled packets to `out` as a pcap file, flushed after every batch of packets so
+/// that readers such as `tcpdump -r -` see them as they're sampled.
+pub async fn stream(ring_buf: RingBuf<MapData>, mut out: Box<dyn AsyncWrite + Send + Unpin>) {
+    let mut ring_buf = match AsyncFd::new(ring_buf) {
+        Ok(ring_buf) => ring_buf,
+        Err(err) => {
+            warn!("failed to poll the packet samples ring buffer: {}", err);
+            return;
+        }
+    };
+
+    let mut pcap = Vec::new();
+    write_pcap_header(&mut pcap, SAMPLE_HEADERS_LEN as u32);
+    loop {
+        if let Err(err) = write(&mut *out, &pcap).await {
+            warn!(
+                "failed to write packet samples, no longer writing them: {}",
+                err
+            );
+            return;
+        }
+        pcap.clear();
+
+        let mut guard = match ring_buf.readable_mut().await {
+            Ok(guard) => guard,
+            Err(err) => {
+                warn!("failed to poll the packet samples ring buffer: {}", err);
+                return;
+            }
+        };
+        // the eBPF programs timestamp packets on the monotonic clock
+        let boot_time = (SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64)
+            .saturating_sub(monotonic_now_ns());
+        while let Some(item) = guard.get_inner_mut().next() {
+            if item.len() < std::mem::size_of::<PacketSample>() {
+                warn!("ignoring a truncated packet sample");
+                continue;
+            }
+            // SAFETY: the eBPF programs only send PacketSamples on the ring buffer.
+            let sample = unsafe { std::ptr::read_unaligned(item.as_ptr() as *const PacketSample) };
+            let captured_len = (sample.header.captured_len as usize).min(SAMPLE_HEADERS_LEN);
+            write_pcap_record(
+                &mut pcap,
+                &CapturedPacket {
+                    timestamp: boot_time + sample.header.timestamp,
+                    len: sample.header.len,
+                    data: sample.data[..captured_len].to_vec(),
+                },
+            );
+        }
+        guard.clear_ready();
+    }
+}
+
+async fn write(out: &mut (dyn AsyncWrite + Send + Unpin), pcap: &[u8]) -> std::io::Result<()> {
+    out.write_all(pcap).await?;
+    out.flush().await
+}
@@ -0,0 +1,43 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! Registration of the dataplane with the Kubernetes API, so that the controlplane can discover
+//! how to reach it.
+
+use std::collections::BTreeMap;
+
+use anyhow::Error;
+use common::DATAPLANE_API_PORT_LABEL;
+use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::api::{Patch, PatchParams};
+use kube::{Api, Client};
+
+const FIELD_MANAGER: &str = "blixt-dataplane";
+
+/// Labels the dataplane's own pod with the port its API server listens on.
+pub async fn register(namespace: &str, pod_name: &str, api_port: u16) -> Result<(), Error> {
+    let client = Client::try_default().await?;
+    let pods = Api::<Pod>::namespaced(client, namespace);
+
+    let patch = Pod {
+        metadata: ObjectMeta {
+            labels: Some(BTreeMap::from([(
+                DATAPLANE_API_PORT_LABEL.to_string(),
+                api_port.to_string(),
+            )])),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    pods.patch(
+        pod_name,
+        &PatchParams::apply(FIELD_MANAGER),
+        &Patch::Merge(&patch),
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,191 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! Runs packets through the eBPF programs with bpf_prog_test_run, the way the Probe RPC does,
+//! with the vips programmed through the API server.
+//!
+//! Loading the programs requires CAP_BPF and the eBPF object built by `cargo xtask build-ebpf`,
+//! so the tests using this are ignored unless run as root with
+//! `cargo test -p loader -- --ignored`.
+
+#![allow(dead_code)]
+
+use std::collections::HashMap as StdHashMap;
+use std::net::Ipv4Addr;
+
+use api_server::backends::backends_server::Backends;
+use api_server::backends::{Protocol, Target, Targets, Vip};
+use api_server::probe::{self, TestRunOutput};
+use api_server::server::{AttachedProgram, BackendService, MapLimits};
+use aya::maps::{
+    Array, HashMap, LpmTrie, MapData, PerCpuArray, PerCpuHashMap, ProgramArray, RingBuf,
+};
+use aya::programs::{tc, SchedClassifier, TcAttachType};
+use aya::{include_bytes_aligned, Ebpf, EbpfLoader};
+use common::{
+    ErrorEvent, TAIL_CALL_EGRESS_ICMP, TAIL_CALL_EGRESS_SCTP, TAIL_CALL_EGRESS_TCP,
+    TAIL_CALL_EGRESS_UDP, TAIL_CALL_INGRESS_SCTP, TAIL_CALL_INGRESS_TCP, TAIL_CALL_INGRESS_UDP,
+};
+use tonic::Request;
+
+/// The programs tc_ingress and tc_egress tail call into, by their index in TAIL_CALLS.
+pub const TAIL_CALLS: &[(u32, &str)] = &[
+    (TAIL_CALL_INGRESS_TCP, "tc_ingress_tcp"),
+    (TAIL_CALL_INGRESS_UDP, "tc_ingress_udp"),
+    (TAIL_CALL_INGRESS_SCTP, "tc_ingress_sctp"),
+    (TAIL_CALL_EGRESS_ICMP, "tc_egress_icmp"),
+    (TAIL_CALL_EGRESS_TCP, "tc_egress_tcp"),
+    (TAIL_CALL_EGRESS_UDP, "tc_egress_udp"),
+    (TAIL_CALL_EGRESS_SCTP, "tc_egress_sctp"),
+];
+
+/// The client of the packets of the tests.
+pub const CLIENT: (Ipv4Addr, u16) = (Ipv4Addr::new(192, 0, 2, 1), 40000);
+
+/// The eBPF programs, loaded but not attached, and the API server programming their maps.
+pub struct Datapath {
+    pub ebpf: Ebpf,
+    pub service: BackendService,
+    pub tail_calls: ProgramArray<MapData>,
+    pub error_events: RingBuf<MapData>,
+}
+
+impl Datapath {
+    /// Loads the eBPF programs, with every program tail called into in TAIL_CALLS.
+    pub fn load() -> Datapath {
+        Datapath::load_on(None)
+    }
+
+    /// Loads the eBPF programs like load, with tc_ingress attached to the interface until the
+    /// Datapath is dropped, so that the API server can probe it. Only packets to the vips the
+    /// test programs are load balanced, the others pass through.
+    pub fn load_attached(iface: &str) -> Datapath {
+        Datapath::load_on(Some(iface))
+    }
+
+    fn load_on(iface: Option<&str>) -> Datapath {
+        #[cfg(debug_assertions)]
+        let object = include_bytes_aligned!("../../../target/bpfel-unknown-none/debug/loader");
+        #[cfg(not(debug_assertions))]
+        let object = include_bytes_aligned!("../../../target/bpfel-unknown-none/release/loader");
+        let mut ebpf = EbpfLoader::new().load(object).unwrap();
+
+        for name in ["tc_ingress", "tc_egress"] {
+            let program: &mut SchedClassifier = ebpf.program_mut(name).unwrap().try_into().unwrap();
+            program.load().unwrap();
+        }
+        let mut tail_calls = ProgramArray::try_from(ebpf.take_map("TAIL_CALLS").unwrap()).unwrap();
+        for &(index, name) in TAIL_CALLS {
+            let program: &mut SchedClassifier = ebpf.program_mut(name).unwrap().try_into().unwrap();
+            program.load().unwrap();
+            tail_calls.set(index, program.fd().unwrap(), 0).unwrap();
+        }
+        let error_events = RingBuf::try_from(ebpf.take_map("ERROR_EVENTS").unwrap()).unwrap();
+
+        let mut attached_programs = StdHashMap::new();
+        if let Some(iface) = iface {
+            // the qdisc may already be there
+            let _ = tc::qdisc_add_clsact(iface);
+            let program: &mut SchedClassifier =
+                ebpf.program_mut("tc_ingress").unwrap().try_into().unwrap();
+            let link = program.attach(iface, TcAttachType::Ingress).unwrap();
+            attached_programs.insert(
+                "tc_ingress".to_string(),
+                AttachedProgram {
+                    link: program.take_link(link).unwrap(),
+                    fd: program.fd().unwrap().try_clone().unwrap(),
+                },
+            );
+        }
+
+        let service = BackendService::new(
+            HashMap::try_from(ebpf.take_map("BACKENDS").unwrap()).unwrap(),
+            HashMap::try_from(ebpf.take_map("BACKEND_SLOTS").unwrap()).unwrap(),
+            LpmTrie::try_from(ebpf.take_map("VIP_PORT_RANGES").unwrap()).unwrap(),
+            HashMap::try_from(ebpf.take_map("GATEWAY_INDEXES").unwrap()).unwrap(),
+            HashMap::try_from(ebpf.take_map("LB_CONNECTIONS").unwrap()).unwrap(),
+            HashMap::try_from(ebpf.take_map("AFFINITIES").unwrap()).unwrap(),
+            Array::try_from(ebpf.take_map("DATAPLANE_STATE").unwrap()).unwrap(),
+            PerCpuHashMap::try_from(ebpf.take_map("VIP_STATS").unwrap()).unwrap(),
+            PerCpuArray::try_from(ebpf.take_map("DROP_STATS").unwrap()).unwrap(),
+            LpmTrie::try_from(ebpf.take_map("VIP_ACLS").unwrap()).unwrap(),
+            Array::try_from(ebpf.take_map("CAPTURE_CONFIG").unwrap()).unwrap(),
+            attached_programs,
+            MapLimits {
+                max_vips: 128,
+                max_connections: 128,
+                max_backends_per_vip: 1024,
+                vips_high_watermark: 100,
+                connections_high_watermark: 100,
+            },
+        );
+
+        Datapath {
+            ebpf,
+            service,
+            tail_calls,
+            error_events,
+        }
+    }
+
+    /// Programs the vip with the backends, on the loopback interface so that no route is
+    /// looked up for them.
+    pub async fn update(
+        &self,
+        protocol: Protocol,
+        vip: (Ipv4Addr, u16),
+        backends: impl IntoIterator<Item = (Ipv4Addr, u16)>,
+    ) {
+        let targets = Targets {
+            vip: Some(Vip {
+                ip: vip.0.into(),
+                port: vip.1 as u32,
+                protocol: protocol as i32,
+                ..Default::default()
+            }),
+            targets: backends
+                .into_iter()
+                .map(|(ip, port)| Target {
+                    daddr: ip.into(),
+                    dport: port as u32,
+                    ifindex: Some(1),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        self.service.update(Request::new(targets)).await.unwrap();
+    }
+
+    /// Runs the packet through the program once.
+    pub fn run(&self, program: &str, packet: &[u8]) -> TestRunOutput {
+        let program: &SchedClassifier = self.ebpf.program(program).unwrap().try_into().unwrap();
+        probe::test_run(program.fd().unwrap(), packet).unwrap()
+    }
+
+    /// Runs a packet of the protocol from the client to the destination through the program,
+    /// returning where it's addressed to afterwards.
+    pub fn send(
+        &self,
+        program: &str,
+        protocol: Protocol,
+        client: (Ipv4Addr, u16),
+        destination: (Ipv4Addr, u16),
+    ) -> (Ipv4Addr, u16) {
+        let output = self.run(program, &probe::build_packet(protocol, client, destination));
+        probe::packet_destination(&output.data).unwrap()
+    }
+
+    /// Returns the error events the programs sent since the last call.
+    pub fn error_events(&mut self) -> Vec<ErrorEvent> {
+        let mut events = Vec::new();
+        while let Some(item) = self.error_events.next() {
+            // SAFETY: the eBPF programs only send ErrorEvents on the ring buffer.
+            events.push(unsafe { std::ptr::read_unaligned(item.as_ptr() as *const ErrorEvent) });
+        }
+        events
+    }
+}
@@ -0,0 +1,83 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+mod datapath;
+
+use std::net::Ipv4Addr;
+
+use api_server::backends::backends_server::Backends;
+use api_server::backends::{GatewayIndex, Protocol, Vip};
+use common::BACKENDS_ARRAY_CAPACITY;
+use datapath::{Datapath, CLIENT};
+use tonic::Request;
+
+const VIP: (Ipv4Addr, u16) = (Ipv4Addr::new(172, 18, 0, 100), 80);
+// enough backends for the BackendList and two BACKEND_SLOTS entries, the last one partly filled
+const BACKENDS: u32 = 300;
+
+fn backend(index: u32) -> (Ipv4Addr, u16) {
+    (
+        Ipv4Addr::from(u32::from(Ipv4Addr::new(10, 244, 0, 1)) + index),
+        8080,
+    )
+}
+
+async fn set_index(datapath: &Datapath, index: u32) {
+    let request = GatewayIndex {
+        vip: Some(Vip {
+            ip: VIP.0.into(),
+            port: VIP.1 as u32,
+            protocol: Protocol::Tcp as i32,
+            ..Default::default()
+        }),
+        index,
+    };
+    datapath
+        .service
+        .set_gateway_index(Request::new(request))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+#[ignore = "requires CAP_BPF and the eBPF object, see tests/datapath"]
+async fn test_round_robin_past_backends_array() {
+    let datapath = Datapath::load();
+    datapath
+        .update(Protocol::Tcp, VIP, (0..BACKENDS).map(backend))
+        .await;
+
+    // the backends on either side of the end of the array and of the first slot
+    let capacity = BACKENDS_ARRAY_CAPACITY as u32;
+    let mut client = CLIENT;
+    for index in [
+        0,
+        capacity - 1,
+        capacity,
+        capacity + 1,
+        2 * capacity - 1,
+        2 * capacity,
+        BACKENDS - 1,
+    ] {
+        set_index(&datapath, index).await;
+        client.1 += 1;
+        let destination = datapath.send("tc_ingress", Protocol::Tcp, client, VIP);
+        assert_eq!(destination, backend(index), "index {}", index);
+    }
+
+    // the round robin goes on from the last backend to the first
+    client.1 += 1;
+    let destination = datapath.send("tc_ingress", Protocol::Tcp, client, VIP);
+    assert_eq!(destination, backend(0));
+
+    // and through the slots one backend after another
+    set_index(&datapath, capacity - 1).await;
+    for index in capacity - 1..capacity + 2 {
+        client.1 += 1;
+        let destination = datapath.send("tc_ingress", Protocol::Tcp, client, VIP);
+        assert_eq!(destination, backend(index), "index {}", index);
+    }
+}
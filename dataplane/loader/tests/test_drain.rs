@@ -0,0 +1,83 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+mod datapath;
+
+use std::net::Ipv4Addr;
+
+use api_server::backends::backends_server::Backends;
+use api_server::backends::{Protocol, TargetRef, Vip};
+use datapath::{Datapath, CLIENT};
+use tonic::Request;
+
+const VIP: (Ipv4Addr, u16) = (Ipv4Addr::new(172, 18, 0, 100), 53);
+const BACKENDS: [(Ipv4Addr, u16); 2] = [
+    (Ipv4Addr::new(10, 244, 0, 2), 5353),
+    (Ipv4Addr::new(10, 244, 0, 3), 5353),
+];
+
+async fn drain(datapath: &Datapath, protocol: Protocol, (ip, port): (Ipv4Addr, u16)) {
+    let target = TargetRef {
+        vip: Some(Vip {
+            ip: VIP.0.into(),
+            port: VIP.1 as u32,
+            protocol: protocol as i32,
+            ..Default::default()
+        }),
+        daddr: ip.into(),
+        dport: port as u32,
+    };
+    datapath
+        .service
+        .drain_backend(Request::new(target))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+#[ignore = "requires CAP_BPF and the eBPF object, see tests/datapath"]
+async fn test_drain_keeps_flows() {
+    for protocol in [Protocol::Tcp, Protocol::Udp] {
+        let datapath = Datapath::load();
+        datapath.update(protocol, VIP, BACKENDS).await;
+        let destination = datapath.send("tc_ingress", protocol, CLIENT, VIP);
+        assert_eq!(destination, BACKENDS[0], "{:?}", protocol);
+
+        drain(&datapath, protocol, BACKENDS[0]).await;
+
+        // the flow established before the drain stays with its backend
+        for _ in 0..2 {
+            let destination = datapath.send("tc_ingress", protocol, CLIENT, VIP);
+            assert_eq!(destination, BACKENDS[0], "{:?}", protocol);
+        }
+        // and new flows go to the backends that aren't draining
+        for n in 1..3 {
+            let client = (CLIENT.0, CLIENT.1 + n);
+            let destination = datapath.send("tc_ingress", protocol, client, VIP);
+            assert_eq!(destination, BACKENDS[1], "{:?}", protocol);
+        }
+    }
+}
+
+#[tokio::test]
+#[ignore = "requires CAP_BPF and the eBPF object, see tests/datapath"]
+async fn test_drain_all_backends() {
+    let datapath = Datapath::load();
+    datapath.update(Protocol::Udp, VIP, BACKENDS).await;
+    let destination = datapath.send("tc_ingress", Protocol::Udp, CLIENT, VIP);
+    assert_eq!(destination, BACKENDS[0]);
+
+    for backend in BACKENDS {
+        drain(&datapath, Protocol::Udp, backend).await;
+    }
+
+    // the flow continues even with every backend draining, while new flows get none
+    let destination = datapath.send("tc_ingress", Protocol::Udp, CLIENT, VIP);
+    assert_eq!(destination, BACKENDS[0]);
+    let client = (CLIENT.0, CLIENT.1 + 1);
+    let destination = datapath.send("tc_ingress", Protocol::Udp, client, VIP);
+    assert!(!BACKENDS.contains(&destination), "{:?}", destination);
+}
@@ -0,0 +1,112 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+mod datapath;
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+
+use api_server::backends::backends_server::Backends;
+use api_server::backends::{Protocol, Target, Targets, Vip};
+use api_server::resolver::Resolver;
+use datapath::{Datapath, CLIENT};
+use tonic::Request;
+
+const VIP: (Ipv4Addr, u16) = (Ipv4Addr::new(172, 18, 0, 100), 80);
+const BACKEND_PORT: u16 = 8080;
+
+// Resolves hostnames to the addresses the test sets.
+#[derive(Clone, Default)]
+struct StubResolver(Arc<Mutex<HashMap<String, Ipv4Addr>>>);
+
+impl StubResolver {
+    fn set(&self, hostname: &str, addr: Ipv4Addr) {
+        self.0.lock().unwrap().insert(hostname.to_string(), addr);
+    }
+}
+
+#[tonic::async_trait]
+impl Resolver for StubResolver {
+    async fn lookup(&self, hostname: &str, port: u16) -> std::io::Result<Vec<SocketAddr>> {
+        let addr = self.0.lock().unwrap().get(hostname).copied();
+        Ok(addr
+            .map(|addr| SocketAddr::from((addr, port)))
+            .into_iter()
+            .collect())
+    }
+}
+
+fn targets(hostnames: &[&str]) -> Targets {
+    Targets {
+        vip: Some(Vip {
+            ip: VIP.0.into(),
+            port: VIP.1 as u32,
+            protocol: Protocol::Tcp as i32,
+            ..Default::default()
+        }),
+        targets: hostnames
+            .iter()
+            .map(|hostname| Target {
+                hostname: hostname.to_string(),
+                dport: BACKEND_PORT as u32,
+                ifindex: Some(1),
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+#[ignore = "requires CAP_BPF and the eBPF object, see tests/datapath"]
+async fn test_refresh_hostnames() {
+    let resolver = StubResolver::default();
+    resolver.set("a.example", Ipv4Addr::new(10, 244, 0, 2));
+    resolver.set("b.example", Ipv4Addr::new(10, 244, 0, 3));
+    let mut datapath = Datapath::load();
+    datapath.service = datapath.service.with_resolver(Arc::new(resolver.clone()));
+    datapath
+        .service
+        .update(Request::new(targets(&["a.example", "b.example"])))
+        .await
+        .unwrap();
+
+    // each new client moves the round-robin position on
+    let client = |n: u16| (CLIENT.0, CLIENT.1 + n);
+    let destination = datapath.send("tc_ingress", Protocol::Tcp, client(0), VIP);
+    assert_eq!(destination, (Ipv4Addr::new(10, 244, 0, 2), BACKEND_PORT));
+
+    // the new address is picked up, without starting the round robin over
+    resolver.set("b.example", Ipv4Addr::new(10, 244, 0, 4));
+    datapath.service.refresh_hostnames().await.unwrap();
+    let destination = datapath.send("tc_ingress", Protocol::Tcp, client(1), VIP);
+    assert_eq!(destination, (Ipv4Addr::new(10, 244, 0, 4), BACKEND_PORT));
+
+    // the connection of the first client stays with its backend
+    let destination = datapath.send("tc_ingress", Protocol::Tcp, client(0), VIP);
+    assert_eq!(destination, (Ipv4Addr::new(10, 244, 0, 2), BACKEND_PORT));
+}
+
+#[tokio::test]
+#[ignore = "requires CAP_BPF and the eBPF object, see tests/datapath"]
+async fn test_refresh_hostnames_failure() {
+    let resolver = StubResolver::default();
+    resolver.set("a.example", Ipv4Addr::new(10, 244, 0, 2));
+    let mut datapath = Datapath::load();
+    datapath.service = datapath.service.with_resolver(Arc::new(resolver.clone()));
+    datapath
+        .service
+        .update(Request::new(targets(&["a.example"])))
+        .await
+        .unwrap();
+
+    // a hostname that no longer resolves keeps its last address
+    resolver.0.lock().unwrap().clear();
+    datapath.service.refresh_hostnames().await.unwrap();
+    let destination = datapath.send("tc_ingress", Protocol::Tcp, CLIENT, VIP);
+    assert_eq!(destination, (Ipv4Addr::new(10, 244, 0, 2), BACKEND_PORT));
+}
@@ -0,0 +1,187 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+mod datapath;
+
+use std::ffi::CString;
+use std::io;
+use std::mem;
+use std::net::Ipv4Addr;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use api_server::backends::backends_server::Backends;
+use api_server::backends::{Protocol, Target, Targets, Vip};
+use datapath::{Datapath, CLIENT};
+use tonic::Request;
+
+const VIP: (Ipv4Addr, u16) = (Ipv4Addr::new(172, 18, 0, 100), 53);
+const BACKEND: (Ipv4Addr, u16) = (Ipv4Addr::new(10, 244, 0, 2), 5353);
+const MIRROR: (Ipv4Addr, u16) = (Ipv4Addr::new(10, 244, 9, 2), 5454);
+const MIRROR_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x02];
+const LINK_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+
+// the interface the mirror is routed through, and its peer the mirror is behind
+const MIRROR_LINK: &str = "blixt-mirror0";
+const MIRROR_PEER: &str = "blixt-mirror1";
+
+const ETH_HDR_LEN: usize = 14;
+const ETH_P_IP: u16 = 0x0800;
+
+fn ip(args: &str) {
+    let status = Command::new("ip")
+        .args(args.split_whitespace())
+        .status()
+        .unwrap();
+    assert!(status.success(), "ip {}", args);
+}
+
+fn mac(addr: [u8; 6]) -> String {
+    addr.map(|byte| format!("{byte:02x}")).join(":")
+}
+
+fn if_index(iface: &str) -> u32 {
+    let name = CString::new(iface).unwrap();
+    let ifindex = unsafe { libc::if_nametoindex(name.as_ptr()) };
+    assert_ne!(ifindex, 0, "{}", io::Error::last_os_error());
+    ifindex
+}
+
+// Moves the thread into a network namespace of its own, where the mirror is routed through a
+// veth pair to a neighbor of a known address. bpf_prog_test_run runs the packets on the loopback
+// interface of the namespace, which forwards them.
+fn mirror_network() {
+    // SAFETY: only the network namespace of the calling thread changes.
+    let ret = unsafe { libc::unshare(libc::CLONE_NEWNET) };
+    assert_eq!(ret, 0, "{}", io::Error::last_os_error());
+    ip("link set lo up");
+    ip(&format!(
+        "link add {MIRROR_LINK} address {} type veth peer name {MIRROR_PEER}",
+        mac(LINK_MAC)
+    ));
+    ip(&format!("link set {MIRROR_LINK} up"));
+    ip(&format!("link set {MIRROR_PEER} up"));
+    ip(&format!("addr add 10.244.9.1/24 dev {MIRROR_LINK}"));
+    ip(&format!(
+        "neigh replace {} lladdr {} dev {MIRROR_LINK} nud permanent",
+        MIRROR.0,
+        mac(MIRROR_MAC)
+    ));
+    std::fs::write("/proc/sys/net/ipv4/conf/all/forwarding", "1").unwrap();
+}
+
+// Opens a socket receiving every frame of the interface.
+fn capture(iface: &str) -> OwnedFd {
+    let protocol = (libc::ETH_P_ALL as u16).to_be();
+    // SAFETY: the socket is owned by the returned OwnedFd.
+    let fd = unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW, protocol as i32) };
+    assert!(fd >= 0, "{}", io::Error::last_os_error());
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+    addr.sll_family = libc::AF_PACKET as u16;
+    addr.sll_protocol = protocol;
+    addr.sll_ifindex = if_index(iface) as i32;
+    let ret = unsafe {
+        libc::bind(
+            fd.as_raw_fd(),
+            &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+            mem::size_of::<libc::sockaddr_ll>() as u32,
+        )
+    };
+    assert_eq!(ret, 0, "{}", io::Error::last_os_error());
+
+    let timeout = libc::timeval {
+        tv_sec: 1,
+        tv_usec: 0,
+    };
+    let ret = unsafe {
+        libc::setsockopt(
+            fd.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_RCVTIMEO,
+            &timeout as *const libc::timeval as *const libc::c_void,
+            mem::size_of::<libc::timeval>() as u32,
+        )
+    };
+    assert_eq!(ret, 0, "{}", io::Error::last_os_error());
+    fd
+}
+
+// Returns the first IPv4 frame the socket receives within a few seconds, skipping the IPv6
+// neighbor discovery of the new interfaces.
+fn receive_ipv4(fd: &OwnedFd) -> Vec<u8> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut buf = [0u8; 2048];
+    while Instant::now() < deadline {
+        let len = unsafe {
+            libc::recv(
+                fd.as_raw_fd(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                0,
+            )
+        };
+        if len < ETH_HDR_LEN as isize {
+            continue;
+        }
+        let frame = &buf[..len as usize];
+        if frame[12..14] == ETH_P_IP.to_be_bytes() {
+            return frame.to_vec();
+        }
+    }
+    panic!("no IPv4 frame received on {}", MIRROR_PEER);
+}
+
+#[tokio::test]
+#[ignore = "requires CAP_BPF, CAP_NET_ADMIN and the eBPF object, see tests/datapath"]
+async fn test_mirror() {
+    mirror_network();
+    let datapath = Datapath::load();
+    let targets = Targets {
+        vip: Some(Vip {
+            ip: VIP.0.into(),
+            port: VIP.1 as u32,
+            protocol: Protocol::Udp as i32,
+            ..Default::default()
+        }),
+        targets: vec![Target {
+            daddr: BACKEND.0.into(),
+            dport: BACKEND.1 as u32,
+            ifindex: Some(1),
+            ..Default::default()
+        }],
+        mirror: Some(Target {
+            daddr: MIRROR.0.into(),
+            dport: MIRROR.1 as u32,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    datapath
+        .service
+        .update(Request::new(targets))
+        .await
+        .unwrap();
+    let socket = capture(MIRROR_PEER);
+
+    // the packet still goes to its backend, with the ethernet addresses it came with
+    let packet = api_server::probe::build_packet(Protocol::Udp, CLIENT, VIP);
+    let output = datapath.run("tc_ingress", &packet);
+    assert_eq!(
+        api_server::probe::packet_destination(&output.data),
+        Some(BACKEND)
+    );
+    assert_eq!(output.data[..12], packet[..12]);
+
+    // and its copy to the mirror is addressed to the mirror's neighbor, from the interface it's
+    // routed through
+    let frame = receive_ipv4(&socket);
+    assert_eq!(frame[..6], MIRROR_MAC);
+    assert_eq!(frame[6..12], LINK_MAC);
+    assert_eq!(api_server::probe::packet_destination(&frame), Some(MIRROR));
+}
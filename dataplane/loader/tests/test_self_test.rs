@@ -0,0 +1,49 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+mod datapath;
+
+use std::net::Ipv4Addr;
+
+use api_server::backends::Protocol;
+use api_server::probe::build_packet;
+use datapath::{Datapath, CLIENT};
+
+const VIP: (Ipv4Addr, u16) = (Ipv4Addr::new(172, 18, 0, 100), 80);
+const BACKEND: (Ipv4Addr, u16) = (Ipv4Addr::new(10, 244, 0, 2), 8080);
+
+// see include/uapi/linux/pkt_cls.h
+const TC_ACT_OK: u32 = 0;
+const TC_ACT_REDIRECT: u32 = 7;
+
+#[tokio::test]
+#[ignore = "requires CAP_BPF and the eBPF object, see tests/datapath"]
+async fn test_ingress_action() {
+    let datapath = Datapath::load();
+    datapath.update(Protocol::Tcp, VIP, [BACKEND]).await;
+    datapath.update(Protocol::Udp, VIP, [BACKEND]).await;
+
+    // the packets to a vip are redirected to their backend
+    for (i, protocol) in [Protocol::Tcp, Protocol::Udp].into_iter().enumerate() {
+        let client = (CLIENT.0, CLIENT.1 + i as u16);
+        let output = datapath.run("tc_ingress", &build_packet(protocol, client, VIP));
+        assert_eq!(output.retval, TC_ACT_REDIRECT, "{:?}", protocol);
+    }
+
+    // and the others are passed on
+    let other = (Ipv4Addr::new(172, 18, 0, 101), 80);
+    let output = datapath.run("tc_ingress", &build_packet(Protocol::Tcp, CLIENT, other));
+    assert_eq!(output.retval, TC_ACT_OK);
+}
+
+#[tokio::test]
+#[ignore = "requires CAP_BPF and the eBPF object, see tests/datapath"]
+async fn test_self_test() {
+    // the self-test programs its temporary vip with a backend on the interface, and only the
+    // packets to that vip are load balanced while it runs
+    let datapath = Datapath::load_attached("lo");
+    datapath.service.self_test(1).await.unwrap();
+}
@@ -0,0 +1,115 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+mod datapath;
+
+use std::net::Ipv4Addr;
+
+use api_server::backends::backends_server::Backends;
+use api_server::backends::{Acl, AclMode, Cidr, GatewayIndex, Protocol, Vip, VipPair};
+use datapath::{Datapath, CLIENT};
+use tonic::Request;
+
+const FIRST: (Ipv4Addr, u16) = (Ipv4Addr::new(172, 18, 0, 100), 80);
+const SECOND: (Ipv4Addr, u16) = (Ipv4Addr::new(172, 18, 0, 101), 80);
+const BLUE: [(Ipv4Addr, u16); 2] = [
+    (Ipv4Addr::new(10, 244, 0, 2), 8080),
+    (Ipv4Addr::new(10, 244, 0, 3), 8080),
+];
+const GREEN: [(Ipv4Addr, u16); 2] = [
+    (Ipv4Addr::new(10, 244, 1, 2), 8080),
+    (Ipv4Addr::new(10, 244, 1, 3), 8080),
+];
+
+fn vip((ip, port): (Ipv4Addr, u16)) -> Vip {
+    Vip {
+        ip: ip.into(),
+        port: port as u32,
+        protocol: Protocol::Tcp as i32,
+        ..Default::default()
+    }
+}
+
+async fn index(datapath: &Datapath, of: (Ipv4Addr, u16)) -> u32 {
+    let response = datapath
+        .service
+        .get_gateway_index(Request::new(vip(of)))
+        .await
+        .unwrap();
+    response.into_inner().index
+}
+
+#[tokio::test]
+#[ignore = "requires CAP_BPF and the eBPF object, see tests/datapath"]
+async fn test_swap() {
+    let datapath = Datapath::load();
+    datapath.update(Protocol::Tcp, FIRST, BLUE).await;
+    datapath.update(Protocol::Tcp, SECOND, GREEN).await;
+    let acl = Acl {
+        vip: Some(vip(FIRST)),
+        mode: AclMode::Denylist as i32,
+        cidrs: vec![Cidr {
+            ip: Ipv4Addr::new(198, 51, 100, 0).into(),
+            prefix_len: 24,
+        }],
+    };
+    datapath
+        .service
+        .set_acl(Request::new(acl.clone()))
+        .await
+        .unwrap();
+    datapath
+        .service
+        .set_gateway_index(Request::new(GatewayIndex {
+            vip: Some(vip(SECOND)),
+            index: 1,
+        }))
+        .await
+        .unwrap();
+
+    datapath
+        .service
+        .swap(Request::new(VipPair {
+            first: Some(vip(FIRST)),
+            second: Some(vip(SECOND)),
+        }))
+        .await
+        .unwrap();
+
+    // the backends were swapped, and both vips start over at their first backend
+    assert_eq!(index(&datapath, FIRST).await, 0);
+    assert_eq!(index(&datapath, SECOND).await, 0);
+    assert_eq!(
+        datapath.send("tc_ingress", Protocol::Tcp, CLIENT, FIRST),
+        GREEN[0]
+    );
+    let client = (CLIENT.0, CLIENT.1 + 1);
+    assert_eq!(
+        datapath.send("tc_ingress", Protocol::Tcp, client, SECOND),
+        BLUE[0]
+    );
+
+    // the ACL stays with its vip
+    let first_acl = datapath
+        .service
+        .get_acl(Request::new(vip(FIRST)))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(first_acl, acl);
+    let second_acl = datapath
+        .service
+        .get_acl(Request::new(vip(SECOND)))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(second_acl.cidrs.is_empty());
+    let denied = (Ipv4Addr::new(198, 51, 100, 1), 40000);
+    assert_eq!(
+        datapath.send("tc_ingress", Protocol::Tcp, denied, SECOND),
+        BLUE[1]
+    );
+}
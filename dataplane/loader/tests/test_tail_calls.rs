@@ -0,0 +1,84 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+mod datapath;
+
+use std::net::Ipv4Addr;
+
+use api_server::backends::Protocol;
+use common::{PROGRAM_TC_INGRESS, TAIL_CALL_INGRESS_UDP};
+use datapath::{Datapath, CLIENT};
+
+const VIP: (Ipv4Addr, u16) = (Ipv4Addr::new(172, 18, 0, 100), 80);
+const BACKEND: (Ipv4Addr, u16) = (Ipv4Addr::new(10, 244, 0, 2), 8080);
+
+// see include/uapi/asm-generic/errno-base.h
+const ENOENT: i64 = 2;
+// see include/uapi/linux/in.h
+const IPPROTO_UDP: u32 = 17;
+
+#[tokio::test]
+#[ignore = "requires CAP_BPF and the eBPF object, see tests/datapath"]
+async fn test_tail_call_ingress() {
+    let datapath = Datapath::load();
+    datapath.update(Protocol::Tcp, VIP, [BACKEND]).await;
+    datapath.update(Protocol::Udp, VIP, [BACKEND]).await;
+
+    // tc_ingress hands the packets of each protocol to its program
+    for protocol in [Protocol::Tcp, Protocol::Udp] {
+        let destination = datapath.send("tc_ingress", protocol, CLIENT, VIP);
+        assert_eq!(destination, BACKEND, "{:?}", protocol);
+    }
+}
+
+#[tokio::test]
+#[ignore = "requires CAP_BPF and the eBPF object, see tests/datapath"]
+async fn test_ingress_protocol_programs() {
+    let datapath = Datapath::load();
+    datapath.update(Protocol::Tcp, VIP, [BACKEND]).await;
+    datapath.update(Protocol::Udp, VIP, [BACKEND]).await;
+
+    // the program of each protocol load balances its packets on its own
+    let destination = datapath.send("tc_ingress_tcp", Protocol::Tcp, CLIENT, VIP);
+    assert_eq!(destination, BACKEND);
+    let client = (CLIENT.0, CLIENT.1 + 1);
+    let destination = datapath.send("tc_ingress_udp", Protocol::Udp, client, VIP);
+    assert_eq!(destination, BACKEND);
+
+    // packets that aren't to a vip pass through untouched
+    let other = (Ipv4Addr::new(172, 18, 0, 101), 80);
+    let destination = datapath.send("tc_ingress_tcp", Protocol::Tcp, CLIENT, other);
+    assert_eq!(destination, other);
+}
+
+#[tokio::test]
+#[ignore = "requires CAP_BPF and the eBPF object, see tests/datapath"]
+async fn test_missing_tail_call() {
+    let mut datapath = Datapath::load();
+    datapath.update(Protocol::Udp, VIP, [BACKEND]).await;
+    datapath
+        .tail_calls
+        .clear_index(&TAIL_CALL_INGRESS_UDP)
+        .unwrap();
+    assert!(datapath.error_events().is_empty());
+
+    // without its program, the packet is passed on as it is and the failure is reported
+    let output = datapath.run(
+        "tc_ingress",
+        &api_server::probe::build_packet(Protocol::Udp, CLIENT, VIP),
+    );
+    assert_eq!(output.retval, 0);
+    assert_eq!(
+        api_server::probe::packet_destination(&output.data),
+        Some(VIP)
+    );
+    let events = datapath.error_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].program, PROGRAM_TC_INGRESS);
+    assert_eq!(events[0].proto, IPPROTO_UDP);
+    assert_eq!(events[0].dst_addr, u32::from(VIP.0));
+    assert_eq!(events[0].error, -ENOENT);
+}
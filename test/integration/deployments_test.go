@@ -0,0 +1,213 @@
+//go:build integration_tests
+// +build integration_tests
+
+/*
+Copyright 2024 The Kubernetes Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+package integration
+
+import (
+	"context"
+	"fmt"
+	"net"
+	"os"
+	"path/filepath"
+	"sort"
+	"strings"
+	"testing"
+	"time"
+
+	"github.com/kong/kubernetes-testing-framework/pkg/clusters"
+	"github.com/stretchr/testify/require"
+	metav1 "k8s.io/apimachinery/pkg/apis/meta/v1"
+	gatewayv1beta1 "sigs.k8s.io/gateway-api/apis/v1beta1"
+
+	testutils "github.com/kubernetes-sigs/blixt/internal/test/utils"
+	"github.com/kubernetes-sigs/blixt/pkg/vars"
+)
+
+// the name of the dataplane DaemonSet once config/default prefixed it
+const dataplaneDaemonSetName = "blixt-dataplane"
+
+// kustomizeOverlay is a kustomize overlay generated at test time on top of a base kustomization,
+// so that the suite can deploy blixt in several configurations without a directory for each.
+type kustomizeOverlay struct {
+	// the kustomization the overlay patches, relative to the test directory
+	base string
+	// inline patches, each merged into the resource of the base with the same kind and name
+	patches []string
+}
+
+// write writes the kustomization of the overlay in a new temporary directory of the test, and
+// returns the directory.
+func (o kustomizeOverlay) write(t *testing.T) string {
+	t.Helper()
+	base, err := filepath.Abs(o.base)
+	require.NoError(t, err)
+
+	var b strings.Builder
+	b.WriteString("apiVersion: kustomize.config.k8s.io/v1beta1\n")
+	b.WriteString("kind: Kustomization\n")
+	fmt.Fprintf(&b, "resources:\n- %s\n", base)
+	if len(o.patches) > 0 {
+		b.WriteString("patches:\n")
+	}
+	for _, patch := range o.patches {
+		b.WriteString("- patch: |-\n")
+		for _, line := range strings.Split(strings.TrimSpace(patch), "\n") {
+			fmt.Fprintf(&b, "    %s\n", line)
+		}
+	}
+
+	dir := t.TempDir()
+	require.NoError(t, os.WriteFile(filepath.Join(dir, "kustomization.yaml"), []byte(b.String()), 0o600))
+	return dir
+}
+
+// dataplaneEnvPatch returns a patch setting environment variables of the dataplane container,
+// which the dataplane reads its options from.
+func dataplaneEnvPatch(env map[string]string) string {
+	names := make([]string, 0, len(env))
+	for name := range env {
+		names = append(names, name)
+	}
+	sort.Strings(names)
+
+	var b strings.Builder
+	fmt.Fprintf(&b, `apiVersion: apps/v1
+kind: DaemonSet
+metadata:
+  name: %s
+  namespace: %s
+spec:
+  template:
+    spec:
+      containers:
+      - name: dataplane
+        env:
+`, dataplaneDaemonSetName, vars.DefaultNamespace)
+	for _, name := range names {
+		fmt.Fprintf(&b, "        - name: %s\n          value: %q\n", name, env[name])
+	}
+	return b.String()
+}
+
+// dataplaneConfigurations are the configurations of the dataplane the suite exercises, in
+// addition to the one of the base it's deployed with.
+var dataplaneConfigurations = []struct {
+	name    string
+	patches []string
+}{
+	{
+		name: "small-maps",
+		patches: []string{dataplaneEnvPatch(map[string]string{
+			"BLIXT_MAX_VIPS":        "16",
+			"BLIXT_MAX_CONNECTIONS": "1024",
+		})},
+	},
+	{
+		name: "self-test",
+		patches: []string{dataplaneEnvPatch(map[string]string{
+			"BLIXT_SELF_TEST": "true",
+		})},
+	},
+}
+
+// TestDataplaneConfigurations redeploys the dataplane in each of the configurations and checks
+// that a TCPRoute serves traffic through it, then restores the base deployment.
+func TestDataplaneConfigurations(t *testing.T) {
+	dataplaneConfigurationsCleanupKey := "dataplaneconfigurations"
+	defer func() {
+		testutils.DumpDiagnosticsIfFailed(ctx, t, env.Cluster())
+		if err := runCleanup(dataplaneConfigurationsCleanupKey); err != nil {
+			t.Errorf("cleanup failed: %s", err)
+		}
+	}()
+
+	addCleanup(dataplaneConfigurationsCleanupKey, func(ctx context.Context) error {
+		cleanupLog("restoring blixt via config/test kustomize")
+		if err := clusters.KustomizeDeployForCluster(ctx, env.Cluster(), testKustomize); err != nil {
+			return err
+		}
+		return waitForDataplaneRollout(ctx)
+	})
+
+	for _, config := range dataplaneConfigurations {
+		t.Run(config.name, func(t *testing.T) {
+			overlay := kustomizeOverlay{base: testKustomize, patches: config.patches}
+			t.Logf("deploying blixt with the %s dataplane configuration", config.name)
+			require.NoError(t, clusters.KustomizeDeployForCluster(ctx, env.Cluster(), overlay.write(t)))
+			require.NoError(t, waitForDataplaneRollout(ctx))
+
+			verifyTCPRouteServes(t)
+		})
+	}
+}
+
+// verifyTCPRouteServes deploys the TCPRoute sample and checks that it's reachable through its
+// Gateway, then deletes it.
+func verifyTCPRouteServes(t *testing.T) {
+	t.Helper()
+	t.Log("deploying config/samples/tcproute kustomize")
+	require.NoError(t, clusters.KustomizeDeployForCluster(ctx, env.Cluster(), tcprouteSampleKustomize))
+	defer func() {
+		require.NoError(t, clusters.KustomizeDeleteForCluster(ctx, env.Cluster(), tcprouteSampleKustomize, "--ignore-not-found=true"))
+	}()
+
+	gw := waitForGatewayCondition(t, tcprouteSampleName, gatewayv1beta1.GatewayConditionProgrammed, metav1.ConditionTrue)
+	require.NotEmpty(t, gw.Status.Addresses)
+	gwaddr := fmt.Sprintf("%s:8080", gw.Status.Addresses[0].Value)
+
+	t.Log("verifying TCP connectivity to the server")
+	var conn net.Conn
+	require.Eventually(t, func() bool {
+		var err error
+		conn, err = net.Dial("tcp", gwaddr)
+		if err != nil {
+			t.Logf("received error connecting to TCP server: [%s], retrying...", err)
+			return false
+		}
+		return true
+	}, time.Minute*5, time.Second)
+	defer conn.Close()
+
+	require.Contains(t, writeAndReadTCP(t, conn), tcprouteSampleName)
+}
+
+// waitForDataplaneRollout waits for every dataplane pod to run the current template of the
+// DaemonSet and be available, since pods of the previous configuration are ready as well while
+// it rolls out.
+func waitForDataplaneRollout(ctx context.Context) error {
+	for {
+		ds, err := env.Cluster().Client().AppsV1().DaemonSets(vars.DefaultNamespace).
+			Get(ctx, dataplaneDaemonSetName, metav1.GetOptions{})
+		if err != nil {
+			return fmt.Errorf("failed to fetch the dataplane DaemonSet: %w", err)
+		}
+		status := ds.Status
+		if status.ObservedGeneration >= ds.Generation &&
+			status.UpdatedNumberScheduled == status.DesiredNumberScheduled &&
+			status.NumberAvailable == status.DesiredNumberScheduled {
+			return waitForDataplaneReadiness(ctx, env)
+		}
+
+		select {
+		case <-ctx.Done():
+			return fmt.Errorf("context completed while waiting for the dataplane rollout: %w", ctx.Err())
+		case <-time.After(time.Second):
+		}
+	}
+}
@@ -23,6 +23,7 @@ import (
 	"context"
 	"fmt"
 	"net/http"
+	"strings"
 	"testing"
 	"time"
 
@@ -32,6 +33,7 @@ import (
 	corev1 "k8s.io/api/core/v1"
 	metav1 "k8s.io/apimachinery/pkg/apis/meta/v1"
 	"k8s.io/apimachinery/pkg/util/intstr"
+	"k8s.io/apimachinery/pkg/watch"
 	gatewayv1alpha2 "sigs.k8s.io/gateway-api/apis/v1alpha2"
 	gatewayv1beta1 "sigs.k8s.io/gateway-api/apis/v1beta1"
 
@@ -231,3 +233,232 @@ func TestGatewayBasics(t *testing.T) {
 	}, time.Minute, time.Second)
 
 }
+
+func TestGatewayConditionTiming(t *testing.T) {
+	gatewayTimingCleanupKey := "gatewaytiming"
+	defer func() {
+		testutils.DumpDiagnosticsIfFailed(ctx, t, env.Cluster())
+		runCleanup(gatewayTimingCleanupKey) //nolint:errcheck
+	}()
+
+	t.Log("deploying GatewayClass")
+	gwc := &gatewayv1beta1.GatewayClass{
+		ObjectMeta: metav1.ObjectMeta{
+			Name: uuid.NewString(),
+		},
+		Spec: gatewayv1beta1.GatewayClassSpec{
+			ControllerName: vars.GatewayClassControllerName,
+		},
+	}
+	gwc, err := gwclient.GatewayV1beta1().GatewayClasses().Create(ctx, gwc, metav1.CreateOptions{})
+	require.NoError(t, err)
+	addCleanup(gatewayTimingCleanupKey, func(ctx context.Context) error {
+		cleanupLog("cleaning up gatewayclass")
+		return gwclient.GatewayV1beta1().GatewayClasses().Delete(ctx, gwc.Name, metav1.DeleteOptions{})
+	})
+
+	t.Log("watching the Gateway before creating it so that no condition update is missed")
+	gwName := uuid.NewString()
+	watcher, err := gwclient.GatewayV1beta1().Gateways(corev1.NamespaceDefault).Watch(ctx, metav1.ListOptions{
+		FieldSelector: fmt.Sprintf("metadata.name=%s", gwName),
+	})
+	require.NoError(t, err)
+	defer watcher.Stop()
+
+	// the controlplane logs are checked from this point on, which covers the whole pending window
+	pendingWindowStart := metav1.Now()
+
+	t.Log("creating a Gateway which is pending until MetalLB assigns it an IP")
+	gw := &gatewayv1beta1.Gateway{
+		ObjectMeta: metav1.ObjectMeta{
+			Name: gwName,
+		},
+		Spec: gatewayv1beta1.GatewaySpec{
+			GatewayClassName: gatewayv1beta1.ObjectName(gwc.Name),
+			Listeners: []gatewayv1beta1.Listener{{
+				Name:     "tcp",
+				Protocol: gatewayv1beta1.TCPProtocolType,
+				Port:     gatewayv1beta1.PortNumber(8080),
+			}},
+		},
+	}
+	gw, err = gwclient.GatewayV1beta1().Gateways(corev1.NamespaceDefault).Create(ctx, gw, metav1.CreateOptions{})
+	require.NoError(t, err)
+	addCleanup(gatewayTimingCleanupKey, func(ctx context.Context) error {
+		cleanupLog("cleaning up gateway")
+		return gwclient.GatewayV1beta1().Gateways(corev1.NamespaceDefault).Delete(ctx, gw.Name, metav1.DeleteOptions{})
+	})
+
+	t.Log("recording the Programmed condition of the Gateway until it's programmed")
+	// each element is the Programmed status seen in an update of the Gateway, with consecutive
+	// duplicates removed, and only once the Gateway was accepted
+	var programmedSequence []metav1.ConditionStatus
+	timeout := time.After(time.Minute)
+	for programmed := false; !programmed; {
+		select {
+		case event, ok := <-watcher.ResultChan():
+			require.True(t, ok, "the Gateway watch closed before the Gateway was programmed")
+			if event.Type != watch.Added && event.Type != watch.Modified {
+				continue
+			}
+			observed, ok := event.Object.(*gatewayv1beta1.Gateway)
+			require.True(t, ok)
+
+			accepted := findGatewayCondition(observed, gatewayv1beta1.GatewayConditionAccepted)
+			programmedCond := findGatewayCondition(observed, gatewayv1beta1.GatewayConditionProgrammed)
+			if programmedCond != nil && programmedCond.Status == metav1.ConditionTrue {
+				require.NotNil(t, accepted, "the Gateway was programmed before it was accepted")
+				require.Equal(t, metav1.ConditionTrue, accepted.Status, "the Gateway was programmed before it was accepted")
+			}
+			if accepted == nil || accepted.Status != metav1.ConditionTrue || programmedCond == nil {
+				continue
+			}
+			if n := len(programmedSequence); n == 0 || programmedSequence[n-1] != programmedCond.Status {
+				programmedSequence = append(programmedSequence, programmedCond.Status)
+			}
+			if programmedCond.Status == metav1.ConditionFalse {
+				require.Failf(t, "the Gateway was not programmed while pending", "reason %s: %s", programmedCond.Reason, programmedCond.Message)
+			}
+			if programmedCond.Status == metav1.ConditionUnknown {
+				require.Equal(t, string(gatewayv1beta1.GatewayReasonPending), programmedCond.Reason)
+			}
+			programmed = programmedCond.Status == metav1.ConditionTrue
+		case <-timeout:
+			require.FailNow(t, "timed out waiting for the Gateway to be programmed", "Programmed sequence: %v", programmedSequence)
+		}
+	}
+
+	t.Logf("verifying the Programmed condition went from Unknown to True: %v", programmedSequence)
+	// MetalLB may assign the IP before the first status update, in which case the pending
+	// Unknown status is never observed.
+	switch len(programmedSequence) {
+	case 1:
+		require.Equal(t, metav1.ConditionTrue, programmedSequence[0])
+	case 2:
+		require.Equal(t, []metav1.ConditionStatus{metav1.ConditionUnknown, metav1.ConditionTrue}, programmedSequence)
+	default:
+		require.Failf(t, "unexpected Programmed condition sequence", "%v", programmedSequence)
+	}
+
+	t.Log("verifying the controlplane logged no errors while the Gateway was pending")
+	controlplanes, err := env.Cluster().Client().CoreV1().Pods(vars.DefaultNamespace).List(ctx, metav1.ListOptions{
+		LabelSelector: "control-plane=controlplane",
+	})
+	require.NoError(t, err)
+	require.NotEmpty(t, controlplanes.Items)
+	for _, pod := range controlplanes.Items {
+		logs, err := env.Cluster().Client().CoreV1().Pods(vars.DefaultNamespace).GetLogs(pod.Name, &corev1.PodLogOptions{
+			Container: "manager",
+			SinceTime: &pendingWindowStart,
+		}).DoRaw(ctx)
+		require.NoError(t, err)
+		for _, line := range strings.Split(string(logs), "\n") {
+			require.NotContains(t, line, "ERROR", "controlplane pod %s logged an error", pod.Name)
+		}
+	}
+}
+
+func TestGatewayAddressChange(t *testing.T) {
+	gatewayAddressCleanupKey := "gatewayaddress"
+	defer func() {
+		testutils.DumpDiagnosticsIfFailed(ctx, t, env.Cluster())
+		runCleanup(gatewayAddressCleanupKey) //nolint:errcheck
+	}()
+
+	t.Log("deploying GatewayClass")
+	gwc := &gatewayv1beta1.GatewayClass{
+		ObjectMeta: metav1.ObjectMeta{
+			Name: uuid.NewString(),
+		},
+		Spec: gatewayv1beta1.GatewayClassSpec{
+			ControllerName: vars.GatewayClassControllerName,
+		},
+	}
+	gwc, err := gwclient.GatewayV1beta1().GatewayClasses().Create(ctx, gwc, metav1.CreateOptions{})
+	require.NoError(t, err)
+	addCleanup(gatewayAddressCleanupKey, func(ctx context.Context) error {
+		cleanupLog("cleaning up gatewayclass")
+		return gwclient.GatewayV1beta1().GatewayClasses().Delete(ctx, gwc.Name, metav1.DeleteOptions{})
+	})
+
+	ipAddrType := gatewayv1beta1.IPAddressType
+	oldAddr := gatewayv1beta1.GatewayAddress{Type: &ipAddrType, Value: "172.18.0.243"}
+	newAddr := gatewayv1beta1.GatewayAddress{Type: &ipAddrType, Value: "172.18.0.244"}
+
+	t.Logf("creating a Gateway with the static IP %s", oldAddr.Value)
+	gw := &gatewayv1beta1.Gateway{
+		ObjectMeta: metav1.ObjectMeta{
+			Name: uuid.NewString(),
+		},
+		Spec: gatewayv1beta1.GatewaySpec{
+			GatewayClassName: gatewayv1beta1.ObjectName(gwc.Name),
+			Addresses:        []gatewayv1beta1.GatewayAddress{oldAddr},
+			Listeners: []gatewayv1beta1.Listener{{
+				Name:     "tcp",
+				Protocol: gatewayv1beta1.TCPProtocolType,
+				Port:     gatewayv1beta1.PortNumber(8080),
+			}},
+		},
+	}
+	gw, err = gwclient.GatewayV1beta1().Gateways(corev1.NamespaceDefault).Create(ctx, gw, metav1.CreateOptions{})
+	require.NoError(t, err)
+	addCleanup(gatewayAddressCleanupKey, func(ctx context.Context) error {
+		cleanupLog("cleaning up gateway")
+		return gwclient.GatewayV1beta1().Gateways(corev1.NamespaceDefault).Delete(ctx, gw.Name, metav1.DeleteOptions{})
+	})
+
+	t.Log("waiting for the Gateway to be programmed with its initial address")
+	gw = waitForGatewayCondition(t, gw.Name, gatewayv1beta1.GatewayConditionProgrammed, metav1.ConditionTrue)
+	require.NotEmpty(t, gw.Status.Addresses)
+	require.Equal(t, oldAddr.Value, gw.Status.Addresses[0].Value)
+
+	t.Logf("changing the address of the Gateway to %s", newAddr.Value)
+	require.Eventually(t, func() bool {
+		current, err := gwclient.GatewayV1beta1().Gateways(corev1.NamespaceDefault).Get(ctx, gw.Name, metav1.GetOptions{})
+		require.NoError(t, err)
+		current.Spec.Addresses = []gatewayv1beta1.GatewayAddress{newAddr}
+		// retry on conflicts with the controlplane's own updates
+		_, err = gwclient.GatewayV1beta1().Gateways(corev1.NamespaceDefault).Update(ctx, current, metav1.UpdateOptions{})
+		return err == nil
+	}, time.Minute, time.Second)
+
+	t.Log("verifying the Gateway reports only the new address once programmed")
+	require.Eventually(t, func() bool {
+		gw, err = gwclient.GatewayV1beta1().Gateways(corev1.NamespaceDefault).Get(ctx, gw.Name, metav1.GetOptions{})
+		require.NoError(t, err)
+		programmed := findGatewayCondition(gw, gatewayv1beta1.GatewayConditionProgrammed)
+		return programmed != nil && programmed.Status == metav1.ConditionTrue &&
+			len(gw.Status.Addresses) == 1 && gw.Status.Addresses[0].Value == newAddr.Value
+	}, time.Minute, time.Second)
+
+	t.Log("verifying the Endpoints of the Gateway's Service no longer point to the old address")
+	require.Eventually(t, func() bool {
+		services, err := env.Cluster().Client().CoreV1().Services(corev1.NamespaceDefault).List(ctx, metav1.ListOptions{
+			LabelSelector: fmt.Sprintf("%s=%s", gatewayServiceLabel, gw.Name),
+		})
+		require.NoError(t, err)
+		if len(services.Items) != 1 {
+			return false
+		}
+		endpoints, err := env.Cluster().Client().CoreV1().Endpoints(corev1.NamespaceDefault).Get(ctx, services.Items[0].Name, metav1.GetOptions{})
+		if err != nil {
+			return false
+		}
+		var ips []string
+		for _, subset := range endpoints.Subsets {
+			for _, addr := range subset.Addresses {
+				ips = append(ips, addr.IP)
+			}
+		}
+		return len(ips) == 1 && ips[0] == newAddr.Value
+	}, time.Minute, time.Second)
+}
+
+func findGatewayCondition(gw *gatewayv1beta1.Gateway, conditionType gatewayv1beta1.GatewayConditionType) *metav1.Condition {
+	for i := range gw.Status.Conditions {
+		if gw.Status.Conditions[i].Type == string(conditionType) {
+			return &gw.Status.Conditions[i]
+		}
+	}
+	return nil
+}
@@ -31,8 +31,13 @@ import (
 	"github.com/kong/kubernetes-testing-framework/pkg/clusters/addons/metallb"
 	"github.com/kong/kubernetes-testing-framework/pkg/clusters/types/kind"
 	"github.com/kong/kubernetes-testing-framework/pkg/environments"
+	"github.com/stretchr/testify/require"
+	corev1 "k8s.io/api/core/v1"
 	"k8s.io/apimachinery/pkg/api/errors"
 	metav1 "k8s.io/apimachinery/pkg/apis/meta/v1"
+	"k8s.io/apimachinery/pkg/runtime"
+	"k8s.io/apimachinery/pkg/watch"
+	gatewayv1beta1 "sigs.k8s.io/gateway-api/apis/v1beta1"
 	"sigs.k8s.io/gateway-api/pkg/client/clientset/versioned"
 
 	testutils "github.com/kubernetes-sigs/blixt/internal/test/utils"
@@ -254,3 +259,60 @@ func waitForDataplaneReadiness(ctx context.Context, env environments.Environment
 		}
 	}
 }
+
+// waitForCondition watches a resource until the conditions returned for it include one of the
+// given type and status, and returns the resource as last seen. The test fails if that doesn't
+// happen within the timeout.
+func waitForCondition(
+	t *testing.T,
+	w watch.Interface,
+	conditions func(runtime.Object) []metav1.Condition,
+	conditionType string,
+	status metav1.ConditionStatus,
+	timeout time.Duration,
+) runtime.Object {
+	t.Helper()
+	defer w.Stop()
+
+	var last []metav1.Condition
+	deadline := time.After(timeout)
+	for {
+		select {
+		case event, ok := <-w.ResultChan():
+			require.True(t, ok, "watch closed while waiting for condition %s=%s", conditionType, status)
+			if event.Type != watch.Added && event.Type != watch.Modified {
+				continue
+			}
+			last = conditions(event.Object)
+			for _, cond := range last {
+				if cond.Type == conditionType && cond.Status == status {
+					return event.Object
+				}
+			}
+		case <-deadline:
+			require.FailNow(t, "timed out waiting for condition", "%s=%s, last conditions: %+v", conditionType, status, last)
+		}
+	}
+}
+
+// waitForGatewayCondition waits for the Gateway in the default namespace to have a condition of
+// the given type and status, and returns it.
+func waitForGatewayCondition(
+	t *testing.T,
+	name string,
+	conditionType gatewayv1beta1.GatewayConditionType,
+	status metav1.ConditionStatus,
+) *gatewayv1beta1.Gateway {
+	t.Helper()
+	w, err := gwclient.GatewayV1beta1().Gateways(corev1.NamespaceDefault).Watch(ctx, metav1.ListOptions{
+		FieldSelector: fmt.Sprintf("metadata.name=%s", name),
+	})
+	require.NoError(t, err)
+	obj := waitForCondition(t, w, func(obj runtime.Object) []metav1.Condition {
+		if gw, ok := obj.(*gatewayv1beta1.Gateway); ok {
+			return gw.Status.Conditions
+		}
+		return nil
+	}, string(conditionType), status, time.Minute)
+	return obj.(*gatewayv1beta1.Gateway)
+}
@@ -30,8 +30,10 @@ import (
 
 	"github.com/kong/kubernetes-testing-framework/pkg/clusters"
 	"github.com/stretchr/testify/require"
+	appsv1 "k8s.io/api/apps/v1"
 	corev1 "k8s.io/api/core/v1"
 	metav1 "k8s.io/apimachinery/pkg/apis/meta/v1"
+	"k8s.io/apimachinery/pkg/types"
 	gatewayv1beta1 "sigs.k8s.io/gateway-api/apis/v1beta1"
 
 	testutils "github.com/kubernetes-sigs/blixt/internal/test/utils"
@@ -41,6 +43,7 @@ const (
 	tcprouteSampleKustomize = "../../config/tests/tcproute"
 	tcprouteRRKustomize     = "../../config/tests/tcproute-rr"
 	tcprouteSampleName      = "blixt-tcproute-sample"
+	gatewayServiceLabel     = "blixt.gateway.networking.k8s.io/owned-by-gateway"
 )
 
 var tcpServerNames = []string{"blixt-tcproute-sample", "tcproute-rr-v1", "tcproute-rr-v2"}
@@ -61,14 +64,9 @@ func TestTCPRouteBasics(t *testing.T) {
 		return clusters.KustomizeDeleteForCluster(ctx, env.Cluster(), tcprouteSampleKustomize, "--ignore-not-found=true")
 	})
 
-	t.Log("waiting for Gateway to have an address")
-	var gw *gatewayv1beta1.Gateway
-	require.Eventually(t, func() bool {
-		var err error
-		gw, err = gwclient.GatewayV1beta1().Gateways(corev1.NamespaceDefault).Get(ctx, tcprouteSampleName, metav1.GetOptions{})
-		require.NoError(t, err)
-		return len(gw.Status.Addresses) > 0
-	}, time.Minute, time.Second)
+	t.Log("waiting for Gateway to be programmed")
+	gw := waitForGatewayCondition(t, tcprouteSampleName, gatewayv1beta1.GatewayConditionProgrammed, metav1.ConditionTrue)
+	require.NotEmpty(t, gw.Status.Addresses)
 	require.NotNil(t, gw.Status.Addresses[0].Type)
 	require.Equal(t, gatewayv1beta1.IPAddressType, *gw.Status.Addresses[0].Type)
 	gwaddr := fmt.Sprintf("%s:8080", gw.Status.Addresses[0].Value)
@@ -132,14 +130,9 @@ func TestTCPRouteRoundRobin(t *testing.T) {
 		return clusters.KustomizeDeleteForCluster(ctx, env.Cluster(), tcprouteRRKustomize, "--ignore-not-found=true")
 	})
 
-	t.Log("waiting for Gateway to have an address")
-	var gw *gatewayv1beta1.Gateway
-	require.Eventually(t, func() bool {
-		var err error
-		gw, err = gwclient.GatewayV1beta1().Gateways(corev1.NamespaceDefault).Get(ctx, tcprouteSampleName, metav1.GetOptions{})
-		require.NoError(t, err)
-		return len(gw.Status.Addresses) > 0
-	}, time.Minute, time.Second)
+	t.Log("waiting for Gateway to be programmed")
+	gw := waitForGatewayCondition(t, tcprouteSampleName, gatewayv1beta1.GatewayConditionProgrammed, metav1.ConditionTrue)
+	require.NotEmpty(t, gw.Status.Addresses)
 	require.NotNil(t, gw.Status.Addresses[0].Type)
 	require.Equal(t, gatewayv1beta1.IPAddressType, *gw.Status.Addresses[0].Type)
 	gwaddr := fmt.Sprintf("%s:8080", gw.Status.Addresses[0].Value)
@@ -230,6 +223,108 @@ func TestTCPRouteRoundRobin(t *testing.T) {
 	}, time.Minute, time.Second)
 }
 
+// TestTCPRouteCrossNode verifies traffic reaches a backend on another node than the one MetalLB
+// announces the Gateway's address from, i.e. that the receiving dataplane redirects it across
+// nodes. It needs a cluster with several schedulable nodes, see `make build.cluster.multinode`.
+func TestTCPRouteCrossNode(t *testing.T) {
+	tcpRouteCrossNodeCleanupKey := "tcproutecrossnode"
+	defer func() {
+		testutils.DumpDiagnosticsIfFailed(ctx, t, env.Cluster())
+		if err := runCleanup(tcpRouteCrossNodeCleanupKey); err != nil {
+			t.Errorf("cleanup failed: %s", err)
+		}
+	}()
+
+	nodes, err := env.Cluster().Client().CoreV1().Nodes().List(ctx, metav1.ListOptions{})
+	require.NoError(t, err)
+	var schedulable int
+	for _, node := range nodes.Items {
+		if !node.Spec.Unschedulable && len(node.Spec.Taints) == 0 {
+			schedulable++
+		}
+	}
+	if schedulable < 2 {
+		t.Skipf("cross-node traffic needs at least 2 schedulable nodes, the cluster has %d", schedulable)
+	}
+
+	t.Log("deploying config/samples/tcproute kustomize")
+	require.NoError(t, clusters.KustomizeDeployForCluster(ctx, env.Cluster(), tcprouteSampleKustomize))
+	addCleanup(tcpRouteCrossNodeCleanupKey, func(ctx context.Context) error {
+		cleanupLog("cleaning up config/samples/tcproute kustomize")
+		return clusters.KustomizeDeleteForCluster(ctx, env.Cluster(), tcprouteSampleKustomize, "--ignore-not-found=true")
+	})
+
+	t.Log("waiting for Gateway to be programmed")
+	gw := waitForGatewayCondition(t, tcprouteSampleName, gatewayv1beta1.GatewayConditionProgrammed, metav1.ConditionTrue)
+	require.NotEmpty(t, gw.Status.Addresses)
+	gwaddr := fmt.Sprintf("%s:8080", gw.Status.Addresses[0].Value)
+
+	t.Log("determining the node MetalLB announces the Gateway's address from")
+	var announcingNode string
+	require.Eventually(t, func() bool {
+		services, err := env.Cluster().Client().CoreV1().Services(corev1.NamespaceDefault).List(ctx, metav1.ListOptions{
+			LabelSelector: fmt.Sprintf("%s=%s", gatewayServiceLabel, gw.Name),
+		})
+		require.NoError(t, err)
+		if len(services.Items) != 1 {
+			return false
+		}
+		events, err := env.Cluster().Client().CoreV1().Events(corev1.NamespaceDefault).List(ctx, metav1.ListOptions{
+			FieldSelector: fmt.Sprintf("involvedObject.kind=Service,involvedObject.name=%s,reason=nodeAssigned", services.Items[0].Name),
+		})
+		require.NoError(t, err)
+		for _, event := range events.Items {
+			// e.g. announcing from node "blixt-dev-worker" with protocol "layer2"
+			if _, err := fmt.Sscanf(event.Message, "announcing from node %q", &announcingNode); err == nil {
+				return true
+			}
+		}
+		return false
+	}, time.Minute, time.Second)
+	t.Logf("the Gateway's address is announced from node %s", announcingNode)
+
+	t.Logf("scheduling the TCP server away from node %s", announcingNode)
+	affinity := fmt.Sprintf(`{"spec":{"template":{"spec":{"affinity":{"nodeAffinity":{"requiredDuringSchedulingIgnoredDuringExecution":{"nodeSelectorTerms":[{"matchExpressions":[{"key":"kubernetes.io/hostname","operator":"NotIn","values":[%q]}]}]}}}}}}}`, announcingNode)
+	_, err = env.Cluster().Client().AppsV1().Deployments(corev1.NamespaceDefault).Patch(ctx, tcprouteSampleName, types.StrategicMergePatchType, []byte(affinity), metav1.PatchOptions{})
+	require.NoError(t, err)
+
+	t.Log("waiting for the TCP server to run on another node")
+	require.Eventually(t, func() bool {
+		var server *appsv1.Deployment
+		server, err = env.Cluster().Client().AppsV1().Deployments(corev1.NamespaceDefault).Get(ctx, tcprouteSampleName, metav1.GetOptions{})
+		require.NoError(t, err)
+		if server.Status.UpdatedReplicas != server.Status.Replicas || server.Status.AvailableReplicas != server.Status.Replicas {
+			return false
+		}
+		pods, err := env.Cluster().Client().CoreV1().Pods(corev1.NamespaceDefault).List(ctx, metav1.ListOptions{
+			LabelSelector: fmt.Sprintf("app=%s", tcprouteSampleName),
+		})
+		require.NoError(t, err)
+		for _, pod := range pods.Items {
+			if pod.DeletionTimestamp != nil || pod.Spec.NodeName == announcingNode {
+				return false
+			}
+		}
+		return len(pods.Items) > 0
+	}, time.Minute*2, time.Second)
+
+	t.Log("verifying TCP connectivity to the server across nodes")
+	var conn net.Conn
+	require.Eventually(t, func() bool {
+		var err error
+		conn, err = net.Dial("tcp", gwaddr)
+		if err != nil {
+			t.Logf("received error connecting to TCP server: [%s], retrying...", err)
+			return false
+		}
+		return true
+	}, time.Minute*5, time.Second)
+	defer conn.Close()
+
+	response := writeAndReadTCP(t, conn)
+	require.Contains(t, response, tcpServerNames[0])
+}
+
 func removeName(names []string, name string) ([]string, bool) {
 	for i, v := range names {
 		if v == name {
@@ -65,14 +65,9 @@ func TestUDPRouteBasics(t *testing.T) {
 		return clusters.KustomizeDeleteForCluster(ctx, env.Cluster(), udprouteSampleKustomize)
 	})
 
-	t.Log("waiting for Gateway to have an address")
-	var gw *gatewayv1beta1.Gateway
-	require.Eventually(t, func() bool {
-		var err error
-		gw, err = gwclient.GatewayV1beta1().Gateways(corev1.NamespaceDefault).Get(ctx, udprouteSampleName, metav1.GetOptions{})
-		require.NoError(t, err)
-		return len(gw.Status.Addresses) > 0
-	}, time.Minute, time.Second)
+	t.Log("waiting for Gateway to be programmed")
+	gw := waitForGatewayCondition(t, udprouteSampleName, gatewayv1beta1.GatewayConditionProgrammed, metav1.ConditionTrue)
+	require.NotEmpty(t, gw.Status.Addresses)
 	require.NotNil(t, gw.Status.Addresses[0].Type)
 	require.Equal(t, gatewayv1beta1.IPAddressType, *gw.Status.Addresses[0].Type)
 	gwaddr := fmt.Sprintf("%s:9875", gw.Status.Addresses[0].Value)
@@ -119,14 +114,9 @@ func TestUDPRouteRoundRobin(t *testing.T) {
 		return clusters.KustomizeDeleteForCluster(ctx, env.Cluster(), udprouteSampleKustomize)
 	})
 
-	t.Log("waiting for Gateway to have an address")
-	var gw *gatewayv1beta1.Gateway
-	require.Eventually(t, func() bool {
-		var err error
-		gw, err = gwclient.GatewayV1beta1().Gateways(corev1.NamespaceDefault).Get(ctx, udprouteSampleName, metav1.GetOptions{})
-		require.NoError(t, err)
-		return len(gw.Status.Addresses) > 0
-	}, time.Minute, time.Second)
+	t.Log("waiting for Gateway to be programmed")
+	gw := waitForGatewayCondition(t, udprouteSampleName, gatewayv1beta1.GatewayConditionProgrammed, metav1.ConditionTrue)
+	require.NotEmpty(t, gw.Status.Addresses)
 	require.NotNil(t, gw.Status.Addresses[0].Type)
 	require.Equal(t, gatewayv1beta1.IPAddressType, *gw.Status.Addresses[0].Type)
 	gwaddr := fmt.Sprintf("%s:9875", gw.Status.Addresses[0].Value)
@@ -254,14 +244,9 @@ func TestUDPRouteNoReach(t *testing.T) {
 		return clusters.KustomizeDeleteForCluster(ctx, env.Cluster(), udproutenoreachSampleKustomize)
 	})
 
-	t.Log("waiting for Gateway to have an address")
-	var gw *gatewayv1beta1.Gateway
-	require.Eventually(t, func() bool {
-		var err error
-		gw, err = gwclient.GatewayV1beta1().Gateways(corev1.NamespaceDefault).Get(ctx, udprouteSampleName, metav1.GetOptions{})
-		require.NoError(t, err)
-		return len(gw.Status.Addresses) > 0
-	}, time.Minute, time.Second)
+	t.Log("waiting for Gateway to be programmed")
+	gw := waitForGatewayCondition(t, udprouteSampleName, gatewayv1beta1.GatewayConditionProgrammed, metav1.ConditionTrue)
+	require.NotEmpty(t, gw.Status.Addresses)
 	require.NotNil(t, gw.Status.Addresses[0].Type)
 	require.Equal(t, gatewayv1beta1.IPAddressType, *gw.Status.Addresses[0].Type)
 	gwaddr := fmt.Sprintf("%s:9875", gw.Status.Addresses[0].Value)
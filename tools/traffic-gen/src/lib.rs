@@ -0,0 +1,11 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+//! Traffic generation for tests of Blixt Gateways, with reports to check distribution fairness
+//! and loss rates against.
+
+pub mod tcp;
+pub mod udp;
@@ -0,0 +1,149 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Error};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    sync::Semaphore,
+    task::JoinSet,
+    time::timeout,
+};
+
+/// Opens many TCP connections to a target, sends a line on each and records which backend
+/// answered. Backends are told apart by the text before the first ':' of their response, as
+/// answered by the echo servers the tests use (e.g. `tcproute-rr-v1: <line>`).
+#[derive(Debug, Clone)]
+pub struct ConnectionStorm {
+    target: SocketAddr,
+    connections: usize,
+    concurrency: usize,
+    timeout: Duration,
+}
+
+/// The outcome of a [`ConnectionStorm`].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TcpReport {
+    pub succeeded: usize,
+    pub failed: usize,
+    /// The number of connections each backend answered.
+    pub backends: BTreeMap<String, usize>,
+}
+
+impl ConnectionStorm {
+    pub fn new(target: SocketAddr) -> Self {
+        Self {
+            target,
+            connections: 100,
+            concurrency: 10,
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// The total number of connections to open.
+    pub fn connections(mut self, connections: usize) -> Self {
+        self.connections = connections;
+        self
+    }
+
+    /// The number of connections open at the same time.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// How long each connection may take, from connecting to reading the response.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub async fn run(&self) -> Result<TcpReport, Error> {
+        let permits = Arc::new(Semaphore::new(self.concurrency));
+        let mut tasks = JoinSet::new();
+        for i in 0..self.connections {
+            let permit = permits.clone().acquire_owned().await?;
+            let (target, limit) = (self.target, self.timeout);
+            tasks.spawn(async move {
+                let response = timeout(limit, exchange(target, i)).await;
+                drop(permit);
+                response
+            });
+        }
+
+        let mut report = TcpReport::default();
+        while let Some(result) = tasks.join_next().await {
+            match result? {
+                Ok(Ok(response)) => {
+                    report.succeeded += 1;
+                    let backend = response.split(':').next().unwrap_or_default().trim();
+                    *report.backends.entry(backend.to_string()).or_default() += 1;
+                }
+                _ => report.failed += 1,
+            }
+        }
+        Ok(report)
+    }
+}
+
+async fn exchange(target: SocketAddr, i: usize) -> std::io::Result<String> {
+    let mut stream = TcpStream::connect(target).await?;
+    stream
+        .write_all(format!("connection {}\n", i).as_bytes())
+        .await?;
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response).await?;
+    if response.is_empty() {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(response)
+}
+
+impl TcpReport {
+    /// Checks that every connection succeeded.
+    pub fn check_no_failures(&self) -> Result<(), Error> {
+        if self.failed > 0 {
+            bail!(
+                "{} of {} connections failed",
+                self.failed,
+                self.failed + self.succeeded
+            );
+        }
+        Ok(())
+    }
+
+    /// Checks that exactly the given number of backends answered, each within the relative
+    /// tolerance (e.g. 0.2 for 20%) of an even share of the successful connections.
+    pub fn check_fairness(&self, backends: usize, tolerance: f64) -> Result<(), Error> {
+        if self.backends.len() != backends {
+            bail!(
+                "expected {} backends to answer, got {}: {:?}",
+                backends,
+                self.backends.len(),
+                self.backends
+            );
+        }
+        let share = self.succeeded as f64 / backends as f64;
+        for (backend, count) in &self.backends {
+            let deviation = (*count as f64 - share).abs() / share;
+            if deviation > tolerance {
+                bail!(
+                    "backend {} answered {} connections, {:.0}% off an even share of {:.1}",
+                    backend,
+                    count,
+                    deviation * 100.0,
+                    share
+                );
+            }
+        }
+        Ok(())
+    }
+}
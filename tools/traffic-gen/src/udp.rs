@@ -0,0 +1,163 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+use std::collections::BTreeSet;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::{bail, Error};
+use tokio::{
+    net::UdpSocket,
+    time::{interval, sleep_until, Instant, MissedTickBehavior},
+};
+
+/// The size of the sequence number leading each datagram, a big endian u64.
+pub const SEQUENCE_LEN: usize = 8;
+
+/// Sends UDP datagrams, each starting with its sequence number, to a target at a fixed rate.
+#[derive(Debug, Clone)]
+pub struct PacketStream {
+    target: SocketAddr,
+    packets: u64,
+    pps: u32,
+    payload_len: usize,
+}
+
+impl PacketStream {
+    pub fn new(target: SocketAddr) -> Self {
+        Self {
+            target,
+            packets: 1000,
+            pps: 1000,
+            payload_len: 64,
+        }
+    }
+
+    /// The number of datagrams to send.
+    pub fn packets(mut self, packets: u64) -> Self {
+        self.packets = packets;
+        self
+    }
+
+    /// The number of datagrams sent per second.
+    pub fn pps(mut self, pps: u32) -> Self {
+        self.pps = pps.max(1);
+        self
+    }
+
+    /// The size of each datagram, including the sequence number.
+    pub fn payload_len(mut self, payload_len: usize) -> Self {
+        self.payload_len = payload_len.max(SEQUENCE_LEN);
+        self
+    }
+
+    /// Sends the stream, returning the number of datagrams sent.
+    pub async fn run(&self) -> Result<u64, Error> {
+        let bind: SocketAddr = if self.target.is_ipv4() {
+            "0.0.0.0:0".parse()?
+        } else {
+            "[::]:0".parse()?
+        };
+        let sock = UdpSocket::bind(bind).await?;
+        sock.connect(self.target).await?;
+
+        let mut ticks = interval(Duration::from_secs(1) / self.pps);
+        // sending late is better than bursting, which would skew loss under load
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut payload = vec![0; self.payload_len];
+        for sequence in 0..self.packets {
+            ticks.tick().await;
+            payload[..SEQUENCE_LEN].copy_from_slice(&sequence.to_be_bytes());
+            sock.send(&payload).await?;
+        }
+        Ok(self.packets)
+    }
+}
+
+/// Receives the datagrams of [`PacketStream`]s.
+pub struct SequenceReceiver {
+    sock: UdpSocket,
+}
+
+/// What a [`SequenceReceiver`] received of a stream.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LossReport {
+    pub expected: u64,
+    pub received: u64,
+    pub duplicates: u64,
+    /// Datagrams that arrived after one with a higher sequence number.
+    pub out_of_order: u64,
+}
+
+impl SequenceReceiver {
+    pub async fn bind(addr: SocketAddr) -> Result<Self, Error> {
+        Ok(Self {
+            sock: UdpSocket::bind(addr).await?,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.sock.local_addr()?)
+    }
+
+    /// Receives datagrams until the expected number arrived or the timeout passed.
+    pub async fn collect(&self, expected: u64, timeout: Duration) -> Result<LossReport, Error> {
+        let deadline = Instant::now() + timeout;
+        let mut report = LossReport {
+            expected,
+            ..Default::default()
+        };
+        let mut seen = BTreeSet::new();
+        let mut highest = None;
+        let mut buf = vec![0; 65536];
+        while report.received < expected {
+            let len = tokio::select! {
+                len = self.sock.recv(&mut buf) => len?,
+                _ = sleep_until(deadline) => break,
+            };
+            let Some(sequence) = buf[..len]
+                .first_chunk::<SEQUENCE_LEN>()
+                .map(|bytes| u64::from_be_bytes(*bytes))
+            else {
+                continue;
+            };
+            if !seen.insert(sequence) {
+                report.duplicates += 1;
+                continue;
+            }
+            report.received += 1;
+            if highest.is_some_and(|highest| sequence < highest) {
+                report.out_of_order += 1;
+            }
+            highest = highest.max(Some(sequence));
+        }
+        Ok(report)
+    }
+}
+
+impl LossReport {
+    /// The share of expected datagrams that never arrived.
+    pub fn loss_rate(&self) -> f64 {
+        if self.expected == 0 {
+            return 0.0;
+        }
+        self.expected.saturating_sub(self.received) as f64 / self.expected as f64
+    }
+
+    /// Checks that no more than the given share (e.g. 0.01 for 1%) of datagrams were lost.
+    pub fn check_loss_rate(&self, max: f64) -> Result<(), Error> {
+        if self.loss_rate() > max {
+            bail!(
+                "lost {} of {} datagrams ({:.2}%), more than {:.2}%",
+                self.expected.saturating_sub(self.received),
+                self.expected,
+                self.loss_rate() * 100.0,
+                max * 100.0
+            );
+        }
+        Ok(())
+    }
+}
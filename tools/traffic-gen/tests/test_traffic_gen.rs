@@ -0,0 +1,115 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use traffic_gen::tcp::{ConnectionStorm, TcpReport};
+use traffic_gen::udp::{LossReport, PacketStream, SequenceReceiver};
+
+// Answers like the tcp-echo-server, alternating between two backend names.
+async fn round_robin_echo_server() -> std::net::SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let next = Arc::new(AtomicUsize::new(0));
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let backend = ["v1", "v2"][next.fetch_add(1, Ordering::Relaxed) % 2];
+            tokio::spawn(async move {
+                let (reader, mut writer) = stream.into_split();
+                let mut line = String::new();
+                BufReader::new(reader).read_line(&mut line).await.unwrap();
+                let response = format!("{}: {}", backend, line);
+                writer.write_all(response.as_bytes()).await.unwrap();
+            });
+        }
+    });
+    addr
+}
+
+#[tokio::test]
+async fn test_connection_storm() {
+    let addr = round_robin_echo_server().await;
+    let report = ConnectionStorm::new(addr)
+        .connections(50)
+        .concurrency(5)
+        .run()
+        .await
+        .unwrap();
+
+    assert_eq!(report.succeeded, 50);
+    assert_eq!(report.failed, 0);
+    assert_eq!(
+        report.backends,
+        BTreeMap::from([("v1".to_string(), 25), ("v2".to_string(), 25)])
+    );
+    report.check_no_failures().unwrap();
+    report.check_fairness(2, 0.0).unwrap();
+    assert!(report.check_fairness(3, 0.5).is_err());
+}
+
+#[tokio::test]
+async fn test_connection_storm_unreachable() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+
+    let report = ConnectionStorm::new(addr)
+        .connections(3)
+        .run()
+        .await
+        .unwrap();
+    assert_eq!(report.succeeded, 0);
+    assert_eq!(report.failed, 3);
+    assert!(report.check_no_failures().is_err());
+}
+
+#[test]
+fn test_check_fairness_tolerance() {
+    let report = TcpReport {
+        succeeded: 100,
+        failed: 0,
+        backends: BTreeMap::from([("v1".to_string(), 60), ("v2".to_string(), 40)]),
+    };
+    report.check_fairness(2, 0.2).unwrap();
+    assert!(report.check_fairness(2, 0.1).is_err());
+}
+
+#[tokio::test]
+async fn test_packet_stream() {
+    let receiver = SequenceReceiver::bind("127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+    let target = receiver.local_addr().unwrap();
+
+    let stream = PacketStream::new(target).packets(100).pps(10_000);
+    let (report, sent) = tokio::join!(receiver.collect(100, Duration::from_secs(5)), stream.run());
+    let report = report.unwrap();
+
+    assert_eq!(sent.unwrap(), 100);
+    assert_eq!(report.received, 100);
+    assert_eq!(report.duplicates, 0);
+    assert_eq!(report.loss_rate(), 0.0);
+    report.check_loss_rate(0.0).unwrap();
+}
+
+#[test]
+fn test_check_loss_rate() {
+    let report = LossReport {
+        expected: 200,
+        received: 196,
+        duplicates: 0,
+        out_of_order: 0,
+    };
+    assert_eq!(report.loss_rate(), 0.02);
+    report.check_loss_rate(0.05).unwrap();
+    assert!(report.check_loss_rate(0.01).is_err());
+}
@@ -4,35 +4,57 @@ Copyright 2023 The Kubernetes Authors.
 SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
 */
 
+use std::collections::BTreeMap;
 use std::env;
 use std::io::{Error, ErrorKind};
 use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
 use tokio::{
-    net::{TcpListener, UdpSocket},
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream, UdpSocket},
     signal,
     sync::mpsc::{self, Receiver, Sender},
 };
 
+// The datagrams received on each port from each source, served by the counters server.
+type Counters = Arc<Mutex<BTreeMap<(u16, SocketAddr), Counter>>>;
+
+#[derive(Default)]
+struct Counter {
+    datagrams: u64,
+    bytes: u64,
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Vec<String> = env::args().collect();
+    let dry_run = args.iter().any(|arg| arg == "--dry-run");
+    let json = args.iter().any(|arg| arg == "--json");
+
+    let counters = Counters::default();
     let (tx, rx) = mpsc::channel(3);
     tokio::spawn(run_health_server(9878, rx));
+    tokio::spawn(run_counters_server(9879, counters.clone()));
 
-    if args.len() == 2 && args[1] == "--dry-run" {
+    if dry_run {
         println!("Running in dry-run mode no udp servers started");
     } else {
         println!("Running udp servers at ports 9875, 9876, and 9877");
-        tokio::spawn(run_server(9875, tx.clone()));
-        tokio::spawn(run_server(9876, tx.clone()));
-        tokio::spawn(run_server(9877, tx.clone()));
+        tokio::spawn(run_server(9875, tx.clone(), counters.clone(), json));
+        tokio::spawn(run_server(9876, tx.clone(), counters.clone(), json));
+        tokio::spawn(run_server(9877, tx.clone(), counters.clone(), json));
     }
 
     signal::ctrl_c().await?;
     Ok(())
 }
 
-async fn run_server(port: u16, start_notifier: Sender<u16>) -> std::io::Result<()> {
+async fn run_server(
+    port: u16,
+    start_notifier: Sender<u16>,
+    counters: Counters,
+    json: bool,
+) -> std::io::Result<()> {
     let bindaddr = format!("0.0.0.0:{}", port);
     let sock = UdpSocket::bind(&bindaddr).await?;
 
@@ -43,6 +65,23 @@ async fn run_server(port: u16, start_notifier: Sender<u16>) -> std::io::Result<(
     let mut buf = [0; 1024];
     loop {
         let (len, addr) = sock.recv_from(&mut buf).await?;
+        {
+            let mut counters = counters.lock().unwrap();
+            let counter = counters.entry((port, addr)).or_default();
+            counter.datagrams += 1;
+            counter.bytes += len as u64;
+        }
+
+        if json {
+            println!(
+                r#"{{"port":{},"source":"{}","bytes":{},"payload_fnv1a":"{:016x}"}}"#,
+                port,
+                addr,
+                len,
+                fnv1a(&buf[..len])
+            );
+            continue;
+        }
         println!("port {}: {} bytes received from {}", port, len, addr);
         println!(
             "port {}: buffer contents: {}",
@@ -52,6 +91,14 @@ async fn run_server(port: u16, start_notifier: Sender<u16>) -> std::io::Result<(
     }
 }
 
+// Returns the 64-bit FNV-1a hash of the payload, which tests can compute with Go's hash/fnv to
+// tell which datagrams were received.
+fn fnv1a(payload: &[u8]) -> u64 {
+    payload.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
 async fn run_health_server(port: u16, mut rx: Receiver<u16>) -> std::io::Result<()> {
     let bindaddr = format!("0.0.0.0:{}", port);
     let listener = TcpListener::bind(&bindaddr).await?;
@@ -74,6 +121,63 @@ async fn run_health_server(port: u16, mut rx: Receiver<u16>) -> std::io::Result<
     }
 }
 
+// Serves the counters of the datagrams received over HTTP: GET /counters returns them as JSON,
+// and DELETE /counters resets them, e.g. between the cases of a test.
+async fn run_counters_server(port: u16, counters: Counters) -> std::io::Result<()> {
+    let bindaddr = format!("0.0.0.0:{}", port);
+    let listener = TcpListener::bind(&bindaddr).await?;
+
+    println!("counters server listening on {}", port);
+
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let counters = counters.clone();
+        tokio::spawn(async move {
+            if let Err(err) = serve_counters(stream, &counters).await {
+                println!("failed to serve counters to {}: {}", addr, err);
+            }
+        });
+    }
+}
+
+async fn serve_counters(mut stream: TcpStream, counters: &Counters) -> std::io::Result<()> {
+    // the request line is all that's needed of the request
+    let mut buf = [0; 1024];
+    let len = stream.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..len]);
+    let request_line: Vec<&str> = request.split_whitespace().take(2).collect();
+
+    let (status, body) = match request_line[..] {
+        ["GET", "/counters"] => ("200 OK", counters_json(&counters.lock().unwrap())),
+        ["DELETE", "/counters"] => {
+            counters.lock().unwrap().clear();
+            ("200 OK", "[]".to_string())
+        }
+        _ => ("404 Not Found", "[]".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await
+}
+
+// Returns the counters as a JSON array with a record per port and source.
+fn counters_json(counters: &BTreeMap<(u16, SocketAddr), Counter>) -> String {
+    let records: Vec<String> = counters
+        .iter()
+        .map(|((port, source), counter)| {
+            format!(
+                r#"{{"port":{},"source":"{}","datagrams":{},"bytes":{}}}"#,
+                port, source, counter.datagrams, counter.bytes
+            )
+        })
+        .collect();
+    format!("[{}]", records.join(","))
+}
+
 #[derive(Default)]
 struct Peers {
     peers: Vec<IpAddr>,
@@ -0,0 +1,222 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use anyhow::{bail, Context as _, Error};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Parser)]
+pub struct Options {
+    /// The `go test -json` output of a conformance run
+    #[clap(long)]
+    pub results: PathBuf,
+    /// A conformance profile the run covered, e.g. GATEWAY-HTTP (repeatable)
+    #[clap(long = "profile", required = true)]
+    pub profiles: Vec<String>,
+    /// An extended feature the run covered, e.g. HTTPRouteQueryParamMatching (repeatable)
+    #[clap(long = "supported-feature")]
+    pub supported_features: Vec<String>,
+    /// The blixt version that was tested
+    #[clap(long, default_value = concat!("v", env!("CARGO_PKG_VERSION")))]
+    pub version: String,
+    /// The Gateway API version the conformance suite belongs to
+    #[clap(long, default_value = "v1.0.0")]
+    pub gateway_api_version: String,
+    /// The Gateway API release channel the run used
+    #[clap(long, default_value = "standard")]
+    pub gateway_api_channel: String,
+    /// Where to write the report, printed when unset
+    #[clap(long, short)]
+    pub output: Option<PathBuf>,
+}
+
+// A line of `go test -json` output.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TestEvent {
+    action: String,
+    #[serde(default)]
+    test: Option<String>,
+}
+
+// The upstream ConformanceReport format, see
+// https://github.com/kubernetes-sigs/gateway-api/tree/main/conformance/reports
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConformanceReport {
+    api_version: &'static str,
+    kind: &'static str,
+    date: String,
+    #[serde(rename = "gatewayAPIVersion")]
+    gateway_api_version: String,
+    #[serde(rename = "gatewayAPIChannel")]
+    gateway_api_channel: String,
+    implementation: Implementation,
+    mode: &'static str,
+    profiles: Vec<ProfileReport>,
+}
+
+#[derive(Debug, Serialize)]
+struct Implementation {
+    organization: &'static str,
+    project: &'static str,
+    url: &'static str,
+    version: String,
+    contact: Vec<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+struct ProfileReport {
+    name: String,
+    core: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extended: Option<ExtendedStatus>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Status {
+    result: &'static str,
+    statistics: Statistics,
+    summary: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExtendedStatus {
+    #[serde(flatten)]
+    status: Status,
+    supported_features: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Statistics {
+    passed: u32,
+    failed: u32,
+    skipped: u32,
+}
+
+/// Generates a Gateway API conformance report from the results of a conformance run
+pub fn conformance_report(opts: Options) -> Result<(), Error> {
+    let results = fs::read_to_string(&opts.results)
+        .with_context(|| format!("failed to read {}", opts.results.display()))?;
+    let statistics = parse_results(&results)?;
+    if statistics.passed + statistics.failed + statistics.skipped == 0 {
+        bail!("no conformance tests found in {}", opts.results.display());
+    }
+
+    let status = Status {
+        result: if statistics.failed == 0 {
+            "success"
+        } else {
+            "failure"
+        },
+        summary: format!(
+            "{} passed, {} failed, {} skipped",
+            statistics.passed, statistics.failed, statistics.skipped
+        ),
+        statistics,
+    };
+    let report = ConformanceReport {
+        api_version: "gateway.networking.k8s.io/v1alpha1",
+        kind: "ConformanceReport",
+        date: rfc3339_now(),
+        gateway_api_version: opts.gateway_api_version,
+        gateway_api_channel: opts.gateway_api_channel,
+        implementation: Implementation {
+            organization: "kubernetes-sigs",
+            project: "blixt",
+            url: "https://github.com/kubernetes-sigs/blixt",
+            version: opts.version,
+            contact: vec!["@kubernetes-sigs/blixt-maintainers"],
+        },
+        mode: "default",
+        profiles: opts
+            .profiles
+            .iter()
+            .map(|profile| ProfileReport {
+                name: profile.clone(),
+                core: status.clone(),
+                extended: (!opts.supported_features.is_empty()).then(|| ExtendedStatus {
+                    status: status.clone(),
+                    supported_features: opts.supported_features.clone(),
+                }),
+            })
+            .collect(),
+    };
+
+    let report = serde_yaml::to_string(&report)?;
+    match &opts.output {
+        Some(path) => fs::write(path, report)
+            .with_context(|| format!("failed to write {}", path.display()))?,
+        None => print!("{}", report),
+    }
+    Ok(())
+}
+
+// Counts the outcome of each conformance test, i.e. each direct subtest of the suite's test
+// function (e.g. TestConformance/TCPRouteSimple).
+fn parse_results(results: &str) -> Result<Statistics, Error> {
+    let mut outcomes = BTreeMap::new();
+    for line in results.lines().filter(|line| line.starts_with('{')) {
+        let event: TestEvent = serde_yaml::from_str(line)
+            .with_context(|| format!("malformed test event: {}", line))?;
+        let Some(test) = event.test else {
+            continue;
+        };
+        if test.matches('/').count() != 1 {
+            continue;
+        }
+        if matches!(event.action.as_str(), "pass" | "fail" | "skip") {
+            outcomes.insert(test, event.action);
+        }
+    }
+
+    let mut statistics = Statistics::default();
+    for outcome in outcomes.values() {
+        match outcome.as_str() {
+            "pass" => statistics.passed += 1,
+            "fail" => statistics.failed += 1,
+            _ => statistics.skipped += 1,
+        }
+    }
+    Ok(statistics)
+}
+
+// Formats the current time as an RFC 3339 UTC timestamp.
+fn rfc3339_now() -> String {
+    let secs = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+
+    // converts days since the epoch to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
@@ -4,14 +4,25 @@ Copyright 2023 The Kubernetes Authors.
 SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
 */
 
-use std::net::{self, SocketAddr};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::net::{self, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
 use std::str::FromStr;
 
-use anyhow::Error;
+use anyhow::{anyhow, Error};
 use clap::Parser;
+use serde::Deserialize;
+use tonic::transport::Channel;
+use tonic::Request;
 
 use api_server::backends::backends_client::BackendsClient;
-use api_server::backends::{Target, Targets, Vip};
+use api_server::backends::{
+    DrainRequest, Gateway, ListRequest, LoadBalancing, Protocol, RateLimit, RemovedBackendPolicy,
+    Target, TargetRef, Targets, Vip,
+};
+use api_server::server::GATEWAY_METADATA_KEY;
+use common::NamespacedName;
 
 #[derive(Debug, Parser)]
 pub struct Options {
@@ -23,6 +34,15 @@ pub struct Options {
     pub vip_ip: String,
     #[clap(default_value = "8080", long)]
     pub vip_port: u32,
+    /// The last port of a VIP that covers the range of ports from --vip-port
+    #[clap(long)]
+    pub vip_port_end: Option<u32>,
+    /// The VIP is a UDP listener rather than a TCP one
+    #[clap(long, action)]
+    pub udp: bool,
+    /// The VIP is an SCTP listener rather than a TCP one
+    #[clap(long, action, conflicts_with = "udp")]
+    pub sctp: bool,
     #[clap(default_value = "127.0.0.1", long)]
     pub daddr: String,
     #[clap(default_value = "8080", long)]
@@ -31,6 +51,142 @@ pub struct Options {
     pub ifindex: u32,
     #[clap(long, short, action)]
     pub delete: bool,
+    /// Load balance the VIP by consistent hashing instead of round robin
+    #[clap(long, action)]
+    pub maglev: bool,
+    /// Pin the connections of each client to the same backend for this many seconds
+    #[clap(long)]
+    pub affinity_timeout: Option<u32>,
+    /// Load balance the connections of backends removed by the update again, instead of
+    /// preserving them
+    #[clap(long, action)]
+    pub rebalance: bool,
+    /// Send the VIP's traffic to its backends from the dataplane's SNAT address
+    #[clap(long, action)]
+    pub full_nat: bool,
+    /// Load balance the QUIC connections of the VIP by their connection IDs, of this length
+    #[clap(long)]
+    pub quic_cid_len: Option<u32>,
+    /// Drop the packets each client sends to the VIP over this many per second
+    #[clap(long)]
+    pub rate_limit: Option<u32>,
+    /// The number of packets a client may send at once, --rate-limit when unset
+    #[clap(long, requires = "rate_limit")]
+    pub rate_limit_burst: Option<u32>,
+    /// Insert a PROXY protocol header before the data of the VIP's TCP connections
+    #[clap(long, action)]
+    pub proxy_protocol: bool,
+    /// Mark the packets forwarded to the backends with this DSCP, 0 to 63
+    #[clap(long)]
+    pub dscp: Option<u32>,
+    /// The Gateway (as namespace/name) the request is made on behalf of
+    #[clap(long)]
+    pub gateway: Option<NamespacedName>,
+    /// Delete every VIP owned by the provided Gateway
+    #[clap(long, action, requires = "gateway")]
+    pub delete_all: bool,
+    /// Start (true) or stop (false) draining the dataplane
+    #[clap(long)]
+    pub drain: Option<bool>,
+    /// Start (true) or stop (false) draining the --daddr/--dport backend of the VIP
+    #[clap(long)]
+    pub drain_backend: Option<bool>,
+    /// A YAML scenario file describing VIPs and their targets to load
+    #[clap(long)]
+    pub file: Option<PathBuf>,
+    /// After loading the scenario file, compare it with what the dataplane lists
+    #[clap(long, action, requires = "file")]
+    pub verify: bool,
+}
+
+/// A set of VIPs and their targets to load into the dataplane, e.g.:
+///
+/// ```yaml
+/// vips:
+///   - ip: 172.18.0.100
+///     port: 8080
+///     port_end: 8090 # optional, the vip covers the range of ports from port
+///     gateway: default/my-gateway # optional
+///     udp: true # optional, a UDP listener rather than a TCP one
+///     sctp: true # optional, an SCTP listener rather than a TCP one
+///     maglev: true # optional, consistent hashing instead of round robin
+///     affinity_timeout: 300 # optional, seconds clients stick to the same backend
+///     rebalance: true # optional, rebalance the connections of removed backends
+///     full_nat: true # optional, send traffic to the backends from the SNAT address
+///     quic_cid_len: 8 # optional, load balance QUIC connections by their connection IDs
+///     rate_limit: 100 # optional, packets per second each client may send
+///     rate_limit_burst: 200 # optional, packets each client may send at once
+///     proxy_protocol: true # optional, send the PROXY protocol header to the backends
+///     dscp: 46 # optional, the DSCP the packets to the backends are marked with
+///     targets:
+///       - daddr: 10.244.0.5
+///         dport: 80
+///         ifindex: 3 # optional, looked up by the dataplane when unset
+///         draining: true # optional, the backend isn't assigned new connections
+///       - hostname: backend.example.com # resolved by the dataplane instead of a daddr
+///         dport: 80
+/// ```
+#[derive(Debug, Deserialize)]
+struct Scenario {
+    vips: Vec<ScenarioVip>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScenarioVip {
+    ip: Ipv4Addr,
+    port: u32,
+    #[serde(default)]
+    port_end: Option<u32>,
+    #[serde(default)]
+    gateway: Option<String>,
+    #[serde(default)]
+    udp: bool,
+    #[serde(default)]
+    sctp: bool,
+    #[serde(default)]
+    maglev: bool,
+    #[serde(default)]
+    affinity_timeout: Option<u32>,
+    #[serde(default)]
+    rebalance: bool,
+    #[serde(default)]
+    full_nat: bool,
+    #[serde(default)]
+    quic_cid_len: Option<u32>,
+    #[serde(default)]
+    rate_limit: Option<u32>,
+    #[serde(default)]
+    rate_limit_burst: Option<u32>,
+    #[serde(default)]
+    proxy_protocol: bool,
+    #[serde(default)]
+    dscp: Option<u32>,
+    targets: Vec<ScenarioTarget>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScenarioTarget {
+    // either daddr or hostname is required
+    #[serde(default)]
+    daddr: Option<Ipv4Addr>,
+    #[serde(default)]
+    hostname: Option<String>,
+    dport: u32,
+    #[serde(default)]
+    ifindex: Option<u32>,
+    #[serde(default)]
+    draining: bool,
+}
+
+// Wraps the message in a request carrying the Gateway metadata, if one was provided.
+fn new_request<T>(message: T, gateway: &Option<NamespacedName>) -> Result<Request<T>, Error> {
+    let mut request = Request::new(message);
+    if let Some(gateway) = gateway {
+        request
+            .metadata_mut()
+            .insert(GATEWAY_METADATA_KEY, gateway.to_string().parse()?);
+    }
+    Ok(request)
 }
 
 pub async fn update(opts: Options) -> Result<(), Error> {
@@ -38,31 +194,96 @@ pub async fn update(opts: Options) -> Result<(), Error> {
 
     let mut client = BackendsClient::connect(format!("http://{}", server_addr)).await?;
 
+    if let Some(file) = &opts.file {
+        let scenario: Scenario = serde_yaml::from_str(&fs::read_to_string(file)?)?;
+        load_scenario(&mut client, &scenario, &opts.gateway).await?;
+        if opts.verify {
+            verify_scenario(&mut client, &scenario).await?;
+        }
+        return Ok(());
+    }
+
     let addr = net::Ipv4Addr::from_str(&opts.vip_ip)?;
     let daddr = net::Ipv4Addr::from_str(&opts.daddr)?;
 
     let vip = Vip {
         ip: addr.into(),
         port: opts.vip_port,
+        protocol: protocol(opts.udp, opts.sctp) as i32,
+        port_end: opts.vip_port_end.unwrap_or_default(),
     };
 
-    if opts.delete {
-        let res = client.delete(vip.clone()).await?;
+    if let Some(draining) = opts.drain {
+        let res = client
+            .drain_node(Request::new(DrainRequest {
+                draining: Some(draining),
+            }))
+            .await?
+            .into_inner();
         println!(
-            "grpc server responded to DELETE: {}",
+            "grpc server responded to DRAIN: draining {}, {} active connections",
+            res.draining, res.active_connections
+        );
+    } else if let Some(draining) = opts.drain_backend {
+        let target = TargetRef {
+            vip: Some(vip.clone()),
+            daddr: daddr.into(),
+            dport: opts.dport,
+        };
+        let res = if draining {
+            client
+                .drain_backend(new_request(target, &opts.gateway)?)
+                .await?
+        } else {
+            client
+                .undrain_backend(new_request(target, &opts.gateway)?)
+                .await?
+        };
+        println!(
+            "grpc server responded to DRAIN_BACKEND: {}",
             res.into_inner().confirmation
         );
-    } else {
+    } else if opts.delete_all {
+        let gateway = opts.gateway.unwrap_or_default();
         let res = client
-            .update(Targets {
-                vip: Some(vip.clone()),
-                targets: vec![Target {
-                    daddr: daddr.into(),
-                    dport: opts.dport,
-                    ifindex: Some(opts.ifindex),
-                }],
-            })
+            .delete_by_gateway(Request::new(Gateway {
+                namespace: gateway.namespace,
+                name: gateway.name,
+            }))
             .await?;
+        println!(
+            "grpc server responded to DELETE_BY_GATEWAY: {}",
+            res.into_inner().confirmation
+        );
+    } else if opts.delete {
+        let res = client
+            .delete(new_request(vip.clone(), &opts.gateway)?)
+            .await?;
+        println!(
+            "grpc server responded to DELETE: {}",
+            res.into_inner().confirmation
+        );
+    } else {
+        let targets = Targets {
+            vip: Some(vip.clone()),
+            targets: vec![Target {
+                daddr: daddr.into(),
+                dport: opts.dport,
+                ifindex: Some(opts.ifindex),
+                hostname: String::new(),
+                draining: false,
+            }],
+            mirror: None,
+            load_balancing: load_balancing(opts.maglev) as i32,
+            affinity_timeout_seconds: opts.affinity_timeout.unwrap_or_default(),
+            removed_backend_policy: removed_backend_policy(opts.rebalance) as i32,
+            full_nat: opts.full_nat,
+            quic_connection_id_length: opts.quic_cid_len.unwrap_or_default(),
+            rate_limit: rate_limit(opts.rate_limit, opts.rate_limit_burst),
+            proxy_protocol: opts.proxy_protocol,
+            dscp: opts.dscp,
+        };
+        let res = client.update(new_request(targets, &opts.gateway)?).await?;
         println!(
             "grpc server responded to UPDATE: {}",
             res.into_inner().confirmation
@@ -71,3 +292,187 @@ pub async fn update(opts: Options) -> Result<(), Error> {
 
     Ok(())
 }
+
+async fn load_scenario(
+    client: &mut BackendsClient<Channel>,
+    scenario: &Scenario,
+    gateway: &Option<NamespacedName>,
+) -> Result<(), Error> {
+    for vip in &scenario.vips {
+        let gateway = match &vip.gateway {
+            Some(gateway) => Some(gateway.parse()?),
+            None => gateway.clone(),
+        };
+        let targets = Targets {
+            vip: Some(Vip {
+                ip: vip.ip.into(),
+                port: vip.port,
+                protocol: protocol(vip.udp, vip.sctp) as i32,
+                port_end: vip.port_end.unwrap_or_default(),
+            }),
+            targets: vip
+                .targets
+                .iter()
+                .map(|target| match (target.daddr, &target.hostname) {
+                    (None, None) => Err(anyhow!(
+                        "a target of vip {}:{} has neither a daddr nor a hostname",
+                        vip.ip,
+                        vip.port
+                    )),
+                    (daddr, hostname) => Ok(Target {
+                        daddr: daddr.map_or(0, u32::from),
+                        dport: target.dport,
+                        ifindex: target.ifindex,
+                        hostname: hostname.clone().unwrap_or_default(),
+                        draining: target.draining,
+                    }),
+                })
+                .collect::<Result<_, Error>>()?,
+            mirror: None,
+            load_balancing: load_balancing(vip.maglev) as i32,
+            affinity_timeout_seconds: vip.affinity_timeout.unwrap_or_default(),
+            removed_backend_policy: removed_backend_policy(vip.rebalance) as i32,
+            full_nat: vip.full_nat,
+            quic_connection_id_length: vip.quic_cid_len.unwrap_or_default(),
+            rate_limit: rate_limit(vip.rate_limit, vip.rate_limit_burst),
+            proxy_protocol: vip.proxy_protocol,
+            dscp: vip.dscp,
+        };
+        let res = client.update(new_request(targets, &gateway)?).await?;
+        println!(
+            "grpc server responded to UPDATE: {}",
+            res.into_inner().confirmation
+        );
+    }
+    Ok(())
+}
+
+fn protocol(udp: bool, sctp: bool) -> Protocol {
+    if udp {
+        Protocol::Udp
+    } else if sctp {
+        Protocol::Sctp
+    } else {
+        Protocol::Tcp
+    }
+}
+
+fn load_balancing(maglev: bool) -> LoadBalancing {
+    if maglev {
+        LoadBalancing::Maglev
+    } else {
+        LoadBalancing::RoundRobin
+    }
+}
+
+fn removed_backend_policy(rebalance: bool) -> RemovedBackendPolicy {
+    if rebalance {
+        RemovedBackendPolicy::Rebalance
+    } else {
+        RemovedBackendPolicy::Preserve
+    }
+}
+
+fn rate_limit(packets_per_second: Option<u32>, burst: Option<u32>) -> Option<RateLimit> {
+    packets_per_second.map(|packets_per_second| RateLimit {
+        packets_per_second,
+        burst: burst.unwrap_or_default(),
+    })
+}
+
+// The number of vips requested per List call.
+const LIST_PAGE_SIZE: u32 = 100;
+
+// Identifies a target by its hostname, or its address when it has none.
+fn target_address(daddr: u32, hostname: &str) -> String {
+    if hostname.is_empty() {
+        Ipv4Addr::from(daddr).to_string()
+    } else {
+        hostname.to_string()
+    }
+}
+
+// Compares the targets of each VIP in the scenario with those the dataplane lists.
+async fn verify_scenario(
+    client: &mut BackendsClient<Channel>,
+    scenario: &Scenario,
+) -> Result<(), Error> {
+    let mut all_targets = Vec::new();
+    let mut page_token = String::new();
+    loop {
+        let page = client
+            .list(Request::new(ListRequest {
+                page_size: LIST_PAGE_SIZE,
+                page_token,
+            }))
+            .await?
+            .into_inner();
+        all_targets.extend(page.targets);
+        if page.next_page_token.is_empty() {
+            break;
+        }
+        page_token = page.next_page_token;
+    }
+
+    let listed: BTreeMap<(u32, u32, u32, i32), BTreeSet<(String, u32)>> = all_targets
+        .into_iter()
+        .filter_map(|targets| {
+            let vip = targets.vip?;
+            let backends = targets
+                .targets
+                .iter()
+                .map(|target| (target_address(target.daddr, &target.hostname), target.dport))
+                .collect();
+            Some(((vip.ip, vip.port, vip.port_end, vip.protocol), backends))
+        })
+        .collect();
+
+    let mut differences = 0;
+    for vip in &scenario.vips {
+        let expected: BTreeSet<(String, u32)> = vip
+            .targets
+            .iter()
+            .map(|target| {
+                let address = target_address(
+                    target.daddr.map_or(0, u32::from),
+                    target.hostname.as_deref().unwrap_or_default(),
+                );
+                (address, target.dport)
+            })
+            .collect();
+        let listed_key = (
+            vip.ip.into(),
+            vip.port,
+            vip.port_end.unwrap_or_default(),
+            protocol(vip.udp, vip.sctp) as i32,
+        );
+        let Some(actual) = listed.get(&listed_key) else {
+            println!("- vip {}:{} is missing", vip.ip, vip.port);
+            differences += 1;
+            continue;
+        };
+        for (daddr, dport) in expected.difference(actual) {
+            println!(
+                "- vip {}:{} is missing target {}:{}",
+                vip.ip, vip.port, daddr, dport
+            );
+            differences += 1;
+        }
+        for (daddr, dport) in actual.difference(&expected) {
+            println!(
+                "+ vip {}:{} has unexpected target {}:{}",
+                vip.ip, vip.port, daddr, dport
+            );
+            differences += 1;
+        }
+    }
+
+    if differences > 0 {
+        return Err(Error::msg(format!(
+            "dataplane differs from the scenario in {} places",
+            differences
+        )));
+    }
+    println!("dataplane matches the scenario");
+    Ok(())
+}
@@ -8,7 +8,9 @@ SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
 
 mod build_ebpf;
 mod build_proto;
+mod conformance_report;
 mod grpc;
+mod netns;
 mod run;
 
 use std::process::exit;
@@ -27,6 +29,7 @@ enum Command {
     BuildProto(build_proto::Options),
     Run(run::Options),
     GrpcClient(grpc::Options),
+    ConformanceReport(conformance_report::Options),
 }
 
 #[tokio::main]
@@ -39,6 +42,7 @@ async fn main() {
         BuildProto(opts) => build_proto::build_proto(opts),
         Run(opts) => run::run(opts),
         GrpcClient(opts) => grpc::update(opts).await,
+        ConformanceReport(opts) => conformance_report::conformance_report(opts),
     };
 
     if let Err(e) = ret {
@@ -0,0 +1,116 @@
+/*
+Copyright 2024 The Kubernetes Authors.
+
+SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
+*/
+
+use std::process::{Command, Output};
+
+use anyhow::{bail, Context as _};
+
+/// The interface the dataplane attaches to, inside the dataplane namespace.
+pub const DATAPLANE_IFACE: &str = "blixt-dp";
+/// The address of the dataplane interface.
+pub const DATAPLANE_ADDR: &str = "10.213.0.1";
+/// The interface traffic is sent from, inside the client namespace.
+pub const CLIENT_IFACE: &str = "blixt-client";
+/// The address of the client interface.
+pub const CLIENT_ADDR: &str = "10.213.0.2";
+
+/// A pair of throwaway network namespaces connected by a veth pair: one for the dataplane and
+/// one to send traffic from. Both are deleted on drop, along with any processes left in them.
+pub struct TestNetwork {
+    runner: Vec<String>,
+    pub name: String,
+    pub client_name: String,
+}
+
+impl TestNetwork {
+    /// Creates the namespaces, using the runner (e.g. `sudo -E`) for privileged commands.
+    pub fn create(name: &str, runner: &[&str]) -> Result<TestNetwork, anyhow::Error> {
+        let network = TestNetwork {
+            runner: runner.iter().map(|arg| arg.to_string()).collect(),
+            name: name.to_string(),
+            client_name: format!("{}-client", name),
+        };
+
+        let setup = [
+            format!("ip netns add {}", network.name),
+            format!("ip netns add {}", network.client_name),
+            format!(
+                "ip link add {} netns {} type veth peer name {} netns {}",
+                DATAPLANE_IFACE, network.name, CLIENT_IFACE, network.client_name
+            ),
+            format!(
+                "ip -n {} addr add {}/24 dev {}",
+                network.name, DATAPLANE_ADDR, DATAPLANE_IFACE
+            ),
+            format!(
+                "ip -n {} addr add {}/24 dev {}",
+                network.client_name, CLIENT_ADDR, CLIENT_IFACE
+            ),
+            format!("ip -n {} link set {} up", network.name, DATAPLANE_IFACE),
+            format!("ip -n {} link set {} up", network.client_name, CLIENT_IFACE),
+            format!("ip -n {} link set lo up", network.name),
+        ];
+        // the namespaces are cleaned up by drop if any of the steps fail
+        for command in &setup {
+            let args: Vec<&str> = command.split_whitespace().collect();
+            network.privileged(&args)?;
+        }
+
+        Ok(network)
+    }
+
+    /// Returns a command that runs the program privileged inside the namespace.
+    pub fn command_in(&self, namespace: &str, program: &str) -> Command {
+        let mut command = Command::new(&self.runner[0]);
+        command
+            .args(&self.runner[1..])
+            .args(["ip", "netns", "exec", namespace, program]);
+        command
+    }
+
+    fn privileged(&self, args: &[&str]) -> Result<Output, anyhow::Error> {
+        let output = Command::new(&self.runner[0])
+            .args(&self.runner[1..])
+            .args(args)
+            .output()
+            .with_context(|| format!("failed to run `{}`", args.join(" ")))?;
+        if !output.status.success() {
+            bail!(
+                "`{}` failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(output)
+    }
+
+    // Kills the processes still running in the namespace.
+    fn kill_processes(&self, namespace: &str) -> Result<(), anyhow::Error> {
+        let output = self.privileged(&["ip", "netns", "pids", namespace])?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let pids: Vec<&str> = stdout.split_whitespace().collect();
+        if !pids.is_empty() {
+            let mut args = vec!["kill"];
+            args.extend(pids);
+            self.privileged(&args)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TestNetwork {
+    fn drop(&mut self) {
+        for namespace in [&self.name, &self.client_name] {
+            let _ = self.kill_processes(namespace);
+            if let Err(err) = self.privileged(&["ip", "netns", "del", namespace]) {
+                eprintln!(
+                    "failed to delete network namespace {}: {:#}",
+                    namespace, err
+                );
+            }
+        }
+    }
+}
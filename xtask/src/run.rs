@@ -4,12 +4,16 @@ Copyright 2023 The Kubernetes Authors.
 SPDX-License-Identifier: (GPL-2.0-only OR BSD-2-Clause)
 */
 
-use std::{os::unix::process::CommandExt, process::Command};
+use std::{os::unix::process::CommandExt, path::PathBuf, process::Command, thread, time::Duration};
 
-use anyhow::Context as _;
+use anyhow::{bail, Context as _};
 use clap::Parser;
 
 use crate::build_ebpf::{build_ebpf, Architecture, Options as BuildOptions};
+use crate::netns::{TestNetwork, CLIENT_ADDR, CLIENT_IFACE, DATAPLANE_ADDR, DATAPLANE_IFACE};
+
+// How long the dataplane is given to start before the traffic script runs.
+const DATAPLANE_STARTUP_DELAY: Duration = Duration::from_secs(3);
 
 #[derive(Debug, Parser)]
 pub struct Options {
@@ -22,6 +26,14 @@ pub struct Options {
     /// The command used to wrap your application
     #[clap(short, long, default_value = "sudo -E")]
     pub runner: String,
+    /// Run the dataplane in a throwaway network namespace with this name instead of on the
+    /// host, attached to a veth pair whose peer lives in a `<name>-client` namespace
+    #[clap(long)]
+    pub netns: Option<String>,
+    /// A script to run in the client namespace once the dataplane is up, after which the
+    /// dataplane is stopped and the namespaces are deleted
+    #[clap(long, requires = "netns")]
+    pub script: Option<PathBuf>,
     /// Arguments to pass to your application
     #[clap(name = "args", last = true)]
     pub run_args: Vec<String>,
@@ -58,6 +70,10 @@ pub fn run(opts: Options) -> Result<(), anyhow::Error> {
     // arguments to pass to the application
     let mut run_args: Vec<_> = opts.run_args.iter().map(String::as_str).collect();
 
+    if let Some(netns) = &opts.netns {
+        return run_in_netns(&opts, netns, &bin_path, &run_args);
+    }
+
     // configure args
     let mut args: Vec<_> = opts.runner.trim().split_terminator(' ').collect();
     args.push(bin_path.as_str());
@@ -72,3 +88,51 @@ pub fn run(opts: Options) -> Result<(), anyhow::Error> {
     // we shouldn't get here unless the command failed to spawn
     Err(anyhow::Error::from(err).context(format!("Failed to run `{}`", args.join(" "))))
 }
+
+/// Run the project in a throwaway network namespace, optionally followed by a traffic script
+fn run_in_netns(
+    opts: &Options,
+    netns: &str,
+    bin_path: &str,
+    run_args: &[&str],
+) -> Result<(), anyhow::Error> {
+    let runner: Vec<_> = opts.runner.trim().split_terminator(' ').collect();
+    let network = TestNetwork::create(netns, &runner)
+        .with_context(|| format!("Failed to create network namespace {}", netns))?;
+
+    let mut dataplane = network
+        .command_in(&network.name, bin_path)
+        .args(["--iface", DATAPLANE_IFACE])
+        .args(run_args)
+        .env("RUST_LOG", "info")
+        .spawn()
+        .context("Failed to start the dataplane")?;
+
+    let Some(script) = &opts.script else {
+        dataplane.wait()?;
+        return Ok(());
+    };
+
+    thread::sleep(DATAPLANE_STARTUP_DELAY);
+    if let Some(status) = dataplane.try_wait()? {
+        bail!("the dataplane exited early: {}", status);
+    }
+
+    let status = network
+        .command_in(&network.client_name, "sh")
+        .arg(script)
+        .env("BLIXT_DATAPLANE_ADDR", DATAPLANE_ADDR)
+        .env("BLIXT_CLIENT_ADDR", CLIENT_ADDR)
+        .env("BLIXT_CLIENT_IFACE", CLIENT_IFACE)
+        .status()
+        .with_context(|| format!("Failed to run `{}`", script.display()))?;
+
+    // dropping the network stops the dataplane
+    drop(network);
+    let _ = dataplane.wait();
+
+    if !status.success() {
+        bail!("`{}` failed: {}", script.display(), status);
+    }
+    Ok(())
+}